@@ -493,7 +493,7 @@ pub fn locate_sda_bases(obj: &mut ObjInfo) -> Result<bool> {
         obj,
         |ExecCbData { executor, vm, result, ins_addr: _, section: _, ins, block_start: _ }| {
             match result {
-                StepResult::Continue | StepResult::LoadStore { .. } => {
+                StepResult::Continue | StepResult::LoadStore { .. } | StepResult::FloatUse(_) => {
                     return Ok(ExecCbResult::Continue);
                 }
                 StepResult::Illegal => bail!("Illegal instruction @ {:#010X}", ins.addr),
@@ -16,6 +16,7 @@ pub mod pass;
 pub mod signatures;
 pub mod slices;
 pub mod tracker;
+pub mod virtual_calls;
 pub mod vm;
 
 pub fn disassemble(section: &ObjSection, address: u32) -> Option<Ins> {
@@ -21,7 +21,7 @@ pub fn detect_objects(obj: &mut ObjInfo) -> Result<()> {
                 ObjDataKind::Byte => 1,
                 ObjDataKind::Byte2 => 2,
                 ObjDataKind::Byte4 | ObjDataKind::Float => 4,
-                ObjDataKind::Byte8 | ObjDataKind::Double => 8,
+                ObjDataKind::Byte8 | ObjDataKind::Float2 | ObjDataKind::Double => 8,
                 _ => 0,
             };
             if !symbol.size_known {
@@ -37,7 +37,7 @@ pub fn detect_objects(obj: &mut ObjInfo) -> Result<()> {
                     (2 | 4, 2) => expected_size,
                     (..=8, 1 | 2 | 4) => {
                         // alignment to double
-                        if obj.symbols.at_section_address(section_index, next_addr).any(|(_, sym)| sym.data_kind == ObjDataKind::Double)
+                        if obj.symbols.at_section_address(section_index, next_addr).any(|(_, sym)| matches!(sym.data_kind, ObjDataKind::Float2 | ObjDataKind::Double))
                         // If we're at a TU boundary, we can assume it's just padding
                         || section.splits.has_split_at(symbol.address as u32 + new_size)
                         {
@@ -1,14 +1,22 @@
+use std::{collections::BTreeSet, fmt::Write};
+
 use anyhow::{bail, ensure, Result};
+use cwdemangle::{demangle, DemangleOptions};
 use flagset::FlagSet;
 use itertools::Itertools;
 use memchr::memmem;
+use ppc750cl::{Argument, Ins, Opcode, GPR};
 
 use crate::{
-    analysis::cfa::{AnalyzerState, FunctionInfo, SectionAddress},
+    analysis::{
+        cfa::{AnalyzerState, FunctionInfo, SectionAddress},
+        read_u32,
+    },
     obj::{
-        ObjInfo, ObjKind, ObjRelocKind, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
-        ObjSymbolFlags, ObjSymbolKind,
+        ObjDataKind, ObjInfo, ObjKind, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
+        ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
     },
+    util::config::is_auto_symbol,
 };
 
 pub trait AnalysisPass {
@@ -297,3 +305,349 @@ impl AnalysisPass for FindRelRodataData {
         Ok(())
     }
 }
+
+pub struct FindVtables {}
+
+struct Vtable {
+    rtti: Option<u32>,
+    end: u32,
+    functions: Vec<SectionAddress>,
+}
+
+// MW vtable layout: RTTI pointer (or 0), this offset (0), then function pointers.
+// Classes with multiple bases continue with secondary tables, each beginning with the
+// same RTTI pointer and a negative this offset. RTTI records are a pointer to the type
+// name string followed by a pointer to the base class list (or 0), which is a list of
+// base RTTI pointer and offset pairs, terminated by 0.
+impl AnalysisPass for FindVtables {
+    fn execute(state: &mut AnalyzerState, obj: &ObjInfo) -> Result<()> {
+        // Data pointers in relocatable objects are unresolved relocations
+        if obj.kind != ObjKind::Executable {
+            return Ok(());
+        }
+
+        let references = CodeReferences::new(obj);
+        for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Data) {
+            let section_end = (section.address + section.size) as u32;
+            let mut address = (section.address as u32 + 3) & !3;
+            while address + 12 <= section_end {
+                if !references.addresses.contains(&address) {
+                    address += 4;
+                    continue;
+                }
+                let Some(vtable) = parse_vtable(obj, section, address, &references) else {
+                    address += 4;
+                    continue;
+                };
+                let start = SectionAddress::new(section_index, address);
+                let rtti = vtable.rtti.and_then(|rtti| Some((rtti, rtti_class_name(obj, rtti)?)));
+                log::debug!(
+                    "Found vtable @ {:#010X} ({} functions, class {:?})",
+                    start,
+                    vtable.functions.len(),
+                    rtti.as_ref().map(|(_, name)| name)
+                );
+                for &function in &vtable.functions {
+                    state.functions.entry(function).or_default();
+                }
+
+                // Tables without a class name are left to data analysis
+                if let Some((rtti, name)) = &rtti {
+                    if let Some(mangled) = mangle_class_name(name) {
+                        let size = vtable.end - address;
+                        add_known_object(state, obj, start, format!("__vt__{}", mangled), size);
+                        add_rtti_objects(state, obj, *rtti, &mut BTreeSet::new());
+                    }
+                }
+                address = vtable.end;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds a known data symbol, unless a user-named symbol already exists at the address.
+fn add_known_object(
+    state: &mut AnalyzerState,
+    obj: &ObjInfo,
+    addr: SectionAddress,
+    name: String,
+    size: u32,
+) {
+    if obj.symbols.at_section_address(addr.section, addr.address).any(|(_, s)| !is_auto_symbol(s)) {
+        return;
+    }
+    state.known_symbols.insert(addr, ObjSymbol {
+        demangled_name: demangle(&name, &DemangleOptions::default()),
+        name,
+        address: addr.address as u64,
+        section: Some(addr.section),
+        size: size as u64,
+        size_known: true,
+        flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+        kind: ObjSymbolKind::Object,
+        data_kind: ObjDataKind::Byte4,
+        ..Default::default()
+    });
+}
+
+/// Adds symbols for an RTTI record and, recursively, the records of its base classes.
+fn add_rtti_objects(
+    state: &mut AnalyzerState,
+    obj: &ObjInfo,
+    rtti: u32,
+    visited: &mut BTreeSet<u32>,
+) {
+    if !visited.insert(rtti) {
+        return;
+    }
+    let Some(mangled) = rtti_class_name(obj, rtti).and_then(|name| mangle_class_name(&name)) else {
+        return;
+    };
+    let Ok((rtti_section, section)) = obj.sections.at_address(rtti) else {
+        return;
+    };
+    add_known_object(
+        state,
+        obj,
+        SectionAddress::new(rtti_section, rtti),
+        format!("__RTTI__{}", mangled),
+        8,
+    );
+    let bases = rtti_bases(obj, read_u32(section, rtti + 4).unwrap_or(0));
+    for &(base, offset) in &bases {
+        log::debug!(
+            "Class {} has base {:?} at offset {:#X}",
+            mangled,
+            rtti_class_name(obj, base),
+            offset
+        );
+        add_rtti_objects(state, obj, base, visited);
+    }
+}
+
+/// Reads an RTTI base class list: base RTTI pointers and their offsets within the class.
+pub fn rtti_bases(obj: &ObjInfo, bases_ptr: u32) -> Vec<(u32, u32)> {
+    let mut out = vec![];
+    let Ok((_, section)) = obj.sections.at_address(bases_ptr) else {
+        return out;
+    };
+    let mut current = bases_ptr;
+    while let (Some(base), Some(offset)) =
+        (read_u32(section, current), read_u32(section, current + 4))
+    {
+        if base == 0 || out.len() >= 64 || rtti_class_name(obj, base).is_none() {
+            break;
+        }
+        out.push((base, offset));
+        current += 8;
+    }
+    out
+}
+
+/// Addresses formed by `lis` + `addi` pairs in code sections.
+struct CodeReferences {
+    addresses: BTreeSet<u32>,
+    /// Addresses stored to offset 0 of an object, as constructors do with a vtable pointer.
+    stored: BTreeSet<u32>,
+}
+
+impl CodeReferences {
+    fn new(obj: &ObjInfo) -> Self {
+        let mut addresses = BTreeSet::new();
+        let mut stored = BTreeSet::new();
+        for (_, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+            let mut hi = [None::<u32>; 32];
+            let mut values = [None::<u32>; 32];
+            let mut address = section.address as u32;
+            for chunk in section.data.chunks_exact(4) {
+                let ins = Ins::new(u32::from_be_bytes(chunk.try_into().unwrap()), address);
+                address += 4;
+                match ins.op {
+                    Opcode::Addis if ins.field_rA() == 0 => {
+                        hi[ins.field_rD()] = Some((ins.field_uimm() as u32) << 16);
+                        values[ins.field_rD()] = None;
+                        continue;
+                    }
+                    Opcode::Addi if ins.field_rA() != 0 => {
+                        let value = hi[ins.field_rA()]
+                            .map(|hi| hi.wrapping_add(ins.field_simm() as i32 as u32));
+                        if let Some(value) = value {
+                            addresses.insert(value);
+                        }
+                        hi[ins.field_rD()] = None;
+                        values[ins.field_rD()] = value;
+                        continue;
+                    }
+                    Opcode::Stw if ins.field_simm() == 0 => {
+                        if let Some(value) = values[ins.field_rS()] {
+                            stored.insert(value);
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+                if ins.is_branch() {
+                    hi = [None; 32];
+                    values = [None; 32];
+                }
+                for field in ins.defs() {
+                    if let Some(Argument::GPR(GPR(reg))) = field.argument() {
+                        hi[reg as usize] = None;
+                        values[reg as usize] = None;
+                    }
+                }
+            }
+        }
+        Self { addresses, stored }
+    }
+}
+
+fn code_pointer(obj: &ObjInfo, value: u32) -> Option<SectionAddress> {
+    if value & 3 != 0 {
+        return None;
+    }
+    match obj.sections.at_address(value) {
+        Ok((section_index, section)) if section.kind == ObjSectionKind::Code => {
+            Some(SectionAddress::new(section_index, value))
+        }
+        _ => None,
+    }
+}
+
+fn parse_vtable(
+    obj: &ObjInfo,
+    section: &ObjSection,
+    address: u32,
+    references: &CodeReferences,
+) -> Option<Vtable> {
+    let rtti = read_u32(section, address)?;
+    // Without RTTI, only accept a table that's stored as a vtable pointer, since
+    // callback tables can also begin with two null pointers
+    if (rtti == 0 && !references.stored.contains(&address))
+        || (rtti != 0 && rtti_class_name(obj, rtti).is_none())
+    {
+        return None;
+    }
+    if read_u32(section, address + 4)? != 0 {
+        return None;
+    }
+    let mut functions = vec![];
+    let mut current = address + 8;
+    while let Some(value) = read_u32(section, current) {
+        if let Some(function) = code_pointer(obj, value) {
+            // Function pointers referenced from code belong to a different object
+            if !functions.is_empty() && references.addresses.contains(&current) {
+                break;
+            }
+            functions.push(function);
+            current += 4;
+            continue;
+        }
+        // Secondary table for an additional base class
+        let is_secondary = value == rtti
+            && !functions.is_empty()
+            && read_u32(section, current + 4).is_some_and(|offset| (offset as i32) < 0)
+            && read_u32(section, current + 8).and_then(|v| code_pointer(obj, v)).is_some();
+        if !is_secondary {
+            break;
+        }
+        current += 8;
+    }
+    if functions.is_empty() {
+        return None;
+    }
+    Some(Vtable { rtti: (rtti != 0).then_some(rtti), end: current, functions })
+}
+
+/// Reads the type name from an RTTI record.
+fn rtti_class_name(obj: &ObjInfo, rtti: u32) -> Option<String> {
+    let (_, section) = obj.sections.at_address(rtti).ok()?;
+    if section.kind == ObjSectionKind::Code || section.kind == ObjSectionKind::Bss {
+        return None;
+    }
+    let name_ptr = read_u32(section, rtti)?;
+    let bases_ptr = read_u32(section, rtti + 4)?;
+    if bases_ptr != 0
+        && !matches!(obj.sections.at_address(bases_ptr), Ok((_, s)) if s.kind == ObjSectionKind::Data)
+    {
+        return None;
+    }
+    let (_, name_section) = obj.sections.at_address(name_ptr).ok()?;
+    if name_section.kind == ObjSectionKind::Code || name_section.kind == ObjSectionKind::Bss {
+        return None;
+    }
+    let data = name_section.data_range(name_ptr, 0).ok()?;
+    let len = data.iter().position(|&b| b == 0)?;
+    if len == 0 || len > 256 || !data[..len].iter().all(|&b| b.is_ascii_graphic() || b == b' ') {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&data[..len]).into_owned())
+}
+
+/// Mangles a (possibly qualified) class name. Template classes aren't supported.
+fn mangle_class_name(name: &str) -> Option<String> {
+    let parts = name.split("::").collect_vec();
+    if parts.len() > 9
+        || !parts.iter().all(|p| {
+            p.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    {
+        return None;
+    }
+    let mut out = String::new();
+    if parts.len() > 1 {
+        write!(out, "Q{}", parts.len()).ok()?;
+    }
+    for part in parts {
+        write!(out, "{}{}", part.len(), part).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::ObjArchitecture;
+
+    fn code_obj(code: &[u32]) -> ObjInfo {
+        let data = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![ObjSection {
+                name: ".text".to_string(),
+                kind: ObjSectionKind::Code,
+                address: 0x80003100,
+                size: data.len() as u64,
+                data,
+                align: 4,
+                elf_index: 0,
+                relocations: Default::default(),
+                original_address: 0,
+                file_offset: 0,
+                section_known: true,
+                splits: Default::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_code_references_redefined() {
+        let obj = code_obj(&[
+            0x3c608040, // lis r3, 0x8040
+            0x38630010, // addi r3, r3, 0x10
+            0x3c808040, // lis r4, 0x8040
+            0x60840000, // ori r4, r4, 0 (redefines r4)
+            0x38a40020, // addi r5, r4, 0x20
+            0x90a60000, // stw r5, 0(r6)
+            0x90660000, // stw r3, 0(r6)
+        ]);
+        let references = CodeReferences::new(&obj);
+        assert_eq!(references.addresses, BTreeSet::from([0x80400010]));
+        assert_eq!(references.stored, BTreeSet::from([0x80400010]));
+    }
+}
@@ -1,4 +1,7 @@
 use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
 
 use crate::{
     analysis::{
@@ -7,9 +10,15 @@ use crate::{
     },
     obj::{
         ObjInfo, ObjSectionKind, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
-        ObjSymbolKind,
+        ObjSymbolKind, SymbolIndex,
+    },
+    util::{
+        config::is_auto_symbol,
+        signatures::{
+            apply_signature, check_signature, check_signatures, check_signatures_str,
+            parse_signatures, score_signature, FunctionSignature, FuzzyFunction, SignatureScore,
+        },
     },
-    util::signatures::{apply_signature, check_signatures, check_signatures_str, parse_signatures},
 };
 
 const SIGNATURES: &[(&str, &str)] = &[
@@ -440,6 +449,129 @@ pub fn apply_signatures_post(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
+/// Scans every detected function against user-supplied signatures (see `signatures` in the
+/// project configuration). Only functions without a user-provided name are considered, and a
+/// signature is only applied when it matches exactly one function.
+pub fn apply_user_signatures(obj: &mut ObjInfo, signatures: &[FunctionSignature]) -> Result<()> {
+    if signatures.is_empty() {
+        return Ok(());
+    }
+    log::debug!("Checking {} user signatures", signatures.len());
+    let mut by_size = FxHashMap::<u32, Vec<usize>>::default();
+    for (idx, signature) in signatures.iter().enumerate() {
+        let size = signature.symbols[signature.symbol].size;
+        if size > 0 {
+            by_size.entry(size).or_default().push(idx);
+        }
+    }
+
+    let mut matches = FxHashMap::<usize, Vec<SectionAddress>>::default();
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (_, symbol) in obj.symbols.for_section(section_index).filter(|(_, sym)| {
+            sym.kind == ObjSymbolKind::Function && sym.size_known && is_auto_symbol(sym)
+        }) {
+            let Some(candidates) = by_size.get(&(symbol.size as u32)) else {
+                continue;
+            };
+            let data =
+                section.data_range(symbol.address as u32, (symbol.address + symbol.size) as u32)?;
+            for &idx in candidates {
+                if check_signature(data, &signatures[idx])? {
+                    matches
+                        .entry(idx)
+                        .or_default()
+                        .push(SectionAddress::new(section_index, symbol.address as u32));
+                    break;
+                }
+            }
+        }
+    }
+
+    for (idx, addrs) in matches.into_iter().sorted_by_key(|&(idx, _)| idx) {
+        let signature = &signatures[idx];
+        let name = &signature.symbols[signature.symbol].name;
+        if addrs.len() > 1 {
+            log::debug!("Skipping signature {} ({} matching functions)", name, addrs.len());
+            continue;
+        }
+        log::debug!("Found {} @ {:#010X} (hash {})", name, addrs[0], signature.hash);
+        if let Err(e) = apply_signature(obj, addrs[0], signature) {
+            log::warn!("Failed to apply signature {} @ {:#010X}: {:?}", name, addrs[0], e);
+        }
+    }
+    Ok(())
+}
+
+/// Minimum number of instructions for a function or signature to be considered for fuzzy matching.
+/// Shorter functions are too generic to produce meaningful scores.
+const FUZZY_MIN_INSTRUCTIONS: usize = 4;
+
+/// Candidate signature for an unnamed function, found by fuzzy matching.
+#[derive(Debug, Clone)]
+pub struct FuzzyMatch {
+    pub symbol_index: SymbolIndex,
+    pub signature_index: usize,
+    pub score: SignatureScore,
+}
+
+/// Scores every unnamed function against the given signatures by instruction-level similarity,
+/// returning the best candidate for each function with a confidence of at least `min_confidence`.
+/// Relocations are used if present, so a [`Tracker`](crate::analysis::tracker::Tracker) should
+/// have been applied to the object beforehand.
+pub fn find_fuzzy_matches(
+    obj: &ObjInfo,
+    signatures: &[FunctionSignature],
+    min_confidence: f32,
+) -> Result<Vec<FuzzyMatch>> {
+    let fuzzy_signatures = signatures
+        .iter()
+        .enumerate()
+        // Data object signatures can't be compared by instruction
+        .filter(|(_, sig)| sig.symbols[sig.symbol].kind == ObjSymbolKind::Function)
+        .map(|(index, sig)| FuzzyFunction::from_signature(sig).map(|f| (index, f)))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, sig)| sig.opcodes.len() >= FUZZY_MIN_INSTRUCTIONS)
+        .collect::<Vec<_>>();
+
+    let mut functions = Vec::new();
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (symbol_index, symbol) in obj.symbols.for_section(section_index).filter(|(_, sym)| {
+            sym.kind == ObjSymbolKind::Function
+                && sym.size_known
+                && sym.size as usize >= FUZZY_MIN_INSTRUCTIONS * 4
+                && is_auto_symbol(sym)
+        }) {
+            let start = symbol.address as u32;
+            let end = (symbol.address + symbol.size) as u32;
+            let relocations =
+                section.relocations.range(start..end).map(|(_, reloc)| reloc.kind).collect();
+            let data = section.data_range(start, end)?;
+            functions.push((symbol_index, FuzzyFunction::from_data(data, relocations)));
+        }
+    }
+
+    let mut matches = functions
+        .par_iter()
+        .filter_map(|(symbol_index, function)| {
+            fuzzy_signatures
+                .iter()
+                .filter_map(|(signature_index, signature)| {
+                    score_signature(function, signature, min_confidence)
+                        .map(|score| (*signature_index, score))
+                })
+                .max_by(|(_, a), (_, b)| a.confidence.total_cmp(&b.confidence))
+                .map(|(signature_index, score)| FuzzyMatch {
+                    symbol_index: *symbol_index,
+                    signature_index,
+                    score,
+                })
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|m| m.symbol_index);
+    Ok(matches)
+}
+
 /// Create _ctors and _dtors symbols if missing
 pub fn update_ctors_dtors(obj: &mut ObjInfo) -> Result<()> {
     if obj.symbols.by_name("_ctors")?.is_none() {
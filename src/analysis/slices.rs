@@ -240,7 +240,7 @@ impl FunctionSlices {
         }
 
         match result {
-            StepResult::Continue | StepResult::LoadStore { .. } => {
+            StepResult::Continue | StepResult::LoadStore { .. } | StepResult::FloatUse(_) => {
                 let next_address = ins_addr + 4;
                 // If we already visited the next address, connect the blocks and end
                 if executor.visited(section.address as u32, next_address) {
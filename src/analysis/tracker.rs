@@ -13,7 +13,11 @@ use crate::{
         cfa::SectionAddress,
         executor::{ExecCbData, ExecCbResult, Executor},
         relocation_target_for, uniq_jump_table_entries,
-        vm::{is_store_op, BranchTarget, GprValue, StepResult, VM},
+        virtual_calls::{member_class, vtable_class},
+        vm::{
+            gqr_type, is_psq_load_op, is_psq_op, is_store_op, BranchTarget, FloatUse, GprValue,
+            StepResult, VM,
+        },
         RelocationTarget,
     },
     obj::{
@@ -58,23 +62,44 @@ pub enum DataKind {
     Half,
     Byte,
     Float,
+    Float2,
     Double,
     // String,
     // String16,
 }
 
+/// An indirect call through a virtual table loaded from `this`.
+#[derive(Debug, Clone)]
+pub struct VirtualCall {
+    /// Start of the calling function
+    pub function: SectionAddress,
+    /// Class of the calling function
+    pub class: String,
+    /// Offset of the virtual table pointer in the object
+    pub object_offset: u32,
+    /// Offset of the function pointer in the virtual table
+    pub slot_offset: u32,
+}
+
 pub struct Tracker {
     processed_functions: BTreeSet<SectionAddress>,
     sda2_base: Option<u32>, // r2
     sda_base: Option<u32>,  // r13
     pub relocations: BTreeMap<SectionAddress, Relocation>,
     data_types: BTreeMap<SectionAddress, DataKind>,
+    float_uses: BTreeMap<SectionAddress, DataKind>, // lfd data typed by use
+    gqr_writes: BTreeMap<u8, BTreeSet<u32>>,        // constant values written to GQRs
+    quantized_to: BTreeMap<SectionAddress, (u8, bool, bool)>, // psq_l/psq_st with unknown GQR
     stack_address: Option<u32>,
     stack_end: Option<u32>,
     db_stack_addr: Option<u32>,
     arena_lo: Option<u32>,
     arena_hi: Option<u32>,
     pub known_relocations: BTreeSet<SectionAddress>,
+    pub virtual_calls: BTreeMap<SectionAddress, VirtualCall>,
+    vtable_classes: BTreeSet<String>, // classes with a __vt__ symbol
+    this_class: Option<String>,       // class of the current member function
+    vptr_offsets: BTreeMap<String, BTreeSet<u32>>, // vtable pointer offsets stored by constructors
 
     stores_to: BTreeSet<SectionAddress>, // for determining data vs rodata, sdata(2)/sbss(2)
     sda_to: BTreeSet<SectionAddress>,    // for determining data vs sdata
@@ -89,6 +114,9 @@ impl Tracker {
             sda_base: obj.sda_base,
             relocations: Default::default(),
             data_types: Default::default(),
+            float_uses: Default::default(),
+            gqr_writes: Default::default(),
+            quantized_to: Default::default(),
             stack_address: obj.stack_address,
             stack_end: obj.stack_end.or_else(|| {
                 // Stack ends after all BSS sections
@@ -103,6 +131,14 @@ impl Tracker {
                 .or_else(|| obj.db_stack_addr.map(|db_stack_addr| (db_stack_addr + 0x1F) & !0x1F)),
             arena_hi: Some(obj.arena_hi.unwrap_or(0x81700000)),
             known_relocations: Default::default(),
+            virtual_calls: Default::default(),
+            vtable_classes: obj
+                .symbols
+                .iter()
+                .filter_map(|s| vtable_class(s).map(str::to_string))
+                .collect(),
+            this_class: None,
+            vptr_offsets: Default::default(),
             stores_to: Default::default(),
             sda_to: Default::default(),
             hal_to: Default::default(),
@@ -112,6 +148,11 @@ impl Tracker {
     #[instrument(name = "tracker", skip(self, obj))]
     pub fn process(&mut self, obj: &ObjInfo) -> Result<()> {
         self.process_code(obj)?;
+        self.resolve_quantized_types();
+        // Calls through other function pointers loaded from `this` aren't virtual calls
+        self.virtual_calls.retain(|_, call| {
+            self.vptr_offsets.get(&call.class).is_some_and(|o| o.contains(&call.object_offset))
+        });
         if obj.kind == ObjKind::Executable {
             for (section_index, section) in obj.sections.iter().filter(|(_, s)| {
                 matches!(s.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData)
@@ -147,6 +188,26 @@ impl Tracker {
         Ok(())
     }
 
+    /// Types quantized loads and stores whose GQR was unknown at the access, using the value
+    /// written to that GQR throughout the program. (Usually set once, e.g. OSInitFastCast)
+    fn resolve_quantized_types(&mut self) {
+        for (&address, &(index, load, paired)) in &self.quantized_to {
+            let gqr = match self.gqr_writes.get(&index) {
+                Some(values) if values.len() == 1 => values.first().cloned(),
+                // GQR0 is always zero (float) by convention
+                None if index == 0 => Some(0),
+                _ => None,
+            };
+            let Some(gqr) = gqr else {
+                continue;
+            };
+            let kind = data_kind_from_gqr(gqr, load, paired);
+            if matches!(self.data_types.get(&address), None | Some(DataKind::Unknown)) {
+                self.data_types.insert(address, kind);
+            }
+        }
+    }
+
     fn process_code(&mut self, obj: &ObjInfo) -> Result<()> {
         if let Some(entry) = obj.entry {
             let (section_index, _) = obj.sections.at_address(entry as u32)?;
@@ -278,13 +339,42 @@ impl Tracker {
                             }
                         }
                     }
+                    // stw rS, d(this): vtable pointer stored by a constructor
+                    Opcode::Stw if vm.gpr[ins.field_rA()].value == GprValue::This => {
+                        if let GprValue::Address(RelocationTarget::Address(target)) =
+                            vm.gpr[ins.field_rS()].value
+                        {
+                            if let Some(class) = obj
+                                .symbols
+                                .at_section_address(target.section, target.address)
+                                .find_map(|(_, s)| vtable_class(s))
+                            {
+                                self.vptr_offsets
+                                    .entry(class.to_string())
+                                    .or_default()
+                                    .insert(ins.field_simm() as u32);
+                            }
+                        }
+                    }
+                    // mtspr SPR, rS
+                    Opcode::Mtspr => {
+                        if let spr @ 912..=919 = ins.field_spr() {
+                            let index = (spr - 912) as u8;
+                            if let Some(value) = vm.gqr_value(index) {
+                                self.gqr_writes.entry(index).or_default().insert(value);
+                            }
+                        }
+                    }
                     _ => {}
                 }
                 Ok(ExecCbResult::Continue)
             }
             StepResult::LoadStore { address, source, source_reg } => {
                 if self.is_valid_section_address(obj, ins_addr) {
-                    if (source_reg == 2
+                    if is_psq_op(ins.op) {
+                        // The 12-bit psq displacement can't hold a relocated address,
+                        // so only the data type is recorded
+                    } else if (source_reg == 2
                         && matches!(self.sda2_base, Some(v) if source.value == GprValue::Constant(v)))
                         || (source_reg == 13
                             && matches!(self.sda_base, Some(v) if source.value == GprValue::Constant(v)))
@@ -340,7 +430,22 @@ impl Tracker {
                         }
                     }
                     if let RelocationTarget::Address(address) = address {
-                        self.data_types.insert(address, data_kind_from_op(ins.op));
+                        if is_psq_op(ins.op) {
+                            // psq_l frD, d(rA), W, I / psq_st frS, d(rA), W, I
+                            let index = ins.field_ps_I() as u8;
+                            let load = is_psq_load_op(ins.op);
+                            // W=0 accesses two values
+                            let paired = !ins.field_ps_W();
+                            if let Some(gqr) = vm.gqr_value(index) {
+                                self.data_types
+                                    .insert(address, data_kind_from_gqr(gqr, load, paired));
+                            } else {
+                                // Resolved after all code is processed
+                                self.quantized_to.insert(address, (index, load, paired));
+                            }
+                        } else {
+                            self.data_types.insert(address, data_kind_from_op(ins.op));
+                        }
                         if is_store_op(ins.op) {
                             self.stores_to.insert(address);
                         }
@@ -348,6 +453,23 @@ impl Tracker {
                 }
                 Ok(ExecCbResult::Continue)
             }
+            StepResult::FloatUse(uses) => {
+                for (target, float_use) in uses {
+                    let RelocationTarget::Address(address) = target else {
+                        continue;
+                    };
+                    match float_use {
+                        // Paired-single use wins over double-precision use
+                        FloatUse::PairedSingle => {
+                            self.float_uses.insert(address, DataKind::Float2);
+                        }
+                        FloatUse::Double => {
+                            self.float_uses.entry(address).or_insert(DataKind::Double);
+                        }
+                    }
+                }
+                Ok(ExecCbResult::Continue)
+            }
             StepResult::Illegal => bail!(
                 "Illegal instruction hit @ {:#010X} (function {:#010X}-{:#010X})",
                 ins_addr,
@@ -393,6 +515,18 @@ impl Tracker {
                 }
             },
             StepResult::Branch(branches) => {
+                if ins.op == Opcode::Bcctr && ins.field_LK() {
+                    if let (GprValue::VirtualFunction { object_offset, slot_offset }, Some(class)) =
+                        (vm.ctr_value(), &self.this_class)
+                    {
+                        self.virtual_calls.insert(ins_addr, VirtualCall {
+                            function: function_start,
+                            class: class.clone(),
+                            object_offset,
+                            slot_offset,
+                        });
+                    }
+                }
                 for branch in branches {
                     match branch.target {
                         BranchTarget::Unknown
@@ -454,8 +588,14 @@ impl Tracker {
         // but we still want to track them.
         let mut possible_missed_branches = BTreeMap::new();
 
+        let mut vm = VM::new_with_base(self.sda2_base, self.sda_base);
+        // Only member functions of classes with a virtual table take a tracked `this`
+        self.this_class = member_class(symbol).filter(|class| self.vtable_classes.contains(class));
+        if self.this_class.is_some() {
+            vm.gpr[3].value = GprValue::This;
+        }
         let mut executor = Executor::new(obj);
-        executor.push(function_start, VM::new_with_base(self.sda2_base, self.sda_base), false);
+        executor.push(function_start, vm, false);
         loop {
             executor.run(obj, |data| -> Result<ExecCbResult<()>> {
                 self.instruction_callback(
@@ -669,14 +809,16 @@ impl Tracker {
                 }
             }
             let data_kind = self
-                .data_types
+                .float_uses
                 .get(&target)
+                .or_else(|| self.data_types.get(&target))
                 .map(|dt| match dt {
                     DataKind::Unknown => ObjDataKind::Unknown,
                     DataKind::Word => ObjDataKind::Byte4,
                     DataKind::Half => ObjDataKind::Byte2,
                     DataKind::Byte => ObjDataKind::Byte,
                     DataKind::Float => ObjDataKind::Float,
+                    DataKind::Float2 => ObjDataKind::Float2,
                     DataKind::Double => ObjDataKind::Double,
                 })
                 .unwrap_or_default();
@@ -801,6 +943,16 @@ fn data_kind_from_op(op: Opcode) -> DataKind {
     }
 }
 
+fn data_kind_from_gqr(gqr: u32, load: bool, paired: bool) -> DataKind {
+    match gqr_type(gqr, load) {
+        0 if paired => DataKind::Float2,
+        0 => DataKind::Float,
+        4 | 6 => DataKind::Byte, // u8, s8
+        5 | 7 => DataKind::Half, // u16, s16
+        _ => DataKind::Unknown,
+    }
+}
+
 fn generate_special_symbol(obj: &mut ObjInfo, addr: u32, name: &str) -> Result<usize> {
     obj.add_symbol(
         ObjSymbol {
@@ -814,3 +966,64 @@ fn generate_special_symbol(obj: &mut ObjInfo, addr: u32, name: &str) -> Result<u
         true,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::ObjArchitecture;
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, data: Vec<u8>) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_psq_no_relocation() {
+        let code = [
+            0x3c608040u32, // lis r3, 0x8040
+            0x38800000,    // li r4, 0
+            0x7c92e3a6,    // mtspr GQR2, r4
+            0xe0232010,    // psq_l f1, 0x10(r3), 0, qr2
+            0x4e800020,    // blr
+        ];
+        let data = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100, data),
+                section(".data", ObjSectionKind::Data, 0x80400000, vec![0; 0x100]),
+            ],
+        );
+        let symbol = ObjSymbol {
+            name: "fn_80003100".to_string(),
+            address: 0x80003100,
+            section: Some(0),
+            size: 0x14,
+            size_known: true,
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(&obj);
+        tracker.process_function(&obj, &symbol).unwrap();
+        assert!(tracker.relocations.is_empty());
+        assert!(matches!(
+            tracker.data_types.get(&SectionAddress::new(1, 0x80400010)),
+            Some(DataKind::Float2)
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    analysis::{cfa::SectionAddress, pass::rtti_bases, read_u32, tracker::VirtualCall},
+    obj::{ObjInfo, ObjKind, ObjSectionKind, ObjSymbol, ObjSymbolKind},
+};
+
+/// Possible targets of indirect calls through virtual tables.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VirtualCallReport {
+    pub calls: Vec<VirtualCallSite>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VirtualCallSite {
+    /// Address of the `bctrl` instruction.
+    pub address: u32,
+    /// Name of the calling function.
+    pub function: Option<String>,
+    /// Class of the calling function, if it's a member function.
+    pub class: Option<String>,
+    /// Offset of the virtual table pointer in the object.
+    pub object_offset: u32,
+    /// Offset of the function pointer in the virtual table.
+    pub slot_offset: u32,
+    pub targets: Vec<VirtualCallTarget>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VirtualCallTarget {
+    /// Name of the `__vt__` symbol.
+    pub vtable: String,
+    /// Class of the virtual table.
+    pub class: Option<String>,
+    /// Address of the target function.
+    pub address: u32,
+    /// Name of the target function.
+    pub name: Option<String>,
+}
+
+struct Vtable<'a> {
+    symbol: &'a ObjSymbol,
+    class: &'a str,
+    rtti: Option<u32>,
+    /// Size of the primary table, excluding secondary tables for other bases
+    size: u32,
+}
+
+/// Matches the slot offset of each virtual call against the `__vt__` symbols of the
+/// calling function's class and the classes derived from it.
+pub fn resolve_virtual_calls(
+    obj: &ObjInfo,
+    calls: &BTreeMap<SectionAddress, VirtualCall>,
+) -> Result<VirtualCallReport> {
+    let vtables = obj
+        .symbols
+        .iter()
+        .filter(|s| s.section.is_some() && s.size_known)
+        .filter_map(|symbol| {
+            let class = vtable_class(symbol)?;
+            let rtti = vtable_word(obj, symbol, 0).filter(|&rtti| rtti != 0);
+            let size = primary_vtable_size(obj, symbol, rtti);
+            Some(Vtable { symbol, class, rtti, size })
+        })
+        .collect::<Vec<_>>();
+    // Direct base classes of each class, from the RTTI base class lists
+    let mut bases = BTreeMap::<u32, Vec<u32>>::new();
+    for rtti in vtables.iter().filter_map(|v| v.rtti) {
+        let Ok((_, section)) = obj.sections.at_address(rtti) else {
+            continue;
+        };
+        let bases_ptr = read_u32(section, rtti + 4).unwrap_or(0);
+        bases.insert(rtti, rtti_bases(obj, bases_ptr).into_iter().map(|(base, _)| base).collect());
+    }
+
+    let mut report = VirtualCallReport::default();
+    for (&address, call) in calls {
+        let function = obj
+            .symbols
+            .at_section_address(call.function.section, call.function.address)
+            .find(|(_, s)| s.kind == ObjSymbolKind::Function)
+            .map(|(_, s)| s);
+        let class_rtti = vtables.iter().find(|v| v.class == call.class).and_then(|v| v.rtti);
+        let mut targets = vec![];
+        for vtable in &vtables {
+            let is_candidate = vtable.class == call.class
+                || matches!((vtable.rtti, class_rtti), (Some(rtti), Some(base))
+                    if derives_from(&bases, rtti, base, &mut BTreeSet::new()));
+            if !is_candidate || call.slot_offset + 4 > vtable.size {
+                continue;
+            }
+            let Some(target) = vtable_slot_target(obj, vtable.symbol, call.slot_offset) else {
+                continue;
+            };
+            let name = obj
+                .symbols
+                .at_section_address(target.section, target.address)
+                .find(|(_, s)| s.kind == ObjSymbolKind::Function)
+                .map(|(_, s)| s.name.clone());
+            targets.push(VirtualCallTarget {
+                vtable: vtable.symbol.name.clone(),
+                class: Some(vtable.class.to_string()),
+                address: target.address,
+                name,
+            });
+        }
+        report.calls.push(VirtualCallSite {
+            address: address.address,
+            function: function.map(|s| s.name.clone()),
+            class: Some(call.class.clone()),
+            object_offset: call.object_offset,
+            slot_offset: call.slot_offset,
+            targets,
+        });
+    }
+    Ok(report)
+}
+
+/// Whether the class with the RTTI record `rtti` is, or derives from, the class `base`.
+fn derives_from(
+    bases: &BTreeMap<u32, Vec<u32>>,
+    rtti: u32,
+    base: u32,
+    visited: &mut BTreeSet<u32>,
+) -> bool {
+    if rtti == base {
+        return true;
+    }
+    if !visited.insert(rtti) {
+        return false;
+    }
+    bases.get(&rtti).is_some_and(|list| list.iter().any(|&b| derives_from(bases, b, base, visited)))
+}
+
+/// Finds the end of the primary table: the first secondary table begins with the same
+/// RTTI pointer, followed by a negative `this` offset.
+fn primary_vtable_size(obj: &ObjInfo, vtable: &ObjSymbol, rtti: Option<u32>) -> u32 {
+    let size = vtable.size as u32;
+    let Some(rtti) = rtti else {
+        return size;
+    };
+    (8..size)
+        .step_by(4)
+        .find(|&offset| {
+            vtable_word(obj, vtable, offset) == Some(rtti)
+                && vtable_word(obj, vtable, offset + 4).is_some_and(|v| (v as i32) < 0)
+        })
+        .unwrap_or(size)
+}
+
+/// Reads a word of a virtual table, resolving its relocation if present.
+fn vtable_word(obj: &ObjInfo, vtable: &ObjSymbol, offset: u32) -> Option<u32> {
+    let section = &obj.sections[vtable.section?];
+    let address = vtable.address as u32 + offset;
+    if let Some(reloc) = section.relocations.at(address) {
+        let symbol = &obj.symbols[reloc.target_symbol];
+        symbol.section?;
+        Some((symbol.address as i64 + reloc.addend) as u32)
+    } else if obj.kind == ObjKind::Executable {
+        read_u32(section, address)
+    } else {
+        None
+    }
+}
+
+/// Reads the function pointer in a virtual table slot, using its relocation if present.
+fn vtable_slot_target(obj: &ObjInfo, vtable: &ObjSymbol, offset: u32) -> Option<SectionAddress> {
+    let section = &obj.sections[vtable.section?];
+    let address = vtable.address as u32 + offset;
+    let target = if let Some(reloc) = section.relocations.at(address) {
+        let symbol = &obj.symbols[reloc.target_symbol];
+        let section_index = symbol.section?;
+        SectionAddress::new(section_index, (symbol.address as i64 + reloc.addend) as u32)
+    } else if obj.kind == ObjKind::Executable {
+        let value = read_u32(section, address)?;
+        let (section_index, _) = obj.sections.at_address(value).ok()?;
+        SectionAddress::new(section_index, value)
+    } else {
+        return None;
+    };
+    (obj.sections[target.section].kind == ObjSectionKind::Code).then_some(target)
+}
+
+/// Class of a `__vt__` symbol, from its demangled name.
+pub fn vtable_class(symbol: &ObjSymbol) -> Option<&str> {
+    if !symbol.name.starts_with("__vt__") {
+        return None;
+    }
+    symbol.demangled_name.as_deref()?.strip_suffix("::__vt")
+}
+
+/// Class name of a member function, from its demangled name.
+pub fn member_class(symbol: &ObjSymbol) -> Option<String> {
+    let demangled = symbol.demangled_name.as_deref()?;
+    let name = demangled.split_once('(').map_or(demangled, |(name, _)| name);
+    name.rsplit_once("::").map(|(class, _)| class.to_string())
+}
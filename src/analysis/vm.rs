@@ -1,6 +1,6 @@
 use std::num::NonZeroU32;
 
-use ppc750cl::{Argument, Ins, Opcode, GPR};
+use ppc750cl::{Argument, Ins, Opcode, FPR, GPR};
 
 use crate::{
     analysis::{cfa::SectionAddress, relocation_target_for, RelocationTarget},
@@ -22,6 +22,12 @@ pub enum GprValue {
     Range { min: u32, max: u32, step: u32 },
     /// GPR value is loaded from an address with a max offset (jump table)
     LoadIndexed { address: RelocationTarget, max_offset: Option<NonZeroU32> },
+    /// GPR value is the first argument on function entry (`this` for member functions)
+    This,
+    /// GPR value is a virtual table pointer loaded from `this`
+    VtablePointer { object_offset: u32 },
+    /// GPR value is a function pointer loaded from a virtual table
+    VirtualFunction { object_offset: u32, slot_offset: u32 },
 }
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
@@ -72,18 +78,46 @@ struct Cr {
     signed: bool,
 }
 
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FprValue {
+    #[default]
+    /// FPR value is unknown
+    Unknown,
+    /// FPR value is 8 bytes loaded from a known address (lfd)
+    Load(RelocationTarget),
+}
+
+/// How an FPR loaded from memory is used by an instruction
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FloatUse {
+    /// Double-precision arithmetic
+    Double,
+    /// Paired-single arithmetic
+    PairedSingle,
+}
+
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 pub struct VM {
     /// General purpose registers
     pub gpr: [Gpr; 32],
+    /// Floating-point registers
+    fpr: [FprValue; 32],
     /// Condition registers
     cr: [Cr; 8],
     /// Count register
     ctr: GprValue,
+    /// Graphics quantization registers
+    gqr: [Option<u32>; 8],
 }
 
 impl VM {
     pub fn gpr_value(&self, reg: u8) -> GprValue { self.gpr[reg as usize].value }
+
+    pub fn fpr_value(&self, reg: u8) -> FprValue { self.fpr[reg as usize] }
+
+    pub fn ctr_value(&self) -> GprValue { self.ctr }
+
+    pub fn gqr_value(&self, index: u8) -> Option<u32> { self.gqr[index as usize] }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -114,6 +148,8 @@ pub enum StepResult {
     Continue,
     /// Load from / store to
     LoadStore { address: RelocationTarget, source: Gpr, source_reg: u8 },
+    /// Floating-point values loaded from memory used by an instruction
+    FloatUse(Vec<(RelocationTarget, FloatUse)>),
     /// Hit illegal instruction
     Illegal,
     /// Jump without affecting VM state
@@ -162,12 +198,13 @@ impl VM {
         vm
     }
 
-    /// When calling a function, only preserve SDA bases
+    /// When calling a function, only preserve SDA bases and GQRs
     #[inline]
     pub fn clone_for_link(&self) -> Box<Self> {
         let mut vm = Self::new();
         vm.gpr[2].value = self.gpr[2].value;
         vm.gpr[13].value = self.gpr[13].value;
+        vm.gqr = self.gqr;
         vm
     }
 
@@ -183,7 +220,9 @@ impl VM {
         // Non-volatile registers
         for i in 14..32 {
             vm.gpr[i] = self.gpr[i];
+            vm.fpr[i] = self.fpr[i];
         }
+        vm.gqr = self.gqr;
         vm
     }
 
@@ -297,6 +336,16 @@ impl VM {
                     self.gpr[ins.field_rA()].set_lo(value, ins_addr, self.gpr[ins.field_rS()]);
                 }
             }
+            // oris rA, rS, UIMM
+            Opcode::Oris => {
+                let value = match self.gpr[ins.field_rS()].value {
+                    GprValue::Constant(value) => {
+                        GprValue::Constant(value | (ins.field_uimm() as u32) << 16)
+                    }
+                    _ => GprValue::Unknown,
+                };
+                self.gpr[ins.field_rA()].set_direct(value);
+            }
             // or rA, rS, rB
             Opcode::Or => {
                 if ins.field_rS() == ins.field_rB() {
@@ -473,21 +522,38 @@ impl VM {
             }
             // mtspr SPR, rS
             Opcode::Mtspr => {
-                if ins.field_spr() == 9 {
-                    // CTR
-                    self.ctr = self.gpr[ins.field_rS()].value;
+                match ins.field_spr() {
+                    9 => {
+                        // CTR
+                        self.ctr = self.gpr[ins.field_rS()].value;
+                    }
+                    spr @ 912..=919 => {
+                        // GQR0-7
+                        self.gqr[spr - 912] = match self.gpr[ins.field_rS()].value {
+                            GprValue::Constant(value) => Some(value),
+                            _ => None,
+                        };
+                    }
+                    _ => {}
                 }
             }
             // mfspr rD, SPR
             Opcode::Mfspr => {
-                let value = if ins.field_spr() == 9 {
+                let value = match ins.field_spr() {
                     // CTR
-                    self.ctr
-                } else {
-                    GprValue::Unknown
+                    9 => self.ctr,
+                    // GQR0-7
+                    spr @ 912..=919 => {
+                        self.gqr[spr - 912].map_or(GprValue::Unknown, GprValue::Constant)
+                    }
+                    _ => GprValue::Unknown,
                 };
                 self.gpr[ins.field_rD()].set_direct(value);
             }
+            // fmr frD, frB
+            Opcode::Fmr => {
+                self.fpr[ins.field_frD()] = self.fpr[ins.field_frB()];
+            }
             // rfi
             Opcode::Rfi => {
                 return StepResult::Jump(BranchTarget::Unknown);
@@ -509,7 +575,9 @@ impl VM {
                         source_reg: source as u8,
                     };
                 } else if let GprValue::Constant(base) = self.gpr[source].value {
-                    let address = base.wrapping_add(ins.field_simm() as u32);
+                    let offset =
+                        if is_psq_op(op) { ins.field_ps_offset() } else { ins.field_simm() };
+                    let address = base.wrapping_add(offset as u32);
                     if let Some(target) = section_address_for(obj, ins_addr, address) {
                         if is_update_op(op) {
                             self.gpr[source].set_lo(
@@ -528,16 +596,61 @@ impl VM {
                     self.gpr[source].set_direct(GprValue::Unknown);
                 }
                 if is_load_op(op) {
-                    self.gpr[ins.field_rD()].set_direct(GprValue::Unknown);
+                    // lwz rD, d(this) / lwz rD, d(vptr)
+                    let value = match (op, self.gpr[source].value) {
+                        (Opcode::Lwz, GprValue::This) => {
+                            GprValue::VtablePointer { object_offset: ins.field_simm() as u32 }
+                        }
+                        (Opcode::Lwz, GprValue::VtablePointer { object_offset })
+                            if ins.field_simm() >= 8 =>
+                        {
+                            GprValue::VirtualFunction {
+                                object_offset,
+                                slot_offset: ins.field_simm() as u32,
+                            }
+                        }
+                        _ => GprValue::Unknown,
+                    };
+                    self.gpr[ins.field_rD()].set_direct(value);
+                } else if is_loadf_op(op) || is_psq_load_op(op) {
+                    // lfd frD, d(rA): track the address until the value is used
+                    self.fpr[ins.field_frD()] = match (op, &result) {
+                        (Opcode::Lfd | Opcode::Lfdu, StepResult::LoadStore { address, .. }) => {
+                            FprValue::Load(*address)
+                        }
+                        _ => FprValue::Unknown,
+                    };
                 }
                 return result;
             }
             _ => {
+                let mut uses = vec![];
+                if self.fpr.iter().any(|v| *v != FprValue::Unknown) {
+                    let kind = if is_paired_single_op(ins.op) {
+                        FloatUse::PairedSingle
+                    } else {
+                        FloatUse::Double
+                    };
+                    for field in ins.uses() {
+                        if let Some(Argument::FPR(FPR(reg))) = field.argument() {
+                            if let FprValue::Load(address) = self.fpr[reg as usize] {
+                                uses.push((address, kind));
+                            }
+                        }
+                    }
+                }
                 for field in ins.defs() {
-                    if let Some(Argument::GPR(GPR(reg))) = field.argument() {
-                        self.gpr[reg as usize].set_direct(GprValue::Unknown);
+                    match field.argument() {
+                        Some(Argument::GPR(GPR(reg))) => {
+                            self.gpr[reg as usize].set_direct(GprValue::Unknown)
+                        }
+                        Some(Argument::FPR(FPR(reg))) => self.fpr[reg as usize] = FprValue::Unknown,
+                        _ => {}
                     }
                 }
+                if !uses.is_empty() {
+                    return StepResult::FloatUse(uses);
+                }
             }
         }
         StepResult::Continue
@@ -686,9 +799,65 @@ pub fn is_storef_op(op: Opcode) -> bool {
     matches!(op, Opcode::Stfd | Opcode::Stfdu | Opcode::Stfs | Opcode::Stfsu)
 }
 
+#[inline]
+pub fn is_psq_load_op(op: Opcode) -> bool { matches!(op, Opcode::PsqL | Opcode::PsqLu) }
+
+#[inline]
+pub fn is_psq_store_op(op: Opcode) -> bool { matches!(op, Opcode::PsqSt | Opcode::PsqStu) }
+
+#[inline]
+pub fn is_psq_op(op: Opcode) -> bool { is_psq_load_op(op) || is_psq_store_op(op) }
+
 #[inline]
 pub fn is_load_store_op(op: Opcode) -> bool {
-    is_load_op(op) || is_loadf_op(op) || is_store_op(op) || is_storef_op(op)
+    is_load_op(op) || is_loadf_op(op) || is_store_op(op) || is_storef_op(op) || is_psq_op(op)
+}
+
+#[inline]
+pub fn is_paired_single_op(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::PsAbs
+            | Opcode::PsAdd
+            | Opcode::PsCmpo0
+            | Opcode::PsCmpo1
+            | Opcode::PsCmpu0
+            | Opcode::PsCmpu1
+            | Opcode::PsDiv
+            | Opcode::PsMadd
+            | Opcode::PsMadds0
+            | Opcode::PsMadds1
+            | Opcode::PsMerge00
+            | Opcode::PsMerge01
+            | Opcode::PsMerge10
+            | Opcode::PsMerge11
+            | Opcode::PsMr
+            | Opcode::PsMsub
+            | Opcode::PsMul
+            | Opcode::PsMuls0
+            | Opcode::PsMuls1
+            | Opcode::PsNabs
+            | Opcode::PsNeg
+            | Opcode::PsNmadd
+            | Opcode::PsNmsub
+            | Opcode::PsRes
+            | Opcode::PsRsqrte
+            | Opcode::PsSel
+            | Opcode::PsSub
+            | Opcode::PsSum0
+            | Opcode::PsSum1
+    )
+}
+
+/// Data type of a quantized load or store from a GQR value.
+/// 0 = float, 4 = u8, 5 = u16, 6 = s8, 7 = s16
+#[inline]
+pub fn gqr_type(gqr: u32, load: bool) -> u8 {
+    if load {
+        ((gqr >> 16) & 7) as u8
+    } else {
+        (gqr & 7) as u8
+    }
 }
 
 #[inline]
@@ -707,6 +876,10 @@ pub fn is_update_op(op: Opcode) -> bool {
             | Opcode::Lhzux
             | Opcode::Lwzu
             | Opcode::Lwzux
+            | Opcode::PsqLu
+            | Opcode::PsqLux
+            | Opcode::PsqStu
+            | Opcode::PsqStux
             | Opcode::Stbu
             | Opcode::Stbux
             | Opcode::Stfdu
@@ -851,3 +1024,93 @@ pub fn is_update_op(op: Opcode) -> bool {
 //         );
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjSection, ObjSectionKind};
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: 0x100,
+            data: vec![0; 0x100],
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            original_address: 0,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn test_obj() -> ObjInfo {
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100),
+                section(".data", ObjSectionKind::Data, 0x80400000),
+            ],
+        )
+    }
+
+    fn step(vm: &mut VM, obj: &ObjInfo, code: u32, addr: u32) -> StepResult {
+        vm.step(obj, SectionAddress::new(0, addr), &Ins::new(code, addr))
+    }
+
+    #[test]
+    fn test_oris() {
+        let obj = test_obj();
+        let mut vm = VM::new();
+        assert_eq!(step(&mut vm, &obj, 0x38800004, 0x80003100), StepResult::Continue); // li r4, 4
+        assert_eq!(step(&mut vm, &obj, 0x64840004, 0x80003104), StepResult::Continue); // oris r4, r4, 4
+        assert_eq!(vm.gpr[4].value, GprValue::Constant(0x00040004));
+    }
+
+    #[test]
+    fn test_gqr() {
+        let obj = test_obj();
+        let mut vm = VM::new();
+        assert_eq!(step(&mut vm, &obj, 0x38800004, 0x80003100), StepResult::Continue); // li r4, 4
+        assert_eq!(step(&mut vm, &obj, 0x64840004, 0x80003104), StepResult::Continue); // oris r4, r4, 4
+        assert_eq!(step(&mut vm, &obj, 0x7c92e3a6, 0x80003108), StepResult::Continue); // mtspr GQR2, r4
+        assert_eq!(vm.gqr_value(2), Some(0x00040004));
+        assert_eq!(gqr_type(0x00040004, true), 4);
+        assert_eq!(step(&mut vm, &obj, 0x7cb2e2a6, 0x8000310c), StepResult::Continue); // mfspr r5, GQR2
+        assert_eq!(vm.gpr[5].value, GprValue::Constant(0x00040004));
+        // Unknown values clear the GQR
+        assert_eq!(step(&mut vm, &obj, 0x7c72e3a6, 0x80003110), StepResult::Continue); // mtspr GQR2, r3
+        assert_eq!(vm.gqr_value(2), None);
+    }
+
+    #[test]
+    fn test_float_use() {
+        let obj = test_obj();
+        let mut vm = VM::new();
+        let target = RelocationTarget::Address(SectionAddress::new(1, 0x80400010));
+        assert_eq!(step(&mut vm, &obj, 0x3c608040, 0x80003100), StepResult::Continue); // lis r3, 0x8040
+        assert_eq!(step(&mut vm, &obj, 0x38630010, 0x80003104), StepResult::Continue); // addi r3, r3, 0x10
+        assert!(matches!(
+            step(&mut vm, &obj, 0xc8230000, 0x80003108), // lfd f1, 0(r3)
+            StepResult::LoadStore { address, .. } if address == target
+        ));
+        assert_eq!(vm.fpr_value(1), FprValue::Load(target));
+        assert_eq!(step(&mut vm, &obj, 0xfc400890, 0x8000310c), StepResult::Continue); // fmr f2, f1
+        assert_eq!(vm.fpr_value(2), FprValue::Load(target));
+        assert_eq!(
+            step(&mut vm, &obj, 0x1062202a, 0x80003110), // ps_add f3, f2, f4
+            StepResult::FloatUse(vec![(target, FloatUse::PairedSingle)])
+        );
+        assert_eq!(
+            step(&mut vm, &obj, 0xfc61202a, 0x80003114), // fadd f3, f1, f4
+            StepResult::FloatUse(vec![(target, FloatUse::Double)])
+        );
+        assert_eq!(vm.fpr_value(3), FprValue::Unknown);
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    fs::{DirBuilder, File},
+    io,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use argp::FromArgs;
+
+use crate::util::{
+    disc::{
+        block::{BlockStream, ContainerFormat},
+        build::build_gcm,
+        wii::{load_common_key, WiiDisc},
+        DiscFs, DiscHeader, DiscNode, DiscNodeKind, ReadSeek,
+    },
+    file::buf_writer,
+    reader::{Endian, FromReader},
+};
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Commands for processing GameCube and Wii disc images.
+#[argp(subcommand, name = "disc")]
+pub struct Args {
+    #[argp(subcommand)]
+    command: SubCommand,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argp(subcommand)]
+enum SubCommand {
+    Info(InfoArgs),
+    List(ListArgs),
+    Extract(ExtractArgs),
+    Build(BuildArgs),
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Views disc image information.
+#[argp(subcommand, name = "info")]
+pub struct InfoArgs {
+    #[argp(positional)]
+    /// disc image
+    file: PathBuf,
+    #[argp(option, short = 'k')]
+    /// Wii common key file (defaults to $DTK_WII_COMMON_KEY)
+    common_key: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Lists the files in a disc image.
+#[argp(subcommand, name = "list")]
+pub struct ListArgs {
+    #[argp(positional)]
+    /// disc image
+    file: PathBuf,
+    #[argp(option, short = 'k')]
+    /// Wii common key file (defaults to $DTK_WII_COMMON_KEY)
+    common_key: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Extracts the files in a disc image.
+#[argp(subcommand, name = "extract")]
+pub struct ExtractArgs {
+    #[argp(positional)]
+    /// disc image
+    file: PathBuf,
+    #[argp(option, short = 'k')]
+    /// Wii common key file (defaults to $DTK_WII_COMMON_KEY)
+    common_key: Option<PathBuf>,
+    #[argp(option, short = 'o')]
+    /// output directory
+    output: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Builds a GameCube disc image from an extracted directory.
+#[argp(subcommand, name = "build")]
+pub struct BuildArgs {
+    #[argp(positional)]
+    /// input directory (containing `sys` and `files`)
+    input: PathBuf,
+    #[argp(positional)]
+    /// output disc image
+    output: PathBuf,
+    #[argp(switch, short = 'z')]
+    /// fill padding with zeroes instead of junk data
+    zero_padding: bool,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    match args.command {
+        SubCommand::Info(c_args) => info(c_args),
+        SubCommand::List(c_args) => list(c_args),
+        SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Build(c_args) => build(c_args),
+    }
+}
+
+/// Opens a disc image, decompressing it on demand if it's in a container format.
+fn open_disc(path: &Path) -> Result<(Box<dyn ReadSeek>, DiscHeader, Option<ContainerFormat>)> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file '{}'", path.display()))?;
+    let (mut reader, format): (Box<dyn ReadSeek>, _) = match BlockStream::open(BufReader::new(file))
+        .with_context(|| format!("Failed to open disc image '{}'", path.display()))?
+    {
+        Some((format, stream)) => (Box::new(stream), Some(format)),
+        None => (Box::new(BufReader::new(File::open(path)?)), None),
+    };
+    let header = DiscHeader::from_reader(&mut reader, Endian::Big)
+        .with_context(|| format!("Failed to read disc image '{}'", path.display()))?;
+    ensure!(
+        header.is_gamecube() || header.is_wii(),
+        "'{}' is not a GameCube or Wii disc image",
+        path.display()
+    );
+    reader.seek(SeekFrom::Start(0))?;
+    Ok((reader, header, format))
+}
+
+/// Calls `f` with the file system of a GameCube disc, or of each Wii partition.
+/// For Wii discs, paths are prefixed with the partition name.
+fn process_disc<F>(path: &Path, common_key: Option<&Path>, mut f: F) -> Result<()>
+where F: FnMut(&Path, &mut dyn ReadSeek, &DiscFs) -> Result<()> {
+    let (mut reader, header, _) = open_disc(path)?;
+    if header.is_wii() {
+        let common_key = load_common_key(common_key)?;
+        let disc = WiiDisc::new(&mut reader, &common_key)
+            .with_context(|| format!("Failed to read Wii disc '{}'", path.display()))?;
+        for partition in &disc.partitions {
+            let mut partition_reader = partition.reader(&mut *reader)?;
+            let fs = DiscFs::new(&mut partition_reader).with_context(|| {
+                format!("Failed to read partition {} in '{}'", partition.name, path.display())
+            })?;
+            f(Path::new(&partition.name), &mut partition_reader, &fs)?;
+        }
+    } else {
+        let fs = DiscFs::new(&mut reader)
+            .with_context(|| format!("Failed to read disc image '{}'", path.display()))?;
+        f(Path::new(""), &mut reader, &fs)?;
+    }
+    Ok(())
+}
+
+fn info(args: InfoArgs) -> Result<()> {
+    let (_, header, format) = open_disc(&args.file)?;
+    println!("{}:", args.file.display());
+    println!("Format: {}", format.map_or("ISO", |f| f.name()));
+    println!("Game ID: {}", header.game_id_str());
+    println!("Game title: {}", header.game_title_str());
+    println!("Disc number: {}", header.disc_num + 1);
+    println!("Disc version: {}", header.disc_version);
+    println!("Platform: {}", if header.is_wii() { "Wii" } else { "GameCube" });
+    process_disc(&args.file, args.common_key.as_deref(), |prefix, _, fs| {
+        if !prefix.as_os_str().is_empty() {
+            println!("Partition {}:", prefix.display());
+        }
+        for node in &fs.nodes {
+            if node.kind == DiscNodeKind::File && node.path.starts_with("sys") {
+                println!(
+                    "{}: offset {:#X}, size {:#X}",
+                    prefix.join(&node.path).display(),
+                    node.offset,
+                    node.size
+                );
+            }
+        }
+        let file_count = fs
+            .nodes
+            .iter()
+            .filter(|n| n.kind == DiscNodeKind::File && n.path.starts_with("files"))
+            .count();
+        println!("Files: {}", file_count);
+        Ok(())
+    })
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    process_disc(&args.file, args.common_key.as_deref(), |prefix, _, fs| {
+        for node in &fs.nodes {
+            if node.kind == DiscNodeKind::File {
+                println!(
+                    "{}: {} bytes, offset {:#X}",
+                    prefix.join(&node.path).display(),
+                    node.size,
+                    node.offset
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+fn extract(args: ExtractArgs) -> Result<()> {
+    process_disc(&args.file, args.common_key.as_deref(), |prefix, reader, fs| {
+        for node in &fs.nodes {
+            let path = prefix.join(&node.path);
+            let output_path =
+                args.output.as_ref().map(|p| p.join(&path)).unwrap_or_else(|| path.clone());
+            match node.kind {
+                DiscNodeKind::Directory => {
+                    DirBuilder::new().recursive(true).create(&output_path)?;
+                }
+                DiscNodeKind::File => {
+                    log::info!("Extracting {}", path.display());
+                    extract_file(reader, node, &output_path)?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+fn extract_file<R>(reader: &mut R, node: &DiscNode, output_path: &Path) -> Result<()>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(node.offset))?;
+    let mut w = buf_writer(output_path)?;
+    let copied = io::copy(&mut (&mut *reader).take(node.size), &mut w)
+        .with_context(|| format!("Failed to write file '{}'", output_path.display()))?;
+    ensure!(copied == node.size, "Unexpected end of disc image reading '{}'", node.path.display());
+    w.flush()?;
+    Ok(())
+}
+
+fn build(args: BuildArgs) -> Result<()> {
+    let mut w = buf_writer(&args.output)?;
+    let size = build_gcm(&args.input, &mut w, !args.zero_padding)
+        .with_context(|| format!("Failed to build disc image from '{}'", args.input.display()))?;
+    w.flush()?;
+    log::info!("Wrote {} ({:#X} bytes)", args.output.display(), size);
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::{
     borrow::Cow,
-    cmp::min,
-    collections::{btree_map::Entry, hash_map, BTreeMap, HashMap},
+    cmp::{max, min},
+    collections::{btree_map::Entry, hash_map, BTreeMap, BTreeSet, HashMap},
     ffi::OsStr,
     fs,
     fs::DirBuilder,
@@ -11,8 +11,9 @@ use std::{
     time::Instant,
 };
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use argp::FromArgs;
+use cwdemangle::{demangle, DemangleOptions};
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -25,15 +26,23 @@ use crate::{
         objects::{detect_objects, detect_strings},
         pass::{
             AnalysisPass, FindRelCtorsDtors, FindRelRodataData, FindSaveRestSleds,
-            FindTRKInterruptVectorTable,
+            FindTRKInterruptVectorTable, FindVtables,
+        },
+        signatures::{
+            apply_signatures, apply_signatures_post, apply_user_signatures, find_fuzzy_matches,
+            update_ctors_dtors,
         },
-        signatures::{apply_signatures, apply_signatures_post, update_ctors_dtors},
         tracker::Tracker,
+        virtual_calls::resolve_virtual_calls,
+    },
+    cmd::{
+        dwarf::{apply_dwarf_splits_file, load_debug_info},
+        shasum::file_sha1_string,
     },
-    cmd::shasum::file_sha1_string,
     obj::{
-        best_match_for_reloc, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSectionKind, ObjSymbol,
-        ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjSymbolScope, SymbolIndex,
+        best_match_for_reloc, ObjDataKind, ObjInfo, ObjKind, ObjReloc, ObjRelocKind,
+        ObjSectionKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjSymbolScope,
+        SymbolIndex,
     },
     util::{
         asm::write_asm,
@@ -44,13 +53,19 @@ use crate::{
             write_symbols_file,
         },
         dep::DepFile,
+        diff::{diff_objs, DiffKind},
         dol::process_dol,
+        dwarf::process_symbols,
         elf::{process_elf, write_elf},
         file::{buf_reader, buf_writer, map_file, touch, verify_hash, FileIterator, FileReadInfo},
         lcf::{asm_path_for_unit, generate_ldscript, obj_path_for_unit},
         map::apply_map_file,
         rel::{process_rel, process_rel_header, update_rel_section_alignment},
-        rso::{process_rso, DOL_SECTION_ABS, DOL_SECTION_ETI, DOL_SECTION_NAMES},
+        rso::{
+            process_rso, update_rso_section_alignment, DOL_SECTION_ABS, DOL_SECTION_ETI,
+            DOL_SECTION_NAMES,
+        },
+        signatures::{read_signature_files, FunctionSignature, SignatureScore},
         split::{is_linker_generated_object, split_obj, update_splits},
         IntoCow, ToCow,
     },
@@ -71,7 +86,10 @@ enum SubCommand {
     Split(SplitArgs),
     Diff(DiffArgs),
     Apply(ApplyArgs),
+    ApplyDwarf(ApplyDwarfArgs),
     Config(ConfigArgs),
+    Sigs(SigsArgs),
+    VirtualCalls(VirtualCallsArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -114,6 +132,9 @@ pub struct DiffArgs {
     #[argp(positional)]
     /// linked ELF
     elf_file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output JSON report
+    out_file: Option<PathBuf>,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -128,6 +149,54 @@ pub struct ApplyArgs {
     elf_file: PathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Applies names, sizes and types from DWARF info to the project configuration.
+#[argp(subcommand, name = "apply-dwarf")]
+pub struct ApplyDwarfArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(positional)]
+    /// ELF file with DWARF info
+    elf_file: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Finds approximate signature matches for unnamed functions.
+#[argp(subcommand, name = "sigs")]
+pub struct SigsArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(option, short = 's')]
+    /// signature file(s) or directories (default: `signatures` from config)
+    signatures: Vec<PathBuf>,
+    #[argp(option, short = 't')]
+    /// minimum confidence to report (default: 0.8)
+    threshold: Option<f32>,
+    #[argp(option, short = 'o')]
+    /// output JSON report
+    out_file: Option<PathBuf>,
+    #[argp(switch)]
+    /// apply high-confidence matches to the symbols file
+    apply: bool,
+    #[argp(option)]
+    /// minimum confidence to apply (default: 0.95)
+    apply_threshold: Option<f32>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Reports possible targets of virtual calls.
+#[argp(subcommand, name = "virtual-calls")]
+pub struct VirtualCallsArgs {
+    #[argp(positional)]
+    /// input configuration file
+    config: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output JSON report
+    out_file: Option<PathBuf>,
+}
+
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Generates a project configuration file from a DOL (& RELs).
 #[argp(subcommand, name = "config")]
@@ -228,6 +297,10 @@ pub struct ProjectConfig {
     /// Fills gaps between symbols with
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub fill_gaps: bool,
+    /// Signature database files or directories, applied to all detected functions.
+    /// Directories are searched recursively for `.yml` and `.yaml` files.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub signatures: Vec<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -245,6 +318,10 @@ pub struct ModuleConfig {
     pub symbols: Option<PathBuf>,
     #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
     pub map: Option<PathBuf>,
+    /// Debug ELF of the same build. Its compile unit address ranges are used to
+    /// generate splits that aren't already defined.
+    #[serde(with = "path_slash_serde_option", default, skip_serializing_if = "is_default")]
+    pub dwarf: Option<PathBuf>,
     /// Forces the given symbols to be active in the linker script.
     #[serde(default, skip_serializing_if = "is_default")]
     pub force_active: Vec<String>,
@@ -332,20 +409,19 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Split(c_args) => split(c_args),
         SubCommand::Diff(c_args) => diff(c_args),
         SubCommand::Apply(c_args) => apply(c_args),
+        SubCommand::ApplyDwarf(c_args) => apply_dwarf(c_args),
         SubCommand::Config(c_args) => config(c_args),
+        SubCommand::Sigs(c_args) => sigs(c_args),
+        SubCommand::VirtualCalls(c_args) => virtual_calls(c_args),
     }
 }
 
 fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
-    let rso = process_rso(&mut Cursor::new(buf))?;
+    let (_, rso) = process_rso(&mut Cursor::new(buf), 0)?;
     for symbol in rso.symbols.iter() {
-        let dol_section_index = match symbol.section {
-            Some(section) => section,
-            None => bail!(
-                "Expected section for symbol '{}' @ {:#010X} in selfile",
-                symbol.name,
-                symbol.address
-            ),
+        let Some(dol_section_index) = symbol.section else {
+            // Imported (undefined) symbol
+            continue;
         };
         let (section, address, section_kind) = if dol_section_index == DOL_SECTION_ABS as usize {
             (None, symbol.address as u32, None)
@@ -428,6 +504,7 @@ pub fn info(args: InfoArgs) -> Result<()> {
 
     let mut state = AnalyzerState::default();
     FindSaveRestSleds::execute(&mut state, &obj)?;
+    FindVtables::execute(&mut state, &obj)?;
     state.detect_functions(&obj)?;
     log::debug!(
         "Discovered {} functions",
@@ -723,7 +800,10 @@ struct AnalyzeResult {
     splits_cache: Option<FileReadInfo>,
 }
 
-fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
+fn load_analyze_dol(
+    config: &ProjectConfig,
+    user_signatures: &[FunctionSignature],
+) -> Result<AnalyzeResult> {
     log::debug!("Loading {}", config.base.object.display());
     let mut obj = {
         let file = map_file(&config.base.object)?;
@@ -750,6 +830,13 @@ fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
         None
     };
 
+    if let Some(dwarf_path) = &config.base.dwarf {
+        if !apply_dwarf_splits_file(dwarf_path, &mut obj)? {
+            bail!("No DWARF information found in '{}'", dwarf_path.display());
+        }
+        dep.push(dwarf_path.clone());
+    }
+
     let symbols_cache = if let Some(symbols_path) = &config.base.symbols {
         dep.push(symbols_path.clone());
         apply_symbols_file(symbols_path, &mut obj)?
@@ -766,12 +853,14 @@ fn load_analyze_dol(config: &ProjectConfig) -> Result<AnalyzeResult> {
             let mut state = AnalyzerState::default();
             debug!("Detecting function boundaries");
             FindSaveRestSleds::execute(&mut state, &obj)?;
+            FindVtables::execute(&mut state, &obj)?;
             state.detect_functions(&obj)?;
             FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
             state.apply(&mut obj)?;
         }
 
         apply_signatures_post(&mut obj)?;
+        apply_user_signatures(&mut obj, user_signatures)?;
     }
 
     if let Some(selfile) = &config.selfile {
@@ -948,19 +1037,63 @@ fn write_if_changed(path: &Path, contents: &[u8]) -> Result<()> {
     Ok(())
 }
 
-fn load_analyze_rel(config: &ProjectConfig, module_config: &ModuleConfig) -> Result<AnalyzeResult> {
+fn load_analyze_rel(
+    config: &ProjectConfig,
+    module_config: &ModuleConfig,
+    user_signatures: &[FunctionSignature],
+) -> Result<AnalyzeResult> {
     debug!("Loading {}", module_config.object.display());
     let file = map_file(&module_config.object)?;
     if let Some(hash_str) = &module_config.hash {
         verify_hash(file.as_slice(), hash_str)?;
     }
-    let (header, mut module_obj) =
+    let (header, module_obj) =
         process_rel(&mut Cursor::new(file.as_slice()), module_config.name().as_ref())?;
+    let mut result = analyze_module(config, module_config, module_obj, user_signatures)?;
+
+    // Determine REL section alignment
+    update_rel_section_alignment(&mut result.obj, &header)?;
+
+    Ok(result)
+}
+
+fn load_analyze_rso(
+    config: &ProjectConfig,
+    module_config: &ModuleConfig,
+    module_id: u32,
+    user_signatures: &[FunctionSignature],
+) -> Result<AnalyzeResult> {
+    debug!("Loading {}", module_config.object.display());
+    let file = map_file(&module_config.object)?;
+    if let Some(hash_str) = &module_config.hash {
+        verify_hash(file.as_slice(), hash_str)?;
+    }
+    let (header, mut module_obj) = process_rso(&mut Cursor::new(file.as_slice()), module_id)?;
+    module_obj.name = module_config.name().to_string();
+    let mut result = analyze_module(config, module_config, module_obj, user_signatures)?;
 
+    // Determine RSO section alignment
+    update_rso_section_alignment(&mut result.obj, &header)?;
+
+    Ok(result)
+}
+
+fn analyze_module(
+    config: &ProjectConfig,
+    module_config: &ModuleConfig,
+    mut module_obj: ObjInfo,
+    user_signatures: &[FunctionSignature],
+) -> Result<AnalyzeResult> {
     if let Some(comment_version) = config.mw_comment_version {
         module_obj.mw_comment = Some(MWComment::new(comment_version)?);
     }
 
+    ensure!(
+        module_config.dwarf.is_none(),
+        "DWARF splits are only supported for the main DOL ({})",
+        module_config.name()
+    );
+
     let mut dep = vec![module_config.object.clone()];
     if let Some(map_path) = &module_config.map {
         apply_map_file(map_path, &mut module_obj, None, None)?;
@@ -992,17 +1125,66 @@ fn load_analyze_rel(config: &ProjectConfig, module_config: &ModuleConfig) -> Res
         }
         apply_signatures(&mut module_obj)?;
         apply_signatures_post(&mut module_obj)?;
+        apply_user_signatures(&mut module_obj, user_signatures)?;
     }
 
     // Create _ctors and _dtors symbols if missing
     update_ctors_dtors(&mut module_obj)?;
 
-    // Determine REL section alignment
-    update_rel_section_alignment(&mut module_obj, &header)?;
-
     Ok(AnalyzeResult { obj: module_obj, dep, symbols_cache, splits_cache })
 }
 
+#[inline]
+pub fn is_rso_module(module_config: &ModuleConfig) -> bool {
+    module_config.object.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(OsStr::new("rso")))
+}
+
+/// RSO modules import symbols by name. Resolves each import referenced by a relocation
+/// against the DOL (via the selfile) and the linked modules, updating the import symbol
+/// to match its target. Returns the module ID and index of each target symbol.
+fn resolve_rso_imports(
+    obj: &mut ObjInfo,
+    modules: &[&ModuleInfo<'_>],
+    dol_obj: &ObjInfo,
+) -> Result<Vec<(u32, SymbolIndex)>> {
+    log::debug!("Resolving imports for module {}", obj.name);
+    let imports = obj
+        .sections
+        .iter()
+        .flat_map(|(_, section)| section.relocations.iter())
+        .map(|(_, reloc)| reloc.target_symbol)
+        .filter(|&symbol_index| obj.symbols[symbol_index].section.is_none())
+        .collect::<BTreeSet<_>>();
+    let mut targets = Vec::with_capacity(imports.len());
+    for symbol_index in imports {
+        let symbol = &obj.symbols[symbol_index];
+        let Some((module_id, target_index, target_symbol)) =
+            std::iter::once(dol_obj).chain(modules.iter().map(|m| &m.obj)).find_map(|target_obj| {
+                target_obj
+                    .symbols
+                    .for_name(&symbol.name)
+                    .find(|(_, s)| s.section.is_some())
+                    .map(|(index, s)| (target_obj.module_id, index, s))
+            })
+        else {
+            bail!(
+                "Module {} imports symbol '{}', which isn't exported by the DOL or any linked module",
+                obj.name,
+                symbol.name
+            );
+        };
+        log::trace!("Resolved import {} to module {}", symbol.name, module_id);
+        let symbol = ObjSymbol {
+            demangled_name: target_symbol.demangled_name.clone(),
+            kind: target_symbol.kind,
+            ..symbol.clone()
+        };
+        obj.symbols.replace(symbol_index, symbol)?;
+        targets.push((module_id, target_index));
+    }
+    Ok(targets)
+}
+
 fn split(args: SplitArgs) -> Result<()> {
     if let Some(jobs) = args.jobs {
         rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global().unwrap();
@@ -1015,6 +1197,7 @@ fn split(args: SplitArgs) -> Result<()> {
         serde_yaml::from_reader(&mut config_file)?
     };
 
+    let mut max_module_id = 0;
     for module_config in config.modules.iter_mut() {
         let file = map_file(&module_config.object)?;
         if let Some(hash_str) = &module_config.hash {
@@ -1022,11 +1205,38 @@ fn split(args: SplitArgs) -> Result<()> {
         } else {
             module_config.hash = Some(file_sha1_string(&mut file.as_reader())?);
         }
+        if !is_rso_module(module_config) {
+            let header = process_rel_header(&mut file.as_reader())?;
+            max_module_id = max(max_module_id, header.module_id);
+        }
     }
 
+    // RSO modules don't have module IDs, so assign them IDs following the highest REL module ID
+    let module_ids = config
+        .modules
+        .iter()
+        .scan(max_module_id, |next_module_id, module_config| {
+            Some(if is_rso_module(module_config) {
+                *next_module_id += 1;
+                *next_module_id
+            } else {
+                0
+            })
+        })
+        .collect_vec();
+
     let out_config_path = args.out_dir.join("config.json");
     let mut dep = DepFile::new(out_config_path.clone());
 
+    let (user_signatures, signature_files, signature_dirs) =
+        read_signature_files(&config.signatures)?;
+    if !user_signatures.is_empty() {
+        info!("Loaded {} signatures from {} files", user_signatures.len(), signature_files.len());
+    }
+    dep.extend(signature_files);
+    // Directories are dependencies too, so that adding a new file triggers a rebuild
+    dep.extend(signature_dirs);
+
     let module_count = config.modules.len() + 1;
     let num_threads = min(rayon::current_num_threads(), module_count);
     info!(
@@ -1044,7 +1254,7 @@ fn split(args: SplitArgs) -> Result<()> {
         s.spawn(|_| {
             let _span = info_span!("module", name = %config.base.name()).entered();
             dol_result =
-                Some(load_analyze_dol(&config).with_context(|| {
+                Some(load_analyze_dol(&config, &user_signatures).with_context(|| {
                     format!("While loading object '{}'", config.base.file_name())
                 }));
         });
@@ -1054,9 +1264,20 @@ fn split(args: SplitArgs) -> Result<()> {
                 config
                     .modules
                     .par_iter()
-                    .map(|module_config| {
+                    .enumerate()
+                    .map(|(idx, module_config)| {
                         let _span = info_span!("module", name = %module_config.name()).entered();
-                        load_analyze_rel(&config, module_config).with_context(|| {
+                        if is_rso_module(module_config) {
+                            load_analyze_rso(
+                                &config,
+                                module_config,
+                                module_ids[idx],
+                                &user_signatures,
+                            )
+                        } else {
+                            load_analyze_rel(&config, module_config, &user_signatures)
+                        }
+                        .with_context(|| {
                             format!("While loading object '{}'", module_config.file_name())
                         })
                     })
@@ -1158,6 +1379,30 @@ fn split(args: SplitArgs) -> Result<()> {
             modules.insert(module_name.clone(), module);
         }
 
+        // Resolve RSO imports by name and set FORCEACTIVE on their targets
+        let mut rso_targets = vec![];
+        for module_name in &module_names {
+            if !is_rso_module(modules[module_name].config) {
+                continue;
+            }
+            let mut module = modules.remove(module_name).unwrap();
+            let links = get_links(&module, &modules)?;
+            rso_targets.extend(resolve_rso_imports(&mut module.obj, &links, &dol.obj)?);
+            modules.insert(module_name.clone(), module);
+        }
+        for (module_id, symbol_index) in rso_targets {
+            let target_obj = if module_id == 0 {
+                &mut dol.obj
+            } else {
+                &mut modules
+                    .values_mut()
+                    .find(|m| m.obj.module_id == module_id)
+                    .ok_or_else(|| anyhow!("Failed to locate module {}", module_id))?
+                    .obj
+            };
+            target_obj.symbols.flags(symbol_index).set_force_active(true);
+        }
+
         // Replace external relocations with internal ones, creating extern symbols
         for module_name in &module_names {
             let mut module = modules.remove(module_name).unwrap();
@@ -1444,139 +1689,72 @@ fn diff(args: DiffArgs) -> Result<()> {
         process_dol(file.as_slice(), config.base.name().as_ref())?
     };
 
+    if let Some(splits_path) = &config.base.splits {
+        apply_splits_file(splits_path, &mut obj)?;
+    }
+
     if let Some(symbols_path) = &config.base.symbols {
         apply_symbols_file(symbols_path, &mut obj)?;
     }
 
+    // Relocations allow comparing pointers by target symbol rather than by address
+    log::info!("Performing relocation analysis");
+    let mut tracker = Tracker::new(&obj);
+    tracker.process(&obj)?;
+    tracker.apply(&mut obj, false)?;
+
     log::info!("Loading {}", args.elf_file.display());
     let linked_obj = process_elf(&args.elf_file)?;
 
-    let common_bss = obj.sections.common_bss_start();
-    for orig_sym in obj.symbols.iter().filter(|s| {
-        !matches!(s.kind, ObjSymbolKind::Unknown | ObjSymbolKind::Section) && !s.flags.is_stripped()
-    }) {
-        let Some(orig_section_index) = orig_sym.section else { continue };
-        let orig_section = &obj.sections[orig_section_index];
-        let (linked_section_index, linked_section) =
-            linked_obj.sections.at_address(orig_sym.address as u32)?;
-
-        let linked_sym = linked_obj
-            .symbols
-            .at_section_address(linked_section_index, orig_sym.address as u32)
-            .find(|(_, sym)| sym.name == orig_sym.name)
-            .or_else(|| {
-                linked_obj
-                    .symbols
-                    .at_section_address(linked_section_index, orig_sym.address as u32)
-                    .find(|(_, sym)| sym.kind == orig_sym.kind)
-            });
-        let mut found = false;
-        if let Some((_, linked_sym)) = linked_sym {
-            if linked_sym.name.starts_with(&orig_sym.name) {
-                if linked_sym.size != orig_sym.size &&
-                    // TODO validate common symbol sizes
-                    // (need to account for inflation bug)
-                    matches!(common_bss, Some((idx, addr)) if
-                        orig_section_index == idx && orig_sym.address as u32 >= addr)
-                {
-                    log::error!(
-                        "Expected {} (type {:?}) to have size {:#X}, but found {:#X}",
-                        orig_sym.name,
-                        orig_sym.kind,
-                        orig_sym.size,
-                        linked_sym.size
-                    );
+    let report = diff_objs(&obj, &linked_obj)?;
+    for unit in report.units.iter().filter(|u| !u.diffs.is_empty()) {
+        println!(
+            "{}: {}/{} symbols match",
+            unit.name.as_deref().unwrap_or("[no unit]"),
+            unit.matching,
+            unit.total
+        );
+        for diff in &unit.diffs {
+            let detail = match diff.kind {
+                DiffKind::Missing => String::new(),
+                DiffKind::Moved => {
+                    format!(" (found at {:#010X})", diff.linked_address.unwrap_or_default())
                 }
-                found = true;
-            } else if linked_sym.kind == orig_sym.kind && linked_sym.size == orig_sym.size {
-                // Fuzzy match
-                let orig_data = orig_section.data_range(
-                    orig_sym.address as u32,
-                    orig_sym.address as u32 + orig_sym.size as u32,
-                )?;
-                let linked_data = linked_section.data_range(
-                    linked_sym.address as u32,
-                    linked_sym.address as u32 + linked_sym.size as u32,
-                )?;
-                if orig_data == linked_data {
-                    found = true;
+                DiffKind::SizeMismatch => {
+                    format!(" (found size {:#X})", diff.linked_size.unwrap_or_default())
                 }
-            }
-        }
-        if !found {
-            log::error!(
-                "Expected to find symbol {} (type {:?}, size {:#X}) at {:#010X}",
-                orig_sym.name,
-                orig_sym.kind,
-                orig_sym.size,
-                orig_sym.address
+                DiffKind::DataMismatch | DiffKind::RelocationMismatch => {
+                    format!(" (first difference at offset {:#X})", diff.offset.unwrap_or_default())
+                }
+                DiffKind::Error => format!(" ({})", diff.error.as_deref().unwrap_or_default()),
+            };
+            println!(
+                "    {:?}: {} (type {:?}, size {:#X}) at {:#010X}{}",
+                diff.kind, diff.name, diff.symbol_kind, diff.size, diff.address, detail
             );
-            for (_, linked_sym) in
-                linked_obj.symbols.at_section_address(linked_section_index, orig_sym.address as u32)
-            {
-                log::error!(
-                    "At {:#010X}, found: {} (type {:?}, size {:#X})",
-                    linked_sym.address,
-                    linked_sym.name,
-                    linked_sym.kind,
-                    linked_sym.size,
-                );
-            }
-            for (_, linked_sym) in linked_obj.symbols.for_name(&orig_sym.name) {
-                log::error!(
-                    "Instead, found {} (type {:?}, size {:#X}) at {:#010X}",
-                    linked_sym.name,
-                    linked_sym.kind,
-                    linked_sym.size,
-                    linked_sym.address,
+            for word in &diff.context {
+                let marker = if Some(word.offset) == diff.offset { '>' } else { ' ' };
+                println!(
+                    "      {} {:#06X} | {: <40} | {}",
+                    marker, word.offset, word.original, word.linked
                 );
             }
-            return Ok(());
         }
     }
-
-    // Data diff
-    for orig_sym in obj.symbols.iter().filter(|s| {
-        s.size > 0 && !matches!(s.kind, ObjSymbolKind::Unknown | ObjSymbolKind::Section)
-    }) {
-        let Some(orig_section_index) = orig_sym.section else { continue };
-        let orig_section = &obj.sections[orig_section_index];
-        let (linked_section_index, linked_section) =
-            linked_obj.sections.at_address(orig_sym.address as u32)?;
-
-        let (_, linked_sym) = linked_obj
-            .symbols
-            .at_section_address(linked_section_index, orig_sym.address as u32)
-            .find(|(_, sym)| sym.name == orig_sym.name)
-            .or_else(|| {
-                linked_obj
-                    .symbols
-                    .at_section_address(linked_section_index, orig_sym.address as u32)
-                    .find(|(_, sym)| sym.kind == orig_sym.kind)
-            })
-            .unwrap();
-
-        let orig_data = orig_section
-            .data_range(orig_sym.address as u32, orig_sym.address as u32 + orig_sym.size as u32)?;
-        let linked_data = linked_section.data_range(
-            linked_sym.address as u32,
-            linked_sym.address as u32 + linked_sym.size as u32,
-        )?;
-        if orig_data != linked_data {
-            log::error!(
-                "Data mismatch for {} (type {:?}, size {:#X}) at {:#010X}",
-                orig_sym.name,
-                orig_sym.kind,
-                orig_sym.size,
-                orig_sym.address
-            );
-            log::error!("Original: {}", hex::encode_upper(orig_data));
-            log::error!("Linked:   {}", hex::encode_upper(linked_data));
-            return Ok(());
-        }
+    for (kind, count) in &report.counts {
+        log::error!("{:?}: {}", kind, count);
+    }
+    if report.is_match() {
+        log::info!("OK ({} symbols)", report.total);
+    } else {
+        log::error!("{}/{} symbols match", report.matching, report.total);
     }
 
-    log::info!("OK");
+    if let Some(out_file) = &args.out_file {
+        let mut w = buf_writer(out_file)?;
+        serde_json::to_writer_pretty(&mut w, &report)?;
+        w.flush()?;
+    }
     Ok(())
 }
 
@@ -1738,6 +1916,325 @@ fn apply(args: ApplyArgs) -> Result<()> {
     Ok(())
 }
 
+fn apply_dwarf(args: ApplyDwarfArgs) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let mut config_file = buf_reader(&args.config)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+
+    log::info!("Loading {}", config.base.object.display());
+    let mut obj = {
+        let file = map_file(&config.base.object)?;
+        if let Some(hash_str) = &config.base.hash {
+            verify_hash(file.as_slice(), hash_str)?;
+        }
+        process_dol(file.as_slice(), config.base.name().as_ref())?
+    };
+
+    let Some(symbols_path) = &config.base.symbols else {
+        bail!("No symbols file specified in config");
+    };
+    let Some(symbols_cache) = apply_symbols_file(symbols_path, &mut obj)? else {
+        bail!("Symbols file '{}' does not exist", symbols_path.display());
+    };
+
+    log::info!("Loading {}", args.elf_file.display());
+    let dwarf_symbols = {
+        let file = map_file(&args.elf_file)?;
+        let obj_file = object::read::File::parse(file.as_slice())?;
+        let info = load_debug_info(&obj_file)?
+            .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+        process_symbols(&info)?
+    };
+
+    let (mut added, mut updated, mut conflicts) = (0usize, 0usize, 0usize);
+    for dwarf_sym in dwarf_symbols {
+        let Ok((section_index, _)) = obj.sections.at_address(dwarf_sym.address as u32) else {
+            log::warn!(
+                "Symbol {} at {:#010X} is not in any section",
+                dwarf_sym.name,
+                dwarf_sym.address
+            );
+            continue;
+        };
+        let existing = obj
+            .symbols
+            .at_section_address(section_index, dwarf_sym.address as u32)
+            .find(|(_, sym)| sym.name == dwarf_sym.name)
+            .or_else(|| {
+                obj.symbols.at_section_address(section_index, dwarf_sym.address as u32).find(
+                    |(_, sym)| sym.kind == dwarf_sym.kind || sym.kind == ObjSymbolKind::Unknown,
+                )
+            })
+            .map(|(idx, sym)| (idx, sym.clone()));
+        if existing.as_ref().map_or(true, |(_, sym)| sym.name != dwarf_sym.name) {
+            if let Some(other) = find_name_conflict(&obj, section_index, &dwarf_sym) {
+                log::warn!(
+                    "Skipping {} (type {:?}) at {:#010X}: name already used by {:?} at {:#010X}",
+                    dwarf_sym.name,
+                    dwarf_sym.kind,
+                    dwarf_sym.address,
+                    other.kind,
+                    other.address
+                );
+                conflicts += 1;
+                continue;
+            }
+        }
+        let Some((orig_idx, orig_sym)) = existing else {
+            log::info!(
+                "Adding symbol {} (type {:?}, size {:#X}) at {:#010X}",
+                dwarf_sym.name,
+                dwarf_sym.kind,
+                dwarf_sym.size,
+                dwarf_sym.address
+            );
+            obj.symbols.add_direct(ObjSymbol { section: Some(section_index), ..dwarf_sym })?;
+            added += 1;
+            continue;
+        };
+
+        // Generated symbols are replaced, but user-named symbols are only filled in
+        let is_auto = is_auto_symbol(&orig_sym);
+        let mut updated_sym = orig_sym.clone();
+        if orig_sym.name != dwarf_sym.name {
+            if !is_auto {
+                log::warn!(
+                    "Conflicting name for {} (type {:?}) at {:#010X}: DWARF has {}",
+                    orig_sym.name,
+                    orig_sym.kind,
+                    orig_sym.address,
+                    dwarf_sym.name
+                );
+                conflicts += 1;
+                continue;
+            }
+            log::info!(
+                "Changing name of {} (type {:?}) to {}",
+                orig_sym.name,
+                orig_sym.kind,
+                dwarf_sym.name
+            );
+            updated_sym.name = dwarf_sym.name.clone();
+            updated_sym.demangled_name = dwarf_sym.demangled_name.clone();
+        }
+        if orig_sym.kind == ObjSymbolKind::Unknown || is_auto {
+            updated_sym.kind = dwarf_sym.kind;
+        }
+        if dwarf_sym.size_known && (orig_sym.size != dwarf_sym.size || !orig_sym.size_known) {
+            if orig_sym.size_known && orig_sym.size != dwarf_sym.size && !is_auto {
+                log::warn!(
+                    "Conflicting size for {} (type {:?}): was {:#X}, DWARF has {:#X}",
+                    orig_sym.name,
+                    orig_sym.kind,
+                    orig_sym.size,
+                    dwarf_sym.size
+                );
+                conflicts += 1;
+            } else {
+                updated_sym.size = dwarf_sym.size;
+                updated_sym.size_known = true;
+            }
+        }
+        if orig_sym.flags.scope() == ObjSymbolScope::Unknown || is_auto {
+            updated_sym.flags.set_scope(dwarf_sym.flags.scope());
+        }
+        if dwarf_sym.data_kind != ObjDataKind::Unknown
+            && (orig_sym.data_kind == ObjDataKind::Unknown || is_auto)
+        {
+            updated_sym.data_kind = dwarf_sym.data_kind;
+        }
+        if updated_sym != orig_sym {
+            log::debug!("Replacing {:?} with {:?}", orig_sym, updated_sym);
+            obj.symbols.replace(orig_idx, updated_sym)?;
+            updated += 1;
+        }
+    }
+    log::info!("Added {} symbols, updated {} symbols, {} conflicts", added, updated, conflicts);
+
+    write_symbols_file(symbols_path, &obj, Some(symbols_cache))?;
+
+    Ok(())
+}
+
+/// Finds a symbol elsewhere with the same name as a DWARF symbol. Local symbols may share
+/// names, so a conflict requires at least one of the two to be global.
+fn find_name_conflict<'a>(
+    obj: &'a ObjInfo,
+    section_index: usize,
+    dwarf_sym: &ObjSymbol,
+) -> Option<&'a ObjSymbol> {
+    obj.symbols
+        .for_name(&dwarf_sym.name)
+        .map(|(_, sym)| sym)
+        .filter(|sym| sym.section != Some(section_index) || sym.address != dwarf_sym.address)
+        .find(|sym| !sym.flags.is_local() || !dwarf_sym.flags.is_local())
+}
+
+#[derive(Serialize)]
+struct FuzzyMatchReport<'a> {
+    section: &'a str,
+    address: u32,
+    size: u64,
+    name: &'a str,
+    candidate: &'a str,
+    hash: &'a str,
+    #[serde(flatten)]
+    score: SignatureScore,
+}
+
+fn sigs(args: SigsArgs) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let mut config_file = buf_reader(&args.config)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+
+    let signature_paths =
+        if args.signatures.is_empty() { &config.signatures } else { &args.signatures };
+    let (signatures, signature_files, _) = read_signature_files(signature_paths)?;
+    if signatures.is_empty() {
+        bail!("No signatures loaded");
+    }
+    log::info!("Loaded {} signatures from {} files", signatures.len(), signature_files.len());
+
+    let mut result = load_analyze_dol(&config, &[])?;
+
+    // Relocations are part of the score, so run relocation analysis on a copy of the object.
+    // Only symbol renames are written back to the symbols file.
+    let mut tracked_obj = result.obj.clone();
+    let mut tracker = Tracker::new(&tracked_obj);
+    tracker.process(&tracked_obj)?;
+    tracker.apply(&mut tracked_obj, false)?;
+
+    let threshold = args.threshold.unwrap_or(0.8);
+    let matches = find_fuzzy_matches(&tracked_obj, &signatures, threshold)?;
+
+    println!(
+        "{: >10} | {: <10} | {: <10} | {: <30} | {: <10}",
+        "Section", "Address", "Confidence", "Function", "Candidate"
+    );
+    let mut report = Vec::with_capacity(matches.len());
+    for m in &matches {
+        let symbol = &tracked_obj.symbols[m.symbol_index];
+        let section = &tracked_obj.sections[symbol.section.unwrap()];
+        let signature = &signatures[m.signature_index];
+        let candidate = &signature.symbols[signature.symbol].name;
+        println!(
+            "{: >10} | {: <#10X} | {: <10.3} | {: <30} | {}",
+            section.name, symbol.address, m.score.confidence, symbol.name, candidate
+        );
+        report.push(FuzzyMatchReport {
+            section: &section.name,
+            address: symbol.address as u32,
+            size: symbol.size,
+            name: &symbol.name,
+            candidate,
+            hash: &signature.hash,
+            score: m.score,
+        });
+    }
+    log::info!("Found {} candidate matches", matches.len());
+
+    if let Some(out_file) = &args.out_file {
+        let mut w = buf_writer(out_file)?;
+        serde_json::to_writer_pretty(&mut w, &report)?;
+        w.flush()?;
+    }
+
+    if args.apply {
+        let Some(symbols_path) = &config.base.symbols else {
+            bail!("No symbols file specified in config");
+        };
+        let apply_threshold = args.apply_threshold.unwrap_or(0.95);
+        let obj = &mut result.obj;
+        let mut applied = 0usize;
+        // Highest confidence first, so that each name is applied to its best match
+        for m in matches
+            .iter()
+            .filter(|m| m.score.confidence >= apply_threshold)
+            .sorted_by(|a, b| b.score.confidence.total_cmp(&a.score.confidence))
+        {
+            let signature = &signatures[m.signature_index];
+            let sig_symbol = &signature.symbols[signature.symbol];
+            if obj.symbols.by_name(&sig_symbol.name)?.is_some() {
+                log::debug!("Skipping {}: symbol already exists", sig_symbol.name);
+                continue;
+            }
+            let mut symbol = obj.symbols[m.symbol_index].clone();
+            log::info!(
+                "Renaming {} to {} (confidence {:.3})",
+                symbol.name,
+                sig_symbol.name,
+                m.score.confidence
+            );
+            symbol.name = sig_symbol.name.clone();
+            symbol.demangled_name = demangle(&symbol.name, &DemangleOptions::default());
+            symbol.flags.set_scope(sig_symbol.flags.scope());
+            obj.symbols.replace(m.symbol_index, symbol)?;
+            applied += 1;
+        }
+        log::info!("Applied {} matches", applied);
+        write_symbols_file(symbols_path, obj, result.symbols_cache)?;
+    }
+    Ok(())
+}
+
+fn virtual_calls(args: VirtualCallsArgs) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let mut config_file = buf_reader(&args.config)?;
+    let config: ProjectConfig = serde_yaml::from_reader(&mut config_file)?;
+
+    log::info!("Loading {}", config.base.object.display());
+    let mut obj = {
+        let file = map_file(&config.base.object)?;
+        if let Some(hash_str) = &config.base.hash {
+            verify_hash(file.as_slice(), hash_str)?;
+        }
+        process_dol(file.as_slice(), config.base.name().as_ref())?
+    };
+
+    if let Some(splits_path) = &config.base.splits {
+        apply_splits_file(splits_path, &mut obj)?;
+    }
+
+    if let Some(symbols_path) = &config.base.symbols {
+        apply_symbols_file(symbols_path, &mut obj)?;
+    }
+
+    log::info!("Performing relocation analysis");
+    let mut tracker = Tracker::new(&obj);
+    tracker.process(&obj)?;
+    tracker.apply(&mut obj, false)?;
+
+    let report = resolve_virtual_calls(&obj, &tracker.virtual_calls)?;
+    for call in &report.calls {
+        println!(
+            "{:#010X} {}: vtable +{:#X}, slot +{:#X}",
+            call.address,
+            call.function.as_deref().unwrap_or("[unknown]"),
+            call.object_offset,
+            call.slot_offset
+        );
+        for target in &call.targets {
+            // Mark targets in the caller's own class
+            let marker = if call.class.is_some() && target.class == call.class { '*' } else { ' ' };
+            println!(
+                "    {} {:#010X} {} ({})",
+                marker,
+                target.address,
+                target.name.as_deref().unwrap_or("[unknown]"),
+                target.vtable
+            );
+        }
+    }
+    log::info!("Found {} virtual calls", report.calls.len());
+
+    if let Some(out_file) = &args.out_file {
+        let mut w = buf_writer(out_file)?;
+        serde_json::to_writer_pretty(&mut w, &report)?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
 fn config(args: ConfigArgs) -> Result<()> {
     let mut config = ProjectConfig {
         base: ModuleConfig {
@@ -1747,6 +2244,7 @@ fn config(args: ConfigArgs) -> Result<()> {
             splits: None,
             symbols: None,
             map: None,
+            dwarf: None,
             force_active: vec![],
             ldscript_template: None,
             links: None,
@@ -1763,6 +2261,7 @@ fn config(args: ConfigArgs) -> Result<()> {
         common_start: None,
         symbols_known: false,
         fill_gaps: true,
+        signatures: vec![],
     };
 
     let mut modules = Vec::<(u32, ModuleConfig)>::new();
@@ -1784,6 +2283,7 @@ fn config(args: ConfigArgs) -> Result<()> {
                     splits: None,
                     symbols: None,
                     map: None,
+                    dwarf: None,
                     force_active: vec![],
                     ldscript_template: None,
                     links: None,
@@ -1802,6 +2302,7 @@ fn config(args: ConfigArgs) -> Result<()> {
                     splits: None,
                     symbols: None,
                     map: None,
+                    dwarf: None,
                     force_active: vec![],
                     ldscript_template: None,
                     links: None,
@@ -1,28 +1,40 @@
 use std::{
-    collections::{btree_map, BTreeMap},
+    collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
     io::{stdout, Cursor, Read, Write},
-    path::PathBuf,
-    str::from_utf8,
+    path::{Path, PathBuf},
+    str::{from_utf8, FromStr},
 };
 
-use anyhow::{anyhow, bail, Context, Result};
-use argp::FromArgs;
-use object::{elf, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, Section};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use argp::{FromArgValue, FromArgs};
+use object::{
+    elf, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, Section,
+    SectionKind,
+};
+use serde::Serialize;
 use syntect::{
     highlighting::{Color, HighlightIterator, HighlightState, Highlighter, Theme, ThemeSet},
     parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet},
 };
 
-use crate::util::{
-    dwarf::{
-        process_root_tag, read_debug_section, should_skip_tag, tag_type_string, AttributeKind,
-        TagKind,
+use crate::{
+    obj::{ObjInfo, ObjSectionKind, ObjSymbolKind},
+    util::{
+        dwarf::{
+            apply_compile_units, find_line, process_compile_units, process_root_tag,
+            process_symbols, read_aranges_section, read_debug_info_section,
+            read_debug_line_section, read_debug_section, read_line_section, should_skip_tag,
+            subroutine_decl_string, subroutine_def_string, tag_type_string, type_string, ud_type,
+            AttributeKind, CompileUnit, DwarfInfo, LineTable, Modifier, StructureKind, Tag,
+            TagKind, TagType, Type, TypeKind, TypedefMap, UserDefinedType,
+        },
+        file::{buf_writer, map_file},
     },
-    file::{buf_writer, map_file},
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
-/// Commands for processing DWARF 1.1 information.
+/// Commands for processing DWARF 1.1 and DWARF 2 information.
 #[argp(subcommand, name = "dwarf")]
 pub struct Args {
     #[argp(subcommand)]
@@ -33,10 +45,12 @@ pub struct Args {
 #[argp(subcommand)]
 enum SubCommand {
     Dump(DumpArgs),
+    Headers(HeadersArgs),
+    Lines(LinesArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Dumps DWARF 1.1 info from an object or archive.
+/// Dumps DWARF 1.1 or DWARF 2 info from an object or archive.
 #[argp(subcommand, name = "dump")]
 pub struct DumpArgs {
     #[argp(positional)]
@@ -48,15 +62,75 @@ pub struct DumpArgs {
     #[argp(switch)]
     /// Disable color output.
     no_color: bool,
+    #[argp(option, short = 'f')]
+    /// Output format. (Default: text)
+    /// Possible values: text, json
+    format: Option<DumpFormat>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Generates per-TU headers and source stubs from DWARF 1.1 or DWARF 2 info.
+#[argp(subcommand, name = "headers")]
+pub struct HeadersArgs {
+    #[argp(positional)]
+    /// Input object. (ELF or archive)
+    in_file: PathBuf,
+    #[argp(positional)]
+    /// Output directory.
+    out_dir: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Maps addresses to source lines, or lists the line range of each function.
+#[argp(subcommand, name = "lines")]
+pub struct LinesArgs {
+    #[argp(positional)]
+    /// Input ELF file.
+    in_file: PathBuf,
+    #[argp(positional)]
+    /// Addresses to look up. (If none, lists all functions)
+    addresses: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum DumpFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for DumpFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromArgValue for DumpFormat {
+    fn from_arg_value(value: &std::ffi::OsStr) -> Result<Self, String> {
+        String::from_arg_value(value)
+            .and_then(|s| Self::from_str(&s).map_err(|_| "Invalid output format".to_string()))
+    }
 }
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Dump(c_args) => dump(c_args),
+        SubCommand::Headers(c_args) => headers(c_args),
+        SubCommand::Lines(c_args) => lines(c_args),
     }
 }
 
 fn dump(args: DumpArgs) -> Result<()> {
+    if args.format.unwrap_or_default() == DumpFormat::Json {
+        return dump_json(args);
+    }
+
     // Load syntect
     let theme_set: ThemeSet =
         syntect::dumps::from_binary(include_bytes!("../../assets/syntax/default.themedump"));
@@ -67,7 +141,43 @@ fn dump(args: DumpArgs) -> Result<()> {
     let syntax = syntax_set.find_syntax_by_name("C++").context("Failed to find syntax")?.clone();
 
     let file = map_file(&args.in_file)?;
-    let buf = file.as_slice();
+    for_each_debug_info(file.as_slice(), |name, info| {
+        match (&name, &args.out) {
+            (Some(name), Some(out_path)) => {
+                let file_path = out_path.join(format!("{}.txt", member_file_name(name)));
+                let mut file = buf_writer(file_path)?;
+                dump_debug_section(&mut file, &info)?;
+                file.flush()?;
+            }
+            (None, Some(out_path)) => {
+                let mut file = buf_writer(out_path)?;
+                dump_debug_section(&mut file, &info)?;
+                file.flush()?;
+            }
+            (_, None) if args.no_color => {
+                if let Some(name) = &name {
+                    println!("\n// File {}:", name);
+                }
+                dump_debug_section(&mut stdout(), &info)?;
+            }
+            (_, None) => {
+                let mut writer = HighlightWriter::new(syntax_set.clone(), syntax.clone(), theme);
+                if let Some(name) = &name {
+                    writeln!(writer, "\n// File {}:", name)?;
+                }
+                dump_debug_section(&mut writer, &info)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Calls `f` with the DWARF information of an ELF file, or of each object in an archive
+/// along with its name. Archive members without debug information are skipped.
+fn for_each_debug_info(
+    buf: &[u8],
+    mut f: impl FnMut(Option<String>, DwarfInfo) -> Result<()>,
+) -> Result<()> {
     if buf.starts_with(b"!<arch>\n") {
         let mut archive = ar::Archive::new(buf);
         while let Some(result) = archive.next_entry() {
@@ -79,64 +189,908 @@ fn dump(args: DumpArgs) -> Result<()> {
             let mut data = vec![0u8; e.header().size() as usize];
             e.read_exact(&mut data)?;
             let obj_file = object::read::File::parse(&*data)?;
-            let debug_section = match obj_file.section_by_name(".debug") {
-                Some(section) => {
-                    log::info!("Processing '{}'", name);
-                    section
-                }
-                None => {
-                    log::warn!("Object '{}' missing .debug section", name);
-                    continue;
-                }
+            let Some(info) = load_debug_info(&obj_file)? else {
+                log::warn!("Object '{}' missing .debug or .debug_info section", name);
+                continue;
             };
-            if let Some(out_path) = &args.out {
-                // TODO make a basename method
-                let name = name.trim_start_matches("D:").replace('\\', "/");
-                let name = name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&name);
-                let file_path = out_path.join(format!("{}.txt", name));
+            log::info!("Processing '{}'", name);
+            f(Some(name), info)?;
+        }
+    } else {
+        let obj_file = object::read::File::parse(buf)?;
+        let info = load_debug_info(&obj_file)?
+            .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+        f(None, info)?;
+    }
+    Ok(())
+}
+
+/// File name of an archive member, without any directory.
+fn member_file_name(name: &str) -> String {
+    let name = name.trim_start_matches("D:").replace('\\', "/");
+    name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&name).to_string()
+}
+
+#[derive(Serialize)]
+struct JsonObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    units: Vec<JsonUnit>,
+}
+
+#[derive(Serialize)]
+struct JsonUnit {
+    key: u32,
+    name: String,
+    tags: Vec<JsonTag>,
+}
+
+#[derive(Serialize)]
+struct JsonTag {
+    key: u32,
+    #[serde(flatten)]
+    tag: TagType,
+}
+
+fn dump_json(args: DumpArgs) -> Result<()> {
+    #[derive(Serialize)]
+    struct JsonArchive {
+        objects: Vec<JsonObject>,
+    }
+
+    let file = map_file(&args.in_file)?;
+    let mut objects = Vec::new();
+    for_each_debug_info(file.as_slice(), |name, info| {
+        let units = json_units(&info)?;
+        match (name, &args.out) {
+            (Some(name), Some(out_path)) => {
+                let file_path = out_path.join(format!("{}.json", member_file_name(&name)));
                 let mut file = buf_writer(file_path)?;
-                dump_debug_section(&mut file, &obj_file, debug_section)?;
+                serde_json::to_writer_pretty(&mut file, &JsonObject { name: None, units })?;
                 file.flush()?;
-            } else if args.no_color {
-                println!("\n// File {}:", name);
-                dump_debug_section(&mut stdout(), &obj_file, debug_section)?;
+            }
+            (Some(name), None) => {
+                objects.push(JsonObject { name: Some(name), units });
+            }
+            (None, Some(out_path)) => {
+                let mut file = buf_writer(out_path)?;
+                serde_json::to_writer_pretty(&mut file, &JsonObject { name: None, units })?;
+                file.flush()?;
+            }
+            (None, None) => {
+                serde_json::to_writer_pretty(&mut stdout(), &JsonObject { name: None, units })?;
+                println!();
+            }
+        }
+        Ok(())
+    })?;
+    if file.as_slice().starts_with(b"!<arch>\n") && args.out.is_none() {
+        serde_json::to_writer_pretty(&mut stdout(), &JsonArchive { objects })?;
+        println!();
+    }
+    Ok(())
+}
+
+/// Collects the tags of each compile unit: all root tags, plus any nested type
+/// definitions that may be referenced by key.
+fn json_units(info: &DwarfInfo) -> Result<Vec<JsonUnit>> {
+    let mut units = Vec::new();
+    let Some((_, mut tag)) = info.tags.first_key_value() else {
+        return Ok(units);
+    };
+    loop {
+        if tag.kind != TagKind::CompileUnit {
+            log::warn!("Expected CompileUnit, got {:?}", tag.kind);
+            break;
+        }
+        let name = tag
+            .string_attribute(AttributeKind::Name)
+            .ok_or_else(|| anyhow!("CompileUnit without name {:?}", tag))?
+            .clone();
+        let next = tag.next_sibling(&info.tags);
+        let children = tag.children(&info.tags).iter().map(|c| c.key).collect::<BTreeSet<_>>();
+        let end = next.map(|t| t.key).unwrap_or(u32::MAX);
+        let mut tags = Vec::new();
+        for (_, child) in info.tags.range(tag.key + 1..end) {
+            let is_root = children.contains(&child.key);
+            let result = if is_root {
+                process_root_tag(info, child)
+            } else if is_nested_type(child) {
+                ud_type(info, child).map(TagType::UserDefined)
             } else {
-                let mut writer = HighlightWriter::new(syntax_set.clone(), syntax.clone(), theme);
-                writeln!(writer, "\n// File {}:", name)?;
-                dump_debug_section(&mut writer, &obj_file, debug_section)?;
+                continue;
+            };
+            match result {
+                Ok(tag_type) => tags.push(JsonTag { key: child.key, tag: tag_type }),
+                Err(e) => {
+                    log::error!("Failed to process tag {} (unit {}): {}", child.key, name, e);
+                }
             }
         }
-    } else {
-        let obj_file = object::read::File::parse(buf)?;
-        let debug_section = obj_file
-            .section_by_name(".debug")
-            .ok_or_else(|| anyhow!("Failed to locate .debug section"))?;
-        if let Some(out_path) = &args.out {
-            let mut file = buf_writer(out_path)?;
-            dump_debug_section(&mut file, &obj_file, debug_section)?;
-            file.flush()?;
-        } else if args.no_color {
-            dump_debug_section(&mut stdout(), &obj_file, debug_section)?;
-        } else {
-            let mut writer = HighlightWriter::new(syntax_set, syntax, theme);
-            dump_debug_section(&mut writer, &obj_file, debug_section)?;
+        units.push(JsonUnit { key: tag.key, name, tags });
+        match next {
+            Some(next) => tag = next,
+            None => break,
+        }
+    }
+    Ok(units)
+}
+
+fn is_nested_type(tag: &Tag) -> bool {
+    matches!(
+        tag.kind,
+        TagKind::StructureType
+            | TagKind::ClassType
+            | TagKind::UnionType
+            | TagKind::EnumerationType
+            | TagKind::ArrayType
+            | TagKind::SubroutineType
+            | TagKind::PtrToMemberType
+    )
+}
+
+fn headers(args: HeadersArgs) -> Result<()> {
+    let file = map_file(&args.in_file)?;
+    let mut infos = Vec::new();
+    for_each_debug_info(file.as_slice(), |_, info| {
+        infos.push(info);
+        Ok(())
+    })?;
+
+    let mut types = HeaderTypes::default();
+    let mut units = Vec::<HeaderUnit>::new();
+    // Reserve the stem of the shared header, so that a unit named "common" can't replace it
+    let mut stems = HashMap::<String, usize>::from([("common".to_string(), 1)]);
+    for info in &infos {
+        let Some((_, mut tag)) = info.tags.first_key_value() else {
+            continue;
+        };
+        loop {
+            if tag.kind != TagKind::CompileUnit {
+                log::warn!("Expected CompileUnit, got {:?}", tag.kind);
+                break;
+            }
+            let mut unit = process_header_unit(info, tag, &mut types)?;
+            let count = stems.entry(unit.stem.to_ascii_lowercase()).or_default();
+            if *count > 0 {
+                unit.stem = format!("{}_{}", unit.stem, count);
+            }
+            *count += 1;
+            units.push(unit);
+            match tag.next_sibling(&info.tags) {
+                Some(next) => tag = next,
+                None => break,
+            }
+        }
+    }
+
+    // Types with the same name, layout and dependencies in more than one unit are moved
+    // into a common header, unless another unit defines a different type with that name.
+    let mut uses = vec![0usize; types.types.len()];
+    for unit in &units {
+        for &idx in &unit.types {
+            uses[idx] += 1;
+        }
+    }
+    let mut names = HashMap::<&str, usize>::new();
+    for t in &types.types {
+        *names.entry(&t.name).or_default() += 1;
+    }
+    let mut common = vec![false; types.types.len()];
+    for (idx, t) in types.types.iter().enumerate() {
+        // Dependencies are always interned before their dependents
+        common[idx] =
+            uses[idx] > 1 && names[t.name.as_str()] == 1 && t.deps.iter().all(|&dep| common[dep]);
+    }
+
+    let common_types = (0..types.types.len()).filter(|&idx| common[idx]).collect::<Vec<_>>();
+    let common_forwards = forward_decls(&types, &common_types);
+    if !common_types.is_empty() {
+        let mut w = buf_writer(args.out_dir.join("common.h"))?;
+        let guard = include_guard("common");
+        writeln!(w, "#ifndef {guard}\n#define {guard}")?;
+        write_types(&mut w, &types, &common_forwards, &common_types)?;
+        writeln!(w, "\n#endif // {guard}")?;
+        w.flush()?;
+    }
+
+    for unit in &units {
+        let header_name = format!("{}.h", unit.stem);
+        let guard = include_guard(&unit.stem);
+        let mut w = buf_writer(args.out_dir.join(&header_name))?;
+        writeln!(w, "// Compile unit: {}", unit.name)?;
+        writeln!(w, "#ifndef {guard}\n#define {guard}")?;
+        if unit.types.iter().any(|&idx| common[idx]) {
+            writeln!(w, "\n#include \"common.h\"")?;
+        }
+        let unit_types = unit.types.iter().copied().filter(|&idx| !common[idx]).collect::<Vec<_>>();
+        let mut forwards = forward_decls(&types, &unit_types);
+        for forward in &unit.forwards {
+            if !forwards.contains(forward) {
+                forwards.push(forward.clone());
+            }
+        }
+        forwards.retain(|forward| !common_forwards.contains(forward));
+        write_types(&mut w, &types, &forwards, &unit_types)?;
+        if !unit.declarations.is_empty() {
+            writeln!(w)?;
+            for declaration in &unit.declarations {
+                writeln!(w, "{}", declaration)?;
+            }
+        }
+        writeln!(w, "\n#endif // {guard}")?;
+        w.flush()?;
+
+        let mut w = buf_writer(args.out_dir.join(format!("{}.{}", unit.stem, unit.ext)))?;
+        writeln!(w, "#include \"{}\"", header_name)?;
+        if !unit.variables.is_empty() {
+            writeln!(w)?;
+            for variable in &unit.variables {
+                writeln!(w, "{}", variable)?;
+            }
+        }
+        for subroutine in &unit.subroutines {
+            write!(w, "\n{}", subroutine)?;
+        }
+        w.flush()?;
+    }
+    log::info!(
+        "Wrote {} units ({} common types) to {}",
+        units.len(),
+        common_types.len(),
+        args.out_dir.display()
+    );
+    Ok(())
+}
+
+/// A type definition written to a header.
+struct HeaderType {
+    /// Name including the keyword, e.g. `struct Foo` or `typedef Foo`.
+    name: String,
+    /// Definition, including member offsets and sizes.
+    text: String,
+    /// Forward declaration, for structures, classes and unions.
+    forward: Option<String>,
+    /// Types that must be defined before this one.
+    deps: Vec<usize>,
+    /// Types that only need to be declared before this one.
+    forwards: Vec<String>,
+}
+
+/// Type definitions across all units. Identical definitions are interned, so that
+/// types shared between units can be detected.
+#[derive(Default)]
+struct HeaderTypes {
+    types: Vec<HeaderType>,
+    lookup: HashMap<(String, Vec<usize>), usize>,
+}
+
+impl HeaderTypes {
+    fn intern(&mut self, t: HeaderType) -> usize {
+        match self.lookup.entry((t.text.clone(), t.deps.clone())) {
+            hash_map::Entry::Occupied(e) => *e.get(),
+            hash_map::Entry::Vacant(e) => {
+                let idx = self.types.len();
+                self.types.push(t);
+                e.insert(idx);
+                idx
+            }
+        }
+    }
+}
+
+struct HeaderUnit {
+    /// Compile unit name.
+    name: String,
+    /// Output file name, without extension.
+    stem: String,
+    /// Source file extension.
+    ext: String,
+    /// Type definitions, in dependency order.
+    types: Vec<usize>,
+    /// Types referenced by declarations.
+    forwards: Vec<String>,
+    /// Global variable and function declarations.
+    declarations: Vec<String>,
+    /// Variable definitions for the source stub.
+    variables: Vec<String>,
+    /// Function definitions for the source stub.
+    subroutines: Vec<String>,
+}
+
+/// A type definition within a single unit, before interning.
+struct UnitType {
+    name: String,
+    text: String,
+    forward: Option<String>,
+    defined: Vec<u32>,
+    declared: Vec<u32>,
+}
+
+fn process_header_unit(info: &DwarfInfo, tag: &Tag, types: &mut HeaderTypes) -> Result<HeaderUnit> {
+    let name = tag
+        .string_attribute(AttributeKind::Name)
+        .ok_or_else(|| anyhow!("CompileUnit without name {:?}", tag))?
+        .clone();
+    let file_name = name.replace('\\', "/");
+    let file_name = file_name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&file_name);
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext))
+            if matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "c" | "cc" | "cp" | "cpp" | "cxx" | "c++"
+            ) =>
+        {
+            (stem.to_string(), ext.to_string())
+        }
+        Some((stem, _)) => (stem.to_string(), "c".to_string()),
+        None => (file_name.to_string(), "c".to_string()),
+    };
+
+    let children = tag.children(&info.tags);
+    let mut typedefs = TypedefMap::new();
+    for child in &children {
+        if child.kind == TagKind::Typedef {
+            if let Some(ud_type_ref) = child.reference_attribute(AttributeKind::UserDefType) {
+                typedefs.entry(ud_type_ref).or_default().push(child.key);
+            }
+        }
+    }
+
+    let mut unit_types = BTreeMap::<u32, UnitType>::new();
+    let mut unit = HeaderUnit {
+        name,
+        stem,
+        ext,
+        types: vec![],
+        forwards: vec![],
+        declarations: vec![],
+        variables: vec![],
+        subroutines: vec![],
+    };
+    for child in children {
+        let tag_type = match process_root_tag(info, child) {
+            Ok(tag_type) => tag_type,
+            Err(e) => {
+                log::error!("Failed to process tag {} (unit {}): {}", child.key, unit.name, e);
+                continue;
+            }
+        };
+        if let Err(e) =
+            process_header_tag(info, &typedefs, child, &tag_type, &mut unit_types, &mut unit)
+        {
+            log::error!("Failed to emit tag {} (unit {}): {}", child.key, unit.name, e);
+        }
+    }
+
+    // Sort definitions so that each type is defined after the types it contains
+    let mut order = Vec::with_capacity(unit_types.len());
+    let mut visited = BTreeMap::<u32, bool>::new();
+    for &key in unit_types.keys() {
+        sort_unit_type(&unit_types, key, &mut visited, &mut order);
+    }
+    let mut indices = BTreeMap::<u32, usize>::new();
+    for key in order {
+        let t = unit_types.remove(&key).unwrap();
+        let mut deps = Vec::new();
+        let mut forwards = Vec::new();
+        for &dep in &t.defined {
+            if let Some(&idx) = indices.get(&dep) {
+                if !deps.contains(&idx) {
+                    deps.push(idx);
+                }
+            } else if dep != key {
+                // Not defined in this unit, the best we can do is a forward declaration
+                push_forward_decl(info, dep, &mut forwards);
+            }
+        }
+        for &dep in &t.declared {
+            push_forward_decl(info, dep, &mut forwards);
+        }
+        let idx = types.intern(HeaderType {
+            name: t.name,
+            text: t.text,
+            forward: t.forward,
+            deps,
+            forwards,
+        });
+        indices.insert(key, idx);
+        unit.types.push(idx);
+    }
+    Ok(unit)
+}
+
+fn process_header_tag(
+    info: &DwarfInfo,
+    typedefs: &TypedefMap,
+    tag: &Tag,
+    tag_type: &TagType,
+    unit_types: &mut BTreeMap<u32, UnitType>,
+    unit: &mut HeaderUnit,
+) -> Result<()> {
+    match tag_type {
+        TagType::Typedef(t) => {
+            // The first typedef of a type is where it's defined, so it must not refer to itself
+            let mut typedefs = typedefs.clone();
+            let mut by_value = true;
+            if let TypeKind::UserDefined(key) = t.kind.kind {
+                if typedefs.get(&key).and_then(|v| v.first()) == Some(&tag.key) {
+                    typedefs.remove(&key);
+                }
+                if t.kind.modifiers.is_empty() {
+                    let ud_tag = info
+                        .tags
+                        .get(&key)
+                        .ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+                    by_value = forward_decl_string(&ud_type(info, ud_tag)?).is_none();
+                }
+            }
+            let mut refs = TypeRefs::new(info, &typedefs);
+            refs.visit_type(&t.kind, by_value)?;
+            unit_types.insert(tag.key, UnitType {
+                name: format!("typedef {}", t.name),
+                text: tag_type_string(info, &typedefs, tag_type)?,
+                forward: None,
+                defined: refs.defined,
+                declared: refs.declared,
+            });
+        }
+        TagType::Variable(v) => {
+            let Some(name) = v.name.as_ref() else {
+                return Ok(());
+            };
+            let mut refs = TypeRefs::new(info, typedefs);
+            refs.visit_type(&v.kind, true)?;
+            for key in refs.defined.into_iter().chain(refs.declared) {
+                push_forward_decl(info, key, &mut unit.forwards);
+            }
+            let ts = type_string(info, typedefs, &v.kind, true)?;
+            if !v.local {
+                unit.declarations.push(format!("extern {} {}{};", ts.prefix, name, ts.suffix));
+            }
+            let mut out = if v.local { "static ".to_string() } else { String::new() };
+            write!(out, "{} {}{};", ts.prefix, name, ts.suffix)?;
+            if let Some(address) = v.address {
+                write!(out, " // {:#010X}", address)?;
+            }
+            unit.variables.push(out);
+        }
+        TagType::UserDefined(UserDefinedType::Subroutine(t)) => {
+            // Skip declarations and inline functions without code
+            let Some(address) = tag.address_attribute(AttributeKind::LowPc) else {
+                return Ok(());
+            };
+            if t.name.is_none() {
+                return Ok(());
+            }
+            let mut refs = TypeRefs::new(info, typedefs);
+            refs.visit_type(&t.return_type, false)?;
+            for parameter in &t.parameters {
+                refs.visit_type(&parameter.kind, false)?;
+            }
+            for key in refs.defined.into_iter().chain(refs.declared) {
+                push_forward_decl(info, key, &mut unit.forwards);
+            }
+            if !t.local && !t.inline && t.member_of.is_none() {
+                unit.declarations.push(subroutine_decl_string(info, typedefs, t)?);
+            }
+            let mut out = format!("// {:#010X}\n", address);
+            out.push_str(&subroutine_def_string(info, typedefs, t)?);
+            unit.subroutines.push(out);
+        }
+        TagType::UserDefined(ud) => {
+            let (name, defined) = match ud {
+                UserDefinedType::Structure(t) => {
+                    let keyword = match t.kind {
+                        StructureKind::Struct => "struct",
+                        StructureKind::Class => "class",
+                    };
+                    (t.name.as_ref().map(|n| format!("{keyword} {n}")), t.byte_size.is_some())
+                }
+                UserDefinedType::Union(t) => {
+                    (t.name.as_ref().map(|n| format!("union {n}")), !t.members.is_empty())
+                }
+                UserDefinedType::Enumeration(t) => {
+                    (t.name.as_ref().map(|n| format!("enum {n}")), !t.members.is_empty())
+                }
+                _ => (None, false),
+            };
+            // Anonymous types are defined inline where they're used
+            let Some(name) = name else {
+                return Ok(());
+            };
+            if !defined {
+                return Ok(());
+            }
+            let mut refs = TypeRefs::new(info, typedefs);
+            refs.visit_ud_type(ud, true)?;
+            unit_types.insert(tag.key, UnitType {
+                name,
+                text: tag_type_string(info, typedefs, tag_type)?,
+                forward: forward_decl_string(ud),
+                defined: refs.defined,
+                declared: refs.declared,
+            });
         }
     }
     Ok(())
 }
 
-fn dump_debug_section<W>(
+fn sort_unit_type(
+    unit_types: &BTreeMap<u32, UnitType>,
+    key: u32,
+    visited: &mut BTreeMap<u32, bool>,
+    order: &mut Vec<u32>,
+) {
+    match visited.get(&key) {
+        Some(true) => return,
+        Some(false) => {
+            log::warn!("Circular dependency on {}", unit_types[&key].name);
+            return;
+        }
+        None => {}
+    }
+    visited.insert(key, false);
+    for &dep in &unit_types[&key].defined {
+        if dep != key && unit_types.contains_key(&dep) {
+            sort_unit_type(unit_types, dep, visited, order);
+        }
+    }
+    visited.insert(key, true);
+    order.push(key);
+}
+
+/// Collects the types referenced by a type. Types used by value must be defined
+/// first, while types used through a pointer or reference only need to be declared.
+struct TypeRefs<'a> {
+    info: &'a DwarfInfo,
+    typedefs: &'a TypedefMap,
+    defined: Vec<u32>,
+    declared: Vec<u32>,
+    visited: BTreeSet<(u32, bool)>,
+}
+
+impl<'a> TypeRefs<'a> {
+    fn new(info: &'a DwarfInfo, typedefs: &'a TypedefMap) -> Self {
+        Self { info, typedefs, defined: vec![], declared: vec![], visited: BTreeSet::new() }
+    }
+
+    fn visit_type(&mut self, t: &Type, by_value: bool) -> Result<()> {
+        let by_value = by_value
+            && !t
+                .modifiers
+                .iter()
+                .any(|m| matches!(m, Modifier::PointerTo | Modifier::ReferenceTo));
+        if let TypeKind::UserDefined(key) = t.kind {
+            self.visit_key(key, by_value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_key(&mut self, key: u32, by_value: bool) -> Result<()> {
+        if !self.visited.insert((key, by_value)) {
+            return Ok(());
+        }
+        let tag = self
+            .info
+            .tags
+            .get(&key)
+            .ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+        let ud = ud_type(self.info, tag)?;
+        if let Some(&td_key) = self.typedefs.get(&key).and_then(|v| v.first()) {
+            // Referred to by the typedef name, which defines anonymous types itself
+            self.defined.push(td_key);
+            if !by_value || ud.name().is_none() {
+                return Ok(());
+            }
+        }
+        match &ud {
+            UserDefinedType::Structure(t) if t.name.is_some() => self.push(key, by_value),
+            UserDefinedType::Union(t) if t.name.is_some() => self.push(key, by_value),
+            UserDefinedType::Enumeration(t) if t.name.is_some() => self.push(key, true),
+            _ => self.visit_ud_type(&ud, by_value)?,
+        }
+        Ok(())
+    }
+
+    /// Visits the contents of a type definition.
+    fn visit_ud_type(&mut self, ud: &UserDefinedType, by_value: bool) -> Result<()> {
+        match ud {
+            UserDefinedType::Structure(t) => {
+                for base in &t.bases {
+                    self.visit_type(&base.base_type, true)?;
+                }
+                for member in &t.members {
+                    self.visit_type(&member.kind, true)?;
+                }
+            }
+            UserDefinedType::Union(t) => {
+                for member in &t.members {
+                    self.visit_type(&member.kind, true)?;
+                }
+            }
+            UserDefinedType::Enumeration(_) => {}
+            UserDefinedType::Array(t) => self.visit_type(&t.element_type, by_value)?,
+            UserDefinedType::Subroutine(t) => {
+                self.visit_type(&t.return_type, false)?;
+                for parameter in &t.parameters {
+                    self.visit_type(&parameter.kind, false)?;
+                }
+            }
+            UserDefinedType::PtrToMember(t) => {
+                self.visit_type(&t.kind, false)?;
+                self.visit_key(t.containing_type, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, key: u32, by_value: bool) {
+        let list = if by_value { &mut self.defined } else { &mut self.declared };
+        if !list.contains(&key) {
+            list.push(key);
+        }
+    }
+}
+
+fn forward_decl_string(ud: &UserDefinedType) -> Option<String> {
+    match ud {
+        UserDefinedType::Structure(t) => t.name.as_ref().map(|name| match t.kind {
+            StructureKind::Struct => format!("struct {};", name),
+            StructureKind::Class => format!("class {};", name),
+        }),
+        UserDefinedType::Union(t) => t.name.as_ref().map(|name| format!("union {};", name)),
+        _ => None,
+    }
+}
+
+fn push_forward_decl(info: &DwarfInfo, key: u32, out: &mut Vec<String>) {
+    let Some(tag) = info.tags.get(&key) else {
+        return;
+    };
+    if !matches!(tag.kind, TagKind::StructureType | TagKind::ClassType | TagKind::UnionType) {
+        return;
+    }
+    if let Some(forward) = ud_type(info, tag).ok().as_ref().and_then(forward_decl_string) {
+        if !out.contains(&forward) {
+            out.push(forward);
+        }
+    }
+}
+
+/// Forward declarations for a list of types, in dependency order.
+fn forward_decls(types: &HeaderTypes, list: &[usize]) -> Vec<String> {
+    let mut out = Vec::<String>::new();
+    for &idx in list {
+        let t = &types.types[idx];
+        for forward in t.forwards.iter().chain(t.forward.as_ref()) {
+            if !out.contains(forward) {
+                out.push(forward.clone());
+            }
+        }
+    }
+    out
+}
+
+fn write_types<W>(
     w: &mut W,
-    obj_file: &object::File<'_>,
-    debug_section: Section,
+    types: &HeaderTypes,
+    forwards: &[String],
+    list: &[usize],
 ) -> Result<()>
 where
     W: Write + ?Sized,
 {
-    let mut data = debug_section.uncompressed_data()?.into_owned();
+    if !forwards.is_empty() {
+        writeln!(w)?;
+        for forward in forwards {
+            writeln!(w, "{}", forward)?;
+        }
+    }
+    for &idx in list {
+        writeln!(w, "\n{}", types.types[idx].text)?;
+    }
+    Ok(())
+}
+
+fn include_guard(stem: &str) -> String {
+    let mut guard = String::with_capacity(stem.len() + 3);
+    if stem.starts_with(|c: char| c.is_ascii_digit()) {
+        guard.push('_');
+    }
+    for c in stem.chars() {
+        guard.push(if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' });
+    }
+    guard.push_str("_H");
+    guard
+}
+
+fn lines(args: LinesArgs) -> Result<()> {
+    let addresses = args
+        .addresses
+        .iter()
+        .map(|s| {
+            u32::from_str_radix(s.trim_start_matches("0x").trim_start_matches("0X"), 16)
+                .with_context(|| format!("Invalid address '{}'", s))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let file = map_file(&args.in_file)?;
+    let obj_file = object::read::File::parse(file.as_slice())?;
+    let info = load_debug_info(&obj_file)?
+        .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+    let tables = load_line_tables(&obj_file, &info)?
+        .ok_or_else(|| anyhow!("Failed to locate .line or .debug_line section"))?;
+    let mut functions = process_symbols(&info)?
+        .into_iter()
+        .filter(|symbol| symbol.kind == ObjSymbolKind::Function)
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|symbol| symbol.address);
+
+    let mut w = stdout();
+    if addresses.is_empty() {
+        for function in &functions {
+            let start = function.address as u32;
+            let end = start + function.size as u32;
+            write!(w, "{:#010X}-{:#010X} {}", start, end, function.name)?;
+            // Lines for the function's own file, excluding any inlined code
+            let Some((table, first)) = find_line(&tables, start) else {
+                writeln!(w, " ??:0")?;
+                continue;
+            };
+            let (min_line, max_line) = table
+                .rows
+                .iter()
+                .filter(|row| (start..end).contains(&row.address) && row.file == first.file)
+                .fold((first.line, first.line), |(min, max), row| {
+                    (min.min(row.line), max.max(row.line))
+                });
+            writeln!(w, " {}:{}-{}", table.file_name(first.file), min_line, max_line)?;
+        }
+    } else {
+        for address in addresses {
+            let function = functions.iter().find(|symbol| {
+                (symbol.address..symbol.address + symbol.size).contains(&(address as u64))
+            });
+            write!(w, "{:#010X} {}", address, function.map_or("??", |f| f.name.as_str()))?;
+            match find_line(&tables, address) {
+                Some((table, row)) => match row.column {
+                    Some(column) => {
+                        writeln!(w, " at {}:{}:{}", table.file_name(row.file), row.line, column)?
+                    }
+                    None => writeln!(w, " at {}:{}", table.file_name(row.file), row.line)?,
+                },
+                None => writeln!(w, " at ??:0")?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads DWARF 1.1 line tables from the .line section or, if not present, DWARF 2
+/// line tables from the .debug_line section.
+pub fn load_line_tables(
+    obj_file: &object::File<'_>,
+    info: &DwarfInfo,
+) -> Result<Option<Vec<LineTable>>> {
+    if let Some(section) = obj_file.section_by_name(".line") {
+        let data = relocated_section_data(obj_file, &section)?;
+        return Ok(Some(read_line_section(&data, info)?));
+    }
+    if let Some(section) = obj_file.section_by_name(".debug_line") {
+        let data = relocated_section_data(obj_file, &section)?;
+        return Ok(Some(read_debug_line_section(&data, info.e)?));
+    }
+    Ok(None)
+}
+
+/// Loads the compile units and their address ranges, using .debug_aranges if present.
+pub fn load_compile_units(obj_file: &object::File<'_>) -> Result<Option<Vec<CompileUnit>>> {
+    let Some(info) = load_debug_info(obj_file)? else {
+        return Ok(None);
+    };
+    let aranges = match obj_file.section_by_name(".debug_aranges") {
+        Some(section) => {
+            let data = relocated_section_data(obj_file, &section)?;
+            read_aranges_section(&mut Cursor::new(&*data), info.e)?
+        }
+        None => vec![],
+    };
+    Ok(Some(process_compile_units(&info, &aranges)?))
+}
+
+/// Creates splits and link order entries from the compile units of a debug ELF.
+/// Returns `false` if the file has no debug information.
+pub fn apply_dwarf_splits_file<P: AsRef<Path>>(path: P, obj: &mut ObjInfo) -> Result<bool> {
+    let path = path.as_ref();
+    let file = map_file(path)?;
+    let obj_file = object::read::File::parse(file.as_slice())
+        .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+    let Some(units) = load_compile_units(&obj_file)? else {
+        return Ok(false);
+    };
+    verify_elf_sections(&obj_file, obj)
+        .with_context(|| format!("'{}' doesn't match the target object", path.display()))?;
+    apply_compile_units(obj, &units)?;
+    Ok(true)
+}
+
+/// Ensures that each allocated ELF section is at the same address, with the same size, as
+/// a section of the object, so that addresses can be applied from one to the other.
+/// Differing contents (e.g. a build with a patch applied) are only reported.
+fn verify_elf_sections(obj_file: &object::File<'_>, obj: &ObjInfo) -> Result<()> {
+    for section in obj_file.sections() {
+        let (address, size) = (section.address() as u32, section.size() as u32);
+        if address == 0 || size == 0 {
+            continue;
+        }
+        let name = section.name()?;
+        let Ok((_, obj_section)) = obj.sections.at_address(address) else {
+            bail!("Section {} at {:#010X} not found", name, address);
+        };
+        if section.kind() == SectionKind::UninitializedData {
+            // Uninitialized sections may be merged in the DOL
+            ensure!(
+                obj_section.kind == ObjSectionKind::Bss,
+                "Section {} at {:#010X} isn't uninitialized in the target ({})",
+                name,
+                address,
+                obj_section.name
+            );
+            continue;
+        }
+        // DOL sections are padded to 32 bytes
+        let padded_size = (size as u64 + 31) & !31;
+        if obj_section.address != address as u64
+            || !(size as u64..=padded_size).contains(&obj_section.size)
+        {
+            bail!(
+                "Section {} at {:#010X}..{:#010X} doesn't match {} at {:#010X}..{:#010X}",
+                name,
+                address,
+                address + size,
+                obj_section.name,
+                obj_section.address,
+                obj_section.address + obj_section.size
+            );
+        }
+        let data = section.uncompressed_data()?;
+        if obj_section.data.get(..data.len()) != Some(&*data) {
+            log::warn!("Section {} data doesn't match {}", name, obj_section.name);
+        }
+    }
+    Ok(())
+}
+
+/// Reads DWARF 1.1 info from the .debug section or, if not present, DWARF 2 info
+/// from the .debug_info section.
+pub fn load_debug_info(obj_file: &object::File<'_>) -> Result<Option<DwarfInfo>> {
+    let e = obj_file.endianness().into();
+    if let Some(section) = obj_file.section_by_name(".debug") {
+        let data = relocated_section_data(obj_file, &section)?;
+        let mut reader = Cursor::new(&*data);
+        return Ok(Some(read_debug_section(&mut reader, e)?));
+    }
+    if let Some(section) = obj_file.section_by_name(".debug_info") {
+        let data = relocated_section_data(obj_file, &section)?;
+        let abbrev_data = match obj_file.section_by_name(".debug_abbrev") {
+            Some(section) => relocated_section_data(obj_file, &section)?,
+            None => bail!("Failed to locate .debug_abbrev section"),
+        };
+        let str_data = match obj_file.section_by_name(".debug_str") {
+            Some(section) => Some(relocated_section_data(obj_file, &section)?),
+            None => None,
+        };
+        return Ok(Some(read_debug_info_section(&data, &abbrev_data, str_data.as_deref(), e)?));
+    }
+    Ok(None)
+}
+
+fn relocated_section_data(obj_file: &object::File<'_>, section: &Section) -> Result<Vec<u8>> {
+    let mut data = section.uncompressed_data()?.into_owned();
 
     // Apply relocations to data
-    for (addr, reloc) in debug_section.relocations() {
+    for (addr, reloc) in section.relocations() {
         match reloc.kind() {
             RelocationKind::Absolute | RelocationKind::Elf(elf::R_PPC_UADDR32) => {
                 let target = match reloc.target() {
@@ -144,18 +1098,19 @@ where
                         let symbol = obj_file.symbol_by_index(symbol_idx)?;
                         (symbol.address() as i64 + reloc.addend()) as u32
                     }
-                    _ => bail!("Invalid .debug relocation target"),
+                    _ => bail!("Invalid {} relocation target", section.name()?),
                 };
                 data[addr as usize..addr as usize + 4].copy_from_slice(&target.to_be_bytes());
             }
             RelocationKind::Elf(elf::R_PPC_NONE) => {}
-            _ => bail!("Unhandled .debug relocation type {:?}", reloc.kind()),
+            _ => bail!("Unhandled {} relocation type {:?}", section.name()?, reloc.kind()),
         }
     }
+    Ok(data)
+}
 
-    let mut reader = Cursor::new(&*data);
-    let info = read_debug_section(&mut reader, obj_file.endianness().into())?;
-
+fn dump_debug_section<W>(w: &mut W, info: &DwarfInfo) -> Result<()>
+where W: Write + ?Sized {
     for (&addr, tag) in &info.tags {
         log::debug!("{}: {:?}", addr, tag);
     }
@@ -178,7 +1133,7 @@ where
                     let children = tag.children(&info.tags);
                     let mut typedefs = BTreeMap::<u32, Vec<u32>>::new();
                     for child in children {
-                        let tag_type = match process_root_tag(&info, child) {
+                        let tag_type = match process_root_tag(info, child) {
                             Ok(tag_type) => tag_type,
                             Err(e) => {
                                 log::error!(
@@ -198,7 +1153,7 @@ where
                         if should_skip_tag(&tag_type) {
                             continue;
                         }
-                        match tag_type_string(&info, &typedefs, &tag_type) {
+                        match tag_type_string(info, &typedefs, &tag_type) {
                             Ok(s) => writeln!(w, "{}", s)?,
                             Err(e) => {
                                 log::error!(
@@ -329,3 +1284,61 @@ impl Write for HighlightWriter<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{
+        dwarf::{Attribute, AttributeValue, FundType, LocationOp},
+        reader::Endian,
+    };
+
+    #[test]
+    fn test_json_units() {
+        let attr = |kind, value| Attribute { kind, value };
+        let tags = [
+            Tag {
+                key: 0,
+                kind: TagKind::CompileUnit,
+                attributes: vec![
+                    attr(AttributeKind::Sibling, AttributeValue::Reference(0x100)),
+                    attr(AttributeKind::Name, AttributeValue::String("test.c".to_string())),
+                ],
+            },
+            Tag {
+                key: 0x10,
+                kind: TagKind::GlobalVariable,
+                attributes: vec![
+                    attr(AttributeKind::Name, AttributeValue::String("x".to_string())),
+                    attr(AttributeKind::FundType, AttributeValue::Data2(FundType::Integer as u16)),
+                    attr(
+                        AttributeKind::Location,
+                        AttributeValue::Block(vec![LocationOp::Address as u8, 0x80, 0, 0, 0x10]),
+                    ),
+                ],
+            },
+        ];
+        let info =
+            DwarfInfo { e: Endian::Big, tags: tags.into_iter().map(|t| (t.key, t)).collect() };
+        let units = json_units(&info).unwrap();
+        let json = serde_json::to_value(JsonObject { name: None, units }).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "units": [{
+                    "key": 0,
+                    "name": "test.c",
+                    "tags": [{
+                        "key": 0x10,
+                        "tag": "variable",
+                        "name": "x",
+                        "mangled_name": null,
+                        "type": { "fundamental": "int", "modifiers": [] },
+                        "address": 0x80000010u32,
+                        "local": false
+                    }]
+                }]
+            })
+        );
+    }
+}
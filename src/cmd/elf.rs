@@ -2,7 +2,7 @@ use std::{
     collections::{btree_map, hash_map, BTreeMap, HashMap},
     fs,
     fs::DirBuilder,
-    io::{Cursor, Write},
+    io::{Cursor, Read, Write},
     path::PathBuf,
 };
 
@@ -14,18 +14,22 @@ use object::{
     FileFlags, Object, ObjectSection, ObjectSymbol, RelocationKind, RelocationTarget, SectionFlags,
     SectionIndex, SectionKind, SymbolFlags, SymbolIndex, SymbolKind, SymbolScope, SymbolSection,
 };
+use sanitise_file_name::sanitize;
 
 use crate::{
+    cmd::dwarf::apply_dwarf_splits_file,
     obj::ObjKind,
     util::{
         asm::write_asm,
         comment::{CommentSym, MWComment},
         config::{write_splits_file, write_symbols_file},
-        elf::{process_elf, write_elf},
-        file::{buf_writer, process_rsp},
+        elf::{process_elf, process_elf_data, write_elf},
+        file::{buf_reader, buf_writer, map_file, process_rsp},
         reader::{Endian, FromReader},
-        signatures::{compare_signature, generate_signature, FunctionSignature},
-        split::split_obj,
+        signatures::{
+            compare_signature, generate_signature, generate_signatures, FunctionSignature,
+        },
+        split::{split_obj, update_splits},
         IntoCow, ToCow,
     },
 };
@@ -45,6 +49,7 @@ enum SubCommand {
     Disasm(DisasmArgs),
     Fixup(FixupArgs),
     Signatures(SignaturesArgs),
+    SignaturesDb(SignaturesDbArgs),
     Split(SplitArgs),
     Info(InfoArgs),
 }
@@ -112,6 +117,21 @@ pub struct SignaturesArgs {
     out_file: PathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Builds a signature database from static libraries or objects.
+#[argp(subcommand, name = "sigs-db")]
+pub struct SignaturesDbArgs {
+    #[argp(positional)]
+    /// input file(s) (.a or .o)
+    files: Vec<PathBuf>,
+    #[argp(option, short = 'o')]
+    /// output directory
+    out_dir: PathBuf,
+    #[argp(switch, short = 'd')]
+    /// also generate signatures for data objects
+    data: bool,
+}
+
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Prints information about an ELF file.
 #[argp(subcommand, name = "info")]
@@ -128,13 +148,20 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Fixup(c_args) => fixup(c_args),
         SubCommand::Split(c_args) => split(c_args),
         SubCommand::Signatures(c_args) => signatures(c_args),
+        SubCommand::SignaturesDb(c_args) => signatures_db(c_args),
         SubCommand::Info(c_args) => info(c_args),
     }
 }
 
 fn config(args: ConfigArgs) -> Result<()> {
     log::info!("Loading {}", args.in_file.display());
-    let obj = process_elf(&args.in_file)?;
+    let mut obj = process_elf(&args.in_file)?;
+    if obj.kind == ObjKind::Executable
+        && obj.link_order.is_empty()
+        && apply_dwarf_splits_file(&args.in_file, &mut obj)?
+    {
+        log::info!("Generated {} units from DWARF compile units", obj.link_order.len());
+    }
 
     DirBuilder::new().recursive(true).create(&args.out_dir)?;
     write_symbols_file(args.out_dir.join("symbols.txt"), &obj, None)?;
@@ -178,8 +205,12 @@ fn disasm(args: DisasmArgs) -> Result<()> {
 }
 
 fn split(args: SplitArgs) -> Result<()> {
-    let obj = process_elf(&args.in_file)?;
+    let mut obj = process_elf(&args.in_file)?;
     ensure!(obj.kind == ObjKind::Executable, "Can only split executable objects");
+    if obj.link_order.is_empty() && apply_dwarf_splits_file(&args.in_file, &mut obj)? {
+        log::info!("Generated {} units from DWARF compile units", obj.link_order.len());
+        update_splits(&mut obj, None, false)?;
+    }
 
     let mut file_map = HashMap::<String, Vec<u8>>::new();
 
@@ -225,7 +256,7 @@ fn file_name_from_unit(str: &str, suffix: &str) -> String {
     format!("{str}{suffix}")
 }
 
-const ASM_SUFFIX: &str = " (asm)";
+pub const ASM_SUFFIX: &str = " (asm)";
 
 // fn fixup(args: FixupArgs) -> Result<()> {
 //     let obj = process_elf(&args.in_file)?;
@@ -482,6 +513,83 @@ fn signatures(args: SignaturesArgs) -> Result<()> {
     Ok(())
 }
 
+fn merge_signature(entries: &mut Vec<FunctionSignature>, signature: FunctionSignature) {
+    if let Some(existing) = entries.iter_mut().find(|s| s.hash == signature.hash) {
+        if let Err(e) = compare_signature(existing, &signature) {
+            log::warn!("Signature {} mismatch: {:?}", signature.hash, e);
+        }
+    } else {
+        entries.push(signature);
+    }
+}
+
+fn add_signatures(
+    signatures: &mut BTreeMap<String, Vec<FunctionSignature>>,
+    new_signatures: Vec<FunctionSignature>,
+) {
+    for signature in new_signatures {
+        let name = signature.symbols[signature.symbol].name.clone();
+        merge_signature(signatures.entry(name).or_default(), signature);
+    }
+}
+
+fn signatures_db(args: SignaturesDbArgs) -> Result<()> {
+    // Process response files (starting with '@')
+    let files = process_rsp(&args.files)?;
+
+    let mut signatures = BTreeMap::<String, Vec<FunctionSignature>>::new();
+    for path in files {
+        log::info!("Processing {}", path.display());
+        let file = map_file(&path)?;
+        let buf = file.as_slice();
+        if buf.starts_with(b"!<arch>\n") {
+            let mut archive = ar::Archive::new(buf);
+            while let Some(result) = archive.next_entry() {
+                let mut e = match result {
+                    Ok(e) => e,
+                    Err(e) => bail!("Failed to read archive entry: {:?}", e),
+                };
+                let name = String::from_utf8_lossy(e.header().identifier()).to_string();
+                let mut data = vec![0u8; e.header().size() as usize];
+                e.read_exact(&mut data)?;
+                let result = process_elf_data(&data)
+                    .and_then(|mut obj| generate_signatures(&mut obj, args.data));
+                match result {
+                    Ok(new_signatures) => add_signatures(&mut signatures, new_signatures),
+                    Err(e) => log::warn!("Skipping '{}': {:?}", name, e),
+                }
+            }
+        } else {
+            let mut obj = process_elf_data(buf)
+                .with_context(|| format!("Failed to process '{}'", path.display()))?;
+            add_signatures(&mut signatures, generate_signatures(&mut obj, args.data)?);
+        }
+    }
+
+    DirBuilder::new().recursive(true).create(&args.out_dir)?;
+    let mut total = 0usize;
+    for (name, new_signatures) in signatures {
+        let out_path = args.out_dir.join(format!("{}.yml", sanitize(&name)));
+        // Merge with any existing database entry
+        let mut entries = if out_path.is_file() {
+            serde_yaml::from_reader(&mut buf_reader(&out_path)?)
+                .with_context(|| format!("Failed to parse '{}'", out_path.display()))?
+        } else {
+            vec![]
+        };
+        for signature in new_signatures {
+            merge_signature(&mut entries, signature);
+        }
+        entries.sort_by_key(|s| s.signature.len());
+        total += entries.len();
+        let mut out = buf_writer(&out_path)?;
+        serde_yaml::to_writer(&mut out, &entries)?;
+        out.flush()?;
+    }
+    log::info!("{} unique signatures", total);
+    Ok(())
+}
+
 fn info(args: InfoArgs) -> Result<()> {
     let in_buf = fs::read(&args.input)
         .with_context(|| format!("Failed to open input file: '{}'", args.input.display()))?;
@@ -1,6 +1,7 @@
 pub mod alf;
 pub mod ar;
 pub mod demangle;
+pub mod disc;
 pub mod dol;
 pub mod dwarf;
 pub mod elf;
@@ -8,8 +9,10 @@ pub mod elf2dol;
 pub mod map;
 pub mod metroidbuildinfo;
 pub mod nlzss;
+pub mod progress;
 pub mod rarc;
 pub mod rel;
 pub mod rso;
 pub mod shasum;
+pub mod u8_arc;
 pub mod yaz0;
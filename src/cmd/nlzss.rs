@@ -5,7 +5,8 @@ use argp::FromArgs;
 
 use crate::util::{
     file::{open_file, process_rsp},
-    IntoCow, ToCow,
+    lz::CompressionMode,
+    nlzss, IntoCow, ToCow,
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -19,9 +20,27 @@ pub struct Args {
 #[derive(FromArgs, PartialEq, Debug)]
 #[argp(subcommand)]
 enum SubCommand {
+    Compress(CompressArgs),
     Decompress(DecompressArgs),
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Compresses files using NLZSS.
+#[argp(subcommand, name = "compress")]
+pub struct CompressArgs {
+    #[argp(positional)]
+    /// Files to compress
+    files: Vec<PathBuf>,
+    #[argp(option, short = 'o')]
+    /// Output file (or directory, if multiple files are specified).
+    /// If not specified, compresses in-place.
+    output: Option<PathBuf>,
+    #[argp(option, short = 'm')]
+    /// Compression mode. (Default: matching)
+    /// Possible values: matching, fast
+    mode: Option<CompressionMode>,
+}
+
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Decompresses NLZSS-compressed files.
 #[argp(subcommand, name = "decompress")]
@@ -37,10 +56,35 @@ pub struct DecompressArgs {
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
+        SubCommand::Compress(args) => compress(args),
         SubCommand::Decompress(args) => decompress(args),
     }
 }
 
+fn compress(args: CompressArgs) -> Result<()> {
+    let files = process_rsp(&args.files)?;
+    let single_file = files.len() == 1;
+    let mode = args.mode.unwrap_or_default();
+    for path in files {
+        let data =
+            fs::read(&path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let compressed = nlzss::compress(&data, mode)
+            .with_context(|| format!("Failed to compress '{}'", path.display()))?;
+        let out_path = if let Some(output) = &args.output {
+            if single_file {
+                output.as_path().to_cow()
+            } else {
+                output.join(path.file_name().unwrap()).into_cow()
+            }
+        } else {
+            path.as_path().to_cow()
+        };
+        fs::write(out_path.as_ref(), compressed)
+            .with_context(|| format!("Failed to write '{}'", out_path.display()))?;
+    }
+    Ok(())
+}
+
 fn decompress(args: DecompressArgs) -> Result<()> {
     let files = process_rsp(&args.files)?;
     let single_file = files.len() == 1;
@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use argp::FromArgs;
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+use serde::Serialize;
+
+use crate::{
+    cmd::{
+        dol::{OutputConfig, OutputModule, OutputUnit},
+        elf::ASM_SUFFIX,
+    },
+    util::{
+        file::{buf_reader, buf_writer, map_file},
+        lcf::obj_path_for_unit,
+    },
+};
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Generates a matching progress report from `dol split` output.
+#[argp(subcommand, name = "progress")]
+pub struct Args {
+    #[argp(positional)]
+    /// split output config.json
+    config: PathBuf,
+    #[argp(positional)]
+    /// build directory containing compiled objects
+    build_dir: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output JSON report
+    out_file: Option<PathBuf>,
+    #[argp(switch, short = 'u')]
+    /// print per-unit progress
+    units: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+struct SizeProgress {
+    total: u64,
+    matched: u64,
+}
+
+impl SizeProgress {
+    fn add(&mut self, size: u64, matched: bool) {
+        self.total += size;
+        if matched {
+            self.matched += size;
+        }
+    }
+
+    fn merge(&mut self, other: &SizeProgress) {
+        self.total += other.total;
+        self.matched += other.matched;
+    }
+
+    fn percent(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.matched as f64 / self.total as f64 * 100.0
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+struct Progress {
+    code: SizeProgress,
+    data: SizeProgress,
+    functions: SizeProgress,
+    /// Code and data in units that `dol split` generated to cover gaps between splits.
+    autogenerated: SizeProgress,
+    sections: BTreeMap<String, SizeProgress>,
+}
+
+impl Progress {
+    fn merge(&mut self, other: &Progress) {
+        self.code.merge(&other.code);
+        self.data.merge(&other.data);
+        self.functions.merge(&other.functions);
+        self.autogenerated.merge(&other.autogenerated);
+        for (name, section) in &other.sections {
+            self.sections.entry(name.clone()).or_default().merge(section);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct UnitProgress {
+    name: String,
+    /// Whether the unit was built from source, rather than assembled.
+    matched: bool,
+    /// Whether the unit was generated by `dol split`, rather than declared in the splits file.
+    autogenerated: bool,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ModuleProgress {
+    name: String,
+    module_id: u32,
+    #[serde(flatten)]
+    progress: Progress,
+    units: Vec<UnitProgress>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+struct ProgressReport {
+    #[serde(flatten)]
+    progress: Progress,
+    modules: Vec<ModuleProgress>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    log::info!("Loading {}", args.config.display());
+    let config: OutputConfig = serde_json::from_reader(&mut buf_reader(&args.config)?)?;
+
+    let mut report = ProgressReport::default();
+    for module in [&config.base].into_iter().chain(&config.modules) {
+        let module_progress = module_progress(&args, module)
+            .with_context(|| format!("While processing module {}", module.name))?;
+        report.progress.merge(&module_progress.progress);
+        report.modules.push(module_progress);
+    }
+
+    print_progress("Total", &report.progress);
+    for module in &report.modules {
+        println!();
+        print_progress(&module.name, &module.progress);
+        if args.units {
+            for unit in &module.units {
+                println!(
+                    "    {} {} (code {:#X}, data {:#X}, {} functions){}",
+                    if unit.matched { '✓' } else { ' ' },
+                    unit.name,
+                    unit.progress.code.total,
+                    unit.progress.data.total,
+                    unit.progress.functions.total,
+                    if unit.autogenerated { " [autogenerated]" } else { "" }
+                );
+            }
+        }
+    }
+
+    if let Some(out_file) = &args.out_file {
+        let mut w = buf_writer(out_file)?;
+        serde_json::to_writer_pretty(&mut w, &report)?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
+fn print_progress(name: &str, progress: &Progress) {
+    println!("{}:", name);
+    let print_line = |label: &str, p: &SizeProgress| {
+        println!("    {: <13} {: >10} / {: <10} ({:.2}%)", label, p.matched, p.total, p.percent());
+    };
+    print_line("Code", &progress.code);
+    print_line("Data", &progress.data);
+    print_line("Functions", &progress.functions);
+    print_line("Autogenerated", &progress.autogenerated);
+    for (name, section) in &progress.sections {
+        print_line(name, section);
+    }
+}
+
+fn module_progress(args: &Args, module: &OutputModule) -> Result<ModuleProgress> {
+    let mut result = ModuleProgress {
+        name: module.name.clone(),
+        module_id: module.module_id,
+        progress: Progress::default(),
+        units: Vec::with_capacity(module.units.len()),
+    };
+    // Module objects are built in a subdirectory, matching the `dol split` output layout
+    let build_dir = if module.module_id == 0 {
+        args.build_dir.clone()
+    } else {
+        args.build_dir.join(&module.name)
+    };
+    for unit in &module.units {
+        let unit_progress = unit_progress(&build_dir, unit)
+            .with_context(|| format!("While processing unit {}", unit.name))?;
+        result.progress.merge(&unit_progress.progress);
+        result.units.push(unit_progress);
+    }
+    Ok(result)
+}
+
+/// A unit is considered matched if its built object was compiled from source. Assembled objects
+/// are tagged with an ` (asm)` suffix on their file symbol by `elf fixup`.
+fn is_unit_matched(build_dir: &Path, unit: &OutputUnit) -> Result<bool> {
+    let path = build_dir.join(obj_path_for_unit(&unit.name));
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let file = map_file(&path)?;
+    let obj = object::File::parse(file.as_slice())
+        .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+    let is_asm = obj
+        .symbols()
+        .filter(|s| s.kind() == SymbolKind::File)
+        .any(|s| s.name().is_ok_and(|name| name.ends_with(ASM_SUFFIX)));
+    Ok(!is_asm)
+}
+
+fn unit_progress(build_dir: &Path, unit: &OutputUnit) -> Result<UnitProgress> {
+    let matched = is_unit_matched(build_dir, unit)?;
+    let mut progress = Progress::default();
+    progress.code.add(unit.code_size as u64, matched);
+    progress.data.add(unit.data_size as u64, matched);
+    if unit.autogenerated {
+        progress.autogenerated.add(unit.code_size as u64 + unit.data_size as u64, matched);
+    }
+
+    let file = map_file(&unit.object)?;
+    let obj = object::File::parse(file.as_slice())
+        .with_context(|| format!("Failed to parse '{}'", unit.object.display()))?;
+    for section in obj.sections() {
+        if !matches!(
+            section.kind(),
+            SectionKind::Text
+                | SectionKind::Data
+                | SectionKind::ReadOnlyData
+                | SectionKind::ReadOnlyString
+                | SectionKind::UninitializedData
+        ) {
+            continue;
+        }
+        let name = section.name()?;
+        progress.sections.entry(name.to_string()).or_default().add(section.size(), matched);
+    }
+    for symbol in obj.symbols() {
+        if symbol.kind() == SymbolKind::Text && symbol.size() > 0 {
+            progress.functions.add(1, matched);
+        }
+    }
+    Ok(UnitProgress {
+        name: unit.name.clone(),
+        matched,
+        autogenerated: unit.autogenerated,
+        progress,
+    })
+}
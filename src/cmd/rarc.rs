@@ -1,11 +1,13 @@
-use std::{fs, fs::DirBuilder, path::PathBuf};
+use std::{fs, fs::DirBuilder, io::Write, path::PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use argp::FromArgs;
 
 use crate::util::{
-    file::{decompress_if_needed, map_file},
-    rarc::{Node, RarcReader},
+    file::{buf_writer, decompress_if_needed, map_file, read_dir_entries},
+    lz::CompressionMode,
+    rarc::{write_rarc, Node, RarcReader},
+    yaz0,
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -21,6 +23,7 @@ pub struct Args {
 enum SubCommand {
     List(ListArgs),
     Extract(ExtractArgs),
+    Create(CreateArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -44,10 +47,33 @@ pub struct ExtractArgs {
     output: Option<PathBuf>,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates an RARC file from a directory.
+#[argp(subcommand, name = "create")]
+pub struct CreateArgs {
+    #[argp(positional)]
+    /// input directory
+    input: PathBuf,
+    #[argp(positional)]
+    /// output RARC file
+    output: PathBuf,
+    #[argp(option, short = 'n')]
+    /// root directory name (defaults to the input directory name)
+    root_name: Option<String>,
+    #[argp(switch, short = 'c')]
+    /// compress the output with Yaz0
+    compress: bool,
+    #[argp(option, short = 'm')]
+    /// Yaz0 compression mode. (Default: matching)
+    /// Possible values: matching, fast
+    mode: Option<CompressionMode>,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::List(c_args) => list(c_args),
         SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Create(c_args) => create(c_args),
     }
 }
 
@@ -109,3 +135,25 @@ fn extract(args: ExtractArgs) -> Result<()> {
     }
     Ok(())
 }
+
+fn create(args: CreateArgs) -> Result<()> {
+    let root_name = match args.root_name {
+        Some(name) => name,
+        None => args
+            .input
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Invalid input directory '{}'", args.input.display()))?
+            .to_string(),
+    };
+    let entries = read_dir_entries(&args.input)?;
+    let mut data = vec![];
+    write_rarc(&mut data, &root_name, &entries)?;
+    if args.compress {
+        data = yaz0::compress(&data, args.mode.unwrap_or_default());
+    }
+    let mut w = buf_writer(&args.output)?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
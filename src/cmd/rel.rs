@@ -115,7 +115,7 @@ pub fn run(args: Args) -> Result<()> {
     }
 }
 
-fn load_obj(buf: &[u8]) -> Result<File> {
+pub fn load_obj(buf: &[u8]) -> Result<File> {
     let obj = File::parse(buf)?;
     match obj.architecture() {
         Architecture::PowerPc => {}
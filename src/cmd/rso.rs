@@ -1,9 +1,30 @@
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf, time::Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use argp::FromArgs;
+use object::{Object, ObjectSymbol, SymbolScope};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use tracing::{info, info_span};
 
-use crate::util::{file::map_file, rso::process_rso};
+use crate::{
+    analysis::{
+        cfa::AnalyzerState,
+        pass::{AnalysisPass, FindRelCtorsDtors, FindRelRodataData},
+        signatures::{apply_signatures, apply_signatures_post},
+    },
+    cmd::{
+        dol::{is_rso_module, ModuleConfig, ProjectConfig},
+        rel::load_obj,
+    },
+    obj::{ObjRelocKind, ObjSectionKind},
+    util::{
+        config::{is_auto_symbol, read_splits_sections},
+        file::{buf_reader, buf_writer, map_file, process_rsp, read_string, verify_hash},
+        rso::{process_rso, process_rso_header, write_rso, write_sel, RsoWriteInfo},
+        IntoCow, ToCow,
+    },
+};
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// Commands for processing RSO files.
@@ -17,6 +38,7 @@ pub struct Args {
 #[argp(subcommand)]
 enum SubCommand {
     Info(InfoArgs),
+    Make(MakeArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -26,21 +48,270 @@ pub struct InfoArgs {
     #[argp(positional)]
     /// RSO file
     rso_file: PathBuf,
+    #[argp(switch, short = 'r')]
+    /// print relocations
+    relocations: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates RSOs and a SEL from an ELF + PLF(s).
+#[argp(subcommand, name = "make")]
+pub struct MakeArgs {
+    #[argp(positional)]
+    /// input file(s)
+    files: Vec<PathBuf>,
+    #[argp(option, short = 'c')]
+    /// (optional) project configuration file
+    config: Option<PathBuf>,
+    #[argp(switch, short = 'w')]
+    /// disable warnings
+    no_warn: bool,
+    #[argp(switch, short = 'q')]
+    /// only print errors
+    quiet: bool,
 }
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Info(c_args) => info(c_args),
+        SubCommand::Make(c_args) => make(c_args),
     }
 }
 
-fn info(args: InfoArgs) -> Result<()> {
-    let rso = {
-        let file = map_file(args.rso_file)?;
-        let obj = process_rso(&mut file.as_reader())?;
-        #[allow(clippy::let_and_return)]
-        obj
+/// Original RSO header info, used to match the section count, alignment and module name.
+struct RsoInfo {
+    name: String,
+    num_sections: usize,
+    section_align: Option<Vec<u32>>,
+}
+
+fn load_rso_info(module_config: &ModuleConfig) -> Result<RsoInfo> {
+    let file = map_file(&module_config.object)?;
+    if let Some(hash_str) = &module_config.hash {
+        verify_hash(file.as_slice(), hash_str)?;
+    }
+    let mut reader = file.as_reader();
+    let header = process_rso_header(&mut reader)?;
+    let name = if header.name_size > 0 {
+        read_string(&mut reader, header.name_offset as u64, header.name_size as usize)?
+    } else {
+        String::new()
     };
-    println!("Read RSO module {}", rso.name);
+    let section_align = if let Some(splits_path) = &module_config.splits {
+        read_splits_sections(splits_path)?
+            .map(|defs| defs.iter().map(|def| def.align).collect::<Option<Vec<u32>>>())
+            .unwrap_or_default()
+    } else {
+        None
+    };
+    Ok(RsoInfo { name, num_sections: header.num_sections as usize, section_align })
+}
+
+fn make(args: MakeArgs) -> Result<()> {
+    let total = Instant::now();
+
+    // Load existing RSO headers (if specified)
+    let mut existing_headers = FxHashMap::<String, RsoInfo>::default();
+    if let Some(config_path) = &args.config {
+        let config: ProjectConfig = serde_yaml::from_reader(&mut buf_reader(config_path)?)?;
+        for module_config in config.modules.iter().filter(|m| is_rso_module(m)) {
+            let _span = info_span!("module", name = %module_config.name()).entered();
+            let info = load_rso_info(module_config).with_context(|| {
+                format!("While loading RSO '{}'", module_config.object.display())
+            })?;
+            existing_headers.insert(module_config.name().to_string(), info);
+        }
+    }
+
+    let paths = process_rsp(&args.files)?;
+    if !args.quiet {
+        info!("Loading {} modules", paths.len());
+    }
+
+    // Load all modules
+    let files = paths.iter().map(map_file).collect::<Result<Vec<_>>>()?;
+    let modules = files
+        .par_iter()
+        .zip(&paths)
+        .map(|(file, path)| {
+            load_obj(file.as_slice())
+                .map(|o| (o, path.clone()))
+                .with_context(|| format!("Failed to load '{}'", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Create symbol map
+    let start = Instant::now();
+    let mut symbol_map = FxHashMap::<&[u8], usize>::default();
+    for (module_id, (module, path)) in modules.iter().enumerate() {
+        let _span = info_span!("file", path = %path.display()).entered();
+        for symbol in module.symbols() {
+            if symbol.is_definition() && symbol.scope() == SymbolScope::Dynamic {
+                symbol_map.entry(symbol.name_bytes()?).or_insert(module_id);
+            }
+        }
+    }
+
+    // Resolve imports, exporting each imported symbol from its defining module
+    let mut resolved = 0usize;
+    let mut exports = vec![FxHashSet::<&[u8]>::default(); modules.len()];
+    for (module, path) in modules.iter().skip(1) {
+        let _span = info_span!("file", path = %path.display()).entered();
+        for symbol in module.symbols().filter(|s| s.is_undefined()) {
+            let name = symbol.name_bytes()?;
+            if name.is_empty() {
+                continue;
+            }
+            let &module_id = symbol_map.get(name).ok_or_else(|| {
+                anyhow!(
+                    "Failed to find symbol {} in any module (imported by '{}')",
+                    symbol.name().unwrap_or("[invalid]"),
+                    path.display()
+                )
+            })?;
+            exports[module_id].insert(name);
+            resolved += 1;
+        }
+    }
+
+    if !args.quiet {
+        let duration = start.elapsed();
+        info!(
+            "Symbol resolution completed in {}.{:03}s (resolved {} symbols)",
+            duration.as_secs(),
+            duration.subsec_millis(),
+            resolved
+        );
+    }
+
+    // Write SEL and RSOs
+    let start = Instant::now();
+    for ((module_id, (module, path)), exports) in modules.iter().enumerate().zip(&exports) {
+        let _span = info_span!("file", path = %path.display()).entered();
+        if module_id == 0 {
+            let sel_path = path.with_extension("sel");
+            let mut w = buf_writer(&sel_path)?;
+            write_sel(&mut w, module, exports)
+                .with_context(|| format!("Failed to write '{}'", sel_path.display()))?;
+            w.flush()?;
+            continue;
+        }
+
+        let rso_path = path.with_extension("rso");
+        let mut info = RsoWriteInfo {
+            name: rso_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            section_count: None,
+            quiet: args.no_warn,
+            section_align: None,
+        };
+        let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+        if let Some(existing) = existing_headers.get(stem.as_ref()) {
+            info.name = existing.name.clone();
+            info.section_count = Some(existing.num_sections);
+            info.section_align = existing.section_align.clone();
+        }
+        let mut w = buf_writer(&rso_path)?;
+        write_rso(&mut w, &info, module, exports)
+            .with_context(|| format!("Failed to write '{}'", rso_path.display()))?;
+        w.flush()?;
+    }
+
+    if !args.quiet {
+        let duration = start.elapsed();
+        info!("RSOs written in {}.{:03}s", duration.as_secs(), duration.subsec_millis());
+
+        let duration = total.elapsed();
+        info!("Total time: {}.{:03}s", duration.as_secs(), duration.subsec_millis());
+    }
+    Ok(())
+}
+
+fn info(args: InfoArgs) -> Result<()> {
+    let file = map_file(args.rso_file)?;
+    let (header, mut module_obj) = process_rso(&mut file.as_reader(), 0)?;
+
+    let mut state = AnalyzerState::default();
+    state.detect_functions(&module_obj)?;
+    FindRelCtorsDtors::execute(&mut state, &module_obj)?;
+    FindRelRodataData::execute(&mut state, &module_obj)?;
+    state.apply(&mut module_obj)?;
+
+    apply_signatures(&mut module_obj)?;
+    apply_signatures_post(&mut module_obj)?;
+
+    println!("RSO module name: {}", module_obj.name);
+    println!("RSO version: {}", header.version);
+    println!("Original section count: {}", header.num_sections);
+    println!("BSS size: {:#X}", header.bss_size);
+    println!("\nSections:");
+    println!(
+        "{: >10} | {: <10} | {: <10} | {: <10} | {: <10}",
+        "Name", "Type", "Size", "File Off", "Index"
+    );
+    for (_, section) in module_obj.sections.iter() {
+        let kind_str = match section.kind {
+            ObjSectionKind::Code => "code",
+            ObjSectionKind::Data => "data",
+            ObjSectionKind::ReadOnlyData => "rodata",
+            ObjSectionKind::Bss => "bss",
+        };
+        println!(
+            "{: >10} | {: <10} | {: <#10X} | {: <#10X} | {: <10}",
+            section.name, kind_str, section.size, section.file_offset, section.elf_index
+        );
+    }
+    println!("\nDiscovered symbols:");
+    println!("{: >10} | {: <10} | {: <10} | {: <10}", "Section", "Address", "Size", "Name");
+    for (_, symbol) in module_obj.symbols.iter_ordered() {
+        if symbol.name.starts_with('@') || is_auto_symbol(symbol) {
+            continue;
+        }
+        let section_str = if let Some(section) = symbol.section {
+            module_obj.sections[section].name.as_str()
+        } else {
+            "ABS"
+        };
+        let size_str = if symbol.size_known {
+            format!("{:#X}", symbol.size).into_cow()
+        } else if symbol.section.is_none() {
+            "ABS".to_cow()
+        } else {
+            "?".to_cow()
+        };
+        println!(
+            "{: >10} | {: <#10X} | {: <10} | {: <10}",
+            section_str, symbol.address, size_str, symbol.name
+        );
+    }
+    println!("\nImported symbols:");
+    for symbol in module_obj.symbols.iter().filter(|s| s.section.is_none()) {
+        println!("    {}", symbol.demangled_name.as_deref().unwrap_or(&symbol.name));
+    }
+
+    if args.relocations {
+        println!("\nRelocations:");
+        println!("    [Source] section:address RelocType -> [Target] symbol+addend");
+        for (_, section) in module_obj.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                let kind_str = match reloc.kind {
+                    ObjRelocKind::Absolute => "R_PPC_ADDR32",
+                    ObjRelocKind::PpcAddr16Hi => "R_PPC_ADDR16_HI",
+                    ObjRelocKind::PpcAddr16Ha => "R_PPC_ADDR16_HA",
+                    ObjRelocKind::PpcAddr16Lo => "R_PPC_ADDR16_LO",
+                    ObjRelocKind::PpcRel24 => "R_PPC_REL24",
+                    ObjRelocKind::PpcRel14 => "R_PPC_REL14",
+                    ObjRelocKind::PpcEmbSda21 => "R_PPC_EMB_SDA21",
+                };
+                let target = &module_obj.symbols[reloc.target_symbol];
+                println!(
+                    "    {}:{:#010X} {} -> {}+{:#X}",
+                    section.name, address, kind_str, target.name, reloc.addend
+                );
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,127 @@
+use std::{fs, fs::DirBuilder, io::Write, path::PathBuf};
+
+use anyhow::{Context, Result};
+use argp::FromArgs;
+
+use crate::util::{
+    file::{buf_writer, decompress_if_needed, map_file, read_dir_entries},
+    lz::CompressionMode,
+    u8_arc::{write_u8, U8NodeKind, U8Reader},
+    yaz0,
+};
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Commands for processing U8 (arc) files.
+#[argp(subcommand, name = "u8")]
+pub struct Args {
+    #[argp(subcommand)]
+    command: SubCommand,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argp(subcommand)]
+enum SubCommand {
+    List(ListArgs),
+    Extract(ExtractArgs),
+    Create(CreateArgs),
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Views U8 (arc) file information.
+#[argp(subcommand, name = "list")]
+pub struct ListArgs {
+    #[argp(positional)]
+    /// U8 (arc) file
+    file: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Extracts U8 (arc) file contents.
+#[argp(subcommand, name = "extract")]
+pub struct ExtractArgs {
+    #[argp(positional)]
+    /// U8 (arc) file
+    file: PathBuf,
+    #[argp(option, short = 'o')]
+    /// output directory
+    output: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates a U8 (arc) file from a directory.
+#[argp(subcommand, name = "create")]
+pub struct CreateArgs {
+    #[argp(positional)]
+    /// input directory
+    input: PathBuf,
+    #[argp(positional)]
+    /// output U8 (arc) file
+    output: PathBuf,
+    #[argp(switch, short = 'c')]
+    /// compress the output with Yaz0
+    compress: bool,
+    #[argp(option, short = 'm')]
+    /// Yaz0 compression mode. (Default: matching)
+    /// Possible values: matching, fast
+    mode: Option<CompressionMode>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    match args.command {
+        SubCommand::List(c_args) => list(c_args),
+        SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Create(c_args) => create(c_args),
+    }
+}
+
+fn list(args: ListArgs) -> Result<()> {
+    let file = map_file(&args.file)?;
+    let reader = U8Reader::new(&mut file.as_reader())
+        .with_context(|| format!("Failed to process U8 file '{}'", args.file.display()))?;
+    for node in reader.nodes() {
+        if node.kind == U8NodeKind::File {
+            println!("{}: {} bytes, offset {:#X}", node.path.display(), node.size, node.offset);
+        }
+    }
+    Ok(())
+}
+
+fn extract(args: ExtractArgs) -> Result<()> {
+    let file = map_file(&args.file)?;
+    let reader = U8Reader::new(&mut file.as_reader())
+        .with_context(|| format!("Failed to process U8 file '{}'", args.file.display()))?;
+    for node in reader.nodes() {
+        let output_path =
+            args.output.as_ref().map(|p| p.join(&node.path)).unwrap_or_else(|| node.path.clone());
+        match node.kind {
+            U8NodeKind::Directory => {
+                DirBuilder::new().recursive(true).create(&output_path)?;
+            }
+            U8NodeKind::File => {
+                let file_data = decompress_if_needed(
+                    &file.as_slice()
+                        [node.offset as usize..node.offset as usize + node.size as usize],
+                )?;
+                if let Some(parent) = output_path.parent() {
+                    DirBuilder::new().recursive(true).create(parent)?;
+                }
+                fs::write(&output_path, file_data)
+                    .with_context(|| format!("Failed to write file '{}'", output_path.display()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn create(args: CreateArgs) -> Result<()> {
+    let entries = read_dir_entries(&args.input)?;
+    let mut data = vec![];
+    write_u8(&mut data, &entries)?;
+    if args.compress {
+        data = yaz0::compress(&data, args.mode.unwrap_or_default());
+    }
+    let mut w = buf_writer(&args.output)?;
+    w.write_all(&data)?;
+    w.flush()?;
+    Ok(())
+}
@@ -83,6 +83,7 @@ enum SubCommand {
     Alf(cmd::alf::Args),
     Ar(cmd::ar::Args),
     Demangle(cmd::demangle::Args),
+    Disc(cmd::disc::Args),
     Dol(cmd::dol::Args),
     Dwarf(cmd::dwarf::Args),
     Elf(cmd::elf::Args),
@@ -90,10 +91,12 @@ enum SubCommand {
     Map(cmd::map::Args),
     MetroidBuildInfo(cmd::metroidbuildinfo::Args),
     Nlzss(cmd::nlzss::Args),
+    Progress(cmd::progress::Args),
     Rarc(cmd::rarc::Args),
     Rel(cmd::rel::Args),
     Rso(cmd::rso::Args),
     Shasum(cmd::shasum::Args),
+    U8(cmd::u8_arc::Args),
     Yaz0(cmd::yaz0::Args),
 }
 
@@ -155,6 +158,7 @@ fn main() {
         SubCommand::Alf(c_args) => cmd::alf::run(c_args),
         SubCommand::Ar(c_args) => cmd::ar::run(c_args),
         SubCommand::Demangle(c_args) => cmd::demangle::run(c_args),
+        SubCommand::Disc(c_args) => cmd::disc::run(c_args),
         SubCommand::Dol(c_args) => cmd::dol::run(c_args),
         SubCommand::Dwarf(c_args) => cmd::dwarf::run(c_args),
         SubCommand::Elf(c_args) => cmd::elf::run(c_args),
@@ -162,10 +166,12 @@ fn main() {
         SubCommand::Map(c_args) => cmd::map::run(c_args),
         SubCommand::MetroidBuildInfo(c_args) => cmd::metroidbuildinfo::run(c_args),
         SubCommand::Nlzss(c_args) => cmd::nlzss::run(c_args),
+        SubCommand::Progress(c_args) => cmd::progress::run(c_args),
         SubCommand::Rarc(c_args) => cmd::rarc::run(c_args),
         SubCommand::Rel(c_args) => cmd::rel::run(c_args),
         SubCommand::Rso(c_args) => cmd::rso::run(c_args),
         SubCommand::Shasum(c_args) => cmd::shasum::run(c_args),
+        SubCommand::U8(c_args) => cmd::u8_arc::run(c_args),
         SubCommand::Yaz0(c_args) => cmd::yaz0::run(c_args),
     });
     if let Err(e) = result {
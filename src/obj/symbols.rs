@@ -156,6 +156,8 @@ pub enum ObjDataKind {
     Byte4,
     Byte8,
     Float,
+    /// Pair of floats, as loaded by paired-single instructions
+    Float2,
     Double,
     String,
     String16,
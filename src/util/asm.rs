@@ -680,7 +680,7 @@ where W: Write + ?Sized {
     }
     let chunk_size = match data_kind {
         ObjDataKind::Byte2 => 2,
-        ObjDataKind::Unknown | ObjDataKind::Byte4 | ObjDataKind::Float => 4,
+        ObjDataKind::Unknown | ObjDataKind::Byte4 | ObjDataKind::Float | ObjDataKind::Float2 => 4,
         ObjDataKind::Byte | ObjDataKind::Byte8 | ObjDataKind::Double => 8,
         ObjDataKind::String
         | ObjDataKind::String16
@@ -706,7 +706,7 @@ where W: Write + ?Sized {
                     let data = u64::from_be_bytes(chunk.try_into().unwrap());
                     writeln!(w, "\t.8byte {data:#018X}")?;
                 }
-                4 if data_kind == ObjDataKind::Float => {
+                4 if matches!(data_kind, ObjDataKind::Float | ObjDataKind::Float2) => {
                     let data = f32::from_be_bytes(chunk.try_into().unwrap());
                     if data.is_nan() {
                         let int_data = u32::from_be_bytes(chunk.try_into().unwrap());
@@ -304,6 +304,7 @@ fn symbol_data_kind_to_str(kind: ObjDataKind) -> Option<&'static str> {
         ObjDataKind::Byte4 => Some("4byte"),
         ObjDataKind::Byte8 => Some("8byte"),
         ObjDataKind::Float => Some("float"),
+        ObjDataKind::Float2 => Some("float2"),
         ObjDataKind::Double => Some("double"),
         ObjDataKind::String => Some("string"),
         ObjDataKind::String16 => Some("wstring"),
@@ -355,6 +356,7 @@ fn symbol_data_kind_from_str(s: &str) -> Option<ObjDataKind> {
         "4byte" => Some(ObjDataKind::Byte4),
         "8byte" => Some(ObjDataKind::Byte8),
         "float" => Some(ObjDataKind::Float),
+        "float2" => Some(ObjDataKind::Float2),
         "double" => Some(ObjDataKind::Double),
         "string" => Some(ObjDataKind::String),
         "wstring" => Some(ObjDataKind::String16),
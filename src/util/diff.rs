@@ -0,0 +1,504 @@
+use std::{
+    cmp::{max, min},
+    collections::BTreeMap,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    analysis::cfa::SectionAddress,
+    obj::{ObjDataKind, ObjInfo, ObjRelocKind, ObjSection, ObjSymbol, ObjSymbolKind},
+};
+
+/// Classification of a difference between an original symbol and the linked output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    /// The symbol wasn't found in the linked output.
+    Missing,
+    /// The symbol was found in the linked output, but at a different address.
+    Moved,
+    /// The symbol was found, but its size differs.
+    SizeMismatch,
+    /// The symbol's data differs.
+    DataMismatch,
+    /// The symbol's data only differs in relocation targets.
+    RelocationMismatch,
+    /// The symbol's data couldn't be read or compared.
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolDiff {
+    pub name: String,
+    pub kind: DiffKind,
+    pub symbol_kind: ObjSymbolKind,
+    pub section: String,
+    pub address: u32,
+    pub size: u32,
+    /// Address of the symbol in the linked output, if found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_address: Option<u32>,
+    /// Size of the symbol in the linked output, if found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_size: Option<u32>,
+    /// Offset of the first differing word, for data mismatches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    /// Decoded words around the first difference, for data mismatches.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<DiffWord>,
+    /// Reason the symbol couldn't be compared, for errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UnitDiff {
+    /// Translation unit name, or `None` for symbols outside of any split.
+    pub name: Option<String>,
+    /// Number of symbols checked.
+    pub total: usize,
+    /// Number of symbols matching the original.
+    pub matching: usize,
+    pub diffs: Vec<SymbolDiff>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffReport {
+    /// Number of symbols checked.
+    pub total: usize,
+    /// Number of symbols matching the original.
+    pub matching: usize,
+    /// Number of differences by kind.
+    pub counts: BTreeMap<DiffKind, usize>,
+    /// Per-unit results, in link order.
+    pub units: Vec<UnitDiff>,
+}
+
+impl DiffReport {
+    pub fn is_match(&self) -> bool { self.total == self.matching }
+}
+
+/// Locates the linked symbol corresponding to an original symbol, preferring a name match at the
+/// same address, then a symbol of the same kind at the same address.
+fn find_linked_symbol<'a>(
+    linked_obj: &'a ObjInfo,
+    linked_section_index: usize,
+    orig_sym: &ObjSymbol,
+) -> Option<&'a ObjSymbol> {
+    linked_obj
+        .symbols
+        .at_section_address(linked_section_index, orig_sym.address as u32)
+        .find(|(_, sym)| sym.name == orig_sym.name)
+        .or_else(|| {
+            linked_obj
+                .symbols
+                .at_section_address(linked_section_index, orig_sym.address as u32)
+                .find(|(_, sym)| sym.kind == orig_sym.kind)
+        })
+        .map(|(_, sym)| sym)
+}
+
+/// A word in the region around the first difference, decoded for both objects.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffWord {
+    /// Offset from the start of the symbol.
+    pub offset: u32,
+    pub original: String,
+    pub linked: String,
+}
+
+/// Number of words shown before and after the first difference.
+const CONTEXT_WORDS: u32 = 4;
+
+/// Bits of an instruction or data word covered by a relocation.
+fn reloc_mask(kind: ObjRelocKind) -> u32 {
+    match kind {
+        ObjRelocKind::Absolute => !0,
+        ObjRelocKind::PpcAddr16Hi | ObjRelocKind::PpcAddr16Ha | ObjRelocKind::PpcAddr16Lo => 0xFFFF,
+        ObjRelocKind::PpcRel24 => 0x3FFFFFC,
+        ObjRelocKind::PpcRel14 => 0xFFFC,
+        ObjRelocKind::PpcEmbSda21 => 0x1FFFFF,
+    }
+}
+
+/// Resolves the target of a relocated word as symbol + addend. Relocations are used if present,
+/// otherwise pointers and branch destinations are resolved from the raw value.
+fn word_target<'a>(
+    obj: &'a ObjInfo,
+    section: &ObjSection,
+    address: u32,
+    value: u32,
+    kind: ObjRelocKind,
+) -> Result<Option<(&'a str, i64)>> {
+    if let Some(reloc) = section.relocations.at(address) {
+        return Ok(Some((obj.symbols[reloc.target_symbol].name.as_str(), reloc.addend)));
+    }
+    let target = match kind {
+        ObjRelocKind::Absolute => value,
+        ObjRelocKind::PpcRel24 => {
+            address.wrapping_add((((value & 0x3FFFFFC) << 6) as i32 >> 6) as u32)
+        }
+        ObjRelocKind::PpcRel14 => address.wrapping_add((value & 0xFFFC) as i16 as i32 as u32),
+        // Split address pairs can't be resolved from a single word
+        _ => return Ok(None),
+    };
+    let Ok((section_index, _)) = obj.sections.at_address(target) else {
+        return Ok(None);
+    };
+    Ok(obj
+        .symbols
+        .for_relocation(SectionAddress::new(section_index, target), kind)?
+        .map(|(_, symbol)| (symbol.name.as_str(), target as i64 - symbol.address as i64)))
+}
+
+/// Linked names may have a suffix added when globalized.
+fn targets_match(orig: (&str, i64), linked: (&str, i64)) -> bool {
+    linked.0.starts_with(orig.0) && orig.1 == linked.1
+}
+
+fn format_word(value: u32, target: Option<(&str, i64)>, data_kind: ObjDataKind) -> String {
+    match target {
+        Some((name, 0)) => format!("{:08X} -> {}", value, name),
+        Some((name, addend)) if addend < 0 => format!("{:08X} -> {}-{:#X}", value, name, -addend),
+        Some((name, addend)) => format!("{:08X} -> {}+{:#X}", value, name, addend),
+        None if matches!(data_kind, ObjDataKind::Float | ObjDataKind::Float2) => {
+            format!("{:08X} ({:?})", value, f32::from_bits(value))
+        }
+        None => format!("{:08X}", value),
+    }
+}
+
+struct WordComparison<'a> {
+    orig_value: u32,
+    linked_value: u32,
+    orig_target: Option<(&'a str, i64)>,
+    linked_target: Option<(&'a str, i64)>,
+    data_mismatch: bool,
+    reloc_mismatch: bool,
+}
+
+struct DataComparison<'a> {
+    obj: &'a ObjInfo,
+    linked_obj: &'a ObjInfo,
+    orig_section: &'a ObjSection,
+    linked_section: &'a ObjSection,
+    orig_sym: &'a ObjSymbol,
+    linked_sym: &'a ObjSymbol,
+    orig_data: &'a [u8],
+    linked_data: &'a [u8],
+}
+
+impl<'a> DataComparison<'a> {
+    /// Compares the word at the given offset. Relocated fields are compared by target symbol
+    /// and addend, and the remaining bits are compared directly.
+    fn compare_word(&self, offset: u32) -> Result<WordComparison<'a>> {
+        let read = |data: &[u8]| {
+            let mut buf = [0u8; 4];
+            let start = offset as usize;
+            let end = min(start + 4, data.len());
+            buf[..end - start].copy_from_slice(&data[start..end]);
+            u32::from_be_bytes(buf)
+        };
+        let orig_value = read(self.orig_data);
+        let linked_value = read(self.linked_data);
+        let orig_address = self.orig_sym.address as u32 + offset;
+        let linked_address = self.linked_sym.address as u32 + offset;
+        // Relocations are only tracked for aligned words
+        let kind = if orig_address & 3 == 0 && linked_address & 3 == 0 {
+            self.orig_section
+                .relocations
+                .at(orig_address)
+                .or_else(|| self.linked_section.relocations.at(linked_address))
+                .map(|reloc| reloc.kind)
+        } else {
+            None
+        };
+        let Some(kind) = kind else {
+            return Ok(WordComparison {
+                orig_value,
+                linked_value,
+                orig_target: None,
+                linked_target: None,
+                data_mismatch: orig_value != linked_value,
+                reloc_mismatch: false,
+            });
+        };
+        let mask = reloc_mask(kind);
+        let orig_target = word_target(self.obj, self.orig_section, orig_address, orig_value, kind)?;
+        let linked_target =
+            word_target(self.linked_obj, self.linked_section, linked_address, linked_value, kind)?;
+        let reloc_mismatch = match (orig_target, linked_target) {
+            (Some(orig), Some(linked)) => !targets_match(orig, linked),
+            _ => orig_value & mask != linked_value & mask,
+        };
+        Ok(WordComparison {
+            orig_value,
+            linked_value,
+            orig_target,
+            linked_target,
+            data_mismatch: orig_value & !mask != linked_value & !mask,
+            reloc_mismatch,
+        })
+    }
+
+    /// Returns the kind of mismatch, the offset of the first differing word, and a decoded view
+    /// of the surrounding words. Returns `None` if the data matches.
+    fn compare(&self) -> Result<Option<(DiffKind, u32, Vec<DiffWord>)>> {
+        if self.orig_data == self.linked_data {
+            return Ok(None);
+        }
+        let len = max(self.orig_data.len(), self.linked_data.len()) as u32;
+        if self.orig_data.len() != self.linked_data.len() {
+            let first_diff = self
+                .orig_data
+                .iter()
+                .zip(self.linked_data)
+                .position(|(a, b)| a != b)
+                .unwrap_or(min(self.orig_data.len(), self.linked_data.len()));
+            let offset = first_diff as u32 & !3;
+            return Ok(Some((DiffKind::DataMismatch, offset, self.context(offset, len)?)));
+        }
+        let mut first_diff = None;
+        let mut data_mismatch = false;
+        for offset in (0..len).step_by(4) {
+            let word = self.compare_word(offset)?;
+            if word.data_mismatch || word.reloc_mismatch {
+                first_diff.get_or_insert(offset);
+            }
+            data_mismatch |= word.data_mismatch;
+        }
+        let Some(offset) = first_diff else {
+            // Only differences are addresses of matching targets
+            return Ok(None);
+        };
+        let kind =
+            if data_mismatch { DiffKind::DataMismatch } else { DiffKind::RelocationMismatch };
+        Ok(Some((kind, offset, self.context(offset, len)?)))
+    }
+
+    fn context(&self, offset: u32, len: u32) -> Result<Vec<DiffWord>> {
+        let start = offset.saturating_sub(CONTEXT_WORDS * 4);
+        let end = min(offset + (CONTEXT_WORDS + 1) * 4, len);
+        let min_len = min(self.orig_data.len(), self.linked_data.len()) as u32;
+        let mut words = Vec::new();
+        for offset in (start..end).step_by(4) {
+            let word = if offset < min_len { Some(self.compare_word(offset)?) } else { None };
+            let format_side = |data: &[u8], value: Option<(u32, Option<(&str, i64)>)>| match value {
+                Some((value, target)) => format_word(value, target, self.orig_sym.data_kind),
+                None if (offset as usize) < data.len() => {
+                    hex::encode_upper(&data[offset as usize..min(offset as usize + 4, data.len())])
+                }
+                None => String::new(),
+            };
+            words.push(DiffWord {
+                offset,
+                original: format_side(
+                    self.orig_data,
+                    word.as_ref().map(|w| (w.orig_value, w.orig_target)),
+                ),
+                linked: format_side(
+                    self.linked_data,
+                    word.as_ref().map(|w| (w.linked_value, w.linked_target)),
+                ),
+            });
+        }
+        Ok(words)
+    }
+}
+
+/// Walks every symbol in the original object and classifies its differences against the linked
+/// object. Results are grouped by translation unit, using the original object's splits.
+pub fn diff_objs(obj: &ObjInfo, linked_obj: &ObjInfo) -> Result<DiffReport> {
+    let mut units = BTreeMap::<Option<&str>, UnitDiff>::new();
+    let common_bss = obj.sections.common_bss_start();
+    for orig_sym in obj.symbols.iter().filter(|s| {
+        !matches!(s.kind, ObjSymbolKind::Unknown | ObjSymbolKind::Section) && !s.flags.is_stripped()
+    }) {
+        let Some(orig_section_index) = orig_sym.section else { continue };
+        let orig_section = &obj.sections[orig_section_index];
+        let unit_name = orig_section
+            .splits
+            .for_address(orig_sym.address as u32)
+            .map(|(_, split)| split.unit.as_str());
+        let unit = units.entry(unit_name).or_default();
+        unit.total += 1;
+
+        let mut diff = SymbolDiff {
+            name: orig_sym.name.clone(),
+            kind: DiffKind::Missing,
+            symbol_kind: orig_sym.kind,
+            section: orig_section.name.clone(),
+            address: orig_sym.address as u32,
+            size: orig_sym.size as u32,
+            linked_address: None,
+            linked_size: None,
+            offset: None,
+            context: vec![],
+            error: None,
+        };
+        let linked = linked_obj.sections.at_address(orig_sym.address as u32).ok().and_then(
+            |(idx, section)| {
+                find_linked_symbol(linked_obj, idx, orig_sym).map(|sym| (section, sym))
+            },
+        );
+        let orig_data = match orig_section.symbol_data(orig_sym) {
+            Ok(data) => data,
+            Err(e) => {
+                diff.kind = DiffKind::Error;
+                diff.error = Some(format!("{:#}", e));
+                unit.diffs.push(diff);
+                continue;
+            }
+        };
+        let (linked_section, linked_sym) = match linked {
+            Some((section, sym)) if sym.name.starts_with(&orig_sym.name) => (section, sym),
+            Some((section, sym))
+                if sym.kind == orig_sym.kind
+                    && sym.size == orig_sym.size
+                    && section.symbol_data(sym).is_ok_and(|data| data == orig_data) =>
+            {
+                // Fuzzy match
+                (section, sym)
+            }
+            _ => {
+                if let Some((_, moved)) = linked_obj.symbols.for_name(&orig_sym.name).next() {
+                    diff.kind = DiffKind::Moved;
+                    diff.linked_address = Some(moved.address as u32);
+                    diff.linked_size = Some(moved.size as u32);
+                }
+                unit.diffs.push(diff);
+                continue;
+            }
+        };
+        diff.linked_address = Some(linked_sym.address as u32);
+        diff.linked_size = Some(linked_sym.size as u32);
+
+        if linked_sym.size != orig_sym.size {
+            // TODO validate common symbol sizes
+            // (need to account for inflation bug)
+            if !matches!(common_bss, Some((idx, addr)) if
+                orig_section_index == idx && orig_sym.address as u32 >= addr)
+            {
+                diff.kind = DiffKind::SizeMismatch;
+                unit.diffs.push(diff);
+                continue;
+            }
+        } else if orig_sym.size > 0 {
+            let result = linked_section.symbol_data(linked_sym).and_then(|linked_data| {
+                DataComparison {
+                    obj,
+                    linked_obj,
+                    orig_section,
+                    linked_section,
+                    orig_sym,
+                    linked_sym,
+                    orig_data,
+                    linked_data,
+                }
+                .compare()
+            });
+            match result {
+                Ok(None) => {}
+                Ok(Some((kind, offset, context))) => {
+                    diff.kind = kind;
+                    diff.offset = Some(offset);
+                    diff.context = context;
+                    unit.diffs.push(diff);
+                    continue;
+                }
+                Err(e) => {
+                    diff.kind = DiffKind::Error;
+                    diff.error = Some(format!("{:#}", e));
+                    unit.diffs.push(diff);
+                    continue;
+                }
+            }
+        }
+        unit.matching += 1;
+    }
+
+    // Order units by link order, followed by symbols outside of any split
+    let mut report = DiffReport::default();
+    let mut ordered = Vec::with_capacity(units.len());
+    for link_unit in &obj.link_order {
+        if let Some(mut unit) = units.remove(&Some(link_unit.name.as_str())) {
+            unit.name = Some(link_unit.name.clone());
+            ordered.push(unit);
+        }
+    }
+    for (name, mut unit) in units {
+        unit.name = name.map(|n| n.to_string());
+        ordered.push(unit);
+    }
+    for unit in &ordered {
+        report.total += unit.total;
+        report.matching += unit.matching;
+        for diff in &unit.diffs {
+            *report.counts.entry(diff.kind).or_default() += 1;
+        }
+    }
+    report.units = ordered;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjKind, ObjSectionKind};
+
+    fn test_obj(data: Vec<u8>, symbols: &[(&str, u64, u64)]) -> ObjInfo {
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![ObjSection {
+                name: ".data".to_string(),
+                kind: ObjSectionKind::Data,
+                address: 0x80000000,
+                size: data.len() as u64,
+                data,
+                align: 4,
+                elf_index: 0,
+                relocations: Default::default(),
+                original_address: 0,
+                file_offset: 0,
+                section_known: true,
+                splits: Default::default(),
+            }],
+        );
+        for &(name, address, size) in symbols {
+            obj.add_symbol(
+                ObjSymbol {
+                    name: name.to_string(),
+                    address,
+                    section: Some(0),
+                    size,
+                    size_known: true,
+                    kind: ObjSymbolKind::Object,
+                    ..Default::default()
+                },
+                false,
+            )
+            .unwrap();
+        }
+        obj
+    }
+
+    #[test]
+    fn test_diff_unreadable_symbol() {
+        let symbols = [("bad", 0x80000000, 0x10), ("good", 0x80000004, 4)];
+        let obj = test_obj(vec![0, 0, 0, 0, 1, 2, 3, 4], &symbols);
+        let linked_obj = test_obj(vec![0, 0, 0, 0, 1, 2, 3, 4], &symbols);
+        let report = diff_objs(&obj, &linked_obj).unwrap();
+        assert_eq!(report.total, 2);
+        assert_eq!(report.matching, 1);
+        assert_eq!(report.counts.get(&DiffKind::Error), Some(&1));
+        let diff = &report.units[0].diffs[0];
+        assert_eq!(diff.name, "bad");
+        assert!(diff.error.is_some());
+    }
+}
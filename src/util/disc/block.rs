@@ -0,0 +1,137 @@
+use std::{
+    cmp::min,
+    io,
+    io::{Read, Seek, SeekFrom},
+};
+
+use anyhow::Result;
+
+use crate::util::disc::{ciso::CisoReader, gcz::GczReader, wia::WiaReader};
+
+/// A decompressed region of a disc image.
+#[derive(Default)]
+pub struct Block {
+    /// Offset of the region in the disc image.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl Block {
+    fn contains(&self, offset: u64) -> bool {
+        offset >= self.offset && offset < self.offset + self.data.len() as u64
+    }
+}
+
+/// A compressed disc image container, decompressed one block at a time.
+pub trait BlockReader {
+    /// Size of the decompressed disc image.
+    fn disc_size(&self) -> u64;
+
+    /// Decompresses the block containing `offset` into `block`.
+    /// Blocks may vary in size, but must be non-empty.
+    fn read_block(&mut self, offset: u64, block: &mut Block) -> io::Result<()>;
+}
+
+/// Supported disc image container formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ContainerFormat {
+    Ciso,
+    Gcz,
+    Wia,
+    Rvz,
+}
+
+impl ContainerFormat {
+    /// Detects the container format from the first four bytes of a file.
+    pub fn detect(magic: &[u8]) -> Option<Self> {
+        match magic.get(0..4)? {
+            b"CISO" => Some(Self::Ciso),
+            [0x01, 0xC0, 0x0B, 0xB1] => Some(Self::Gcz),
+            b"WIA\x01" => Some(Self::Wia),
+            b"RVZ\x01" => Some(Self::Rvz),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ciso => "CISO",
+            Self::Gcz => "GCZ",
+            Self::Wia => "WIA",
+            Self::Rvz => "RVZ",
+        }
+    }
+}
+
+/// Reads a decompressed disc image from a container, holding one block in memory at a time.
+pub struct BlockStream<'a> {
+    inner: Box<dyn BlockReader + 'a>,
+    block: Block,
+    pos: u64,
+}
+
+impl<'a> BlockStream<'a> {
+    pub fn new(inner: Box<dyn BlockReader + 'a>) -> Self {
+        Self { inner, block: Block::default(), pos: 0 }
+    }
+
+    /// Opens a disc image container, if the data is in a supported format.
+    pub fn open<R>(mut reader: R) -> Result<Option<(ContainerFormat, Self)>>
+    where R: Read + Seek + 'a {
+        let mut magic = [0u8; 4];
+        reader.seek(SeekFrom::Start(0))?;
+        if reader.read_exact(&mut magic).is_err() {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(0))?;
+        let Some(format) = ContainerFormat::detect(&magic) else {
+            return Ok(None);
+        };
+        let inner: Box<dyn BlockReader + 'a> = match format {
+            ContainerFormat::Ciso => Box::new(CisoReader::new(reader)?),
+            ContainerFormat::Gcz => Box::new(GczReader::new(reader)?),
+            ContainerFormat::Wia | ContainerFormat::Rvz => Box::new(WiaReader::new(reader)?),
+        };
+        Ok(Some((format, Self::new(inner))))
+    }
+
+    /// Size of the decompressed disc image.
+    pub fn len(&self) -> u64 { self.inner.disc_size() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+impl Read for BlockStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.block.contains(self.pos) {
+            self.inner.read_block(self.pos, &mut self.block)?;
+            if !self.block.contains(self.pos) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Block does not contain offset {:#X}", self.pos),
+                ));
+            }
+        }
+        let offset = (self.pos - self.block.offset) as usize;
+        let len = min(buf.len(), self.block.data.len() - offset);
+        buf[..len].copy_from_slice(&self.block.data[offset..offset + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for BlockStream<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(offset) => self.len().saturating_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.saturating_add_signed(offset),
+        };
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> { Ok(self.pos) }
+}
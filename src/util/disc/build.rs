@@ -0,0 +1,470 @@
+use std::{
+    cmp::min,
+    collections::HashMap,
+    fs,
+    fs::File,
+    io,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::util::{
+    align_up,
+    disc::{
+        lfg::LaggedFibonacci, read_fst, DiscHeader, DiscNodeKind, APPLOADER_OFFSET, BI2_SIZE,
+        BOOT_SIZE,
+    },
+    reader::{Endian, FromReader},
+};
+
+/// Size of a standard GameCube disc image.
+pub const GCN_DISC_SIZE: u64 = 0x57058000;
+/// Alignment used when relocating `main.dol` or `fst.bin`.
+const SYS_ALIGN: u64 = 0x100;
+/// Maximum (and default) file data alignment.
+const MAX_FILE_ALIGN: u64 = 0x8000;
+
+/// A file or directory in the generated FST.
+struct FstEntry {
+    name: String,
+    kind: FstEntryKind,
+}
+
+enum FstEntryKind {
+    File {
+        source: PathBuf,
+        /// Path relative to `files`, used to look up the original layout.
+        path: String,
+        size: u64,
+        offset: u64,
+    },
+    Directory {
+        parent: u32,
+        next: u32,
+    },
+}
+
+/// Placement of a file in the original image.
+struct OriginalFile {
+    index: usize,
+    offset: u64,
+}
+
+/// A node in the input directory tree.
+struct TreeNode {
+    name: String,
+    source: PathBuf,
+    path: String,
+    children: Option<Vec<TreeNode>>,
+}
+
+fn path_key(path: &str) -> String { path.to_ascii_lowercase() }
+
+fn read_tree(
+    dir: &Path,
+    prefix: &str,
+    original: &HashMap<String, OriginalFile>,
+) -> Result<Vec<TreeNode>> {
+    let mut nodes = vec![];
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory '{}'", dir.display()))?
+    {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            anyhow::anyhow!("File name is not valid UTF-8: '{}'", name.to_string_lossy())
+        })?;
+        ensure!(name.is_ascii(), "File name is not ASCII: '{}'", name);
+        let path = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+        let source = entry.path();
+        let children = if entry.file_type()?.is_dir() {
+            Some(read_tree(&source, &path, original)?)
+        } else {
+            None
+        };
+        nodes.push(TreeNode { name, source, path, children });
+    }
+    // Preserve the original order, followed by new entries sorted by name
+    nodes.sort_by_cached_key(|n| {
+        let index = original.get(&path_key(&n.path)).map_or(usize::MAX, |f| f.index);
+        (index, n.name.to_ascii_lowercase())
+    });
+    Ok(nodes)
+}
+
+fn flatten_tree(nodes: Vec<TreeNode>, parent: u32, entries: &mut Vec<FstEntry>) -> Result<()> {
+    for node in nodes {
+        let index = entries.len();
+        match node.children {
+            Some(children) => {
+                entries.push(FstEntry {
+                    name: node.name,
+                    kind: FstEntryKind::Directory { parent, next: 0 },
+                });
+                // Entry indices include the root
+                flatten_tree(children, index as u32 + 1, entries)?;
+                let next = entries.len() as u32;
+                if let FstEntryKind::Directory { next: n, .. } = &mut entries[index].kind {
+                    *n = next;
+                }
+            }
+            None => {
+                let size = fs::metadata(&node.source)
+                    .with_context(|| format!("Failed to stat '{}'", node.source.display()))?
+                    .len();
+                ensure!(
+                    size <= u32::MAX as u64,
+                    "File '{}' is too large for a disc image",
+                    node.source.display()
+                );
+                entries.push(FstEntry {
+                    name: node.name,
+                    kind: FstEntryKind::File {
+                        source: node.source,
+                        path: node.path,
+                        size,
+                        offset: 0,
+                    },
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_fst(entries: &[FstEntry], size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size);
+    let mut names = vec![0u8];
+    // Root directory
+    out.extend_from_slice(&0x01000000u32.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u32 + 1).to_be_bytes());
+    for entry in entries {
+        let name_offset = names.len() as u32;
+        names.extend_from_slice(entry.name.as_bytes());
+        names.push(0);
+        let (flags, a, b) = match &entry.kind {
+            FstEntryKind::File { offset, size, .. } => (0u32, *offset as u32, *size as u32),
+            // Entry indices include the root
+            FstEntryKind::Directory { parent, next } => (0x01000000, *parent, *next + 1),
+        };
+        out.extend_from_slice(&(flags | name_offset).to_be_bytes());
+        out.extend_from_slice(&a.to_be_bytes());
+        out.extend_from_slice(&b.to_be_bytes());
+    }
+    out.extend_from_slice(&names);
+    out.resize(size.max(out.len()), 0);
+    out
+}
+
+fn fst_size(entries: &[FstEntry]) -> usize {
+    (entries.len() + 1) * 12 + 1 + entries.iter().map(|e| e.name.len() + 1).sum::<usize>()
+}
+
+/// Aligns a disc offset, which must fit in 32 bits.
+fn align_offset(offset: u64, align: u64) -> Result<u64> {
+    let offset = u32::try_from(offset).ok().context("Disc image is too large")?;
+    Ok(align_up(offset, align as u32) as u64)
+}
+
+/// Places an item at its original offset if it's past `cursor`, otherwise at the next
+/// aligned offset. Returns the offset, and advances `cursor` past the item.
+fn place(original: Option<u64>, size: u64, align: u64, cursor: &mut u64) -> Result<u64> {
+    let offset = match original {
+        Some(offset) if offset >= *cursor => offset,
+        _ => align_offset(*cursor, align)?,
+    };
+    *cursor = offset + size;
+    Ok(offset)
+}
+
+enum Source {
+    Data(Vec<u8>),
+    File(PathBuf),
+}
+
+struct Region {
+    offset: u64,
+    size: u64,
+    source: Source,
+}
+
+fn read_sys_file(sys_dir: &Path, name: &str) -> Result<Vec<u8>> {
+    let path = sys_dir.join(name);
+    fs::read(&path).with_context(|| format!("Failed to read '{}'", path.display()))
+}
+
+/// Builds a GameCube disc image from a directory laid out as by `disc extract`, with system
+/// files in `sys` and the file system in `files`.
+///
+/// If `sys/fst.bin` is present, the original file order and offsets are preserved where the
+/// files still fit, so an unmodified directory produces the original image. Padding after the
+/// FST is filled with the junk data found on retail discs, unless `junk` is false.
+/// Returns the size of the image.
+pub fn build_gcm<W>(dir: &Path, w: &mut W, junk: bool) -> Result<u64>
+where W: Write + ?Sized {
+    let sys_dir = dir.join("sys");
+    let mut boot = read_sys_file(&sys_dir, "boot.bin")?;
+    ensure!(boot.len() as u64 == BOOT_SIZE, "boot.bin must be {:#X} bytes", BOOT_SIZE);
+    let header = DiscHeader::from_reader(&mut Cursor::new(&boot), Endian::Big)?;
+    if header.is_wii() {
+        bail!("Building Wii disc images is not supported");
+    }
+    ensure!(header.is_gamecube(), "Invalid disc magic in boot.bin");
+    let bi2 = read_sys_file(&sys_dir, "bi2.bin")?;
+    ensure!(bi2.len() as u64 == BI2_SIZE, "bi2.bin must be {:#X} bytes", BI2_SIZE);
+    let apploader = read_sys_file(&sys_dir, "apploader.img")?;
+    let dol_path = sys_dir.join("main.dol");
+    let dol_size = fs::metadata(&dol_path)
+        .with_context(|| format!("Failed to stat '{}'", dol_path.display()))?
+        .len();
+
+    // The original FST determines the order and placement of existing files
+    let fst_path = sys_dir.join("fst.bin");
+    let mut original = HashMap::new();
+    let mut original_fst_size = 0;
+    let mut file_align = MAX_FILE_ALIGN;
+    if fst_path.is_file() {
+        let fst = fs::read(&fst_path)
+            .with_context(|| format!("Failed to read '{}'", fst_path.display()))?;
+        original_fst_size = fst.len();
+        let nodes = read_fst(&fst, 0, Path::new(""))
+            .with_context(|| format!("Failed to parse '{}'", fst_path.display()))?;
+        for (index, node) in nodes.into_iter().enumerate() {
+            let path = node.path.to_string_lossy().replace('\\', "/");
+            if node.kind == DiscNodeKind::File && node.size > 0 {
+                file_align = min(file_align, 1 << node.offset.trailing_zeros().min(15));
+            }
+            original.insert(path_key(&path), OriginalFile { index, offset: node.offset });
+        }
+    }
+
+    let files_dir = dir.join("files");
+    let tree = if files_dir.is_dir() { read_tree(&files_dir, "", &original)? } else { vec![] };
+    let mut entries = vec![];
+    flatten_tree(tree, 0, &mut entries)?;
+    let fst_size = fst_size(&entries).max(original_fst_size) as u64;
+
+    // System files
+    let mut cursor = APPLOADER_OFFSET + apploader.len() as u64;
+    let (dol_offset, fst_offset) = if header.fst_offset < header.dol_offset {
+        let fst_offset = place(Some(header.fst_offset as u64), fst_size, SYS_ALIGN, &mut cursor)?;
+        (place(Some(header.dol_offset as u64), dol_size, SYS_ALIGN, &mut cursor)?, fst_offset)
+    } else {
+        let dol_offset = place(Some(header.dol_offset as u64), dol_size, SYS_ALIGN, &mut cursor)?;
+        (dol_offset, place(Some(header.fst_offset as u64), fst_size, SYS_ALIGN, &mut cursor)?)
+    };
+    let junk_start = cursor;
+
+    // File data, in original offset order followed by new files
+    let mut order = (0..entries.len())
+        .filter_map(|i| match &entries[i].kind {
+            FstEntryKind::File { path, .. } => {
+                Some((original.get(&path_key(path)).map(|f| f.offset), i))
+            }
+            FstEntryKind::Directory { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    order.sort_by_key(|&(offset, i)| (offset.unwrap_or(u64::MAX), i));
+    for (original_offset, i) in order {
+        let FstEntryKind::File { size, offset, .. } = &mut entries[i].kind else { unreachable!() };
+        *offset = if *size == 0 {
+            // Empty files don't occupy space, so keep their original offset
+            match original_offset {
+                Some(offset) => offset,
+                None => align_offset(cursor, file_align)?,
+            }
+        } else {
+            place(original_offset, *size, file_align, &mut cursor)?
+        };
+        ensure!(*offset <= u32::MAX as u64, "Disc image is too large");
+    }
+    ensure!(
+        fst_offset <= u32::MAX as u64 && dol_offset <= u32::MAX as u64,
+        "Disc image is too large"
+    );
+
+    // Update boot.bin with the new layout
+    boot[0x420..0x424].copy_from_slice(&(dol_offset as u32).to_be_bytes());
+    boot[0x424..0x428].copy_from_slice(&(fst_offset as u32).to_be_bytes());
+    boot[0x428..0x42C].copy_from_slice(&(fst_size as u32).to_be_bytes());
+    boot[0x42C..0x430].copy_from_slice(&(header.fst_max_size.max(fst_size as u32)).to_be_bytes());
+
+    let mut regions = vec![
+        Region { offset: 0, size: BOOT_SIZE, source: Source::Data(boot) },
+        Region { offset: BOOT_SIZE, size: BI2_SIZE, source: Source::Data(bi2) },
+        Region {
+            offset: APPLOADER_OFFSET,
+            size: apploader.len() as u64,
+            source: Source::Data(apploader),
+        },
+        Region { offset: dol_offset, size: dol_size, source: Source::File(dol_path) },
+        Region {
+            offset: fst_offset,
+            size: fst_size,
+            source: Source::Data(write_fst(&entries, fst_size as usize)),
+        },
+    ];
+    for entry in entries {
+        if let FstEntryKind::File { source, size, offset, .. } = entry.kind {
+            if size > 0 {
+                regions.push(Region { offset, size, source: Source::File(source) });
+            }
+        }
+    }
+    regions.sort_by_key(|r| r.offset);
+
+    let game_id: [u8; 4] = header.game_id[..4].try_into().unwrap();
+    let mut writer = PaddingWriter {
+        w,
+        pos: 0,
+        junk: junk.then(LaggedFibonacci::default),
+        junk_start,
+        game_id,
+        disc_num: header.disc_num,
+    };
+    for region in regions {
+        ensure!(region.offset >= writer.pos, "Overlapping data at {:#X}", region.offset);
+        writer.pad_to(region.offset)?;
+        match region.source {
+            Source::Data(data) => writer.write(&data)?,
+            Source::File(path) => {
+                let file = File::open(&path)
+                    .with_context(|| format!("Failed to open '{}'", path.display()))?;
+                let copied = io::copy(&mut file.take(region.size), &mut writer.w)
+                    .with_context(|| format!("Failed to copy '{}'", path.display()))?;
+                ensure!(copied == region.size, "File '{}' changed while reading", path.display());
+                writer.pos += copied;
+            }
+        }
+    }
+    if writer.pos > GCN_DISC_SIZE {
+        log::warn!(
+            "Disc image size {:#X} exceeds the standard size {:#X}",
+            writer.pos,
+            GCN_DISC_SIZE
+        );
+    }
+    writer.pad_to(GCN_DISC_SIZE)?;
+    Ok(writer.pos)
+}
+
+/// Writes data sequentially, filling gaps with zeroes or junk data.
+struct PaddingWriter<'a, W: ?Sized> {
+    w: &'a mut W,
+    pos: u64,
+    junk: Option<LaggedFibonacci>,
+    /// Offset from which gaps are filled with junk data.
+    junk_start: u64,
+    game_id: [u8; 4],
+    disc_num: u8,
+}
+
+impl<W> PaddingWriter<'_, W>
+where W: Write + ?Sized
+{
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.w.write_all(data)?;
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+
+    fn pad_to(&mut self, offset: u64) -> Result<()> {
+        let mut buf = [0u8; 0x8000];
+        while self.pos < offset {
+            let mut end = min(offset, self.pos + buf.len() as u64);
+            if self.pos < self.junk_start {
+                end = min(end, self.junk_start);
+            }
+            let buf = &mut buf[..(end - self.pos) as usize];
+            match &mut self.junk {
+                Some(lfg) if self.pos >= self.junk_start => {
+                    lfg.fill_junk(buf, self.game_id, self.disc_num, self.pos)
+                }
+                _ => buf.fill(0),
+            }
+            self.w.write_all(buf)?;
+            self.pos = end;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::disc::{read_file, DiscFs, GCN_MAGIC};
+
+    /// Keeps the start of the image and discards the padding up to the full disc size.
+    struct Prefix {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Prefix {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = min(buf.len(), self.limit.saturating_sub(self.data.len()));
+            self.data.extend_from_slice(&buf[..n]);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn test_build_gcm_round_trip() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("dtk-build-gcm-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sys"))?;
+        fs::create_dir_all(dir.join("files/dir"))?;
+
+        let mut boot = vec![0u8; BOOT_SIZE as usize];
+        boot[..6].copy_from_slice(b"GTEST0");
+        boot[0x1C..0x20].copy_from_slice(&GCN_MAGIC.to_be_bytes());
+        fs::write(dir.join("sys/boot.bin"), boot)?;
+        fs::write(dir.join("sys/bi2.bin"), vec![0u8; BI2_SIZE as usize])?;
+        let mut apploader = vec![0u8; 0x40];
+        apploader[0x14..0x18].copy_from_slice(&0x20u32.to_be_bytes());
+        fs::write(dir.join("sys/apploader.img"), apploader)?;
+        let mut dol = vec![0u8; 0x120];
+        dol[0..4].copy_from_slice(&0x100u32.to_be_bytes());
+        dol[0x90..0x94].copy_from_slice(&0x20u32.to_be_bytes());
+        dol[0x100..].fill(0x60);
+        fs::write(dir.join("sys/main.dol"), &dol)?;
+        let files = [
+            ("files/a.bin", (0..0x30).collect::<Vec<u8>>()),
+            ("files/dir/b.bin", b"hello".to_vec()),
+            ("files/empty.bin", vec![]),
+        ];
+        for (path, data) in &files {
+            fs::write(dir.join(path), data)?;
+        }
+
+        let mut out = Prefix { data: vec![], limit: 0x20000 };
+        let result = build_gcm(&dir, &mut out, false);
+        fs::remove_dir_all(&dir)?;
+        assert_eq!(result?, GCN_DISC_SIZE);
+
+        let mut reader = Cursor::new(out.data);
+        let fs = DiscFs::new(&mut reader)?;
+        let paths = fs
+            .nodes
+            .iter()
+            .filter(|n| n.path.starts_with("files"))
+            .map(|n| n.path.to_string_lossy().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [
+            "files",
+            "files/a.bin",
+            "files/dir",
+            "files/dir/b.bin",
+            "files/empty.bin"
+        ]);
+        for (path, data) in &files {
+            assert_eq!(&read_file(&mut reader, Path::new(path))?, data, "{}", path);
+        }
+        assert_eq!(read_file(&mut reader, Path::new("sys/main.dol"))?, dol);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    io,
+    io::{Read, Seek, SeekFrom},
+};
+
+use anyhow::{ensure, Result};
+
+use crate::util::{
+    disc::block::{Block, BlockReader},
+    reader::{Endian, FromReader},
+};
+
+/// Size of the CISO header, including the block map.
+const CISO_HEADER_SIZE: usize = 0x8000;
+const CISO_MAP_SIZE: usize = CISO_HEADER_SIZE - 8;
+
+/// Reads CISO (compact ISO) images, which omit unused blocks.
+pub struct CisoReader<R> {
+    reader: R,
+    block_size: u32,
+    /// Index of each block's data in the file, or `None` if the block is unused.
+    block_map: Vec<Option<u32>>,
+}
+
+impl<R> CisoReader<R>
+where R: Read + Seek
+{
+    pub fn new(mut reader: R) -> Result<Self> {
+        let magic = <[u8; 4]>::from_reader(&mut reader, Endian::Little)?;
+        ensure!(magic == *b"CISO", "Invalid CISO magic: {:?}", magic);
+        let block_size = u32::from_reader(&mut reader, Endian::Little)?;
+        ensure!(block_size > 0, "Invalid CISO block size");
+        let mut map = vec![0u8; CISO_MAP_SIZE];
+        reader.read_exact(&mut map)?;
+
+        let mut block_map = Vec::with_capacity(CISO_MAP_SIZE);
+        let mut next_index = 0u32;
+        for &used in &map {
+            if used == 1 {
+                block_map.push(Some(next_index));
+                next_index += 1;
+            } else {
+                block_map.push(None);
+            }
+        }
+        // Trim unused blocks from the end
+        while block_map.last().is_some_and(|b| b.is_none()) {
+            block_map.pop();
+        }
+        Ok(Self { reader, block_size, block_map })
+    }
+}
+
+impl<R> BlockReader for CisoReader<R>
+where R: Read + Seek
+{
+    fn disc_size(&self) -> u64 { self.block_map.len() as u64 * self.block_size as u64 }
+
+    fn read_block(&mut self, offset: u64, block: &mut Block) -> io::Result<()> {
+        let index = (offset / self.block_size as u64) as usize;
+        block.offset = index as u64 * self.block_size as u64;
+        block.data.resize(self.block_size as usize, 0);
+        match self.block_map.get(index).copied().flatten() {
+            Some(file_index) => {
+                self.reader.seek(SeekFrom::Start(
+                    CISO_HEADER_SIZE as u64 + file_index as u64 * self.block_size as u64,
+                ))?;
+                self.reader.read_exact(&mut block.data)?;
+            }
+            None => block.data.fill(0),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    cmp::min,
+    io,
+    io::{Read, Seek, SeekFrom},
+};
+
+use anyhow::{ensure, Result};
+use flate2::read::ZlibDecoder;
+
+use crate::util::{
+    disc::block::{Block, BlockReader},
+    reader::{Endian, FromReader},
+};
+
+const GCZ_MAGIC: u32 = 0xB10BC001;
+const GCZ_HEADER_SIZE: u64 = 0x20;
+/// Set in a block pointer if the block is stored uncompressed.
+const UNCOMPRESSED_FLAG: u64 = 1 << 63;
+
+/// Reads GCZ images, which store zlib-compressed blocks.
+pub struct GczReader<R> {
+    reader: R,
+    compressed_size: u64,
+    disc_size: u64,
+    block_size: u32,
+    block_pointers: Vec<u64>,
+    data_offset: u64,
+    compressed: Vec<u8>,
+}
+
+impl<R> GczReader<R>
+where R: Read + Seek
+{
+    pub fn new(mut reader: R) -> Result<Self> {
+        let magic = u32::from_reader(&mut reader, Endian::Little)?;
+        ensure!(magic == GCZ_MAGIC, "Invalid GCZ magic: {:#010X}", magic);
+        let _sub_type = u32::from_reader(&mut reader, Endian::Little)?;
+        let compressed_size = u64::from_reader(&mut reader, Endian::Little)?;
+        let disc_size = u64::from_reader(&mut reader, Endian::Little)?;
+        let block_size = u32::from_reader(&mut reader, Endian::Little)?;
+        let block_count = u32::from_reader(&mut reader, Endian::Little)?;
+        ensure!(block_size > 0, "Invalid GCZ block size");
+        ensure!(
+            block_count as u64 * block_size as u64 >= disc_size,
+            "Invalid GCZ block count: {}",
+            block_count
+        );
+
+        let mut block_pointers = Vec::with_capacity(block_count as usize);
+        for _ in 0..block_count {
+            block_pointers.push(u64::from_reader(&mut reader, Endian::Little)?);
+        }
+        // Followed by a table of Adler-32 hashes, which are redundant with zlib's own checksum
+        let data_offset = GCZ_HEADER_SIZE + block_count as u64 * 12;
+        Ok(Self {
+            reader,
+            compressed_size,
+            disc_size,
+            block_size,
+            block_pointers,
+            data_offset,
+            compressed: vec![],
+        })
+    }
+}
+
+impl<R> BlockReader for GczReader<R>
+where R: Read + Seek
+{
+    fn disc_size(&self) -> u64 { self.disc_size }
+
+    fn read_block(&mut self, offset: u64, block: &mut Block) -> io::Result<()> {
+        let index = (offset / self.block_size as u64) as usize;
+        let block_offset = index as u64 * self.block_size as u64;
+        let pointer = match self.block_pointers.get(index) {
+            Some(&pointer) if block_offset < self.disc_size => pointer,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("GCZ offset {:#X} out of bounds", offset),
+                ))
+            }
+        };
+        let start = pointer & !UNCOMPRESSED_FLAG;
+        let end = self
+            .block_pointers
+            .get(index + 1)
+            .map_or(self.compressed_size, |&next| next & !UNCOMPRESSED_FLAG);
+        if end < start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid GCZ block pointer for block {}", index),
+            ));
+        }
+
+        block.offset = block_offset;
+        // The final block only covers the rest of the disc
+        let len = min(self.block_size as u64, self.disc_size - block_offset);
+        block.data.resize(len as usize, 0);
+        self.reader.seek(SeekFrom::Start(self.data_offset + start))?;
+        if pointer & UNCOMPRESSED_FLAG != 0 {
+            self.reader.read_exact(&mut block.data)?;
+        } else {
+            self.compressed.resize((end - start) as usize, 0);
+            self.reader.read_exact(&mut self.compressed)?;
+            ZlibDecoder::new(self.compressed.as_slice()).read_exact(&mut block.data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use super::*;
+    use crate::util::disc::block::BlockStream;
+
+    #[test]
+    fn test_partial_final_block() {
+        let disc = (0..0x30u8).collect::<Vec<u8>>();
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&disc[0x20..]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut data = vec![];
+        data.extend_from_slice(&GCZ_MAGIC.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(0x20 + compressed.len() as u64).to_le_bytes());
+        data.extend_from_slice(&(disc.len() as u64).to_le_bytes());
+        data.extend_from_slice(&0x20u32.to_le_bytes());
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&UNCOMPRESSED_FLAG.to_le_bytes());
+        data.extend_from_slice(&0x20u64.to_le_bytes());
+        data.extend_from_slice(&[0; 8]); // Adler-32 hashes
+        data.extend_from_slice(&disc[..0x20]);
+        data.extend_from_slice(&compressed);
+
+        let reader = GczReader::new(Cursor::new(data)).unwrap();
+        let mut stream = BlockStream::new(Box::new(reader));
+        let mut out = vec![];
+        stream.read_to_end(&mut out).unwrap();
+        assert_eq!(out, disc);
+    }
+}
@@ -0,0 +1,120 @@
+use std::cmp::min;
+
+use crate::util::disc::wii::CLUSTER_SIZE;
+
+const K: usize = 521;
+const J: usize = 32;
+/// Number of words in a seed.
+const SEED_WORDS: usize = 17;
+/// Size of a seed in bytes.
+pub const SEED_SIZE: usize = SEED_WORDS * 4;
+
+/// Lagged Fibonacci generator used to create the junk data on GameCube and Wii discs.
+pub struct LaggedFibonacci {
+    buffer: [u32; K],
+    /// `buffer` as output bytes.
+    bytes: [u8; K * 4],
+    position: usize,
+}
+
+impl Default for LaggedFibonacci {
+    fn default() -> Self { Self { buffer: [0; K], bytes: [0; K * 4], position: 0 } }
+}
+
+impl LaggedFibonacci {
+    /// Initializes the generator from a big-endian seed, as stored in RVZ files.
+    pub fn init(&mut self, seed: &[u8; SEED_SIZE]) {
+        for (i, word) in seed.chunks_exact(4).enumerate() {
+            self.buffer[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        self.init_buffer();
+    }
+
+    /// Initializes the generator with the seed used for junk data at `offset` on a disc,
+    /// derived from the first four bytes of the game ID, the disc number and the sector.
+    pub fn init_with_seed(&mut self, game_id: [u8; 4], disc_num: u8, offset: u64) {
+        let seed = u32::from_be_bytes([
+            game_id[2],
+            game_id[1],
+            game_id[3].wrapping_add(game_id[2]),
+            game_id[0].wrapping_add(game_id[1]),
+        ]) ^ disc_num as u32;
+        let sector = (offset / CLUSTER_SIZE) as u32;
+        let mut n = seed.wrapping_mul(0x260BCD5) ^ sector.wrapping_mul(0x1EF29123);
+        for word in &mut self.buffer[..SEED_WORDS] {
+            let mut v = 0u32;
+            for _ in 0..J {
+                n = n.wrapping_mul(0x5D588B65).wrapping_add(1);
+                v = (v >> 1) | (n & 0x80000000);
+            }
+            *word = v;
+        }
+        self.buffer[16] ^= (self.buffer[0] >> 9) ^ (self.buffer[16] << 23);
+        self.init_buffer();
+        self.skip((offset % CLUSTER_SIZE) as usize);
+    }
+
+    fn init_buffer(&mut self) {
+        for i in SEED_WORDS..K {
+            self.buffer[i] =
+                (self.buffer[i - 17] << 23) ^ (self.buffer[i - 16] >> 9) ^ self.buffer[i - 1];
+        }
+        // Output uses bits 18..26 instead of 16..24; shift and byteswap up front
+        // so that the buffer can be output directly as little-endian bytes.
+        for x in self.buffer.iter_mut() {
+            *x = ((*x & 0xFF00FFFF) | ((*x >> 2) & 0x00FF0000)).swap_bytes();
+        }
+        for _ in 0..4 {
+            self.forward();
+        }
+        self.position = 0;
+    }
+
+    fn forward(&mut self) {
+        for i in 0..J {
+            self.buffer[i] ^= self.buffer[i + K - J];
+        }
+        for i in J..K {
+            self.buffer[i] ^= self.buffer[i - J];
+        }
+        for (out, x) in self.bytes.chunks_exact_mut(4).zip(&self.buffer) {
+            out.copy_from_slice(&x.to_le_bytes());
+        }
+    }
+
+    /// Skips `count` bytes of output.
+    pub fn skip(&mut self, count: usize) {
+        self.position += count;
+        while self.position >= K * 4 {
+            self.forward();
+            self.position -= K * 4;
+        }
+    }
+
+    /// Fills `out` with generated data.
+    pub fn fill(&mut self, mut out: &mut [u8]) {
+        while !out.is_empty() {
+            let len = min(out.len(), K * 4 - self.position);
+            out[..len].copy_from_slice(&self.bytes[self.position..self.position + len]);
+            out = &mut out[len..];
+            self.position += len;
+            if self.position == K * 4 {
+                self.forward();
+                self.position = 0;
+            }
+        }
+    }
+
+    /// Fills `out` with the junk data found at `offset` on a disc. The generator is
+    /// reseeded at each sector boundary.
+    pub fn fill_junk(&mut self, mut out: &mut [u8], game_id: [u8; 4], disc_num: u8, offset: u64) {
+        let mut offset = offset;
+        while !out.is_empty() {
+            self.init_with_seed(game_id, disc_num, offset);
+            let len = min(out.len(), (CLUSTER_SIZE - offset % CLUSTER_SIZE) as usize);
+            self.fill(&mut out[..len]);
+            out = &mut out[len..];
+            offset += len as u64;
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    io,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::util::reader::{struct_size, Endian, FromReader};
+
+pub mod block;
+pub mod build;
+pub mod ciso;
+pub mod gcz;
+pub mod lfg;
+pub mod wia;
+pub mod wii;
+
+/// A reader over a disc image or partition, used as a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<R> ReadSeek for R where R: Read + Seek {}
+
+/// GameCube disc magic, at offset 0x1C.
+pub const GCN_MAGIC: u32 = 0xC2339F3D;
+/// Wii disc magic, at offset 0x18.
+pub const WII_MAGIC: u32 = 0x5D1C9EA3;
+
+/// Size of `boot.bin`.
+pub const BOOT_SIZE: u64 = 0x440;
+/// Size of `bi2.bin`.
+pub const BI2_SIZE: u64 = 0x2000;
+/// Offset of the apploader.
+pub const APPLOADER_OFFSET: u64 = BOOT_SIZE + BI2_SIZE;
+
+/// Disc header (`boot.bin`).
+#[derive(Debug, Clone)]
+pub struct DiscHeader {
+    pub game_id: [u8; 6],
+    pub disc_num: u8,
+    pub disc_version: u8,
+    pub audio_streaming: u8,
+    pub audio_stream_buf_size: u8,
+    pub wii_magic: u32,
+    pub gcn_magic: u32,
+    pub game_title: [u8; 64],
+    pub debug_mon_offset: u32,
+    pub debug_load_address: u32,
+    /// Offset of `main.dol`. (Shifted right by 2 on Wii)
+    pub dol_offset: u32,
+    /// Offset of `fst.bin`. (Shifted right by 2 on Wii)
+    pub fst_offset: u32,
+    /// Size of `fst.bin`. (Shifted right by 2 on Wii)
+    pub fst_size: u32,
+    /// Maximum size of `fst.bin` across all discs. (Shifted right by 2 on Wii)
+    pub fst_max_size: u32,
+}
+
+impl FromReader for DiscHeader {
+    type Args = ();
+
+    const STATIC_SIZE: usize = BOOT_SIZE as usize;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let header = Self {
+            game_id: <[u8; 6]>::from_reader(reader, e)?,
+            disc_num: u8::from_reader(reader, e)?,
+            disc_version: u8::from_reader(reader, e)?,
+            audio_streaming: u8::from_reader(reader, e)?,
+            audio_stream_buf_size: u8::from_reader(reader, e)?,
+            wii_magic: {
+                <[u8; 14]>::from_reader(reader, e)?; // padding
+                u32::from_reader(reader, e)?
+            },
+            gcn_magic: u32::from_reader(reader, e)?,
+            game_title: <[u8; 64]>::from_reader(reader, e)?,
+            debug_mon_offset: {
+                // Disable hash verification, disable encryption, padding
+                reader.seek(SeekFrom::Current(0x400 - 0x60))?;
+                u32::from_reader(reader, e)?
+            },
+            debug_load_address: u32::from_reader(reader, e)?,
+            dol_offset: {
+                <[u8; 0x18]>::from_reader(reader, e)?; // padding
+                u32::from_reader(reader, e)?
+            },
+            fst_offset: u32::from_reader(reader, e)?,
+            fst_size: u32::from_reader(reader, e)?,
+            fst_max_size: u32::from_reader(reader, e)?,
+        };
+        // User position, user length, padding
+        <[u8; 0x10]>::from_reader(reader, e)?;
+        Ok(header)
+    }
+}
+
+impl DiscHeader {
+    pub fn is_gamecube(&self) -> bool { self.gcn_magic == GCN_MAGIC }
+
+    pub fn is_wii(&self) -> bool { self.wii_magic == WII_MAGIC }
+
+    pub fn game_id_str(&self) -> String { String::from_utf8_lossy(&self.game_id).into_owned() }
+
+    pub fn game_title_str(&self) -> String {
+        let end = self.game_title.iter().position(|&c| c == 0).unwrap_or(self.game_title.len());
+        String::from_utf8_lossy(&self.game_title[..end]).into_owned()
+    }
+
+    /// Offsets and sizes in the header and FST are stored shifted right by 2 on Wii.
+    fn offset_shift(&self) -> u32 {
+        if self.is_wii() {
+            2
+        } else {
+            0
+        }
+    }
+}
+
+/// Apploader header, at [`APPLOADER_OFFSET`].
+struct ApploaderHeader {
+    size: u32,
+    trailer_size: u32,
+}
+
+impl FromReader for ApploaderHeader {
+    type Args = ();
+
+    const STATIC_SIZE: usize = struct_size([
+        16,               // date
+        u32::STATIC_SIZE, // entry_point
+        u32::STATIC_SIZE, // size
+        u32::STATIC_SIZE, // trailer_size
+        u32::STATIC_SIZE, // padding
+    ]);
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        <[u8; 16]>::from_reader(reader, e)?; // date
+        u32::from_reader(reader, e)?; // entry_point
+        let size = u32::from_reader(reader, e)?;
+        let trailer_size = u32::from_reader(reader, e)?;
+        u32::from_reader(reader, e)?; // padding
+        Ok(Self { size, trailer_size })
+    }
+}
+
+impl ApploaderHeader {
+    fn total_size(&self) -> u64 {
+        Self::STATIC_SIZE as u64 + self.size as u64 + self.trailer_size as u64
+    }
+}
+
+/// Determines the size of a DOL from its section table.
+fn dol_size<R>(reader: &mut R, offset: u64) -> Result<u64>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(offset))?;
+    let section_offsets = <[u32; 18]>::from_reader(reader, Endian::Big)?;
+    <[u32; 18]>::from_reader(reader, Endian::Big)?; // section addresses
+    let section_sizes = <[u32; 18]>::from_reader(reader, Endian::Big)?;
+    let mut size = 0x100u64;
+    for (&offset, &len) in section_offsets.iter().zip(&section_sizes) {
+        if len > 0 {
+            size = size.max(offset as u64 + len as u64);
+        }
+    }
+    Ok(size)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiscNodeKind {
+    File,
+    Directory,
+}
+
+/// A file or directory on a disc, including the `sys` files.
+#[derive(Debug, Clone)]
+pub struct DiscNode {
+    pub kind: DiscNodeKind,
+    /// Path of the node, such as `sys/main.dol` or `files/rels.arc`.
+    pub path: PathBuf,
+    /// Offset of the file data, relative to the start of the disc (or partition).
+    pub offset: u64,
+    /// Size of the file data.
+    pub size: u64,
+}
+
+/// The file system of a GameCube disc or Wii partition.
+pub struct DiscFs {
+    pub header: DiscHeader,
+    /// `sys` files followed by FST nodes, in FST order.
+    pub nodes: Vec<DiscNode>,
+}
+
+impl DiscFs {
+    /// Reads the file system. The reader must be positioned at the start of the disc
+    /// (or decrypted partition data).
+    pub fn new<R>(reader: &mut R) -> Result<Self>
+    where R: Read + Seek + ?Sized {
+        let base = reader.stream_position()?;
+        let header = DiscHeader::from_reader(reader, Endian::Big)?;
+        ensure!(
+            header.is_gamecube() || header.is_wii(),
+            "Invalid disc magic (game ID {})",
+            header.game_id_str()
+        );
+        let shift = header.offset_shift();
+
+        reader.seek(SeekFrom::Start(base + APPLOADER_OFFSET))?;
+        let apploader = ApploaderHeader::from_reader(reader, Endian::Big)?;
+        let dol_offset = (header.dol_offset as u64) << shift;
+        let dol_size = dol_size(reader, base + dol_offset)?;
+        let fst_offset = (header.fst_offset as u64) << shift;
+        let fst_size = (header.fst_size as u64) << shift;
+
+        let mut nodes = vec![
+            DiscNode {
+                kind: DiscNodeKind::Directory,
+                path: PathBuf::from("sys"),
+                offset: 0,
+                size: 0,
+            },
+            DiscNode {
+                kind: DiscNodeKind::File,
+                path: PathBuf::from("sys/boot.bin"),
+                offset: 0,
+                size: BOOT_SIZE,
+            },
+            DiscNode {
+                kind: DiscNodeKind::File,
+                path: PathBuf::from("sys/bi2.bin"),
+                offset: BOOT_SIZE,
+                size: BI2_SIZE,
+            },
+            DiscNode {
+                kind: DiscNodeKind::File,
+                path: PathBuf::from("sys/apploader.img"),
+                offset: APPLOADER_OFFSET,
+                size: apploader.total_size(),
+            },
+            DiscNode {
+                kind: DiscNodeKind::File,
+                path: PathBuf::from("sys/main.dol"),
+                offset: dol_offset,
+                size: dol_size,
+            },
+            DiscNode {
+                kind: DiscNodeKind::File,
+                path: PathBuf::from("sys/fst.bin"),
+                offset: fst_offset,
+                size: fst_size,
+            },
+        ];
+
+        reader.seek(SeekFrom::Start(base + fst_offset))?;
+        let mut fst = vec![0u8; fst_size as usize];
+        reader.read_exact(&mut fst).context("Failed to read FST")?;
+        nodes.extend(read_fst(&fst, shift, Path::new("files"))?);
+        Ok(Self { header, nodes })
+    }
+
+    /// Find a file on the disc. Comparison is case-insensitive.
+    pub fn find_file<P>(&self, path: P) -> Result<Option<&DiscNode>>
+    where P: AsRef<Path> {
+        let cmp_path = normalize_path(path.as_ref())?;
+        Ok(self.nodes.iter().find(|node| {
+            node.kind == DiscNodeKind::File
+                && node.path.to_string_lossy().to_ascii_lowercase() == cmp_path
+        }))
+    }
+}
+
+/// Lowercases a path for comparison, rejecting relative components.
+pub fn normalize_path(path: &Path) -> Result<String> {
+    let mut cmp_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => cmp_path.push(name.to_ascii_lowercase()),
+            Component::RootDir => {}
+            component => bail!("Invalid path component: {:?}", component),
+        }
+    }
+    Ok(cmp_path.to_string_lossy().into_owned())
+}
+
+const FST_NODE_SIZE: usize = 12;
+
+/// Parses a file system table. File offsets are shifted left by `shift`.
+pub fn read_fst(fst: &[u8], shift: u32, root: &Path) -> Result<Vec<DiscNode>> {
+    let read_node = |index: usize| -> Result<(u8, u32, u32, u32)> {
+        let start = index * FST_NODE_SIZE;
+        ensure!(start + FST_NODE_SIZE <= fst.len(), "FST node {} out of bounds", index);
+        let word = |i: usize| u32::from_be_bytes(fst[start + i..start + i + 4].try_into().unwrap());
+        let kind_and_name = word(0);
+        Ok(((kind_and_name >> 24) as u8, kind_and_name & 0xFFFFFF, word(4), word(8)))
+    };
+    let (root_kind, _, _, node_count) = read_node(0)?;
+    ensure!(root_kind == 1, "FST root node is not a directory");
+    let string_base = node_count as usize * FST_NODE_SIZE;
+    ensure!(string_base <= fst.len(), "Invalid FST node count: {}", node_count);
+    let read_name = |offset: u32| -> Result<String> {
+        let start = string_base + offset as usize;
+        ensure!(start < fst.len(), "Invalid FST string offset: {:#X}", offset);
+        let end = fst[start..].iter().position(|&c| c == 0).map_or(fst.len(), |p| start + p);
+        Ok(String::from_utf8_lossy(&fst[start..end]).into_owned())
+    };
+
+    let mut nodes = Vec::with_capacity(node_count as usize);
+    nodes.push(DiscNode {
+        kind: DiscNodeKind::Directory,
+        path: root.to_owned(),
+        offset: 0,
+        size: 0,
+    });
+    let mut dir_stack: Vec<u32> = vec![node_count];
+    let mut current_path = root.to_owned();
+    for index in 1..node_count {
+        while dir_stack.last().is_some_and(|&end| index >= end) {
+            dir_stack.pop();
+            current_path.pop();
+        }
+        let (kind, name_offset, offset, length) = read_node(index as usize)?;
+        let path = current_path.join(read_name(name_offset)?);
+        match kind {
+            0 => nodes.push(DiscNode {
+                kind: DiscNodeKind::File,
+                path,
+                offset: (offset as u64) << shift,
+                size: length as u64,
+            }),
+            1 => {
+                ensure!(
+                    length > index && length <= node_count,
+                    "Invalid FST directory end: {}",
+                    length
+                );
+                nodes.push(DiscNode {
+                    kind: DiscNodeKind::Directory,
+                    path: path.clone(),
+                    offset: 0,
+                    size: 0,
+                });
+                dir_stack.push(length);
+                current_path = path;
+            }
+            kind => bail!("Invalid FST node kind: {}", kind),
+        }
+    }
+    Ok(nodes)
+}
+
+/// Checks whether the data begins with a Wii disc header.
+pub fn is_wii_disc(data: &[u8]) -> bool {
+    data.len() >= 0x20 && u32::from_be_bytes(data[0x18..0x1C].try_into().unwrap()) == WII_MAGIC
+}
+
+/// Checks whether the data begins with a GameCube or Wii disc header.
+pub fn is_disc(data: &[u8]) -> bool {
+    if data.len() < 0x20 {
+        return false;
+    }
+    let wii_magic = u32::from_be_bytes(data[0x18..0x1C].try_into().unwrap());
+    let gcn_magic = u32::from_be_bytes(data[0x1C..0x20].try_into().unwrap());
+    wii_magic == WII_MAGIC || gcn_magic == GCN_MAGIC
+}
+
+/// Reads a file's data from a disc or partition.
+pub fn read_node<R>(reader: &mut R, node: &DiscNode) -> Result<Vec<u8>>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(node.offset))?;
+    let mut data = vec![0u8; node.size as usize];
+    reader
+        .read_exact(&mut data)
+        .with_context(|| format!("Failed to read '{}'", node.path.display()))?;
+    Ok(data)
+}
+
+/// Reads a file from a GameCube disc, or from a Wii disc using the common key named by
+/// [`wii::COMMON_KEY_ENV`]. Wii paths begin with the partition name, such as `DATA`.
+pub fn read_file<R>(reader: &mut R, path: &Path) -> Result<Vec<u8>>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(0))?;
+    let header = DiscHeader::from_reader(reader, Endian::Big)?;
+    if header.is_wii() {
+        let common_key = wii::load_common_key(None)?;
+        return wii::read_file(reader, path, &common_key);
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    let fs = DiscFs::new(reader)?;
+    let Some(node) = fs.find_file(path)? else {
+        bail!("File '{}' not found", path.display());
+    };
+    read_node(reader, node)
+}
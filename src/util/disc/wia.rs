@@ -0,0 +1,761 @@
+use std::{
+    cmp::min,
+    io,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use bzip2::read::BzDecoder;
+use liblzma::{
+    read::XzDecoder,
+    stream::{Filters, Stream},
+};
+use sha1::{Digest, Sha1};
+
+use crate::util::{
+    align_up,
+    disc::{
+        block::{Block, BlockReader},
+        lfg::{LaggedFibonacci, SEED_SIZE},
+        wii::{
+            build_hash_blocks, encrypt_cluster, CLUSTER_DATA_SIZE, CLUSTER_SIZE, GROUP_CLUSTERS,
+            HASH_SIZE,
+        },
+    },
+    reader::{Endian, FromReader},
+};
+
+const WIA_MAGIC: [u8; 4] = *b"WIA\x01";
+const RVZ_MAGIC: [u8; 4] = *b"RVZ\x01";
+/// Size of the part of the disc header stored in the WIA header.
+const DISC_HEADER_SIZE: usize = 0x80;
+/// Size of the data covered by one hash exception list.
+const HASH_GROUP_SIZE: u32 = CLUSTER_SIZE as u32 * GROUP_CLUSTERS as u32;
+/// Upper bound on the size of zero-filled blocks returned for unmapped regions.
+const GAP_BLOCK_SIZE: u64 = 0x200000;
+
+fn invalid_data<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn read_u16<R: Read + ?Sized>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read + ?Sized>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Compression {
+    None,
+    /// Runs of zeroes removed.
+    Purge,
+    Bzip2,
+    Lzma,
+    Lzma2,
+    /// Zstandard (RVZ only).
+    Zstandard,
+}
+
+impl TryFrom<u32> for Compression {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        Ok(match value {
+            0 => Self::None,
+            1 => Self::Purge,
+            2 => Self::Bzip2,
+            3 => Self::Lzma,
+            4 => Self::Lzma2,
+            5 => Self::Zstandard,
+            _ => bail!("Unknown WIA compression type {}", value),
+        })
+    }
+}
+
+/// First WIA header, at the start of the file.
+struct WiaHeader1 {
+    magic: [u8; 4],
+    header_2_size: u32,
+    header_2_hash: [u8; 20],
+    iso_file_size: u64,
+}
+
+impl FromReader for WiaHeader1 {
+    type Args = ();
+
+    const STATIC_SIZE: usize = 0x48;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let mut data = [0u8; Self::STATIC_SIZE];
+        reader.read_exact(&mut data)?;
+        if Sha1::digest(&data[..0x34]).as_slice() != &data[0x34..] {
+            return Err(invalid_data("WIA header hash mismatch"));
+        }
+        let reader = &mut Cursor::new(&data);
+        let magic = <[u8; 4]>::from_reader(reader, e)?;
+        u32::from_reader(reader, e)?; // version
+        u32::from_reader(reader, e)?; // version_compatible
+        let header_2_size = u32::from_reader(reader, e)?;
+        let header_2_hash = <[u8; 20]>::from_reader(reader, e)?;
+        let iso_file_size = u64::from_reader(reader, e)?;
+        u64::from_reader(reader, e)?; // wia_file_size
+        Ok(Self { magic, header_2_size, header_2_hash, iso_file_size })
+    }
+}
+
+/// Second WIA header, following the first.
+struct WiaHeader2 {
+    disc_type: u32,
+    compression: u32,
+    chunk_size: u32,
+    disc_header: [u8; DISC_HEADER_SIZE],
+    partition_entry_count: u32,
+    partition_entry_size: u32,
+    partition_entries_offset: u64,
+    partition_entries_hash: [u8; 20],
+    raw_data_entry_count: u32,
+    raw_data_entries_offset: u64,
+    raw_data_entries_size: u32,
+    group_entry_count: u32,
+    group_entries_offset: u64,
+    group_entries_size: u32,
+    compressor_data: Vec<u8>,
+}
+
+impl FromReader for WiaHeader2 {
+    type Args = ();
+
+    const STATIC_SIZE: usize = 0xDC;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let disc_type = u32::from_reader(reader, e)?;
+        let compression = u32::from_reader(reader, e)?;
+        i32::from_reader(reader, e)?; // compression_level
+        let chunk_size = u32::from_reader(reader, e)?;
+        let disc_header = <[u8; DISC_HEADER_SIZE]>::from_reader(reader, e)?;
+        let partition_entry_count = u32::from_reader(reader, e)?;
+        let partition_entry_size = u32::from_reader(reader, e)?;
+        let partition_entries_offset = u64::from_reader(reader, e)?;
+        let partition_entries_hash = <[u8; 20]>::from_reader(reader, e)?;
+        let raw_data_entry_count = u32::from_reader(reader, e)?;
+        let raw_data_entries_offset = u64::from_reader(reader, e)?;
+        let raw_data_entries_size = u32::from_reader(reader, e)?;
+        let group_entry_count = u32::from_reader(reader, e)?;
+        let group_entries_offset = u64::from_reader(reader, e)?;
+        let group_entries_size = u32::from_reader(reader, e)?;
+        let compressor_data_size = u8::from_reader(reader, e)?;
+        let compressor_data = <[u8; 7]>::from_reader(reader, e)?;
+        if compressor_data_size > 7 {
+            return Err(invalid_data("Invalid WIA compressor data size"));
+        }
+        Ok(Self {
+            disc_type,
+            compression,
+            chunk_size,
+            disc_header,
+            partition_entry_count,
+            partition_entry_size,
+            partition_entries_offset,
+            partition_entries_hash,
+            raw_data_entry_count,
+            raw_data_entries_offset,
+            raw_data_entries_size,
+            group_entry_count,
+            group_entries_offset,
+            group_entries_size,
+            compressor_data: compressor_data[..compressor_data_size as usize].to_vec(),
+        })
+    }
+}
+
+/// A range of sectors in a Wii partition, stored decrypted and without hashes.
+#[derive(Debug, Copy, Clone)]
+struct PartitionDataEntry {
+    first_sector: u32,
+    sector_count: u32,
+    group_index: u32,
+    group_count: u32,
+}
+
+impl FromReader for PartitionDataEntry {
+    type Args = ();
+
+    const STATIC_SIZE: usize = 0x10;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        Ok(Self {
+            first_sector: u32::from_reader(reader, e)?,
+            sector_count: u32::from_reader(reader, e)?,
+            group_index: u32::from_reader(reader, e)?,
+            group_count: u32::from_reader(reader, e)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PartitionEntry {
+    /// Decrypted title key.
+    key: [u8; 16],
+    data_entries: [PartitionDataEntry; 2],
+}
+
+impl FromReader for PartitionEntry {
+    type Args = ();
+
+    const STATIC_SIZE: usize = 0x30;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        Ok(Self {
+            key: <[u8; 16]>::from_reader(reader, e)?,
+            data_entries: [
+                PartitionDataEntry::from_reader(reader, e)?,
+                PartitionDataEntry::from_reader(reader, e)?,
+            ],
+        })
+    }
+}
+
+/// A range of data outside of Wii partitions, stored as-is.
+#[derive(Debug, Copy, Clone)]
+struct RawDataEntry {
+    data_offset: u64,
+    data_size: u64,
+    group_index: u32,
+    group_count: u32,
+}
+
+impl FromReader for RawDataEntry {
+    type Args = ();
+
+    const STATIC_SIZE: usize = 0x18;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        Ok(Self {
+            data_offset: u64::from_reader(reader, e)?,
+            data_size: u64::from_reader(reader, e)?,
+            group_index: u32::from_reader(reader, e)?,
+            group_count: u32::from_reader(reader, e)?,
+        })
+    }
+}
+
+impl RawDataEntry {
+    /// Groups start at the data offset aligned down to a sector.
+    fn start(&self) -> u64 { self.data_offset & !(CLUSTER_SIZE - 1) }
+
+    fn end(&self) -> u64 { self.data_offset + self.data_size }
+}
+
+/// A chunk of compressed data.
+#[derive(Debug, Copy, Clone)]
+struct GroupEntry {
+    /// Offset in the file.
+    offset: u64,
+    /// Size in the file. Zero if the chunk is entirely zeroes.
+    size: u32,
+    /// Whether the chunk is compressed. (Always true for WIA)
+    compressed: bool,
+    /// Size of the RVZ-packed data after decompression, or zero if not packed.
+    rvz_packed_size: u32,
+}
+
+impl FromReader for GroupEntry {
+    type Args = bool;
+
+    const STATIC_SIZE: usize = 8;
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, rvz: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let offset = (u32::from_reader(reader, e)? as u64) << 2;
+        let size = u32::from_reader(reader, e)?;
+        if rvz {
+            Ok(Self {
+                offset,
+                size: size & 0x7FFFFFFF,
+                compressed: size & 0x80000000 != 0,
+                rvz_packed_size: u32::from_reader(reader, e)?,
+            })
+        } else {
+            Ok(Self { offset, size, compressed: true, rvz_packed_size: 0 })
+        }
+    }
+}
+
+/// A hash block value that differs from the recalculated one.
+struct HashException {
+    /// Offset in the hash blocks of the exception list's sectors.
+    offset: u16,
+    hash: [u8; 20],
+}
+
+#[derive(Debug, Copy, Clone)]
+enum RegionKind {
+    Raw(usize),
+    Partition { index: usize, entry: usize },
+}
+
+/// A mapped region of the disc image.
+#[derive(Debug, Copy, Clone)]
+struct Region {
+    start: u64,
+    end: u64,
+    kind: RegionKind,
+}
+
+/// Reads WIA and RVZ images.
+///
+/// Wii partition data is stored decrypted and without hashes, so the hash blocks are
+/// recalculated and the clusters re-encrypted to reproduce the original disc image.
+pub struct WiaReader<R> {
+    reader: R,
+    disc_size: u64,
+    compression: Compression,
+    compressor_data: Vec<u8>,
+    chunk_size: u32,
+    disc_header: [u8; DISC_HEADER_SIZE],
+    partitions: Vec<PartitionEntry>,
+    raw_data: Vec<RawDataEntry>,
+    groups: Vec<GroupEntry>,
+    /// Mapped regions, sorted by offset.
+    regions: Vec<Region>,
+    compressed: Vec<u8>,
+}
+
+impl<R> WiaReader<R>
+where R: Read + Seek
+{
+    pub fn new(mut reader: R) -> Result<Self> {
+        let header_1 = WiaHeader1::from_reader(&mut reader, Endian::Big)?;
+        let rvz = match header_1.magic {
+            WIA_MAGIC => false,
+            RVZ_MAGIC => true,
+            magic => bail!("Invalid WIA magic: {:?}", magic),
+        };
+        ensure!(
+            header_1.header_2_size as usize >= WiaHeader2::STATIC_SIZE,
+            "Invalid WIA header size {:#X}",
+            header_1.header_2_size
+        );
+        let mut data = vec![0u8; header_1.header_2_size as usize];
+        reader.read_exact(&mut data)?;
+        ensure!(
+            Sha1::digest(&data).as_slice() == header_1.header_2_hash,
+            "WIA header hash mismatch"
+        );
+        let header_2 = WiaHeader2::from_reader(&mut Cursor::new(&data), Endian::Big)?;
+        ensure!(
+            matches!(header_2.disc_type, 1 | 2),
+            "Unknown WIA disc type {}",
+            header_2.disc_type
+        );
+        ensure!(
+            header_2.chunk_size > 0 && header_2.chunk_size % CLUSTER_SIZE as u32 == 0,
+            "Invalid WIA chunk size {:#X}",
+            header_2.chunk_size
+        );
+
+        let mut wia = Self {
+            reader,
+            disc_size: header_1.iso_file_size,
+            compression: Compression::try_from(header_2.compression)?,
+            compressor_data: header_2.compressor_data.clone(),
+            chunk_size: header_2.chunk_size,
+            disc_header: header_2.disc_header,
+            partitions: vec![],
+            raw_data: vec![],
+            groups: vec![],
+            regions: vec![],
+            compressed: vec![],
+        };
+
+        // Partition entries are stored uncompressed
+        let mut data = vec![
+            0u8;
+            header_2.partition_entry_count as usize
+                * header_2.partition_entry_size as usize
+        ];
+        wia.reader.seek(SeekFrom::Start(header_2.partition_entries_offset))?;
+        wia.reader.read_exact(&mut data)?;
+        ensure!(
+            Sha1::digest(&data).as_slice() == header_2.partition_entries_hash,
+            "WIA partition entries hash mismatch"
+        );
+        if header_2.partition_entry_count > 0 {
+            ensure!(
+                header_2.partition_entry_size as usize >= PartitionEntry::STATIC_SIZE,
+                "Invalid WIA partition entry size {:#X}",
+                header_2.partition_entry_size
+            );
+            for entry in data.chunks_exact(header_2.partition_entry_size as usize) {
+                wia.partitions
+                    .push(PartitionEntry::from_reader(&mut Cursor::new(entry), Endian::Big)?);
+            }
+        }
+
+        let data = wia
+            .read_table(
+                header_2.raw_data_entries_offset,
+                header_2.raw_data_entries_size,
+                header_2.raw_data_entry_count as usize * RawDataEntry::STATIC_SIZE,
+            )
+            .context("Failed to read WIA raw data entries")?;
+        let mut cursor = Cursor::new(data.as_slice());
+        for _ in 0..header_2.raw_data_entry_count {
+            wia.raw_data.push(RawDataEntry::from_reader(&mut cursor, Endian::Big)?);
+        }
+
+        let group_entry_size = if rvz { 12 } else { GroupEntry::STATIC_SIZE };
+        let data = wia
+            .read_table(
+                header_2.group_entries_offset,
+                header_2.group_entries_size,
+                header_2.group_entry_count as usize * group_entry_size,
+            )
+            .context("Failed to read WIA group entries")?;
+        let mut cursor = Cursor::new(data.as_slice());
+        for _ in 0..header_2.group_entry_count {
+            wia.groups.push(GroupEntry::from_reader_args(&mut cursor, Endian::Big, rvz)?);
+        }
+
+        for (index, entry) in wia.raw_data.iter().enumerate() {
+            if entry.data_size > 0 {
+                wia.regions.push(Region {
+                    start: entry.data_offset,
+                    end: entry.end(),
+                    kind: RegionKind::Raw(index),
+                });
+            }
+        }
+        for (index, partition) in wia.partitions.iter().enumerate() {
+            for (entry_index, entry) in partition.data_entries.iter().enumerate() {
+                if entry.sector_count > 0 {
+                    wia.regions.push(Region {
+                        start: entry.first_sector as u64 * CLUSTER_SIZE,
+                        end: (entry.first_sector as u64 + entry.sector_count as u64) * CLUSTER_SIZE,
+                        kind: RegionKind::Partition { index, entry: entry_index },
+                    });
+                }
+            }
+        }
+        wia.regions.sort_by_key(|r| r.start);
+        Ok(wia)
+    }
+
+    fn read_table(&mut self, offset: u64, size: u32, decompressed_size: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; size as usize];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(self.decompress(self.compression, &data, decompressed_size)?)
+    }
+
+    fn decoder<'a>(
+        &self,
+        compression: Compression,
+        data: &'a [u8],
+    ) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match compression {
+            Compression::None => Box::new(data),
+            Compression::Purge => return Err(invalid_data("Purge has no stream decoder")),
+            Compression::Bzip2 => Box::new(BzDecoder::new(data)),
+            Compression::Lzma => {
+                let mut filters = Filters::new();
+                filters.lzma1_properties(&self.compressor_data)?;
+                Box::new(XzDecoder::new_stream(data, Stream::new_raw_decoder(&filters)?))
+            }
+            Compression::Lzma2 => {
+                let mut filters = Filters::new();
+                filters.lzma2_properties(&self.compressor_data)?;
+                Box::new(XzDecoder::new_stream(data, Stream::new_raw_decoder(&filters)?))
+            }
+            Compression::Zstandard => Box::new(zstd::stream::read::Decoder::with_buffer(data)?),
+        })
+    }
+
+    fn decompress(
+        &self,
+        compression: Compression,
+        data: &[u8],
+        size: usize,
+    ) -> io::Result<Vec<u8>> {
+        if compression == Compression::Purge {
+            return purge_decompress(data, size);
+        }
+        let mut out = vec![0u8; size];
+        self.decoder(compression, data)?.read_exact(&mut out)?;
+        Ok(out)
+    }
+
+    /// Reads and decompresses a group, returning its data and hash exception lists.
+    /// `data_offset` is the offset of the data in the disc (or partition), used to
+    /// regenerate RVZ junk data.
+    fn read_group(
+        &mut self,
+        index: u32,
+        size: usize,
+        data_offset: u64,
+        exception_lists: usize,
+    ) -> io::Result<(Vec<u8>, Vec<Vec<HashException>>)> {
+        let group = *self
+            .groups
+            .get(index as usize)
+            .ok_or_else(|| invalid_data(format!("Invalid WIA group index {}", index)))?;
+        if group.size == 0 {
+            let lists = (0..exception_lists).map(|_| vec![]).collect();
+            return Ok((vec![0u8; size], lists));
+        }
+        let mut compressed = std::mem::take(&mut self.compressed);
+        compressed.resize(group.size as usize, 0);
+        self.reader.seek(SeekFrom::Start(group.offset))?;
+        self.reader.read_exact(&mut compressed)?;
+
+        let compression = if group.compressed { self.compression } else { Compression::None };
+        let result = if matches!(compression, Compression::None | Compression::Purge) {
+            // Exception lists are stored uncompressed, aligned to 4 bytes
+            let mut cursor = Cursor::new(compressed.as_slice());
+            let lists = read_exception_lists(&mut cursor, exception_lists)?;
+            let mut data = &compressed[..];
+            if exception_lists > 0 {
+                data = compressed
+                    .get(align_up(cursor.position() as u32, 4) as usize..)
+                    .ok_or_else(|| invalid_data("Invalid WIA hash exception lists"))?;
+            }
+            let out = if compression == Compression::Purge {
+                purge_decompress(data, size)?
+            } else {
+                read_data(&mut data, size, group.rvz_packed_size, data_offset)?
+            };
+            (out, lists)
+        } else {
+            let mut decoder = self.decoder(compression, &compressed)?;
+            let lists = read_exception_lists(&mut decoder, exception_lists)?;
+            (read_data(&mut decoder, size, group.rvz_packed_size, data_offset)?, lists)
+        };
+        self.compressed = compressed;
+        Ok(result)
+    }
+
+    fn read_raw_block(&mut self, index: usize, offset: u64, block: &mut Block) -> io::Result<()> {
+        let entry = self.raw_data[index];
+        let chunk_size = self.chunk_size as u64;
+        let group = (offset - entry.start()) / chunk_size;
+        if group >= entry.group_count as u64 {
+            return Err(invalid_data(format!("Missing WIA group for offset {:#X}", offset)));
+        }
+        let group_start = entry.start() + group * chunk_size;
+        let size = min(chunk_size, entry.end() - group_start) as usize;
+        let (data, _) = self.read_group(entry.group_index + group as u32, size, group_start, 0)?;
+        block.offset = group_start;
+        block.data = data;
+        if group_start < DISC_HEADER_SIZE as u64 {
+            let len = min(DISC_HEADER_SIZE - group_start as usize, block.data.len());
+            block.data[..len].copy_from_slice(
+                &self.disc_header[group_start as usize..group_start as usize + len],
+            );
+        }
+        Ok(())
+    }
+
+    fn read_partition_block(
+        &mut self,
+        index: usize,
+        entry_index: usize,
+        offset: u64,
+        block: &mut Block,
+    ) -> io::Result<()> {
+        let partition = self.partitions[index].clone();
+        let entry = partition.data_entries[entry_index];
+        let partition_first_sector = partition.data_entries[0].first_sector as u64;
+        let group_sectors = self.chunk_size as u64 / CLUSTER_SIZE;
+        let exception_lists = (self.chunk_size / HASH_GROUP_SIZE).max(1) as usize;
+
+        // Hashes are calculated over groups of 64 sectors, so read at least that many
+        let unit_sectors = group_sectors.max(GROUP_CLUSTERS as u64);
+        let sector = offset / CLUSTER_SIZE - entry.first_sector as u64;
+        let unit_first = sector / unit_sectors * unit_sectors;
+        let unit_count = min(unit_sectors, entry.sector_count as u64 - unit_first);
+
+        let mut data = Vec::with_capacity((unit_count * CLUSTER_DATA_SIZE) as usize);
+        let mut exceptions = vec![];
+        let first_group = unit_first / group_sectors;
+        let end_group = (unit_first + unit_count + group_sectors - 1) / group_sectors;
+        for group in first_group..end_group {
+            if group >= entry.group_count as u64 {
+                return Err(invalid_data(format!("Missing WIA group for offset {:#X}", offset)));
+            }
+            let group_first = group * group_sectors;
+            let group_count = min(group_sectors, entry.sector_count as u64 - group_first);
+            let data_offset = (entry.first_sector as u64 - partition_first_sector + group_first)
+                * CLUSTER_DATA_SIZE;
+            let (group_data, lists) = self.read_group(
+                entry.group_index + group as u32,
+                (group_count * CLUSTER_DATA_SIZE) as usize,
+                data_offset,
+                exception_lists,
+            )?;
+            data.extend_from_slice(&group_data);
+            for (list_index, list) in lists.into_iter().enumerate() {
+                let list_sector = group_first - unit_first + (list_index * GROUP_CLUSTERS) as u64;
+                let base = list_sector as usize * HASH_SIZE;
+                exceptions.extend(list.into_iter().map(|e| (base + e.offset as usize, e.hash)));
+            }
+        }
+
+        let mut hashes = Vec::with_capacity(unit_count as usize * HASH_SIZE);
+        for group in data.chunks(GROUP_CLUSTERS * CLUSTER_DATA_SIZE as usize) {
+            for hash_block in build_hash_blocks(group) {
+                hashes.extend_from_slice(&hash_block);
+            }
+        }
+        for (offset, hash) in exceptions {
+            let Some(out) = hashes.get_mut(offset..offset + hash.len()) else {
+                return Err(invalid_data("Invalid WIA hash exception offset"));
+            };
+            out.copy_from_slice(&hash);
+        }
+
+        block.offset = (entry.first_sector as u64 + unit_first) * CLUSTER_SIZE;
+        block.data.resize((unit_count * CLUSTER_SIZE) as usize, 0);
+        for ((out, hashes), data) in block
+            .data
+            .chunks_exact_mut(CLUSTER_SIZE as usize)
+            .zip(hashes.chunks_exact(HASH_SIZE))
+            .zip(data.chunks(CLUSTER_DATA_SIZE as usize))
+        {
+            encrypt_cluster(
+                &partition.key,
+                hashes.try_into().unwrap(),
+                data,
+                out.try_into().unwrap(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<R> BlockReader for WiaReader<R>
+where R: Read + Seek
+{
+    fn disc_size(&self) -> u64 { self.disc_size }
+
+    fn read_block(&mut self, offset: u64, block: &mut Block) -> io::Result<()> {
+        let region = self.regions.iter().find(|r| offset >= r.start && offset < r.end).copied();
+        match region.map(|r| r.kind) {
+            Some(RegionKind::Raw(index)) => self.read_raw_block(index, offset, block),
+            Some(RegionKind::Partition { index, entry }) => {
+                self.read_partition_block(index, entry, offset, block)
+            }
+            None => {
+                // Unmapped regions read as zeroes
+                let next = self
+                    .regions
+                    .iter()
+                    .map(|r| r.start)
+                    .filter(|&start| start > offset)
+                    .min()
+                    .unwrap_or(self.disc_size);
+                let end = min(next, offset + GAP_BLOCK_SIZE).max(offset + 1);
+                block.offset = offset;
+                block.data.clear();
+                block.data.resize((end - offset) as usize, 0);
+                if offset < DISC_HEADER_SIZE as u64 {
+                    let len = min(DISC_HEADER_SIZE - offset as usize, block.data.len());
+                    block.data[..len]
+                        .copy_from_slice(&self.disc_header[offset as usize..offset as usize + len]);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_exception_lists<R>(reader: &mut R, count: usize) -> io::Result<Vec<Vec<HashException>>>
+where R: Read + ?Sized {
+    let mut lists = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = read_u16(reader)?;
+        let mut list = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            let offset = read_u16(reader)?;
+            let mut hash = [0u8; 20];
+            reader.read_exact(&mut hash)?;
+            list.push(HashException { offset, hash });
+        }
+        lists.push(list);
+    }
+    Ok(lists)
+}
+
+fn read_data<R>(
+    reader: &mut R,
+    size: usize,
+    packed_size: u32,
+    data_offset: u64,
+) -> io::Result<Vec<u8>>
+where
+    R: Read + ?Sized,
+{
+    let mut out = vec![0u8; size];
+    if packed_size == 0 {
+        reader.read_exact(&mut out)?;
+    } else {
+        rvz_unpack(&mut reader.take(packed_size as u64), &mut out, data_offset)?;
+    }
+    Ok(out)
+}
+
+/// Decompresses purged data: a list of segments, followed by a SHA-1 hash.
+/// Data not covered by a segment is zero.
+fn purge_decompress(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut out = vec![0u8; size];
+    let Some(segments) = data.len().checked_sub(20).map(|len| &data[..len]) else {
+        return Err(invalid_data("Invalid purged data"));
+    };
+    let mut reader = segments;
+    while !reader.is_empty() {
+        let offset = read_u32(&mut reader)? as usize;
+        let len = read_u32(&mut reader)? as usize;
+        let (Some(src), Some(dst)) = (reader.get(..len), out.get_mut(offset..offset + len)) else {
+            return Err(invalid_data("Invalid purged data segment"));
+        };
+        dst.copy_from_slice(src);
+        reader = &reader[len..];
+    }
+    Ok(out)
+}
+
+/// Unpacks RVZ-packed data, which replaces junk data with the seeds used to generate it.
+fn rvz_unpack<R>(reader: &mut R, out: &mut [u8], mut data_offset: u64) -> io::Result<()>
+where R: Read + ?Sized {
+    let mut lfg = LaggedFibonacci::default();
+    let mut pos = 0;
+    while pos < out.len() {
+        let size = read_u32(reader)?;
+        let len = (size & 0x7FFFFFFF) as usize;
+        let Some(dst) = out.get_mut(pos..pos + len) else {
+            return Err(invalid_data("Invalid RVZ packed data size"));
+        };
+        if size & 0x80000000 != 0 {
+            let mut seed = [0u8; SEED_SIZE];
+            reader.read_exact(&mut seed)?;
+            lfg.init(&seed);
+            lfg.skip((data_offset % CLUSTER_SIZE) as usize);
+            lfg.fill(dst);
+        } else {
+            reader.read_exact(dst)?;
+        }
+        pos += len;
+        data_offset += len as u64;
+    }
+    Ok(())
+}
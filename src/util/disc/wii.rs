@@ -0,0 +1,406 @@
+use std::{
+    cmp::min,
+    env, fs, io,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use aes::{
+    cipher::{block_padding::NoPadding, BlockDecryptMut, BlockEncryptMut, KeyIvInit},
+    Aes128,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use sha1::{Digest, Sha1};
+
+use crate::util::{
+    disc::{read_node, DiscFs, DiscHeader},
+    reader::{Endian, FromReader},
+};
+
+/// Environment variable naming the Wii common key file, used when no key file is specified.
+pub const COMMON_KEY_ENV: &str = "DTK_WII_COMMON_KEY";
+
+/// Offset of the partition tables on a Wii disc.
+const PARTITION_INFO_OFFSET: u64 = 0x40000;
+/// Size of an encrypted cluster.
+pub const CLUSTER_SIZE: u64 = 0x8000;
+/// Size of the hash block at the start of each cluster.
+pub const HASH_SIZE: usize = 0x400;
+/// Size of the user data in each cluster.
+pub const CLUSTER_DATA_SIZE: u64 = CLUSTER_SIZE - HASH_SIZE as u64;
+/// Size of the H3 table.
+const H3_SIZE: usize = 0x18000;
+
+/// Number of clusters covered by one H3 hash.
+pub const GROUP_CLUSTERS: usize = 64;
+
+type Aes128CbcDec = cbc::Decryptor<Aes128>;
+type Aes128CbcEnc = cbc::Encryptor<Aes128>;
+
+fn aes_decrypt(key: &[u8; 16], iv: &[u8; 16], data: &mut [u8]) {
+    Aes128CbcDec::new(key.into(), iv.into()).decrypt_padded_mut::<NoPadding>(data).unwrap();
+}
+
+fn aes_encrypt(key: &[u8; 16], iv: &[u8; 16], data: &mut [u8]) {
+    let len = data.len();
+    Aes128CbcEnc::new(key.into(), iv.into()).encrypt_padded_mut::<NoPadding>(data, len).unwrap();
+}
+
+/// Builds the H0, H1 and H2 hash blocks for a group of up to 64 clusters of decrypted data.
+/// Clusters missing from the end of the group are hashed as zeroes.
+pub fn build_hash_blocks(data: &[u8]) -> Vec<[u8; HASH_SIZE]> {
+    let cluster_count = (data.len() + CLUSTER_DATA_SIZE as usize - 1) / CLUSTER_DATA_SIZE as usize;
+    let zeroes = [0u8; CLUSTER_DATA_SIZE as usize];
+    let mut blocks = vec![[0u8; HASH_SIZE]; cluster_count];
+    // H0: hashes of each 0x400-byte block of data
+    let mut h0_tables = vec![[0u8; 0x26C]; GROUP_CLUSTERS];
+    for (i, table) in h0_tables.iter_mut().enumerate() {
+        let start = i * CLUSTER_DATA_SIZE as usize;
+        let cluster = data
+            .get(start..)
+            .map_or(&zeroes[..], |d| &d[..min(d.len(), CLUSTER_DATA_SIZE as usize)]);
+        for (j, hash) in table.chunks_exact_mut(20).enumerate() {
+            let block = cluster.get(j * 0x400..).unwrap_or_default();
+            let mut hasher = Sha1::new();
+            hasher.update(&block[..min(block.len(), 0x400)]);
+            hasher.update(&zeroes[..0x400 - min(block.len(), 0x400)]);
+            hash.copy_from_slice(&hasher.finalize());
+        }
+    }
+    // H1: hashes of each cluster's H0 table, per subgroup of 8 clusters
+    let mut h1_tables = vec![[0u8; 0xA0]; 8];
+    for (i, table) in h1_tables.iter_mut().enumerate() {
+        for (j, hash) in table.chunks_exact_mut(20).enumerate() {
+            hash.copy_from_slice(&Sha1::digest(h0_tables[i * 8 + j]));
+        }
+    }
+    // H2: hashes of each subgroup's H1 table
+    let mut h2_table = [0u8; 0xA0];
+    for (i, hash) in h2_table.chunks_exact_mut(20).enumerate() {
+        hash.copy_from_slice(&Sha1::digest(h1_tables[i]));
+    }
+    for (i, block) in blocks.iter_mut().enumerate() {
+        block[0..0x26C].copy_from_slice(&h0_tables[i]);
+        block[0x280..0x320].copy_from_slice(&h1_tables[i / 8]);
+        block[0x340..0x3E0].copy_from_slice(&h2_table);
+    }
+    blocks
+}
+
+/// Encrypts a cluster from its hash block and decrypted data.
+pub fn encrypt_cluster(
+    key: &[u8; 16],
+    hashes: &[u8; HASH_SIZE],
+    data: &[u8],
+    out: &mut [u8; CLUSTER_SIZE as usize],
+) {
+    let (out_hashes, out_data) = out.split_at_mut(HASH_SIZE);
+    out_hashes.copy_from_slice(hashes);
+    aes_encrypt(key, &[0u8; 16], out_hashes);
+    out_data[..data.len()].copy_from_slice(data);
+    out_data[data.len()..].fill(0);
+    // The data IV is taken from the encrypted hash block
+    let data_iv: [u8; 16] = out_hashes[0x3D0..0x3E0].try_into().unwrap();
+    aes_encrypt(key, &data_iv, out_data);
+}
+
+/// Loads the Wii common key from `path`, or from the file named by [`COMMON_KEY_ENV`].
+/// The file may contain the 16-byte key, or the key as 32 hex characters.
+pub fn load_common_key(path: Option<&Path>) -> Result<[u8; 16]> {
+    let path = match path {
+        Some(path) => path.to_owned(),
+        None => env::var_os(COMMON_KEY_ENV).map(PathBuf::from).ok_or_else(|| {
+            anyhow!("Wii common key required: specify a key file or set {}", COMMON_KEY_ENV)
+        })?,
+    };
+    let data = fs::read(&path)
+        .with_context(|| format!("Failed to read common key '{}'", path.display()))?;
+    let mut key = [0u8; 16];
+    if data.len() == key.len() {
+        key.copy_from_slice(&data);
+    } else {
+        let text = std::str::from_utf8(&data)
+            .map_err(|_| anyhow!("Invalid common key file '{}'", path.display()))?;
+        hex::decode_to_slice(text.trim(), &mut key)
+            .with_context(|| format!("Invalid common key file '{}'", path.display()))?;
+    }
+    Ok(key)
+}
+
+/// A partition on a Wii disc.
+#[derive(Debug, Clone)]
+pub struct WiiPartition {
+    /// Partition type. (0: data, 1: update, 2: channel)
+    pub kind: u32,
+    /// Name used in paths, such as `DATA` or `UPDATE`.
+    pub name: String,
+    /// Offset of the partition.
+    pub offset: u64,
+    /// Decrypted title key.
+    pub title_key: [u8; 16],
+    /// Offset of the H3 table.
+    pub h3_offset: u64,
+    /// Offset of the encrypted partition data.
+    pub data_offset: u64,
+    /// Size of the encrypted partition data.
+    pub data_size: u64,
+}
+
+impl WiiPartition {
+    /// Creates a reader over the decrypted partition data, verifying hashes as clusters are read.
+    pub fn reader<R>(&self, mut reader: R) -> Result<PartitionReader<R>>
+    where R: Read + Seek {
+        reader.seek(SeekFrom::Start(self.h3_offset))?;
+        let mut h3 = vec![0u8; H3_SIZE];
+        reader.read_exact(&mut h3).context("Failed to read H3 table")?;
+        Ok(PartitionReader {
+            reader,
+            data_offset: self.data_offset,
+            cluster_count: self.data_size / CLUSTER_SIZE,
+            key: self.title_key,
+            h3,
+            raw: vec![0u8; CLUSTER_SIZE as usize],
+            cluster: None,
+            pos: 0,
+        })
+    }
+}
+
+fn partition_name(kind: u32) -> String {
+    match kind {
+        0 => "DATA".to_string(),
+        1 => "UPDATE".to_string(),
+        2 => "CHANNEL".to_string(),
+        kind => {
+            let bytes = kind.to_be_bytes();
+            if bytes.iter().all(|c| c.is_ascii_alphanumeric()) {
+                String::from_utf8_lossy(&bytes).into_owned()
+            } else {
+                format!("P{:08X}", kind)
+            }
+        }
+    }
+}
+
+/// A Wii disc and its partitions.
+pub struct WiiDisc {
+    pub header: DiscHeader,
+    pub partitions: Vec<WiiPartition>,
+}
+
+impl WiiDisc {
+    /// Reads the partition tables and decrypts each partition's title key.
+    pub fn new<R>(reader: &mut R, common_key: &[u8; 16]) -> Result<Self>
+    where R: Read + Seek + ?Sized {
+        reader.seek(SeekFrom::Start(0))?;
+        let header = DiscHeader::from_reader(reader, Endian::Big)?;
+        ensure!(header.is_wii(), "Not a Wii disc (game ID {})", header.game_id_str());
+
+        reader.seek(SeekFrom::Start(PARTITION_INFO_OFFSET))?;
+        let tables = <[u32; 8]>::from_reader(reader, Endian::Big)?;
+        let mut entries = vec![];
+        for table in tables.chunks_exact(2) {
+            let (count, offset) = (table[0], (table[1] as u64) << 2);
+            ensure!(count <= 0x100, "Invalid partition count: {}", count);
+            reader.seek(SeekFrom::Start(offset))?;
+            for _ in 0..count {
+                let offset = (u32::from_reader(reader, Endian::Big)? as u64) << 2;
+                let kind = u32::from_reader(reader, Endian::Big)?;
+                entries.push((offset, kind));
+            }
+        }
+
+        let mut partitions: Vec<WiiPartition> = Vec::with_capacity(entries.len());
+        for (offset, kind) in entries {
+            let mut name = partition_name(kind);
+            let duplicates = partitions.iter().filter(|p| p.kind == kind).count();
+            if duplicates > 0 {
+                name = format!("{}{}", name, duplicates);
+            }
+            partitions.push(
+                read_partition(reader, offset, kind, name, common_key)
+                    .with_context(|| format!("Failed to read partition at {:#X}", offset))?,
+            );
+        }
+        Ok(Self { header, partitions })
+    }
+
+    /// Finds a partition by name. Comparison is case-insensitive.
+    pub fn partition(&self, name: &str) -> Option<&WiiPartition> {
+        self.partitions.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+fn read_partition<R>(
+    reader: &mut R,
+    offset: u64,
+    kind: u32,
+    name: String,
+    common_key: &[u8; 16],
+) -> Result<WiiPartition>
+where
+    R: Read + Seek + ?Sized,
+{
+    // Ticket
+    reader.seek(SeekFrom::Start(offset + 0x1BF))?;
+    let mut title_key = <[u8; 16]>::from_reader(reader, Endian::Big)?;
+    reader.seek(SeekFrom::Start(offset + 0x1DC))?;
+    let title_id = <[u8; 8]>::from_reader(reader, Endian::Big)?;
+    reader.seek(SeekFrom::Start(offset + 0x1F1))?;
+    let common_key_index = u8::from_reader(reader, Endian::Big)?;
+    if common_key_index != 0 {
+        log::warn!(
+            "Partition {} uses common key index {}; the supplied key must match",
+            name,
+            common_key_index
+        );
+    }
+    let mut iv = [0u8; 16];
+    iv[..8].copy_from_slice(&title_id);
+    aes_decrypt(common_key, &iv, &mut title_key);
+
+    // Partition header, following the ticket
+    reader.seek(SeekFrom::Start(offset + 0x2B4))?;
+    let h3_offset = (u32::from_reader(reader, Endian::Big)? as u64) << 2;
+    let data_offset = (u32::from_reader(reader, Endian::Big)? as u64) << 2;
+    let data_size = (u32::from_reader(reader, Endian::Big)? as u64) << 2;
+    Ok(WiiPartition {
+        kind,
+        name,
+        offset,
+        title_key,
+        h3_offset: offset + h3_offset,
+        data_offset: offset + data_offset,
+        data_size,
+    })
+}
+
+/// Reads decrypted partition data, verifying the hash tree of each cluster as it's loaded.
+pub struct PartitionReader<R> {
+    reader: R,
+    data_offset: u64,
+    cluster_count: u64,
+    key: [u8; 16],
+    h3: Vec<u8>,
+    /// Encrypted cluster buffer, decrypted in place.
+    raw: Vec<u8>,
+    /// Index of the cluster currently in `raw`.
+    cluster: Option<u64>,
+    pos: u64,
+}
+
+fn hash_error(cluster: u64, level: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} hash mismatch in cluster {} (wrong common key?)", level, cluster),
+    )
+}
+
+impl<R> PartitionReader<R>
+where R: Read + Seek
+{
+    /// Size of the decrypted partition data.
+    pub fn len(&self) -> u64 { self.cluster_count * CLUSTER_DATA_SIZE }
+
+    pub fn is_empty(&self) -> bool { self.cluster_count == 0 }
+
+    fn load_cluster(&mut self, cluster: u64) -> io::Result<()> {
+        if self.cluster == Some(cluster) {
+            return Ok(());
+        }
+        self.cluster = None;
+        self.reader.seek(SeekFrom::Start(self.data_offset + cluster * CLUSTER_SIZE))?;
+        self.reader.read_exact(&mut self.raw)?;
+
+        // The data IV is taken from the encrypted hash block
+        let data_iv: [u8; 16] = self.raw[0x3D0..0x3E0].try_into().unwrap();
+        let (hashes, data) = self.raw.split_at_mut(HASH_SIZE);
+        aes_decrypt(&self.key, &[0u8; 16], hashes);
+        aes_decrypt(&self.key, &data_iv, data);
+
+        // H0: hashes of each 0x400-byte block of data
+        for (i, block) in data.chunks_exact(0x400).enumerate() {
+            if Sha1::digest(block).as_slice() != &hashes[i * 20..(i + 1) * 20] {
+                return Err(hash_error(cluster, "H0"));
+            }
+        }
+        // H1: hash of this cluster's H0 table, within its subgroup of 8 clusters
+        let h1_index = (cluster % 8) as usize;
+        if Sha1::digest(&hashes[0..0x26C]).as_slice()
+            != &hashes[0x280 + h1_index * 20..0x280 + (h1_index + 1) * 20]
+        {
+            return Err(hash_error(cluster, "H1"));
+        }
+        // H2: hash of this subgroup's H1 table, within its group of 8 subgroups
+        let h2_index = ((cluster / 8) % 8) as usize;
+        if Sha1::digest(&hashes[0x280..0x320]).as_slice()
+            != &hashes[0x340 + h2_index * 20..0x340 + (h2_index + 1) * 20]
+        {
+            return Err(hash_error(cluster, "H2"));
+        }
+        // H3: hash of this group's H2 table
+        let h3_index = (cluster / 64) as usize;
+        if Sha1::digest(&hashes[0x340..0x3E0]).as_slice()
+            != &self.h3[h3_index * 20..(h3_index + 1) * 20]
+        {
+            return Err(hash_error(cluster, "H3"));
+        }
+        self.cluster = Some(cluster);
+        Ok(())
+    }
+}
+
+impl<R> Read for PartitionReader<R>
+where R: Read + Seek
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let cluster = self.pos / CLUSTER_DATA_SIZE;
+        if cluster >= self.cluster_count || buf.is_empty() {
+            return Ok(0);
+        }
+        self.load_cluster(cluster)?;
+        let offset = (self.pos % CLUSTER_DATA_SIZE) as usize;
+        let len = min(buf.len(), CLUSTER_DATA_SIZE as usize - offset);
+        let start = HASH_SIZE + offset;
+        buf[..len].copy_from_slice(&self.raw[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R> Seek for PartitionReader<R>
+where R: Read + Seek
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(offset) => self.len().saturating_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.saturating_add_signed(offset),
+        };
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> { Ok(self.pos) }
+}
+
+/// Reads a file from a Wii disc. The first path component names the partition,
+/// such as `DATA/sys/main.dol`.
+pub fn read_file<R>(reader: R, path: &Path, common_key: &[u8; 16]) -> Result<Vec<u8>>
+where R: Read + Seek {
+    let mut reader = reader;
+    let disc = WiiDisc::new(&mut reader, common_key)?;
+    let mut components = path.components();
+    let Some(partition_name) = components.next().and_then(|c| c.as_os_str().to_str()) else {
+        bail!("Invalid Wii disc path '{}'", path.display());
+    };
+    let Some(partition) = disc.partition(partition_name) else {
+        bail!("Partition '{}' not found", partition_name);
+    };
+    let mut reader = partition.reader(reader)?;
+    let fs = DiscFs::new(&mut reader)?;
+    let file_path = components.as_path();
+    let Some(node) = fs.find_file(file_path)? else {
+        bail!("File '{}' not found in partition {}", file_path.display(), partition.name);
+    };
+    read_node(&mut reader, node)
+}
@@ -1,19 +1,27 @@
 use std::{
     cmp::max,
-    collections::BTreeMap,
+    collections::{btree_map, BTreeMap, BTreeSet},
     convert::TryFrom,
     fmt::{Display, Formatter, Write},
-    io::{BufRead, Cursor, Seek, SeekFrom},
+    io::{BufRead, Cursor, Read, Seek, SeekFrom},
+    mem::take,
     num::NonZeroU32,
+    ops::Range,
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
+use cwdemangle::demangle;
 use indent::indent_all_by;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use serde::{Serialize, Serializer};
 
 use crate::{
     array_ref,
-    util::reader::{Endian, FromBytes, FromReader},
+    obj::{
+        ObjDataKind, ObjInfo, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolKind, ObjSymbolScope,
+        ObjUnit,
+    },
+    util::reader::{Endian, FromBytes, FromReader, ToWriter},
 };
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
@@ -104,15 +112,16 @@ impl FundType {
             | FundType::UnsignedLong
             | FundType::Pointer
             | FundType::Float => 4,
+            // long double is double precision on PowerPC
             FundType::DblPrecFloat
+            | FundType::ExtPrecFloat
             | FundType::LongLong
             | FundType::SignedLongLong
             | FundType::UnsignedLongLong
             | FundType::Vec2x32Float => 8,
             FundType::Int128 => 16,
             FundType::Void => 0,
-            FundType::ExtPrecFloat
-            | FundType::Complex
+            FundType::Complex
             | FundType::DblPrecComplex
             | FundType::ExtPrecComplex
             | FundType::Label => bail!("Unhandled fundamental type {self:?}"),
@@ -153,8 +162,19 @@ impl FundType {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+impl Serialize for FundType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        match self.name() {
+            Ok(name) => serializer.serialize_str(name),
+            Err(_) => serializer.serialize_str(&format!("{self:?}")),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive, Serialize)]
 #[repr(u8)]
+#[serde(rename_all = "snake_case")]
 pub enum Modifier {
     PointerTo = 0x01,
     ReferenceTo = 0x02,
@@ -478,8 +498,17 @@ where R: BufRead + Seek + ?Sized {
     Ok(info)
 }
 
-#[allow(unused)]
-pub fn read_aranges_section<R>(reader: &mut R, e: Endian) -> Result<()>
+/// Address ranges covered by a compile unit.
+#[derive(Debug, Clone)]
+pub struct UnitRanges {
+    /// Offset of the compile unit in the .debug or .debug_info section.
+    pub offset: u32,
+    pub ranges: Vec<Range<u32>>,
+}
+
+/// Reads compile unit address ranges from the .debug_aranges section. Both the DWARF 1.1
+/// (version 1) and DWARF 2 (version 2) layouts are supported.
+pub fn read_aranges_section<R>(reader: &mut R, e: Endian) -> Result<Vec<UnitRanges>>
 where R: BufRead + Seek + ?Sized {
     let len = {
         let old_pos = reader.stream_position()?;
@@ -488,7 +517,7 @@ where R: BufRead + Seek + ?Sized {
         len
     };
 
-    // let mut tags = BTreeMap::new();
+    let mut units = Vec::new();
     loop {
         let position = reader.stream_position()?;
         if position >= len {
@@ -496,16 +525,298 @@ where R: BufRead + Seek + ?Sized {
         }
 
         let size = u32::from_reader(reader, e)?;
-        let version = u8::from_reader(reader, e)?;
-        ensure!(version == 1, "Expected version 1, got {version}");
-        let _debug_offs = u32::from_reader(reader, e)?;
-        let _debug_size = u32::from_reader(reader, e)?;
-        while reader.stream_position()? < position + size as u64 {
-            let _address = u32::from_reader(reader, e)?;
-            let _length = u32::from_reader(reader, e)?;
+        let end = position + 4 + size as u64;
+        let version = u16::from_reader(reader, e)?;
+        let mut ranges = Vec::new();
+        let offset = if version == 2 {
+            let offset = u32::from_reader(reader, e)?;
+            let address_size = u8::from_reader(reader, e)?;
+            ensure!(address_size == 4, "Unsupported address size {}", address_size);
+            let _segment_size = u8::from_reader(reader, e)?;
+            // Tuples are aligned to twice the address size
+            reader.seek(SeekFrom::Start(position + 16))?;
+            while reader.stream_position()? + 8 <= end {
+                let address = u32::from_reader(reader, e)?;
+                let length = u32::from_reader(reader, e)?;
+                if address == 0 && length == 0 {
+                    break;
+                }
+                ranges.push(address..address + length);
+            }
+            reader.seek(SeekFrom::Start(end))?;
+            offset
+        } else {
+            // In DWARF 1.1, the size includes the size field itself and the version is a byte
+            reader.seek(SeekFrom::Start(position + 4))?;
+            let version = u8::from_reader(reader, e)?;
+            ensure!(version == 1, "Expected version 1, got {version}");
+            let offset = u32::from_reader(reader, e)?;
+            let _debug_size = u32::from_reader(reader, e)?;
+            while reader.stream_position()? < position + size as u64 {
+                let address = u32::from_reader(reader, e)?;
+                let length = u32::from_reader(reader, e)?;
+                ranges.push(address..address + length);
+            }
+            offset
+        };
+        units.push(UnitRanges { offset, ranges });
+    }
+    Ok(units)
+}
+
+/// A row of a line number table. Each row covers the addresses up to the next row.
+#[derive(Debug, Clone)]
+pub struct LineRow {
+    pub address: u32,
+    /// Index into [`LineTable::files`], which may be out of range for malformed input.
+    pub file: usize,
+    pub line: u32,
+    /// Column (or character position) within the line, if known.
+    pub column: Option<u32>,
+}
+
+/// A contiguous sequence of line number rows.
+#[derive(Debug, Clone)]
+pub struct LineTable {
+    pub files: Vec<String>,
+    pub rows: Vec<LineRow>,
+    /// The address just past the last row.
+    pub end_address: u32,
+}
+
+impl LineTable {
+    /// Finds the row covering an address.
+    pub fn find_row(&self, address: u32) -> Option<&LineRow> {
+        if address >= self.end_address {
+            return None;
         }
+        let idx = self.rows.partition_point(|row| row.address <= address);
+        idx.checked_sub(1).map(|idx| &self.rows[idx])
+    }
+
+    /// Name of a file by index, or `??` if it's out of range.
+    pub fn file_name(&self, file: usize) -> &str {
+        self.files.get(file).map_or("??", String::as_str)
+    }
+}
+
+/// Finds the line table and row covering an address.
+pub fn find_line(tables: &[LineTable], address: u32) -> Option<(&LineTable, &LineRow)> {
+    tables.iter().find_map(|table| table.find_row(address).map(|row| (table, row)))
+}
+
+/// Reads DWARF 1.1 line number tables from the .line section. Tables don't contain file
+/// names, so the name of the compile unit referencing each table is used instead.
+pub fn read_line_section(data: &[u8], info: &DwarfInfo) -> Result<Vec<LineTable>> {
+    let e = info.e;
+    let mut units = BTreeMap::<u32, &Tag>::new();
+    for tag in info.tags.values() {
+        if tag.kind == TagKind::CompileUnit {
+            if let Some(offset) = tag.data4_attribute(AttributeKind::StmtList) {
+                units.insert(offset, tag);
+            }
+        }
+    }
+
+    let mut reader = Cursor::new(data);
+    let mut tables = Vec::new();
+    while (reader.position() as usize) < data.len() {
+        let position = reader.position();
+        let size = u32::from_reader(&mut reader, e)?;
+        ensure!(size >= 8, "Invalid line table size {:#X} at {:#X}", size, position);
+        let end = position + size as u64;
+        ensure!(end <= data.len() as u64, "Line table at {:#X} exceeds section size", position);
+        let base_address = u32::from_reader(&mut reader, e)?;
+        let unit = units.get(&(position as u32));
+        let name = unit
+            .and_then(|tag| tag.string_attribute(AttributeKind::Name))
+            .cloned()
+            .unwrap_or_else(|| "[unknown]".to_string());
+
+        let mut rows = Vec::new();
+        let mut end_address = None;
+        while reader.position() + 10 <= end {
+            let line = u32::from_reader(&mut reader, e)?;
+            let column = u16::from_reader(&mut reader, e)?;
+            let address = base_address.wrapping_add(u32::from_reader(&mut reader, e)?);
+            if line == 0 {
+                // End of sequence
+                end_address = Some(address);
+                break;
+            }
+            rows.push(LineRow {
+                address,
+                file: 0,
+                line,
+                // 0xFFFF: no specific position
+                column: (column != 0xFFFF).then_some(column as u32),
+            });
+        }
+        reader.set_position(end);
+
+        let end_address = end_address
+            .or_else(|| unit.and_then(|tag| tag.address_attribute(AttributeKind::HighPc)))
+            .or_else(|| rows.last().map(|row| row.address + 4))
+            .unwrap_or(base_address);
+        if !rows.is_empty() {
+            tables.push(LineTable { files: vec![name], rows, end_address });
+        }
+    }
+    Ok(tables)
+}
+
+/// Reads DWARF 2 line number programs from the .debug_line section. Each sequence
+/// becomes a separate table.
+pub fn read_debug_line_section(data: &[u8], e: Endian) -> Result<Vec<LineTable>> {
+    let mut reader = Cursor::new(data);
+    let mut tables = Vec::new();
+    while (reader.position() as usize) < data.len() {
+        let position = reader.position();
+        let size = u32::from_reader(&mut reader, e)?;
+        ensure!(size != 0xFFFFFFFF, "64-bit DWARF is not supported");
+        let end = reader.position() + size as u64;
+        ensure!(end <= data.len() as u64, "Line program at {:#X} exceeds section size", position);
+        let version = u16::from_reader(&mut reader, e)?;
+        ensure!((2..=4).contains(&version), "Unsupported line program version {}", version);
+        let header_size = u32::from_reader(&mut reader, e)?;
+        let program_start = reader.position() + header_size as u64;
+        let min_inst_length = u8::from_reader(&mut reader, e)? as u32;
+        if version >= 4 {
+            let _max_ops_per_inst = u8::from_reader(&mut reader, e)?;
+        }
+        let default_is_stmt = u8::from_reader(&mut reader, e)? != 0;
+        let line_base = u8::from_reader(&mut reader, e)? as i8 as i64;
+        let line_range = u8::from_reader(&mut reader, e)?;
+        ensure!(line_range != 0, "Invalid line range in line program at {:#X}", position);
+        let opcode_base = u8::from_reader(&mut reader, e)?;
+        let mut opcode_lengths = Vec::with_capacity(opcode_base.saturating_sub(1) as usize);
+        for _ in 1..opcode_base {
+            opcode_lengths.push(u8::from_reader(&mut reader, e)?);
+        }
+        let mut directories = Vec::new();
+        loop {
+            let directory = read_string(&mut reader)?;
+            if directory.is_empty() {
+                break;
+            }
+            directories.push(directory);
+        }
+        let mut files = Vec::new();
+        loop {
+            let name = read_string(&mut reader)?;
+            if name.is_empty() {
+                break;
+            }
+            let directory = read_uleb128(&mut reader)?;
+            let _mtime = read_uleb128(&mut reader)?;
+            let _length = read_uleb128(&mut reader)?;
+            files.push(line_file_name(&directories, directory, name));
+        }
+        reader.set_position(program_start);
+
+        let mut address = 0u32;
+        let mut file = 1u64;
+        let mut line = 1i64;
+        let mut column = 0u64;
+        let mut rows = Vec::new();
+        let mut is_stmt = default_is_stmt;
+        while reader.position() < end {
+            let opcode = u8::from_reader(&mut reader, e)?;
+            let mut emit = false;
+            let mut end_sequence = false;
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                address = address.wrapping_add((adjusted / line_range) as u32 * min_inst_length);
+                line += line_base + (adjusted % line_range) as i64;
+                emit = true;
+            } else {
+                match opcode {
+                    0 => {
+                        let length = read_uleb128(&mut reader)?;
+                        let next = reader.position() + length;
+                        match u8::from_reader(&mut reader, e)? {
+                            DW_LNE_END_SEQUENCE => end_sequence = true,
+                            DW_LNE_SET_ADDRESS => address = u32::from_reader(&mut reader, e)?,
+                            DW_LNE_DEFINE_FILE => {
+                                let name = read_string(&mut reader)?;
+                                let directory = read_uleb128(&mut reader)?;
+                                files.push(line_file_name(&directories, directory, name));
+                            }
+                            _ => {}
+                        }
+                        reader.set_position(next);
+                    }
+                    DW_LNS_COPY => emit = true,
+                    DW_LNS_ADVANCE_PC => {
+                        address = address.wrapping_add(
+                            (read_uleb128(&mut reader)? as u32).wrapping_mul(min_inst_length),
+                        )
+                    }
+                    DW_LNS_ADVANCE_LINE => line += read_sleb128(&mut reader)?,
+                    DW_LNS_SET_FILE => file = read_uleb128(&mut reader)?,
+                    DW_LNS_SET_COLUMN => column = read_uleb128(&mut reader)?,
+                    DW_LNS_NEGATE_STMT => is_stmt = !is_stmt,
+                    DW_LNS_CONST_ADD_PC => {
+                        address = address.wrapping_add(
+                            ((255 - opcode_base) / line_range) as u32 * min_inst_length,
+                        )
+                    }
+                    DW_LNS_FIXED_ADVANCE_PC => {
+                        address = address.wrapping_add(u16::from_reader(&mut reader, e)? as u32)
+                    }
+                    _ => {
+                        // Skip unknown standard opcodes, and ones that don't affect rows
+                        for _ in 0..opcode_lengths[opcode as usize - 1] {
+                            read_uleb128(&mut reader)?;
+                        }
+                    }
+                }
+            }
+            if end_sequence {
+                if !rows.is_empty() {
+                    tables.push(LineTable {
+                        files: files.clone(),
+                        rows: take(&mut rows),
+                        end_address: address,
+                    });
+                }
+                address = 0;
+                file = 1;
+                line = 1;
+                column = 0;
+                is_stmt = default_is_stmt;
+            } else if emit {
+                rows.push(LineRow {
+                    address,
+                    // File 0 is invalid, and won't be found in the table
+                    file: (file as usize).wrapping_sub(1),
+                    line: line as u32,
+                    column: (column != 0).then_some(column as u32),
+                });
+            }
+        }
+        reader.set_position(end);
+    }
+    Ok(tables)
+}
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_FILE: u8 = 0x04;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNS_NEGATE_STMT: u8 = 0x06;
+const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+const DW_LNE_DEFINE_FILE: u8 = 0x03;
+
+fn line_file_name(directories: &[String], directory: u64, name: String) -> String {
+    match directory.checked_sub(1).and_then(|idx| directories.get(idx as usize)) {
+        Some(directory) if !name.starts_with('/') => format!("{}/{}", directory, name),
+        _ => name,
     }
-    Ok(())
 }
 
 fn read_tag<R>(reader: &mut R, e: Endian) -> Result<Tag>
@@ -576,7 +887,1146 @@ where R: BufRead + Seek + ?Sized {
     Ok(Attribute { kind: attr, value })
 }
 
+#[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[repr(u16)]
+enum Dwarf2Tag {
+    ArrayType = 0x01,
+    ClassType = 0x02,
+    EntryPoint = 0x03,
+    EnumerationType = 0x04,
+    FormalParameter = 0x05,
+    Label = 0x0a,
+    LexicalBlock = 0x0b,
+    Member = 0x0d,
+    PointerType = 0x0f,
+    ReferenceType = 0x10,
+    CompileUnit = 0x11,
+    StructureType = 0x13,
+    SubroutineType = 0x15,
+    Typedef = 0x16,
+    UnionType = 0x17,
+    UnspecifiedParameters = 0x18,
+    Inheritance = 0x1c,
+    InlinedSubroutine = 0x1d,
+    PtrToMemberType = 0x1f,
+    SubrangeType = 0x21,
+    BaseType = 0x24,
+    CatchBlock = 0x25,
+    ConstType = 0x26,
+    Enumerator = 0x28,
+    Subprogram = 0x2e,
+    TryBlock = 0x32,
+    Variable = 0x34,
+    VolatileType = 0x35,
+    RestrictType = 0x37,
+    Namespace = 0x39,
+    UnspecifiedType = 0x3b,
+    RvalueReferenceType = 0x42,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[repr(u16)]
+enum Dwarf2Attribute {
+    Location = 0x02,
+    Name = 0x03,
+    ByteSize = 0x0b,
+    BitOffset = 0x0c,
+    BitSize = 0x0d,
+    StmtList = 0x10,
+    LowPc = 0x11,
+    HighPc = 0x12,
+    Language = 0x13,
+    CompDir = 0x1b,
+    ConstValue = 0x1c,
+    ContainingType = 0x1d,
+    Inline = 0x20,
+    LowerBound = 0x22,
+    Producer = 0x25,
+    Prototyped = 0x27,
+    UpperBound = 0x2f,
+    AbstractOrigin = 0x31,
+    Accessibility = 0x32,
+    Count = 0x37,
+    DataMemberLocation = 0x38,
+    Declaration = 0x3c,
+    Encoding = 0x3e,
+    External = 0x3f,
+    FrameBase = 0x40,
+    Specification = 0x47,
+    Type = 0x49,
+    Virtuality = 0x4c,
+    LinkageName = 0x6e,
+    MipsLinkageName = 0x2007,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
+#[repr(u16)]
+enum Dwarf2Form {
+    Addr = 0x01,
+    Block2 = 0x03,
+    Block4 = 0x04,
+    Data2 = 0x05,
+    Data4 = 0x06,
+    Data8 = 0x07,
+    String = 0x08,
+    Block = 0x09,
+    Block1 = 0x0a,
+    Data1 = 0x0b,
+    Flag = 0x0c,
+    Sdata = 0x0d,
+    Strp = 0x0e,
+    Udata = 0x0f,
+    RefAddr = 0x10,
+    Ref1 = 0x11,
+    Ref2 = 0x12,
+    Ref4 = 0x13,
+    Ref8 = 0x14,
+    RefUdata = 0x15,
+    Indirect = 0x16,
+    SecOffset = 0x17,
+    Exprloc = 0x18,
+    FlagPresent = 0x19,
+}
+
+// DW_ATE_*
+const DW_ATE_ADDRESS: u64 = 0x1;
+const DW_ATE_BOOLEAN: u64 = 0x2;
+const DW_ATE_FLOAT: u64 = 0x4;
+const DW_ATE_SIGNED: u64 = 0x5;
+const DW_ATE_SIGNED_CHAR: u64 = 0x6;
+const DW_ATE_UNSIGNED: u64 = 0x7;
+const DW_ATE_UNSIGNED_CHAR: u64 = 0x8;
+
+// DW_ACCESS_*
+const DW_ACCESS_PUBLIC: u64 = 0x1;
+const DW_ACCESS_PROTECTED: u64 = 0x2;
+const DW_ACCESS_PRIVATE: u64 = 0x3;
+
+// DW_INL_*
+const DW_INL_DECLARED_NOT_INLINED: u64 = 0x2;
+const DW_INL_DECLARED_INLINED: u64 = 0x3;
+
+// DW_OP_*
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_PLUS_UCONST: u8 = 0x23;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REG31: u8 = 0x6f;
+const DW_OP_BREG0: u8 = 0x70;
+const DW_OP_BREG31: u8 = 0x8f;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_FBREG: u8 = 0x91;
+const DW_OP_BREGX: u8 = 0x92;
+
+#[derive(Debug, Clone)]
+enum Dwarf2Value {
+    Address(u32),
+    Unsigned(u64),
+    Signed(i64),
+    Flag(bool),
+    Block(Vec<u8>),
+    String(String),
+    /// Offset into .debug_info
+    Reference(u32),
+}
+
+#[derive(Debug, Clone)]
+struct Dwarf2Abbrev {
+    tag: u64,
+    has_children: bool,
+    attributes: Vec<(u64, u64)>,
+}
+
 #[derive(Debug, Clone)]
+struct Dwarf2Entry {
+    offset: u32,
+    tag: Option<Dwarf2Tag>,
+    attributes: Vec<(Dwarf2Attribute, Dwarf2Value)>,
+    parent: Option<u32>,
+    /// Offset following this entry and all of its children
+    end: u32,
+}
+
+type Dwarf2EntryMap = BTreeMap<u32, Dwarf2Entry>;
+
+impl Dwarf2Entry {
+    #[inline]
+    fn attribute(&self, kind: Dwarf2Attribute) -> Option<&Dwarf2Value> {
+        self.attributes.iter().find(|(k, _)| *k == kind).map(|(_, v)| v)
+    }
+
+    fn unsigned(&self, kind: Dwarf2Attribute) -> Option<u64> {
+        match self.attribute(kind) {
+            Some(&Dwarf2Value::Unsigned(value)) => Some(value),
+            Some(&Dwarf2Value::Signed(value)) => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    fn reference(&self, kind: Dwarf2Attribute) -> Option<u32> {
+        match self.attribute(kind) {
+            Some(&Dwarf2Value::Reference(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn string(&self, kind: Dwarf2Attribute) -> Option<&str> {
+        match self.attribute(kind) {
+            Some(Dwarf2Value::String(str)) => Some(str),
+            _ => None,
+        }
+    }
+
+    fn flag(&self, kind: Dwarf2Attribute) -> bool {
+        match self.attribute(kind) {
+            Some(&Dwarf2Value::Flag(value)) => value,
+            Some(&Dwarf2Value::Unsigned(value)) => value != 0,
+            _ => false,
+        }
+    }
+
+    fn children<'a>(&self, entries: &'a Dwarf2EntryMap) -> impl Iterator<Item = &'a Dwarf2Entry> {
+        let offset = self.offset;
+        entries
+            .range(offset + 1..self.end)
+            .map(|(_, entry)| entry)
+            .filter(move |entry| entry.parent == Some(offset))
+    }
+
+    /// Returns the entry referenced by DW_AT_specification or DW_AT_abstract_origin, if any
+    fn origin<'a>(&self, entries: &'a Dwarf2EntryMap) -> Option<&'a Dwarf2Entry> {
+        self.reference(Dwarf2Attribute::Specification)
+            .or_else(|| self.reference(Dwarf2Attribute::AbstractOrigin))
+            .and_then(|key| entries.get(&key))
+    }
+
+    /// Looks up an attribute, falling back to the specification or abstract origin entry
+    fn merged_attribute<'a>(
+        &'a self,
+        entries: &'a Dwarf2EntryMap,
+        kind: Dwarf2Attribute,
+    ) -> Option<&'a Dwarf2Value> {
+        let mut entry = self;
+        // Guard against reference cycles
+        for _ in 0..8 {
+            if let Some(value) = entry.attribute(kind) {
+                return Some(value);
+            }
+            entry = entry.origin(entries)?;
+        }
+        None
+    }
+}
+
+fn read_uleb128<R>(reader: &mut R) -> Result<u64>
+where R: Read + Seek + ?Sized {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+fn read_sleb128<R>(reader: &mut R) -> Result<i64>
+where R: Read + Seek + ?Sized {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7f) as i64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                result |= -1i64 << shift;
+            }
+            return Ok(result);
+        }
+    }
+}
+
+/// Reads DWARF 2 information from the .debug_info, .debug_abbrev and (optional) .debug_str
+/// sections. Entries are translated into the DWARF 1.1 tag model, keyed by their offset
+/// in .debug_info, so that they can be processed the same way as a .debug section.
+pub fn read_debug_info_section(
+    data: &[u8],
+    abbrev_data: &[u8],
+    str_data: Option<&[u8]>,
+    e: Endian,
+) -> Result<DwarfInfo> {
+    let entries = read_dwarf2_entries(data, abbrev_data, str_data, e)?;
+    translate_dwarf2_entries(&entries, e)
+}
+
+fn read_dwarf2_abbrevs(data: &[u8], offset: u32) -> Result<BTreeMap<u64, Dwarf2Abbrev>> {
+    ensure!((offset as usize) < data.len(), "Invalid .debug_abbrev offset {:#X}", offset);
+    let mut reader = Cursor::new(data);
+    reader.set_position(offset as u64);
+    let mut abbrevs = BTreeMap::new();
+    loop {
+        let code = read_uleb128(&mut reader)?;
+        if code == 0 {
+            break;
+        }
+        let tag = read_uleb128(&mut reader)?;
+        let has_children = u8::from_reader(&mut reader, Endian::Big)? != 0;
+        let mut attributes = Vec::new();
+        loop {
+            let name = read_uleb128(&mut reader)?;
+            let form = read_uleb128(&mut reader)?;
+            if name == 0 && form == 0 {
+                break;
+            }
+            attributes.push((name, form));
+        }
+        abbrevs.insert(code, Dwarf2Abbrev { tag, has_children, attributes });
+    }
+    Ok(abbrevs)
+}
+
+fn read_dwarf2_value<R>(
+    reader: &mut R,
+    form: u64,
+    unit_offset: u32,
+    str_data: Option<&[u8]>,
+    e: Endian,
+) -> Result<Dwarf2Value>
+where
+    R: BufRead + Seek + ?Sized,
+{
+    let form = u16::try_from(form)
+        .ok()
+        .and_then(|form| Dwarf2Form::try_from(form).ok())
+        .ok_or_else(|| anyhow!("Unknown DWARF 2 form {:#X}", form))?;
+    let read_block = |reader: &mut R, size: u64| -> Result<Dwarf2Value> {
+        let mut data = vec![0u8; size as usize];
+        reader.read_exact(&mut data)?;
+        Ok(Dwarf2Value::Block(data))
+    };
+    Ok(match form {
+        Dwarf2Form::Addr => Dwarf2Value::Address(u32::from_reader(reader, e)?),
+        Dwarf2Form::Block1 => {
+            let size = u8::from_reader(reader, e)? as u64;
+            read_block(reader, size)?
+        }
+        Dwarf2Form::Block2 => {
+            let size = u16::from_reader(reader, e)? as u64;
+            read_block(reader, size)?
+        }
+        Dwarf2Form::Block4 => {
+            let size = u32::from_reader(reader, e)? as u64;
+            read_block(reader, size)?
+        }
+        Dwarf2Form::Block | Dwarf2Form::Exprloc => {
+            let size = read_uleb128(reader)?;
+            read_block(reader, size)?
+        }
+        Dwarf2Form::Data1 => Dwarf2Value::Unsigned(u8::from_reader(reader, e)? as u64),
+        Dwarf2Form::Data2 => Dwarf2Value::Unsigned(u16::from_reader(reader, e)? as u64),
+        Dwarf2Form::Data4 | Dwarf2Form::SecOffset => {
+            Dwarf2Value::Unsigned(u32::from_reader(reader, e)? as u64)
+        }
+        Dwarf2Form::Data8 => Dwarf2Value::Unsigned(u64::from_reader(reader, e)?),
+        Dwarf2Form::Sdata => Dwarf2Value::Signed(read_sleb128(reader)?),
+        Dwarf2Form::Udata => Dwarf2Value::Unsigned(read_uleb128(reader)?),
+        Dwarf2Form::Flag => Dwarf2Value::Flag(u8::from_reader(reader, e)? != 0),
+        Dwarf2Form::FlagPresent => Dwarf2Value::Flag(true),
+        Dwarf2Form::String => Dwarf2Value::String(read_string(reader)?),
+        Dwarf2Form::Strp => {
+            let offset = u32::from_reader(reader, e)? as usize;
+            let str_data = str_data.ok_or_else(|| anyhow!("DW_FORM_strp without .debug_str"))?;
+            let str = str_data
+                .get(offset..)
+                .ok_or_else(|| anyhow!("Invalid .debug_str offset {:#X}", offset))?;
+            let end = str.iter().position(|&b| b == 0).unwrap_or(str.len());
+            Dwarf2Value::String(str[..end].iter().map(|&b| b as char).collect())
+        }
+        Dwarf2Form::RefAddr => Dwarf2Value::Reference(u32::from_reader(reader, e)?),
+        Dwarf2Form::Ref1 => {
+            Dwarf2Value::Reference(unit_offset + u8::from_reader(reader, e)? as u32)
+        }
+        Dwarf2Form::Ref2 => {
+            Dwarf2Value::Reference(unit_offset + u16::from_reader(reader, e)? as u32)
+        }
+        Dwarf2Form::Ref4 => Dwarf2Value::Reference(unit_offset + u32::from_reader(reader, e)?),
+        Dwarf2Form::Ref8 => {
+            Dwarf2Value::Reference(unit_offset + u64::from_reader(reader, e)? as u32)
+        }
+        Dwarf2Form::RefUdata => Dwarf2Value::Reference(unit_offset + read_uleb128(reader)? as u32),
+        Dwarf2Form::Indirect => {
+            let form = read_uleb128(reader)?;
+            read_dwarf2_value(reader, form, unit_offset, str_data, e)?
+        }
+    })
+}
+
+fn read_dwarf2_entries(
+    data: &[u8],
+    abbrev_data: &[u8],
+    str_data: Option<&[u8]>,
+    e: Endian,
+) -> Result<Dwarf2EntryMap> {
+    let mut abbrev_tables = BTreeMap::<u32, BTreeMap<u64, Dwarf2Abbrev>>::new();
+    let mut entries = Dwarf2EntryMap::new();
+    let mut reader = Cursor::new(data);
+    while reader.position() < data.len() as u64 {
+        let unit_offset = reader.position() as u32;
+        let unit_length = u32::from_reader(&mut reader, e)?;
+        ensure!(unit_length != u32::MAX, "64-bit DWARF is not supported");
+        let unit_end = reader.position() + unit_length as u64;
+        ensure!(
+            unit_end <= data.len() as u64,
+            "Compile unit at {:#X} extends past end of .debug_info",
+            unit_offset
+        );
+        let version = u16::from_reader(&mut reader, e)?;
+        ensure!((2..=4).contains(&version), "Unsupported DWARF version {version}");
+        let abbrev_offset = u32::from_reader(&mut reader, e)?;
+        let address_size = u8::from_reader(&mut reader, e)?;
+        ensure!(address_size == 4, "Unsupported address size {address_size}");
+        let abbrevs = match abbrev_tables.entry(abbrev_offset) {
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(read_dwarf2_abbrevs(abbrev_data, abbrev_offset)?)
+            }
+            btree_map::Entry::Occupied(entry) => entry.into_mut(),
+        };
+
+        let mut parents = Vec::<u32>::new();
+        while reader.position() < unit_end {
+            let offset = reader.position() as u32;
+            let code = read_uleb128(&mut reader)?;
+            if code == 0 {
+                // End of children
+                if let Some(parent) = parents.pop() {
+                    entries.get_mut(&parent).unwrap().end = reader.position() as u32;
+                }
+                continue;
+            }
+            let abbrev = abbrevs
+                .get(&code)
+                .ok_or_else(|| anyhow!("Unknown abbreviation code {} at {:#X}", code, offset))?;
+            let mut attributes = Vec::with_capacity(abbrev.attributes.len());
+            for &(name, form) in &abbrev.attributes {
+                let value = read_dwarf2_value(&mut reader, form, unit_offset, str_data, e)
+                    .with_context(|| {
+                        format!("Failed to read attribute {name:#X} at {offset:#X}")
+                    })?;
+                if let Some(kind) =
+                    u16::try_from(name).ok().and_then(|name| Dwarf2Attribute::try_from(name).ok())
+                {
+                    attributes.push((kind, value));
+                }
+            }
+            let tag = u16::try_from(abbrev.tag).ok().and_then(|tag| Dwarf2Tag::try_from(tag).ok());
+            if tag.is_none() {
+                log::debug!("Unknown DWARF 2 tag {:#X} at {:#X}", abbrev.tag, offset);
+            }
+            entries.insert(offset, Dwarf2Entry {
+                offset,
+                tag,
+                attributes,
+                parent: parents.last().cloned(),
+                end: reader.position() as u32,
+            });
+            if abbrev.has_children {
+                parents.push(offset);
+            }
+        }
+        for parent in parents {
+            entries.get_mut(&parent).unwrap().end = unit_end as u32;
+        }
+        reader.set_position(unit_end);
+    }
+    Ok(entries)
+}
+
+enum Dwarf2Translation {
+    Tag(Tag),
+    /// Drop the entry, but keep its children (e.g. namespaces)
+    Flatten,
+    /// Drop the entry and its children
+    Skip,
+}
+
+fn translate_dwarf2_entries(entries: &Dwarf2EntryMap, e: Endian) -> Result<DwarfInfo> {
+    let mut info = DwarfInfo { e, tags: BTreeMap::new() };
+    let mut skipped = BTreeSet::<u32>::new();
+    for (&offset, entry) in entries {
+        if entry.parent.is_some_and(|parent| skipped.contains(&parent)) {
+            skipped.insert(offset);
+            continue;
+        }
+        match translate_dwarf2_entry(entries, entry, e) {
+            Ok(Dwarf2Translation::Tag(tag)) => {
+                info.tags.insert(offset, tag);
+            }
+            Ok(Dwarf2Translation::Flatten) => {}
+            Ok(Dwarf2Translation::Skip) => {
+                skipped.insert(offset);
+            }
+            Err(err) => {
+                log::warn!("Failed to translate DWARF 2 entry {:#X}: {:?}", offset, err);
+                skipped.insert(offset);
+            }
+        }
+    }
+
+    // Link each tag to the first tag following its children, which is how
+    // DWARF 1.1 expresses the tree structure. At the end of the section, the
+    // sibling points past the last tag.
+    let siblings = info
+        .tags
+        .keys()
+        .map(|key| {
+            let end = entries[key].end;
+            (*key, info.tags.range(end..).next().map_or(end, |(&sibling, _)| sibling))
+        })
+        .collect::<Vec<_>>();
+    for (key, sibling) in siblings {
+        info.tags.get_mut(&key).unwrap().attributes.insert(0, Attribute {
+            kind: AttributeKind::Sibling,
+            value: AttributeValue::Reference(sibling),
+        });
+    }
+    Ok(info)
+}
+
+/// Returns the parent entry, looking through entries that are flattened during translation.
+fn dwarf2_parent<'a>(entries: &'a Dwarf2EntryMap, entry: &Dwarf2Entry) -> Option<&'a Dwarf2Entry> {
+    let mut parent = entries.get(&entry.parent?)?;
+    while matches!(
+        parent.tag,
+        Some(Dwarf2Tag::Namespace | Dwarf2Tag::TryBlock | Dwarf2Tag::CatchBlock)
+    ) {
+        parent = entries.get(&parent.parent?)?;
+    }
+    Some(parent)
+}
+
+fn translate_dwarf2_entry(
+    entries: &Dwarf2EntryMap,
+    entry: &Dwarf2Entry,
+    e: Endian,
+) -> Result<Dwarf2Translation> {
+    let Some(tag) = entry.tag else {
+        return Ok(Dwarf2Translation::Skip);
+    };
+    let parent_tag = dwarf2_parent(entries, entry).and_then(|parent| parent.tag);
+    let in_structure = matches!(
+        parent_tag,
+        Some(Dwarf2Tag::StructureType | Dwarf2Tag::ClassType | Dwarf2Tag::UnionType)
+    );
+    let in_subroutine = matches!(
+        parent_tag,
+        Some(Dwarf2Tag::Subprogram | Dwarf2Tag::LexicalBlock | Dwarf2Tag::InlinedSubroutine)
+    );
+    let external = matches!(
+        entry.merged_attribute(entries, Dwarf2Attribute::External),
+        Some(Dwarf2Value::Flag(true))
+    );
+    let has_range = entry.attribute(Dwarf2Attribute::LowPc).is_some()
+        && entry.attribute(Dwarf2Attribute::HighPc).is_some();
+    let kind = match tag {
+        Dwarf2Tag::CompileUnit => TagKind::CompileUnit,
+        Dwarf2Tag::ArrayType => TagKind::ArrayType,
+        Dwarf2Tag::ClassType => TagKind::ClassType,
+        Dwarf2Tag::StructureType => TagKind::StructureType,
+        Dwarf2Tag::UnionType => TagKind::UnionType,
+        Dwarf2Tag::EnumerationType => TagKind::EnumerationType,
+        Dwarf2Tag::SubroutineType => TagKind::SubroutineType,
+        Dwarf2Tag::PtrToMemberType => TagKind::PtrToMemberType,
+        Dwarf2Tag::Typedef => TagKind::Typedef,
+        // Static data members
+        Dwarf2Tag::Member if entry.flag(Dwarf2Attribute::Declaration) => {
+            return Ok(Dwarf2Translation::Skip)
+        }
+        Dwarf2Tag::Member => TagKind::Member,
+        Dwarf2Tag::Inheritance => TagKind::Inheritance,
+        Dwarf2Tag::FormalParameter => TagKind::FormalParameter,
+        Dwarf2Tag::UnspecifiedParameters => TagKind::UnspecifiedParameters,
+        Dwarf2Tag::Label => TagKind::Label,
+        // Blocks described by DW_AT_ranges are merged into their parent
+        Dwarf2Tag::LexicalBlock if !has_range => return Ok(Dwarf2Translation::Flatten),
+        Dwarf2Tag::LexicalBlock => TagKind::LexicalBlock,
+        Dwarf2Tag::InlinedSubroutine if !has_range => return Ok(Dwarf2Translation::Skip),
+        Dwarf2Tag::InlinedSubroutine => TagKind::InlinedSubroutine,
+        // Local function declarations
+        Dwarf2Tag::Subprogram if in_subroutine => return Ok(Dwarf2Translation::Skip),
+        Dwarf2Tag::Subprogram if external => TagKind::GlobalSubroutine,
+        Dwarf2Tag::Subprogram => TagKind::Subroutine,
+        // Static members and extern declarations
+        Dwarf2Tag::Variable if in_structure || entry.flag(Dwarf2Attribute::Declaration) => {
+            return Ok(Dwarf2Translation::Skip)
+        }
+        Dwarf2Tag::Variable if !in_subroutine && external => TagKind::GlobalVariable,
+        Dwarf2Tag::Variable => TagKind::LocalVariable,
+        Dwarf2Tag::Namespace | Dwarf2Tag::TryBlock | Dwarf2Tag::CatchBlock => {
+            return Ok(Dwarf2Translation::Flatten)
+        }
+        // Base and modifier types are folded into type attributes,
+        // subranges and enumerators into their parent
+        _ => return Ok(Dwarf2Translation::Skip),
+    };
+
+    let mut attributes = Vec::new();
+    match kind {
+        TagKind::LocalVariable
+        | TagKind::GlobalVariable
+        | TagKind::FormalParameter
+        | TagKind::Typedef => {
+            // Merged from the specification or abstract origin, if necessary
+            if let Some(Dwarf2Value::String(name)) =
+                entry.merged_attribute(entries, Dwarf2Attribute::Name)
+            {
+                attributes.push(Attribute {
+                    kind: AttributeKind::Name,
+                    value: AttributeValue::String(name.clone()),
+                });
+            }
+            if let Some(Dwarf2Value::String(name)) = entry
+                .merged_attribute(entries, Dwarf2Attribute::MipsLinkageName)
+                .or_else(|| entry.merged_attribute(entries, Dwarf2Attribute::LinkageName))
+            {
+                attributes.push(Attribute {
+                    kind: AttributeKind::MwMangled,
+                    value: AttributeValue::String(name.clone()),
+                });
+            }
+            let type_ref = match entry.merged_attribute(entries, Dwarf2Attribute::Type) {
+                Some(&Dwarf2Value::Reference(key)) => Some(key),
+                _ => None,
+            };
+            let attr = dwarf2_type_attribute(entries, type_ref, e)?;
+            attributes.push(attr);
+            if let Some(Dwarf2Value::Block(expr)) = entry.attribute(Dwarf2Attribute::Location) {
+                let frame_base = dwarf2_frame_base(entries, entry);
+                match dwarf2_location(expr, frame_base, e) {
+                    Some(block) => attributes.push(Attribute {
+                        kind: AttributeKind::Location,
+                        value: AttributeValue::Block(block),
+                    }),
+                    None => log::debug!(
+                        "Unhandled location expression {:?} at {:#X}",
+                        expr,
+                        entry.offset
+                    ),
+                }
+            }
+            return Ok(Dwarf2Translation::Tag(Tag { key: entry.offset, kind, attributes }));
+        }
+        _ => {}
+    }
+
+    let low_pc = match entry.attribute(Dwarf2Attribute::LowPc) {
+        Some(&Dwarf2Value::Address(addr)) => Some(addr),
+        _ => None,
+    };
+    for (attr, value) in &entry.attributes {
+        match (attr, value) {
+            (Dwarf2Attribute::Name, Dwarf2Value::String(s)) => attributes.push(Attribute {
+                kind: AttributeKind::Name,
+                value: AttributeValue::String(s.clone()),
+            }),
+            (
+                Dwarf2Attribute::MipsLinkageName | Dwarf2Attribute::LinkageName,
+                Dwarf2Value::String(s),
+            ) if matches!(kind, TagKind::GlobalSubroutine | TagKind::Subroutine) => attributes
+                .push(Attribute {
+                    kind: AttributeKind::MwMangled,
+                    value: AttributeValue::String(s.clone()),
+                }),
+            (Dwarf2Attribute::Producer, Dwarf2Value::String(s)) => attributes.push(Attribute {
+                kind: AttributeKind::Producer,
+                value: AttributeValue::String(s.clone()),
+            }),
+            (Dwarf2Attribute::CompDir, Dwarf2Value::String(s)) => attributes.push(Attribute {
+                kind: AttributeKind::CompDir,
+                value: AttributeValue::String(s.clone()),
+            }),
+            (Dwarf2Attribute::Language, &Dwarf2Value::Unsigned(value)) => {
+                attributes.push(Attribute {
+                    kind: AttributeKind::Language,
+                    value: AttributeValue::Data4(value as u32),
+                })
+            }
+            (Dwarf2Attribute::StmtList, &Dwarf2Value::Unsigned(value)) => {
+                attributes.push(Attribute {
+                    kind: AttributeKind::StmtList,
+                    value: AttributeValue::Data4(value as u32),
+                })
+            }
+            (Dwarf2Attribute::ByteSize, &Dwarf2Value::Unsigned(value)) => {
+                attributes.push(Attribute {
+                    kind: AttributeKind::ByteSize,
+                    value: AttributeValue::Data4(value as u32),
+                })
+            }
+            (Dwarf2Attribute::BitSize, &Dwarf2Value::Unsigned(value)) => {
+                attributes.push(Attribute {
+                    kind: AttributeKind::BitSize,
+                    value: AttributeValue::Data4(value as u32),
+                })
+            }
+            (Dwarf2Attribute::BitOffset, &Dwarf2Value::Unsigned(value)) => {
+                attributes.push(Attribute {
+                    kind: AttributeKind::BitOffset,
+                    value: AttributeValue::Data2(value as u16),
+                })
+            }
+            (Dwarf2Attribute::LowPc, &Dwarf2Value::Address(addr)) => attributes.push(Attribute {
+                kind: AttributeKind::LowPc,
+                value: AttributeValue::Address(addr),
+            }),
+            (Dwarf2Attribute::HighPc, &Dwarf2Value::Address(addr)) => attributes.push(Attribute {
+                kind: AttributeKind::HighPc,
+                value: AttributeValue::Address(addr),
+            }),
+            // DWARF 4: offset from DW_AT_low_pc
+            (Dwarf2Attribute::HighPc, &Dwarf2Value::Unsigned(size)) => {
+                if let Some(low_pc) = low_pc {
+                    attributes.push(Attribute {
+                        kind: AttributeKind::HighPc,
+                        value: AttributeValue::Address(low_pc + size as u32),
+                    })
+                }
+            }
+            (Dwarf2Attribute::Prototyped, &Dwarf2Value::Flag(true)) => attributes.push(Attribute {
+                kind: AttributeKind::Prototyped,
+                value: AttributeValue::String(String::new()),
+            }),
+            (
+                Dwarf2Attribute::Inline,
+                &Dwarf2Value::Unsigned(DW_INL_DECLARED_NOT_INLINED | DW_INL_DECLARED_INLINED),
+            ) => attributes.push(Attribute {
+                kind: AttributeKind::Inline,
+                value: AttributeValue::String(String::new()),
+            }),
+            (
+                Dwarf2Attribute::Specification | Dwarf2Attribute::AbstractOrigin,
+                &Dwarf2Value::Reference(key),
+            ) if matches!(
+                kind,
+                TagKind::GlobalSubroutine | TagKind::Subroutine | TagKind::InlinedSubroutine
+            ) =>
+            {
+                attributes.push(Attribute {
+                    kind: AttributeKind::Specification,
+                    value: AttributeValue::Reference(key),
+                })
+            }
+            (Dwarf2Attribute::ContainingType, &Dwarf2Value::Reference(key))
+                if kind == TagKind::PtrToMemberType =>
+            {
+                attributes.push(Attribute {
+                    kind: AttributeKind::ContainingType,
+                    value: AttributeValue::Reference(key),
+                })
+            }
+            (Dwarf2Attribute::Accessibility, &Dwarf2Value::Unsigned(value))
+                if matches!(kind, TagKind::Member | TagKind::Inheritance) =>
+            {
+                match value {
+                    DW_ACCESS_PUBLIC => attributes.push(Attribute {
+                        kind: AttributeKind::Public,
+                        value: AttributeValue::String(String::new()),
+                    }),
+                    DW_ACCESS_PROTECTED => attributes.push(Attribute {
+                        kind: AttributeKind::Protected,
+                        value: AttributeValue::String(String::new()),
+                    }),
+                    DW_ACCESS_PRIVATE => attributes.push(Attribute {
+                        kind: AttributeKind::Private,
+                        value: AttributeValue::String(String::new()),
+                    }),
+                    _ => bail!("Invalid accessibility {}", value),
+                }
+            }
+            (Dwarf2Attribute::Virtuality, &Dwarf2Value::Unsigned(value))
+                if kind == TagKind::Inheritance && value != 0 =>
+            {
+                attributes.push(Attribute {
+                    kind: AttributeKind::Virtual,
+                    value: AttributeValue::String(String::new()),
+                })
+            }
+            (Dwarf2Attribute::DataMemberLocation, value) => {
+                let offset = match value {
+                    &Dwarf2Value::Unsigned(offset) => offset as u32,
+                    Dwarf2Value::Block(expr) => dwarf2_member_offset(expr)
+                        .ok_or_else(|| anyhow!("Unhandled member location {:?}", expr))?,
+                    _ => bail!("Unhandled member location {:?}", value),
+                };
+                attributes.push(Attribute {
+                    kind: AttributeKind::Location,
+                    value: AttributeValue::Block(offset_block(offset, e)?),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    match kind {
+        TagKind::ArrayType => {
+            let block = dwarf2_subscript_data(entries, entry, e)?;
+            attributes.push(Attribute {
+                kind: AttributeKind::SubscrData,
+                value: AttributeValue::Block(block),
+            });
+        }
+        TagKind::EnumerationType => {
+            let mut block = Vec::new();
+            for child in entry.children(entries) {
+                if child.tag != Some(Dwarf2Tag::Enumerator) {
+                    continue;
+                }
+                let name = child
+                    .string(Dwarf2Attribute::Name)
+                    .ok_or_else(|| anyhow!("Enumerator without name at {:#X}", child.offset))?;
+                let value = child
+                    .unsigned(Dwarf2Attribute::ConstValue)
+                    .ok_or_else(|| anyhow!("Enumerator without value at {:#X}", child.offset))?;
+                (value as i32).to_writer(&mut block, e)?;
+                block.extend(name.chars().map(|c| c as u8));
+                block.push(0);
+            }
+            attributes.push(Attribute {
+                kind: AttributeKind::ElementList,
+                value: AttributeValue::Block(block),
+            });
+            if entry.attribute(Dwarf2Attribute::ByteSize).is_none() {
+                attributes.push(Attribute {
+                    kind: AttributeKind::ByteSize,
+                    value: AttributeValue::Data4(4),
+                });
+            }
+        }
+        // Declarations have no size
+        TagKind::UnionType if entry.attribute(Dwarf2Attribute::ByteSize).is_none() => {
+            attributes
+                .push(Attribute { kind: AttributeKind::ByteSize, value: AttributeValue::Data4(0) });
+        }
+        TagKind::Member | TagKind::Inheritance => {
+            if entry.attribute(Dwarf2Attribute::Accessibility).is_none() {
+                // Members of classes are private by default
+                if parent_tag == Some(Dwarf2Tag::ClassType) {
+                    attributes.push(Attribute {
+                        kind: AttributeKind::Private,
+                        value: AttributeValue::String(String::new()),
+                    });
+                } else {
+                    attributes.push(Attribute {
+                        kind: AttributeKind::Public,
+                        value: AttributeValue::String(String::new()),
+                    });
+                }
+            }
+            // Union members may omit their location
+            if entry.attribute(Dwarf2Attribute::DataMemberLocation).is_none() {
+                attributes.push(Attribute {
+                    kind: AttributeKind::Location,
+                    value: AttributeValue::Block(offset_block(0, e)?),
+                });
+            }
+        }
+        TagKind::GlobalSubroutine | TagKind::Subroutine if in_structure => {
+            attributes.push(Attribute {
+                kind: AttributeKind::Member,
+                value: AttributeValue::Reference(entry.parent.unwrap()),
+            });
+        }
+        _ => {}
+    }
+
+    if matches!(
+        kind,
+        TagKind::Member
+            | TagKind::Inheritance
+            | TagKind::GlobalSubroutine
+            | TagKind::Subroutine
+            | TagKind::SubroutineType
+            | TagKind::PtrToMemberType
+    ) {
+        if let Some(key) = entry.reference(Dwarf2Attribute::Type) {
+            attributes.push(dwarf2_type_attribute(entries, Some(key), e)?);
+        } else if matches!(kind, TagKind::Member | TagKind::Inheritance | TagKind::PtrToMemberType)
+        {
+            bail!("{:?} without type", kind);
+        }
+    }
+    Ok(Dwarf2Translation::Tag(Tag { key: entry.offset, kind, attributes }))
+}
+
+fn offset_block(offset: u32, e: Endian) -> Result<Vec<u8>> {
+    let mut block = vec![LocationOp::Const as u8];
+    offset.to_writer(&mut block, e)?;
+    block.push(LocationOp::Add as u8);
+    Ok(block)
+}
+
+fn dwarf2_member_offset(expr: &[u8]) -> Option<u32> {
+    let mut reader = Cursor::new(expr);
+    if u8::from_reader(&mut reader, Endian::Big).ok()? != DW_OP_PLUS_UCONST {
+        return None;
+    }
+    let offset = read_uleb128(&mut reader).ok()?;
+    (reader.position() == expr.len() as u64).then_some(offset as u32)
+}
+
+/// Returns the register and offset of the frame base of the enclosing subroutine,
+/// if it is expressed relative to a register.
+fn dwarf2_frame_base(entries: &Dwarf2EntryMap, entry: &Dwarf2Entry) -> Option<(u32, i64)> {
+    let mut parent = entries.get(&entry.parent?)?;
+    while parent.tag != Some(Dwarf2Tag::Subprogram) {
+        parent = entries.get(&parent.parent?)?;
+    }
+    let Some(Dwarf2Value::Block(expr)) = parent.attribute(Dwarf2Attribute::FrameBase) else {
+        return None;
+    };
+    let mut reader = Cursor::new(expr.as_slice());
+    let frame_base = match u8::from_reader(&mut reader, Endian::Big).ok()? {
+        op @ DW_OP_REG0..=DW_OP_REG31 => ((op - DW_OP_REG0) as u32, 0),
+        op @ DW_OP_BREG0..=DW_OP_BREG31 => {
+            ((op - DW_OP_BREG0) as u32, read_sleb128(&mut reader).ok()?)
+        }
+        _ => return None,
+    };
+    (reader.position() == expr.len() as u64).then_some(frame_base)
+}
+
+/// Translates a DWARF 2 location expression into the DWARF 1.1 form
+/// understood by `process_variable_location`.
+fn dwarf2_location(expr: &[u8], frame_base: Option<(u32, i64)>, e: Endian) -> Option<Vec<u8>> {
+    let mut reader = Cursor::new(expr);
+    let op = u8::from_reader(&mut reader, Endian::Big).ok()?;
+    let (base_op, value, offset) = match op {
+        DW_OP_ADDR => (LocationOp::Address, u32::from_reader(&mut reader, e).ok()?, None),
+        DW_OP_REG0..=DW_OP_REG31 => (LocationOp::Register, (op - DW_OP_REG0) as u32, None),
+        DW_OP_REGX => (LocationOp::Register, read_uleb128(&mut reader).ok()? as u32, None),
+        DW_OP_BREG0..=DW_OP_BREG31 => {
+            let offset = read_sleb128(&mut reader).ok()?;
+            (LocationOp::BaseRegister, (op - DW_OP_BREG0) as u32, Some(offset))
+        }
+        DW_OP_BREGX => {
+            let reg = read_uleb128(&mut reader).ok()? as u32;
+            let offset = read_sleb128(&mut reader).ok()?;
+            (LocationOp::BaseRegister, reg, Some(offset))
+        }
+        DW_OP_FBREG => {
+            let (reg, base_offset) = frame_base?;
+            let offset = read_sleb128(&mut reader).ok()?;
+            (LocationOp::BaseRegister, reg, Some(base_offset + offset))
+        }
+        _ => return None,
+    };
+    if reader.position() != expr.len() as u64 {
+        return None;
+    }
+    let mut block = vec![base_op as u8];
+    value.to_writer(&mut block, e).ok()?;
+    if let Some(offset) = offset {
+        block.push(LocationOp::Const as u8);
+        (offset as u32).to_writer(&mut block, e).ok()?;
+        block.push(LocationOp::Add as u8);
+    }
+    Some(block)
+}
+
+fn dwarf2_fund_type(entry: &Dwarf2Entry) -> Result<FundType> {
+    if let Some(name) = entry.string(Dwarf2Attribute::Name) {
+        let fund_type = match name {
+            "char" => Some(FundType::Char),
+            "signed char" => Some(FundType::SignedChar),
+            "unsigned char" => Some(FundType::UnsignedChar),
+            "wchar_t" => Some(FundType::WideChar),
+            "short" | "short int" => Some(FundType::Short),
+            "signed short" => Some(FundType::SignedShort),
+            "unsigned short" | "short unsigned int" => Some(FundType::UnsignedShort),
+            "int" => Some(FundType::Integer),
+            "signed int" => Some(FundType::SignedInteger),
+            "unsigned int" => Some(FundType::UnsignedInteger),
+            "long" | "long int" => Some(FundType::Long),
+            "signed long" => Some(FundType::SignedLong),
+            "unsigned long" | "long unsigned int" => Some(FundType::UnsignedLong),
+            "long long" | "long long int" => Some(FundType::LongLong),
+            "signed long long" => Some(FundType::SignedLongLong),
+            "unsigned long long" | "long long unsigned int" => Some(FundType::UnsignedLongLong),
+            "float" => Some(FundType::Float),
+            "double" => Some(FundType::DblPrecFloat),
+            "long double" => Some(FundType::ExtPrecFloat),
+            "bool" | "_Bool" => Some(FundType::Boolean),
+            "void" => Some(FundType::Void),
+            "__int128" => Some(FundType::Int128),
+            "__vec2x32float__" => Some(FundType::Vec2x32Float),
+            _ => None,
+        };
+        if let Some(fund_type) = fund_type {
+            return Ok(fund_type);
+        }
+    }
+    let encoding = entry.unsigned(Dwarf2Attribute::Encoding).unwrap_or_default();
+    let byte_size = entry.unsigned(Dwarf2Attribute::ByteSize).unwrap_or_default();
+    Ok(match (encoding, byte_size) {
+        (DW_ATE_ADDRESS, 4) => FundType::Pointer,
+        (DW_ATE_BOOLEAN, 1) => FundType::Boolean,
+        (DW_ATE_FLOAT, 4) => FundType::Float,
+        (DW_ATE_FLOAT, 8) => FundType::DblPrecFloat,
+        (DW_ATE_SIGNED_CHAR, 1) => FundType::Char,
+        (DW_ATE_UNSIGNED_CHAR, 1) => FundType::UnsignedChar,
+        (DW_ATE_SIGNED, 1) => FundType::SignedChar,
+        (DW_ATE_SIGNED, 2) => FundType::Short,
+        (DW_ATE_SIGNED, 4) => FundType::Integer,
+        (DW_ATE_SIGNED, 8) => FundType::LongLong,
+        (DW_ATE_UNSIGNED, 1) => FundType::UnsignedChar,
+        (DW_ATE_UNSIGNED, 2) => FundType::UnsignedShort,
+        (DW_ATE_UNSIGNED, 4) => FundType::UnsignedInteger,
+        (DW_ATE_UNSIGNED, 8) => FundType::UnsignedLongLong,
+        _ => bail!(
+            "Unhandled base type {:?} (encoding {:#X}, size {})",
+            entry.string(Dwarf2Attribute::Name),
+            encoding,
+            byte_size
+        ),
+    })
+}
+
+/// Follows a chain of DWARF 2 modifier types and typedefs, producing a DWARF 1.1 type.
+fn dwarf2_type(entries: &Dwarf2EntryMap, key: Option<u32>) -> Result<Type> {
+    let mut modifiers = Vec::new();
+    let mut key = key;
+    // Guard against reference cycles
+    for _ in 0..64 {
+        let Some(current) = key else {
+            return Ok(Type { kind: TypeKind::Fundamental(FundType::Void), modifiers });
+        };
+        let entry = entries
+            .get(&current)
+            .ok_or_else(|| anyhow!("Failed to locate type entry {:#X}", current))?;
+        match entry.tag {
+            Some(Dwarf2Tag::PointerType) => modifiers.push(Modifier::PointerTo),
+            Some(Dwarf2Tag::ReferenceType | Dwarf2Tag::RvalueReferenceType) => {
+                modifiers.push(Modifier::ReferenceTo)
+            }
+            Some(Dwarf2Tag::ConstType) => modifiers.push(Modifier::Const),
+            Some(Dwarf2Tag::VolatileType) => modifiers.push(Modifier::Volatile),
+            Some(Dwarf2Tag::RestrictType | Dwarf2Tag::Typedef) => {}
+            Some(Dwarf2Tag::BaseType) => {
+                let fund_type = dwarf2_fund_type(entry)?;
+                return Ok(Type { kind: TypeKind::Fundamental(fund_type), modifiers });
+            }
+            Some(Dwarf2Tag::UnspecifiedType) => {
+                return Ok(Type { kind: TypeKind::Fundamental(FundType::Void), modifiers });
+            }
+            Some(
+                Dwarf2Tag::ArrayType
+                | Dwarf2Tag::StructureType
+                | Dwarf2Tag::ClassType
+                | Dwarf2Tag::UnionType
+                | Dwarf2Tag::EnumerationType
+                | Dwarf2Tag::SubroutineType
+                | Dwarf2Tag::PtrToMemberType,
+            ) => return Ok(Type { kind: TypeKind::UserDefined(current), modifiers }),
+            tag => bail!("Unhandled type entry {:?} at {:#X}", tag, current),
+        }
+        key = entry.reference(Dwarf2Attribute::Type);
+    }
+    bail!("Type chain too deep at {:?}", key)
+}
+
+fn dwarf2_type_attribute(
+    entries: &Dwarf2EntryMap,
+    key: Option<u32>,
+    e: Endian,
+) -> Result<Attribute> {
+    let t = dwarf2_type(entries, key)?;
+    let mut block: Vec<u8> = t.modifiers.iter().map(|&m| u8::from(m)).collect();
+    Ok(match (t.kind, t.modifiers.is_empty()) {
+        (TypeKind::Fundamental(ft), true) => {
+            Attribute { kind: AttributeKind::FundType, value: AttributeValue::Data2(ft.into()) }
+        }
+        (TypeKind::Fundamental(ft), false) => {
+            u16::from(ft).to_writer(&mut block, e)?;
+            Attribute { kind: AttributeKind::ModFundType, value: AttributeValue::Block(block) }
+        }
+        (TypeKind::UserDefined(key), true) => {
+            Attribute { kind: AttributeKind::UserDefType, value: AttributeValue::Reference(key) }
+        }
+        (TypeKind::UserDefined(key), false) => {
+            key.to_writer(&mut block, e)?;
+            Attribute { kind: AttributeKind::ModUDType, value: AttributeValue::Block(block) }
+        }
+    })
+}
+
+fn write_attribute(out: &mut Vec<u8>, attr: &Attribute, e: Endian) -> Result<()> {
+    let kind = u16::from(attr.kind);
+    kind.to_writer(out, e)?;
+    let form = FormKind::try_from(kind & FORM_MASK).context("Unknown DWARF form type")?;
+    match (form, &attr.value) {
+        (FormKind::Addr, &AttributeValue::Address(value))
+        | (FormKind::Ref, &AttributeValue::Reference(value))
+        | (FormKind::Data4, &AttributeValue::Data4(value)) => value.to_writer(out, e)?,
+        (FormKind::Data2, &AttributeValue::Data2(value)) => value.to_writer(out, e)?,
+        (FormKind::Data8, &AttributeValue::Data8(value)) => value.to_writer(out, e)?,
+        (FormKind::Block2, AttributeValue::Block(data)) => {
+            u16::try_from(data.len())?.to_writer(out, e)?;
+            out.extend_from_slice(data);
+        }
+        (FormKind::Block4, AttributeValue::Block(data)) => {
+            u32::try_from(data.len())?.to_writer(out, e)?;
+            out.extend_from_slice(data);
+        }
+        (FormKind::String, AttributeValue::String(str)) => {
+            out.extend(str.chars().map(|c| c as u8));
+            out.push(0);
+        }
+        _ => bail!("Mismatched attribute form {:?}", attr),
+    }
+    Ok(())
+}
+
+/// Builds DWARF 1.1 subscript data from the subrange children of a DWARF 2 array type.
+fn dwarf2_subscript_data(
+    entries: &Dwarf2EntryMap,
+    entry: &Dwarf2Entry,
+    e: Endian,
+) -> Result<Vec<u8>> {
+    let subranges = entry
+        .children(entries)
+        .filter(|child| child.tag == Some(Dwarf2Tag::SubrangeType))
+        .collect::<Vec<_>>();
+    let mut block = Vec::new();
+    // array_type_string emits the last dimension first
+    for subrange in subranges.iter().rev() {
+        let low_bound = subrange.unsigned(Dwarf2Attribute::LowerBound).unwrap_or_default();
+        ensure!(low_bound == 0, "Invalid array low bound {low_bound}, expected 0");
+        let high_bound = match (
+            subrange.unsigned(Dwarf2Attribute::UpperBound),
+            subrange.unsigned(Dwarf2Attribute::Count),
+        ) {
+            (Some(upper_bound), _) => upper_bound as u32,
+            (None, Some(count)) => (count as u32).wrapping_sub(1),
+            // Unbounded
+            (None, None) => u32::MAX,
+        };
+        block.push(SubscriptFormat::FundTypeConstConst as u8);
+        u16::from(FundType::Long).to_writer(&mut block, e)?;
+        0u32.to_writer(&mut block, e)?;
+        high_bound.to_writer(&mut block, e)?;
+    }
+    block.push(SubscriptFormat::ElementType as u8);
+    let element_type = dwarf2_type_attribute(entries, entry.reference(Dwarf2Attribute::Type), e)?;
+    write_attribute(&mut block, &element_type, e)?;
+    Ok(block)
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ArrayDimension {
     pub index_type: Type,
     pub size: Option<NonZeroU32>,
@@ -590,21 +2040,22 @@ pub enum ArrayOrdering {
     ColMajor = 1, // ORD_col_major
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ArrayType {
     pub element_type: Box<Type>,
     pub dimensions: Vec<ArrayDimension>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct BitData {
     pub bit_size: u32,
     pub bit_offset: u16,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct StructureMember {
     pub name: String,
+    #[serde(rename = "type")]
     pub kind: Type,
     pub offset: u32,
     pub bit: Option<BitData>,
@@ -612,13 +2063,14 @@ pub struct StructureMember {
     pub byte_size: Option<u32>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StructureKind {
     Struct,
     Class,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct StructureType {
     pub kind: StructureKind,
     pub name: Option<String>,
@@ -627,14 +2079,15 @@ pub struct StructureType {
     pub bases: Vec<StructureBase>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Visibility {
     Private,
     Protected,
     Public,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct StructureBase {
     pub name: Option<String>,
     pub base_type: Type,
@@ -643,48 +2096,50 @@ pub struct StructureBase {
     pub virtual_base: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct EnumerationMember {
     pub name: String,
     pub value: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct EnumerationType {
     pub name: Option<String>,
     pub byte_size: u32,
     pub members: Vec<EnumerationMember>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UnionType {
     pub name: Option<String>,
     pub byte_size: u32,
     pub members: Vec<StructureMember>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineParameter {
     pub name: Option<String>,
+    #[serde(rename = "type")]
     pub kind: Type,
     pub location: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineVariable {
     pub name: Option<String>,
     pub mangled_name: Option<String>,
+    #[serde(rename = "type")]
     pub kind: Type,
     pub location: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineLabel {
     pub name: String,
     pub address: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineBlock {
     pub name: Option<String>,
     pub start_address: u32,
@@ -693,14 +2148,14 @@ pub struct SubroutineBlock {
     pub blocks: Vec<SubroutineBlock>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineInline {
     pub specification: u32,
     pub start_address: u32,
     pub end_address: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineType {
     pub name: Option<String>,
     pub mangled_name: Option<String>,
@@ -718,13 +2173,15 @@ pub struct SubroutineType {
     pub inlines: Vec<SubroutineInline>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PtrToMemberType {
+    #[serde(rename = "type")]
     pub kind: Type,
     pub containing_type: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "tag", rename_all = "snake_case")]
 pub enum UserDefinedType {
     Array(ArrayType),
     Structure(StructureType),
@@ -734,25 +2191,29 @@ pub enum UserDefinedType {
     PtrToMember(PtrToMemberType),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct VariableTag {
     pub name: Option<String>,
     pub mangled_name: Option<String>,
+    #[serde(rename = "type")]
     pub kind: Type,
     pub address: Option<u32>,
     pub local: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedefTag {
     pub name: String,
+    #[serde(rename = "type")]
     pub kind: Type,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "tag", rename_all = "snake_case")]
 pub enum TagType {
     Variable(VariableTag),
     Typedef(TypedefTag),
+    #[serde(untagged)]
     UserDefined(UserDefinedType),
 }
 
@@ -808,14 +2269,16 @@ impl UserDefinedType {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TypeKind {
     Fundamental(FundType),
     UserDefined(u32),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Type {
+    #[serde(flatten)]
     pub kind: TypeKind,
     pub modifiers: Vec<Modifier>,
 }
@@ -1307,6 +2770,48 @@ pub fn subroutine_def_string(
     Ok(out)
 }
 
+pub fn subroutine_decl_string(
+    info: &DwarfInfo,
+    typedefs: &TypedefMap,
+    t: &SubroutineType,
+) -> Result<String> {
+    let rt = type_string(info, typedefs, &t.return_type, true)?;
+    let mut out = if t.local { "static ".to_string() } else { String::new() };
+    if t.inline {
+        out.push_str("inline ");
+    }
+    out.push_str(&rt.prefix);
+    out.push(' ');
+    if let Some(name) = t.name.as_ref() {
+        out.push_str(name);
+    }
+    let mut parameters = String::new();
+    if t.parameters.is_empty() {
+        if t.var_args {
+            parameters = "...".to_string();
+        } else if t.prototyped {
+            parameters = "void".to_string();
+        }
+    } else {
+        for (idx, parameter) in t.parameters.iter().enumerate() {
+            if idx > 0 {
+                write!(parameters, ", ")?;
+            }
+            let ts = type_string(info, typedefs, &parameter.kind, true)?;
+            if let Some(name) = &parameter.name {
+                write!(parameters, "{} {}{}", ts.prefix, name, ts.suffix)?;
+            } else {
+                write!(parameters, "{}{}", ts.prefix, ts.suffix)?;
+            }
+        }
+        if t.var_args {
+            write!(parameters, ", ...")?;
+        }
+    }
+    write!(out, "({}){};", parameters, rt.suffix)?;
+    Ok(out)
+}
+
 fn subroutine_block_string(
     info: &DwarfInfo,
     typedefs: &TypedefMap,
@@ -1852,6 +3357,7 @@ fn process_subroutine_tag(info: &DwarfInfo, tag: &Tag) -> Result<SubroutineType>
     let mut references = Vec::new();
     let mut member_of = None;
     let mut inline = false;
+    let mut spec_parameters = None;
     for attr in &tag.attributes {
         match (attr.kind, &attr.value) {
             (AttributeKind::Sibling, _) => {}
@@ -1925,8 +3431,7 @@ fn process_subroutine_tag(info: &DwarfInfo, tag: &Tag) -> Result<SubroutineType>
                 mangled_name = mangled_name.or(spec.mangled_name);
                 return_type = return_type.or(Some(spec.return_type));
                 prototyped = prototyped || spec.prototyped;
-                parameters.extend(spec.parameters);
-                var_args = var_args || spec.var_args;
+                spec_parameters = Some((spec.parameters, spec.var_args));
                 references.extend(spec.references);
                 member_of = member_of.or(spec.member_of);
                 inline = inline || spec.inline;
@@ -1963,12 +3468,20 @@ fn process_subroutine_tag(info: &DwarfInfo, tag: &Tag) -> Result<SubroutineType>
             | TagKind::UnionType
             | TagKind::ClassType
             | TagKind::SubroutineType
-            | TagKind::PtrToMemberType => {
+            | TagKind::PtrToMemberType
+            | TagKind::Typedef => {
                 // Variable type, ignore
             }
             kind => bail!("Unhandled SubroutineType child {:?}", kind),
         }
     }
+    // Parameters of the definition take precedence over the specification's
+    if let Some((spec_parameters, spec_var_args)) = spec_parameters {
+        if parameters.is_empty() && !var_args {
+            parameters = spec_parameters;
+            var_args = spec_var_args;
+        }
+    }
 
     let return_type = return_type
         .unwrap_or_else(|| Type { kind: TypeKind::Fundamental(FundType::Void), modifiers: vec![] });
@@ -2045,7 +3558,8 @@ fn process_subroutine_block_tag(info: &DwarfInfo, tag: &Tag) -> Result<Subroutin
             | TagKind::UnionType
             | TagKind::ClassType
             | TagKind::SubroutineType
-            | TagKind::PtrToMemberType => {
+            | TagKind::PtrToMemberType
+            | TagKind::Typedef => {
                 // Variable type, ignore
             }
             kind => bail!("Unhandled LexicalBlock child {:?}", kind),
@@ -2086,6 +3600,13 @@ fn process_inlined_subroutine_tag(info: &DwarfInfo, tag: &Tag) -> Result<Subrout
             TagKind::GlobalVariable => {
                 // TODO GlobalVariable refs?
             }
+            TagKind::FormalParameter
+            | TagKind::LocalVariable
+            | TagKind::LexicalBlock
+            | TagKind::InlinedSubroutine
+            | TagKind::Label => {
+                // DWARF 2 inlined instance, ignore
+            }
             kind => bail!("Unhandled InlinedSubroutine child {:?}", kind),
         }
     }
@@ -2435,3 +3956,455 @@ fn process_variable_tag(info: &DwarfInfo, tag: &Tag) -> Result<VariableTag> {
     let local = tag.kind == TagKind::LocalVariable;
     Ok(VariableTag { name, mangled_name, kind, address, local })
 }
+
+/// Collects symbols for all variables and subroutines with an address, for applying
+/// to a symbols file. Sections are not resolved. Tags that can't be processed are skipped.
+pub fn process_symbols(info: &DwarfInfo) -> Result<Vec<ObjSymbol>> {
+    let mut out = Vec::new();
+    let Some((_, mut unit)) = info.tags.first_key_value() else {
+        return Ok(out);
+    };
+    loop {
+        ensure!(unit.kind == TagKind::CompileUnit, "Expected CompileUnit, got {:?}", unit.kind);
+        for tag in unit.children(&info.tags) {
+            process_symbol_tag(info, tag, false, &mut out);
+        }
+        match unit.next_sibling(&info.tags) {
+            Some(next) => unit = next,
+            None => break,
+        }
+    }
+    Ok(out)
+}
+
+/// Adds a symbol for a variable or subroutine tag, logging and skipping it on failure.
+/// Subroutines and lexical blocks are searched for function-scope statics.
+fn process_symbol_tag(info: &DwarfInfo, tag: &Tag, nested: bool, out: &mut Vec<ObjSymbol>) {
+    let result = match tag.kind {
+        // Within a function, only variables with a fixed address are symbols
+        TagKind::LocalVariable if nested && !has_address_location(tag) => return,
+        TagKind::GlobalVariable | TagKind::LocalVariable => variable_symbol(info, tag),
+        TagKind::GlobalSubroutine | TagKind::Subroutine => subroutine_symbol(info, tag),
+        TagKind::LexicalBlock if nested => Ok(None),
+        _ => return,
+    };
+    match result {
+        Ok(Some(symbol)) => out.push(ObjSymbol {
+            demangled_name: demangle(&symbol.name, &Default::default()),
+            size_known: symbol.size != 0,
+            ..symbol
+        }),
+        Ok(None) => {}
+        Err(e) => {
+            log::warn!("Skipping tag {} ({:?}): {:?}", tag.key, tag.kind, e);
+        }
+    }
+    if matches!(tag.kind, TagKind::GlobalSubroutine | TagKind::Subroutine | TagKind::LexicalBlock) {
+        for child in tag.children(&info.tags) {
+            process_symbol_tag(info, child, true, out);
+        }
+    }
+}
+
+fn has_address_location(tag: &Tag) -> bool {
+    tag.attributes.iter().any(|attr| {
+        matches!(
+            (attr.kind, &attr.value),
+            (AttributeKind::Location, AttributeValue::Block(block))
+                if block.len() == 5 && block[0] == LocationOp::Address as u8
+        )
+    })
+}
+
+fn variable_symbol(info: &DwarfInfo, tag: &Tag) -> Result<Option<ObjSymbol>> {
+    let variable = process_variable_tag(info, tag)?;
+    let (Some(name), Some(address)) = (variable.mangled_name.or(variable.name), variable.address)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(ObjSymbol {
+        name,
+        address: address as u64,
+        size: variable.kind.size(info)? as u64,
+        kind: ObjSymbolKind::Object,
+        flags: symbol_flags(variable.local),
+        data_kind: type_data_kind(info, &variable.kind)?,
+        ..Default::default()
+    }))
+}
+
+fn subroutine_symbol(info: &DwarfInfo, tag: &Tag) -> Result<Option<ObjSymbol>> {
+    let (Some(start), Some(end)) =
+        (tag.address_attribute(AttributeKind::LowPc), tag.address_attribute(AttributeKind::HighPc))
+    else {
+        return Ok(None);
+    };
+    let subroutine = process_subroutine_tag(info, tag)?;
+    // Member functions can only be named by their mangled name
+    let name = match (subroutine.mangled_name, subroutine.member_of) {
+        (Some(name), _) => name,
+        (None, None) if subroutine.name.is_some() => subroutine.name.unwrap(),
+        _ => return Ok(None),
+    };
+    Ok(Some(ObjSymbol {
+        name,
+        address: start as u64,
+        size: end.saturating_sub(start) as u64,
+        kind: ObjSymbolKind::Function,
+        flags: symbol_flags(subroutine.local),
+        ..Default::default()
+    }))
+}
+
+/// A compile unit and the address ranges it occupies.
+#[derive(Debug, Clone)]
+pub struct CompileUnit {
+    pub name: String,
+    pub ranges: Vec<Range<u32>>,
+}
+
+/// Collects the address ranges of each compile unit, in order. Ranges come from
+/// .debug_aranges (if provided), the unit's own LowPc/HighPc, and its subroutines and
+/// variables. Units without any ranges are omitted.
+pub fn process_compile_units(info: &DwarfInfo, aranges: &[UnitRanges]) -> Result<Vec<CompileUnit>> {
+    let mut unit_ranges = BTreeMap::<u32, Vec<Range<u32>>>::new();
+    for entry in aranges {
+        match info.tags.range(entry.offset..).next() {
+            Some((&key, tag)) if tag.kind == TagKind::CompileUnit => {
+                unit_ranges.entry(key).or_default().extend(entry.ranges.iter().cloned());
+            }
+            _ => log::warn!("Failed to locate compile unit at {:#X} for aranges", entry.offset),
+        }
+    }
+
+    let mut out = Vec::new();
+    let Some((_, mut unit)) = info.tags.first_key_value() else {
+        return Ok(out);
+    };
+    loop {
+        ensure!(unit.kind == TagKind::CompileUnit, "Expected CompileUnit, got {:?}", unit.kind);
+        let name = unit
+            .string_attribute(AttributeKind::Name)
+            .ok_or_else(|| anyhow!("CompileUnit without name {:?}", unit))?;
+        let mut ranges = unit_ranges.remove(&unit.key).unwrap_or_default();
+        if let (Some(start), Some(end)) = (
+            unit.address_attribute(AttributeKind::LowPc),
+            unit.address_attribute(AttributeKind::HighPc),
+        ) {
+            ranges.push(start..end);
+        }
+        for tag in unit.children(&info.tags) {
+            match tag.kind {
+                TagKind::GlobalVariable | TagKind::LocalVariable => {
+                    let result = process_variable_tag(info, tag).and_then(|variable| {
+                        variable
+                            .address
+                            .map(|address| Ok(address..address + variable.kind.size(info)?))
+                            .transpose()
+                    });
+                    match result {
+                        Ok(Some(range)) => ranges.push(range),
+                        Ok(None) => {}
+                        Err(e) => log::warn!("Skipping tag {} (unit {}): {:?}", tag.key, name, e),
+                    }
+                }
+                TagKind::GlobalSubroutine | TagKind::Subroutine => {
+                    if let (Some(start), Some(end)) = (
+                        tag.address_attribute(AttributeKind::LowPc),
+                        tag.address_attribute(AttributeKind::HighPc),
+                    ) {
+                        ranges.push(start..end);
+                    }
+                }
+                _ => {}
+            }
+        }
+        ranges.retain(|r| r.start < r.end);
+        if !ranges.is_empty() {
+            ranges.sort_by_key(|r| (r.start, r.end));
+            ranges.dedup();
+            out.push(CompileUnit { name: normalize_unit_name(name), ranges });
+        }
+        match unit.next_sibling(&info.tags) {
+            Some(next) => unit = next,
+            None => break,
+        }
+    }
+    Ok(out)
+}
+
+/// Normalizes a compile unit path to use forward slashes, without a drive letter or
+/// leading slash, so that units with the same file name in different directories remain
+/// distinct.
+fn normalize_unit_name(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    let name = match name.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => &name[2..],
+        _ => &name,
+    };
+    name.trim_start_matches('/').to_string()
+}
+
+/// Creates a split in each section covered by a compile unit, and adds the units to
+/// the link order. Splits that would overlap an existing split are skipped.
+pub fn apply_compile_units(obj: &mut ObjInfo, units: &[CompileUnit]) -> Result<()> {
+    let mut unit_names = BTreeSet::<String>::new();
+    for unit in units {
+        let mut name = unit.name.clone();
+        let mut n = 1;
+        while unit_names.contains(&name) {
+            n += 1;
+            name = match unit.name.rsplit_once('.') {
+                Some((stem, ext)) => format!("{stem}_{n}.{ext}"),
+                None => format!("{}_{n}", unit.name),
+            };
+        }
+        unit_names.insert(name.clone());
+
+        let mut section_ranges = BTreeMap::<usize, Range<u32>>::new();
+        for range in &unit.ranges {
+            let Ok((section_index, section)) = obj.sections.at_address(range.start) else {
+                log::warn!(
+                    "Failed to locate section for {} range {:#010X}..{:#010X}",
+                    name,
+                    range.start,
+                    range.end
+                );
+                continue;
+            };
+            let end = range.end.min((section.address + section.size) as u32);
+            let entry = section_ranges.entry(section_index).or_insert(range.start..end);
+            entry.start = entry.start.min(range.start);
+            entry.end = entry.end.max(end);
+        }
+
+        let mut added = false;
+        for (section_index, range) in section_ranges {
+            let section = &mut obj.sections[section_index];
+            if let Some((addr, split)) = section
+                .splits
+                .for_range(..range.end)
+                .find(|&(addr, split)| addr >= range.start || split.end > range.start)
+            {
+                // Splits previously generated from DWARF (e.g. written to splits.txt) are kept
+                if split.unit != name {
+                    log::warn!(
+                        "Skipping {} {} split {:#010X}..{:#010X}: overlaps {} ({:#010X}..{:#010X})",
+                        name,
+                        section.name,
+                        range.start,
+                        range.end,
+                        split.unit,
+                        addr,
+                        split.end
+                    );
+                }
+                continue;
+            }
+            section.splits.push(range.start, ObjSplit {
+                unit: name.clone(),
+                end: range.end,
+                align: None,
+                common: false,
+                autogenerated: false,
+                skip: false,
+                rename: None,
+            });
+            added = true;
+        }
+        if added && !obj.link_order.iter().any(|u| u.name == name) {
+            obj.link_order.push(ObjUnit { name, autogenerated: false, comment_version: None });
+        }
+    }
+    Ok(())
+}
+
+fn symbol_flags(local: bool) -> ObjSymbolFlagSet {
+    let mut flags = ObjSymbolFlagSet::default();
+    flags.set_scope(if local { ObjSymbolScope::Local } else { ObjSymbolScope::Global });
+    flags
+}
+
+/// Data kind for a variable type. Arrays use the kind of their elements.
+fn type_data_kind(info: &DwarfInfo, t: &Type) -> Result<ObjDataKind> {
+    if t.modifiers.iter().any(|m| matches!(m, Modifier::PointerTo | Modifier::ReferenceTo)) {
+        return Ok(ObjDataKind::Byte4);
+    }
+    Ok(match t.kind {
+        TypeKind::Fundamental(ft) => match ft {
+            FundType::Float => ObjDataKind::Float,
+            FundType::Vec2x32Float => ObjDataKind::Float2,
+            FundType::DblPrecFloat | FundType::ExtPrecFloat => ObjDataKind::Double,
+            FundType::Char | FundType::SignedChar | FundType::UnsignedChar | FundType::Boolean => {
+                ObjDataKind::Byte
+            }
+            FundType::WideChar
+            | FundType::Short
+            | FundType::SignedShort
+            | FundType::UnsignedShort => ObjDataKind::Byte2,
+            FundType::Integer
+            | FundType::SignedInteger
+            | FundType::UnsignedInteger
+            | FundType::Long
+            | FundType::SignedLong
+            | FundType::UnsignedLong
+            | FundType::Pointer => ObjDataKind::Byte4,
+            FundType::LongLong | FundType::SignedLongLong | FundType::UnsignedLongLong => {
+                ObjDataKind::Byte8
+            }
+            _ => ObjDataKind::Unknown,
+        },
+        TypeKind::UserDefined(key) => {
+            let tag = info
+                .tags
+                .get(&key)
+                .ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+            match ud_type(info, tag)? {
+                UserDefinedType::Array(t) => type_data_kind(info, &t.element_type)?,
+                UserDefinedType::Enumeration(t) => match t.byte_size {
+                    1 => ObjDataKind::Byte,
+                    2 => ObjDataKind::Byte2,
+                    4 => ObjDataKind::Byte4,
+                    _ => ObjDataKind::Unknown,
+                },
+                UserDefinedType::PtrToMember(_) => ObjDataKind::Byte4,
+                _ => ObjDataKind::Unknown,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uleb(data: &[u8]) -> u64 { read_uleb128(&mut Cursor::new(data)).unwrap() }
+
+    fn sleb(data: &[u8]) -> i64 { read_sleb128(&mut Cursor::new(data)).unwrap() }
+
+    #[test]
+    fn test_leb128() {
+        assert_eq!(uleb(&[0x00]), 0);
+        assert_eq!(uleb(&[0x7f]), 127);
+        assert_eq!(uleb(&[0x80, 0x01]), 128);
+        // Redundant continuation bytes
+        assert_eq!(uleb(&[0x80, 0x80, 0x00]), 0);
+        assert_eq!(uleb(&[0xff, 0xff, 0xff, 0xff, 0x0f]), u32::MAX as u64);
+        assert_eq!(uleb(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]), u64::MAX);
+        // Bits past 64 are discarded
+        assert_eq!(uleb(&[0x81, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f]), 1);
+        assert!(read_uleb128(&mut Cursor::new([0x80u8])).is_err());
+
+        assert_eq!(sleb(&[0x00]), 0);
+        assert_eq!(sleb(&[0x3f]), 63);
+        assert_eq!(sleb(&[0x40]), -64);
+        assert_eq!(sleb(&[0x7f]), -1);
+        assert_eq!(sleb(&[0xc0, 0x00]), 64);
+        assert_eq!(sleb(&[0x80, 0x7f]), -128);
+        assert_eq!(sleb(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f]), i64::MIN);
+        assert_eq!(sleb(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]), i64::MAX);
+        assert!(read_sleb128(&mut Cursor::new([0xffu8])).is_err());
+    }
+
+    /// Abbreviations for a compile unit containing `int x;` and `void f() { int y; }`.
+    const ABBREVS: &[u8] = &[
+        // 1: DW_TAG_compile_unit, children, DW_AT_name/string
+        0x01, 0x11, 0x01, 0x03, 0x08, 0x00, 0x00,
+        // 2: DW_TAG_base_type, DW_AT_name/string, DW_AT_encoding/data1, DW_AT_byte_size/data1
+        0x02, 0x24, 0x00, 0x03, 0x08, 0x3e, 0x0b, 0x0b, 0x0b, 0x00, 0x00,
+        // 3: DW_TAG_variable, DW_AT_name/string, DW_AT_type/ref4, DW_AT_external/flag,
+        //    DW_AT_location/block1
+        0x03, 0x34, 0x00, 0x03, 0x08, 0x49, 0x13, 0x3f, 0x0c, 0x02, 0x0a, 0x00, 0x00,
+        // 4: DW_TAG_subprogram, children, DW_AT_name/string, DW_AT_external/flag,
+        //    DW_AT_low_pc/addr, DW_AT_high_pc/addr, DW_AT_frame_base/block1
+        0x04, 0x2e, 0x01, 0x03, 0x08, 0x3f, 0x0c, 0x11, 0x01, 0x12, 0x01, 0x40, 0x0a, 0x00, 0x00,
+        // 5: DW_TAG_variable, DW_AT_name/string, DW_AT_type/ref4, DW_AT_location/block1
+        0x05, 0x34, 0x00, 0x03, 0x08, 0x49, 0x13, 0x02, 0x0a, 0x00, 0x00,
+        // End of table, followed by an unrelated table
+        0x00, 0x01, 0x24, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_read_abbrevs() {
+        let abbrevs = read_dwarf2_abbrevs(ABBREVS, 0).unwrap();
+        assert_eq!(abbrevs.keys().copied().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        let abbrev = &abbrevs[&4];
+        assert_eq!(abbrev.tag, Dwarf2Tag::Subprogram as u64);
+        assert!(abbrev.has_children);
+        assert_eq!(abbrev.attributes, [
+            (0x03, 0x08),
+            (0x3f, 0x0c),
+            (0x11, 0x01),
+            (0x12, 0x01),
+            (0x40, 0x0a)
+        ]);
+        assert!(!abbrevs[&2].has_children);
+
+        let offset = ABBREVS.len() as u32 - 6;
+        let abbrevs = read_dwarf2_abbrevs(ABBREVS, offset).unwrap();
+        assert_eq!(abbrevs.len(), 1);
+        assert_eq!(abbrevs[&1].tag, Dwarf2Tag::BaseType as u64);
+        assert!(abbrevs[&1].attributes.is_empty());
+        assert!(read_dwarf2_abbrevs(ABBREVS, ABBREVS.len() as u32).is_err());
+    }
+
+    #[test]
+    fn test_translate_entries() {
+        let mut data = vec![0, 0, 0, 0, 0x00, 0x02, 0, 0, 0, 0, 0x04];
+        let cu = data.len() as u32;
+        data.extend_from_slice(b"\x01test.c\0");
+        let int = data.len() as u32;
+        data.extend_from_slice(b"\x02int\0\x05\x04");
+        let x = data.len() as u32;
+        data.extend_from_slice(b"\x03x\0");
+        data.extend_from_slice(&int.to_be_bytes());
+        // DW_OP_addr 0x80000010
+        data.extend_from_slice(&[0x01, 0x05, 0x03, 0x80, 0x00, 0x00, 0x10]);
+        let f = data.len() as u32;
+        data.extend_from_slice(b"\x04f\0\x01");
+        data.extend_from_slice(&0x80001000u32.to_be_bytes());
+        data.extend_from_slice(&0x80001020u32.to_be_bytes());
+        // DW_OP_breg1 0
+        data.extend_from_slice(&[0x02, 0x71, 0x00]);
+        let y = data.len() as u32;
+        data.extend_from_slice(b"\x05y\0");
+        data.extend_from_slice(&int.to_be_bytes());
+        // DW_OP_fbreg 8
+        data.extend_from_slice(&[0x02, 0x91, 0x08]);
+        // End of children of f, then of the compile unit
+        data.extend_from_slice(&[0x00, 0x00]);
+        let unit_length = data.len() as u32 - 4;
+        data[..4].copy_from_slice(&unit_length.to_be_bytes());
+
+        let info = read_debug_info_section(&data, ABBREVS, None, Endian::Big).unwrap();
+        // The base type is folded into the variable types
+        assert_eq!(info.tags.values().map(|t| (t.key, t.kind)).collect::<Vec<_>>(), [
+            (cu, TagKind::CompileUnit),
+            (x, TagKind::GlobalVariable),
+            (f, TagKind::GlobalSubroutine),
+            (y, TagKind::LocalVariable),
+        ]);
+        let unit = &info.tags[&cu];
+        assert_eq!(unit.string_attribute(AttributeKind::Name).map(String::as_str), Some("test.c"));
+        let children = unit.children(&info.tags).iter().map(|t| t.key).collect::<Vec<_>>();
+        assert_eq!(children, [x, f]);
+        let subroutine = &info.tags[&f];
+        assert_eq!(subroutine.address_attribute(AttributeKind::LowPc), Some(0x80001000));
+        assert_eq!(subroutine.address_attribute(AttributeKind::HighPc), Some(0x80001020));
+        let children = subroutine.children(&info.tags).iter().map(|t| t.key).collect::<Vec<_>>();
+        assert_eq!(children, [y]);
+
+        let TagType::Variable(variable) = process_root_tag(&info, &info.tags[&x]).unwrap() else {
+            panic!("Expected a variable");
+        };
+        assert_eq!(variable.name.as_deref(), Some("x"));
+        assert!(matches!(variable.kind.kind, TypeKind::Fundamental(FundType::Integer)));
+        assert_eq!(variable.address, Some(0x80000010));
+        assert!(!variable.local);
+
+        // The frame base offset is folded into the local's location
+        let local = &info.tags[&y];
+        let location = local.block_attribute(AttributeKind::Location).unwrap();
+        assert_eq!(process_variable_location(location, Endian::Big).unwrap(), "r1+0x8");
+    }
+}
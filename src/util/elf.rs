@@ -45,7 +45,12 @@ enum BoundaryState {
 pub fn process_elf<P>(path: P) -> Result<ObjInfo>
 where P: AsRef<Path> {
     let file = map_file(path)?;
-    let obj_file = object::read::File::parse(file.as_slice())?;
+    process_elf_data(file.as_slice())
+}
+
+/// Processes an ELF already loaded into memory, such as an archive member.
+pub fn process_elf_data(data: &[u8]) -> Result<ObjInfo> {
+    let obj_file = object::read::File::parse(data)?;
     let architecture = match obj_file.architecture() {
         Architecture::PowerPc => ObjArchitecture::PowerPc,
         Architecture::Mips => ObjArchitecture::Mips,
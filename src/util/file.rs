@@ -1,12 +1,13 @@
 use std::{
     borrow::Cow,
     ffi::OsStr,
+    fs,
     fs::{DirBuilder, File, OpenOptions},
     io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom},
     path::{Component, Path, PathBuf},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use filetime::{set_file_mtime, FileTime};
 use memmap2::{Mmap, MmapOptions};
 use path_slash::PathBufExt;
@@ -16,9 +17,15 @@ use xxhash_rust::xxh3::xxh3_64;
 use crate::{
     array_ref,
     util::{
+        disc,
+        disc::{
+            block::{BlockStream, ContainerFormat},
+            DiscFs, ReadSeek,
+        },
         rarc,
         rarc::{Node, RARC_MAGIC},
         take_seek::{TakeSeek, TakeSeekExt},
+        u8_arc::{U8NodeKind, U8Reader, U8_MAGIC},
         yaz0,
         yaz0::YAZ0_MAGIC,
         IntoCow, ToCow,
@@ -46,11 +53,17 @@ impl MappedFile {
     pub fn into_inner(self) -> Mmap { self.mmap }
 }
 
+/// Splits a path at the first component containing a `:`, such as `game.iso:sys/main.dol`.
+/// The sub-path may itself contain further `:` separators for nested archives.
 pub fn split_path<P>(path: P) -> Result<(PathBuf, Option<PathBuf>)>
 where P: AsRef<Path> {
     let mut base_path = PathBuf::new();
     let mut sub_path: Option<PathBuf> = None;
     for component in path.as_ref().components() {
+        if let Some(sub_path) = &mut sub_path {
+            sub_path.push(component);
+            continue;
+        }
         if let Component::Normal(str) = component {
             let str = str.to_str().ok_or(anyhow!("Path is not valid UTF-8"))?;
             if let Some((a, b)) = str.split_once(':') {
@@ -59,11 +72,7 @@ where P: AsRef<Path> {
                 continue;
             }
         }
-        if let Some(sub_path) = &mut sub_path {
-            sub_path.push(component);
-        } else {
-            base_path.push(component);
-        }
+        base_path.push(component);
     }
     Ok((base_path, sub_path))
 }
@@ -78,28 +87,12 @@ where P: AsRef<Path> {
     let mmap = unsafe { MmapOptions::new().map(&file) }
         .with_context(|| format!("Failed to mmap file: '{}'", base_path.display()))?;
     let (offset, len) = if let Some(sub_path) = sub_path {
-        let mut reader = Cursor::new(&*mmap);
-        if sub_path.as_os_str() == OsStr::new("nlzss") {
-            return Ok(FileEntry::Buffer(
-                nintendo_lz::decompress(&mut reader).map_err(|e| {
-                    anyhow!("Failed to decompress '{}' with NLZSS: {}", path.as_ref().display(), e)
-                })?,
-                mtime,
-            ));
-        } else if sub_path.as_os_str() == OsStr::new("yaz0") {
-            return Ok(FileEntry::Buffer(
-                yaz0::decompress_file(&mut reader).with_context(|| {
-                    format!("Failed to decompress '{}' with Yaz0", path.as_ref().display())
-                })?,
-                mtime,
-            ));
+        match resolve_sub_path(&mmap, &base_path, &sub_path)? {
+            SubFile::Range(offset, len) => (offset, len),
+            SubFile::Buffer(buf) => {
+                return Ok(FileEntry::Buffer(decompress_if_needed(&buf)?.into_owned(), mtime))
+            }
         }
-
-        let rarc = rarc::RarcReader::new(&mut reader)
-            .with_context(|| format!("Failed to open '{}' as RARC archive", base_path.display()))?;
-        rarc.find_file(&sub_path)?.map(|(o, s)| (o, s as u64)).ok_or_else(|| {
-            anyhow!("File '{}' not found in '{}'", sub_path.display(), base_path.display())
-        })?
     } else {
         (0, mmap.len() as u64)
     };
@@ -117,6 +110,116 @@ where P: AsRef<Path> {
     Ok(FileEntry::MappedFile(map))
 }
 
+/// A file located within another file.
+enum SubFile {
+    /// Offset and size within the containing data.
+    Range(u64, u64),
+    /// Decompressed or otherwise transformed data.
+    Buffer(Vec<u8>),
+}
+
+impl SubFile {
+    /// Converts a result relative to `buf` into an owned buffer.
+    fn into_buffer(self, buf: &[u8]) -> Vec<u8> {
+        match self {
+            SubFile::Range(offset, len) => buf[offset as usize..(offset + len) as usize].to_vec(),
+            SubFile::Buffer(buf) => buf,
+        }
+    }
+}
+
+/// Resolves a sub-path within `data`, which may be a compressed file, archive or disc image.
+/// Nested sub-paths (e.g. `files/rels.arc:mod.rel`) are resolved recursively.
+fn resolve_sub_path(data: &[u8], base_path: &Path, sub_path: &Path) -> Result<SubFile> {
+    if sub_path.as_os_str() == OsStr::new("nlzss") {
+        return Ok(SubFile::Buffer(nintendo_lz::decompress(&mut Cursor::new(data)).map_err(
+            |e| anyhow!("Failed to decompress '{}' with NLZSS: {}", base_path.display(), e),
+        )?));
+    } else if sub_path.as_os_str() == OsStr::new("yaz0") {
+        return Ok(SubFile::Buffer(yaz0::decompress_file(&mut Cursor::new(data)).with_context(
+            || format!("Failed to decompress '{}' with Yaz0", base_path.display()),
+        )?));
+    }
+    if data.len() > 4 && data[0..4] == YAZ0_MAGIC {
+        // Compressed archive: decompress it, then resolve the sub-path within it
+        let buf = yaz0::decompress_file(&mut Cursor::new(data))
+            .with_context(|| format!("Failed to decompress '{}' with Yaz0", base_path.display()))?;
+        let result = resolve_sub_path(&buf, base_path, sub_path)?;
+        return Ok(SubFile::Buffer(result.into_buffer(&buf)));
+    }
+
+    let (file_path, next_path) = split_path(sub_path)?;
+    let read_disc_file = |reader: &mut dyn ReadSeek| {
+        disc::read_file(reader, &file_path).with_context(|| {
+            format!("Failed to read '{}' from '{}'", file_path.display(), base_path.display())
+        })
+    };
+    let result = if let Some((_, mut stream)) = BlockStream::open(Cursor::new(data))
+        .with_context(|| format!("Failed to open disc image '{}'", base_path.display()))?
+    {
+        // Compressed disc images are decompressed on demand, reading only the requested file
+        SubFile::Buffer(read_disc_file(&mut stream)?)
+    } else if disc::is_wii_disc(data) {
+        // Wii partitions are encrypted, so the file must be read into a buffer
+        SubFile::Buffer(read_disc_file(&mut Cursor::new(data))?)
+    } else {
+        let (offset, len) = find_archive_file(&mut Cursor::new(data), base_path, &file_path)?;
+        SubFile::Range(offset, len)
+    };
+    let Some(next_path) = next_path else {
+        return Ok(result);
+    };
+    let inner_path = base_path.join(&file_path);
+    Ok(match result {
+        SubFile::Range(offset, len) => {
+            let inner = &data[offset as usize..(offset + len) as usize];
+            match resolve_sub_path(inner, &inner_path, &next_path)? {
+                SubFile::Range(inner_offset, len) => SubFile::Range(offset + inner_offset, len),
+                buffer => buffer,
+            }
+        }
+        SubFile::Buffer(buf) => {
+            SubFile::Buffer(resolve_sub_path(&buf, &inner_path, &next_path)?.into_buffer(&buf))
+        }
+    })
+}
+
+/// Locates a file within a RARC archive, U8 archive or GameCube disc image, detected by magic.
+/// Returns the offset and size of the file data.
+fn find_archive_file<R>(reader: &mut R, base_path: &Path, sub_path: &Path) -> Result<(u64, u64)>
+where R: Read + Seek + ?Sized {
+    let base = reader.stream_position()?;
+    let mut magic = [0u8; 0x20];
+    reader
+        .read_exact(&mut magic)
+        .with_context(|| format!("Failed to read archive '{}'", base_path.display()))?;
+    reader.seek(SeekFrom::Current(-(magic.len() as i64)))?;
+    let result = match *array_ref!(magic, 0, 4) {
+        RARC_MAGIC => rarc::RarcReader::new(reader)
+            .with_context(|| format!("Failed to open '{}' as RARC archive", base_path.display()))?
+            .find_file(sub_path)?
+            .map(|(o, s)| (o, s as u64)),
+        U8_MAGIC => U8Reader::new(reader)
+            .with_context(|| format!("Failed to open '{}' as U8 archive", base_path.display()))?
+            .find_file(sub_path)?
+            .map(|(o, s)| (o, s as u64)),
+        _ if ContainerFormat::detect(&magic).is_some() || disc::is_wii_disc(&magic) => bail!(
+            "'{}' is a compressed or Wii disc image, which can only be read through a memory mapped file",
+            base_path.display()
+        ),
+        _ if disc::is_disc(&magic) => {
+            let fs = DiscFs::new(reader).with_context(|| {
+                format!("Failed to open '{}' as disc image", base_path.display())
+            })?;
+            fs.find_file(sub_path)?.map(|node| (base + node.offset, node.size))
+        }
+        _ => bail!("'{}' is not a RARC archive, U8 archive or disc image", base_path.display()),
+    };
+    result.ok_or_else(|| {
+        anyhow!("File '{}' not found in '{}'", sub_path.display(), base_path.display())
+    })
+}
+
 pub type OpenedFile = TakeSeek<File>;
 
 /// Opens a file (not memory mapped). No decompression is performed.
@@ -125,15 +228,15 @@ where P: AsRef<Path> {
     let (base_path, sub_path) = split_path(path)?;
     let mut file = File::open(&base_path)
         .with_context(|| format!("Failed to open file '{}'", base_path.display()))?;
-    let (offset, size) = if let Some(sub_path) = sub_path {
-        let rarc = rarc::RarcReader::new(&mut BufReader::new(&file))
-            .with_context(|| format!("Failed to read RARC '{}'", base_path.display()))?;
-        rarc.find_file(&sub_path)?.map(|(o, s)| (o, s as u64)).ok_or_else(|| {
-            anyhow!("File '{}' not found in '{}'", sub_path.display(), base_path.display())
-        })?
-    } else {
-        (0, file.seek(SeekFrom::End(0))?)
-    };
+    let (mut offset, mut size) = (0, file.seek(SeekFrom::End(0))?);
+    let mut next_path = sub_path;
+    while let Some(sub_path) = next_path {
+        let (file_path, rest) = split_path(&sub_path)?;
+        let mut reader = BufReader::new(&file);
+        reader.seek(SeekFrom::Start(offset))?;
+        (offset, size) = find_archive_file(&mut reader, &base_path, &file_path)?;
+        next_path = rest;
+    }
     file.seek(SeekFrom::Start(offset))?;
     Ok(file.take_seek(size))
 }
@@ -215,22 +318,72 @@ pub fn process_rsp(files: &[PathBuf]) -> Result<Vec<PathBuf>> {
     Ok(out)
 }
 
-/// Iterator over files in a RARC archive.
-struct RarcIterator {
+/// An entry in a directory tree, used when building archives.
+pub enum ArchiveEntry {
+    File { name: String, data: Vec<u8> },
+    Directory { name: String, entries: Vec<ArchiveEntry> },
+}
+
+impl ArchiveEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            ArchiveEntry::File { name, .. } | ArchiveEntry::Directory { name, .. } => name,
+        }
+    }
+}
+
+/// Reads the contents of a directory recursively.
+pub fn read_dir_entries<P>(path: P) -> Result<Vec<ArchiveEntry>>
+where P: AsRef<Path> {
+    let path = path.as_ref();
+    let mut entries = vec![];
+    for entry in fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory '{}'", path.display()))?
+    {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("Invalid file name {:?}", name))?;
+        if entry.file_type()?.is_dir() {
+            entries.push(ArchiveEntry::Directory { name, entries: read_dir_entries(&entry_path)? });
+        } else {
+            let data = fs::read(&entry_path)
+                .with_context(|| format!("Failed to read file '{}'", entry_path.display()))?;
+            entries.push(ArchiveEntry::File { name, data });
+        }
+    }
+    Ok(entries)
+}
+
+/// Iterator over files in a RARC or U8 archive.
+struct ArchiveIterator {
     file: MappedFile,
     base_path: PathBuf,
     paths: Vec<(PathBuf, u64, u32)>,
     index: usize,
 }
 
-impl RarcIterator {
+impl ArchiveIterator {
     pub fn new(file: MappedFile, base_path: &Path) -> Result<Self> {
-        let reader = rarc::RarcReader::new(&mut file.as_reader())?;
-        let paths = Self::collect_paths(&reader, base_path);
+        let buf = file.as_slice();
+        let paths = if buf.len() > 4 && buf[0..4] == U8_MAGIC {
+            let reader = U8Reader::new(&mut file.as_reader())?;
+            reader
+                .nodes()
+                .iter()
+                .filter(|node| node.kind == U8NodeKind::File)
+                .map(|node| (base_path.join(&node.path), node.offset, node.size))
+                .collect()
+        } else {
+            let reader = rarc::RarcReader::new(&mut file.as_reader())?;
+            Self::collect_rarc_paths(&reader, base_path)
+        };
         Ok(Self { file, base_path: base_path.to_owned(), paths, index: 0 })
     }
 
-    fn collect_paths(reader: &rarc::RarcReader, base_path: &Path) -> Vec<(PathBuf, u64, u32)> {
+    fn collect_rarc_paths(reader: &rarc::RarcReader, base_path: &Path) -> Vec<(PathBuf, u64, u32)> {
         let mut current_path = PathBuf::new();
         let mut paths = vec![];
         for node in reader.nodes() {
@@ -253,7 +406,7 @@ impl RarcIterator {
     }
 }
 
-impl Iterator for RarcIterator {
+impl Iterator for ArchiveIterator {
     type Item = Result<(PathBuf, Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -333,30 +486,30 @@ impl FileReadInfo {
 }
 
 /// Iterate over file paths, expanding response files (@) and glob patterns (*).
-/// If a file is a RARC archive, iterate over its contents.
+/// If a file is a RARC or U8 archive, iterate over its contents.
 /// If a file is a Yaz0 compressed file, decompress it.
 pub struct FileIterator {
     paths: Vec<PathBuf>,
     index: usize,
-    rarc: Option<RarcIterator>,
+    archive: Option<ArchiveIterator>,
 }
 
 impl FileIterator {
     pub fn new(paths: &[PathBuf]) -> Result<Self> {
-        Ok(Self { paths: process_rsp(paths)?, index: 0, rarc: None })
+        Ok(Self { paths: process_rsp(paths)?, index: 0, archive: None })
     }
 
-    fn next_rarc(&mut self) -> Option<Result<(PathBuf, FileEntry)>> {
-        if let Some(rarc) = &mut self.rarc {
-            match rarc.next() {
+    fn next_archive(&mut self) -> Option<Result<(PathBuf, FileEntry)>> {
+        if let Some(archive) = &mut self.archive {
+            match archive.next() {
                 Some(Ok((path, buf))) => {
-                    let mut path_str = rarc.base_path.as_os_str().to_os_string();
+                    let mut path_str = archive.base_path.as_os_str().to_os_string();
                     path_str.push(OsStr::new(":"));
                     path_str.push(path.as_os_str());
-                    return Some(Ok((path, FileEntry::Buffer(buf, rarc.file.mtime))));
+                    return Some(Ok((path, FileEntry::Buffer(buf, archive.file.mtime))));
                 }
                 Some(Err(err)) => return Some(Err(err)),
-                None => self.rarc = None,
+                None => self.archive = None,
             }
         }
         None
@@ -388,7 +541,7 @@ impl FileIterator {
 
         match *array_ref!(buf, 0, 4) {
             YAZ0_MAGIC => self.handle_yaz0(file, path),
-            RARC_MAGIC => self.handle_rarc(file, path),
+            RARC_MAGIC | U8_MAGIC => self.handle_archive(file, path),
             _ => Some(Ok((path, FileEntry::MappedFile(file)))),
         }
     }
@@ -404,12 +557,12 @@ impl FileIterator {
         })
     }
 
-    fn handle_rarc(
+    fn handle_archive(
         &mut self,
         file: MappedFile,
         path: PathBuf,
     ) -> Option<Result<(PathBuf, FileEntry)>> {
-        self.rarc = match RarcIterator::new(file, &path) {
+        self.archive = match ArchiveIterator::new(file, &path) {
             Ok(iter) => Some(iter),
             Err(e) => return Some(Err(e)),
         };
@@ -420,7 +573,7 @@ impl FileIterator {
 impl Iterator for FileIterator {
     type Item = Result<(PathBuf, FileEntry)>;
 
-    fn next(&mut self) -> Option<Self::Item> { self.next_rarc().or_else(|| self.next_path()) }
+    fn next(&mut self) -> Option<Self::Item> { self.next_archive().or_else(|| self.next_path()) }
 }
 
 pub fn touch<P>(path: P) -> std::io::Result<()>
@@ -0,0 +1,176 @@
+use std::{ffi::OsStr, str::FromStr};
+
+use argp::FromArgValue;
+
+/// Compression mode for the LZ-based encoders.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub enum CompressionMode {
+    /// Reproduces Nintendo's encoder: an exhaustive search for the longest match,
+    /// with a one-byte lookahead that defers to a better match at the next position.
+    #[default]
+    Matching,
+    /// Greedy encoder with a bounded search. Output is valid, but not byte-identical
+    /// to Nintendo's tools.
+    Fast,
+}
+
+impl FromStr for CompressionMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "matching" => Self::Matching,
+            "fast" => Self::Fast,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl FromArgValue for CompressionMode {
+    fn from_arg_value(value: &OsStr) -> Result<Self, String> {
+        String::from_arg_value(value)
+            .and_then(|s| Self::from_str(&s).map_err(|_| "Invalid compression mode".to_string()))
+    }
+}
+
+/// Shortest back-reference either encoder will emit.
+pub const MIN_MATCH: usize = 3;
+
+const HASH_BITS: u32 = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const NO_POS: u32 = u32::MAX;
+/// Maximum number of candidates visited per search in [`CompressionMode::Fast`].
+const FAST_MAX_CHAIN: usize = 128;
+
+/// A back-reference into the sliding window.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Match {
+    /// Length of the match. Values below [`MIN_MATCH`] indicate a literal.
+    pub len: usize,
+    /// Absolute position of the match source.
+    pub pos: usize,
+}
+
+/// Searches the sliding window for matches using hash chains over [`MIN_MATCH`]-byte prefixes.
+///
+/// In [`CompressionMode::Matching`], every candidate in the window is visited and the
+/// earliest of the longest matches is selected, which is equivalent to the brute-force
+/// search used by Nintendo's encoder. Like Nintendo's encoder, match lengths are only
+/// bounded by the end of the data; [`encode`] clamps them to the maximum length.
+pub struct MatchFinder<'a> {
+    data: &'a [u8],
+    window: usize,
+    max_len: usize,
+    mode: CompressionMode,
+    head: Vec<u32>,
+    prev: Vec<u32>,
+    /// Positions below this value have been inserted into the hash chains.
+    inserted: usize,
+}
+
+#[inline]
+fn hash(data: &[u8]) -> usize {
+    let v = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (v.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+}
+
+impl<'a> MatchFinder<'a> {
+    pub fn new(data: &'a [u8], window: usize, max_len: usize, mode: CompressionMode) -> Self {
+        Self {
+            data,
+            window,
+            max_len,
+            mode,
+            head: vec![NO_POS; HASH_SIZE],
+            prev: vec![NO_POS; data.len()],
+            inserted: 0,
+        }
+    }
+
+    fn insert_until(&mut self, pos: usize) {
+        let end = pos.min(self.data.len().saturating_sub(MIN_MATCH - 1));
+        while self.inserted < end {
+            let h = hash(&self.data[self.inserted..]);
+            self.prev[self.inserted] = self.head[h];
+            self.head[h] = self.inserted as u32;
+            self.inserted += 1;
+        }
+        self.inserted = self.inserted.max(pos);
+    }
+
+    /// Finds the longest match for the data at `pos`. Matches shorter than [`MIN_MATCH`]
+    /// are reported with a length of 1.
+    ///
+    /// In [`CompressionMode::Fast`], match lengths are capped to the maximum length.
+    /// Otherwise, they may exceed it.
+    pub fn find(&mut self, pos: usize) -> Match {
+        let mut result = Match { len: 1, pos: 0 };
+        let remaining = self.data.len().saturating_sub(pos);
+        let max_len = match self.mode {
+            CompressionMode::Matching => remaining,
+            CompressionMode::Fast => self.max_len.min(remaining),
+        };
+        if max_len < MIN_MATCH {
+            return result;
+        }
+        self.insert_until(pos);
+        let start = pos.saturating_sub(self.window);
+        let target = &self.data[pos..pos + max_len];
+        let mut candidate = self.head[hash(target)];
+        let mut visited = 0;
+        while candidate != NO_POS && candidate as usize >= start {
+            let i = candidate as usize;
+            let len = self.data[i..].iter().zip(target).take_while(|(a, b)| a == b).count();
+            // Walking from the newest candidate to the oldest, so `>=` prefers the earliest
+            if len >= MIN_MATCH && len >= result.len {
+                result = Match { len, pos: i };
+                if self.mode == CompressionMode::Fast && len == max_len {
+                    break;
+                }
+            }
+            visited += 1;
+            if self.mode == CompressionMode::Fast && visited >= FAST_MAX_CHAIN {
+                break;
+            }
+            candidate = self.prev[i];
+        }
+        if result.len < MIN_MATCH {
+            result.len = 1;
+        }
+        result
+    }
+}
+
+/// Drives a [`MatchFinder`] over the input, calling `emit` for each literal (length 1)
+/// or back-reference.
+///
+/// In [`CompressionMode::Matching`], a match is replaced by a literal when the match at the
+/// next position is at least two bytes longer, as Nintendo's encoder does. Lengths are
+/// compared before clamping to `max_len`, which only happens when emitting.
+pub fn encode<F>(data: &[u8], window: usize, max_len: usize, mode: CompressionMode, mut emit: F)
+where F: FnMut(usize, Match) {
+    let mut finder = MatchFinder::new(data, window, max_len, mode);
+    let mut pos = 0;
+    let mut pending: Option<Match> = None;
+    while pos < data.len() {
+        let m = if let Some(m) = pending.take() {
+            m
+        } else {
+            let m = finder.find(pos);
+            if mode == CompressionMode::Matching && m.len >= MIN_MATCH {
+                let next = finder.find(pos + 1);
+                if next.len >= m.len + 2 {
+                    pending = Some(next);
+                    Match { len: 1, pos: 0 }
+                } else {
+                    m
+                }
+            } else {
+                m
+            }
+        };
+        let m = Match { len: m.len.min(max_len), ..m };
+        emit(pos, m);
+        pos += m.len;
+    }
+}
@@ -6,13 +6,17 @@ pub mod bin2c;
 pub mod comment;
 pub mod config;
 pub mod dep;
+pub mod diff;
+pub mod disc;
 pub mod dol;
 pub mod dwarf;
 pub mod elf;
 pub mod file;
 pub mod lcf;
+pub mod lz;
 pub mod map;
 pub mod nested;
+pub mod nlzss;
 pub mod rarc;
 pub mod reader;
 pub mod rel;
@@ -20,6 +24,7 @@ pub mod rso;
 pub mod signatures;
 pub mod split;
 pub mod take_seek;
+pub mod u8_arc;
 pub mod yaz0;
 
 #[inline]
@@ -0,0 +1,35 @@
+use anyhow::{ensure, Result};
+
+use crate::util::{lz, lz::CompressionMode};
+
+/// LZ10 compression type, as used by `nintendo_lz` for decompression.
+const LZ10_TYPE: u8 = 0x10;
+
+/// Compresses the data into a new allocated [`Vec`] using the LZ10 format, including the header.
+/// Output is padded to a multiple of 4 bytes, matching Nintendo's tools.
+pub fn compress(input: &[u8], mode: CompressionMode) -> Result<Vec<u8>> {
+    ensure!(input.len() <= 0xFFFFFF, "Input too large for NLZSS: {:#X} bytes", input.len());
+    let mut output = Vec::with_capacity(4 + input.len() + input.len() / 8 + 4);
+    output.extend_from_slice(&((input.len() as u32) << 8 | LZ10_TYPE as u32).to_le_bytes());
+
+    let mut flag_pos = output.len();
+    let mut flag_bits = 0;
+    lz::encode(input, 0x1000, 0x12, mode, |pos, m| {
+        if flag_bits == 0 {
+            flag_pos = output.len();
+            output.push(0);
+            flag_bits = 8;
+        }
+        flag_bits -= 1;
+        if m.len < lz::MIN_MATCH {
+            output.push(input[pos]);
+        } else {
+            output[flag_pos] |= 1 << flag_bits;
+            let disp = pos - m.pos - 1;
+            output.push((((m.len - 3) << 4) | (disp >> 8)) as u8);
+            output.push(disp as u8);
+        }
+    });
+    output.resize((output.len() + 3) & !3, 0);
+    Ok(output)
+}
@@ -1,20 +1,22 @@
 // Source: https://github.com/Julgodis/picori/blob/650da9f4fe6050b39b80d5360416591c748058d5/src/rarc.rs
 // License: MIT
 // Modified to use `std::io::Cursor<&[u8]>` and project's FromReader trait
+// Writer added for `rarc create`.
 use std::{
     collections::HashMap,
     fmt::Display,
     hash::{Hash, Hasher},
     io,
-    io::{Read, Seek, SeekFrom},
+    io::{Read, Seek, SeekFrom, Write},
     path::{Component, Path, PathBuf},
 };
 
 use anyhow::{anyhow, bail, ensure, Result};
 
 use crate::util::{
-    file::read_c_string,
-    reader::{struct_size, Endian, FromReader},
+    align_up,
+    file::{read_c_string, ArchiveEntry},
+    reader::{struct_size, Endian, FromReader, ToWriter},
 };
 
 #[derive(Debug, Clone)]
@@ -48,6 +50,18 @@ impl PartialEq for NamedHash {
 
 impl Eq for NamedHash {}
 
+impl NamedHash {
+    pub fn new(name: String) -> Self {
+        let hash = Self::hash_name(&name);
+        Self { name, hash }
+    }
+
+    /// Computes the name hash used by `JKRArchive`.
+    pub fn hash_name(name: &str) -> u16 {
+        name.bytes().fold(0u16, |hash, c| hash.wrapping_mul(3).wrapping_add(c as u16))
+    }
+}
+
 #[derive(Debug, Clone)]
 enum RarcDirectory {
     File {
@@ -168,6 +182,32 @@ impl FromReader for RarcHeader {
     }
 }
 
+impl ToWriter for RarcHeader {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        self.magic.to_writer(writer, e)?;
+        self._file_length.to_writer(writer, e)?;
+        self.header_length.to_writer(writer, e)?;
+        self.file_offset.to_writer(writer, e)?;
+        self._file_length_2.to_writer(writer, e)?;
+        self._unk0.to_writer(writer, e)?;
+        self._unk1.to_writer(writer, e)?;
+        self._unk2.to_writer(writer, e)?;
+        self.node_count.to_writer(writer, e)?;
+        self.node_offset.to_writer(writer, e)?;
+        self.directory_count.to_writer(writer, e)?;
+        self.directory_offset.to_writer(writer, e)?;
+        self.string_table_length.to_writer(writer, e)?;
+        self.string_table_offset.to_writer(writer, e)?;
+        self._file_count.to_writer(writer, e)?;
+        self._unk3.to_writer(writer, e)?;
+        self._unk4.to_writer(writer, e)?;
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
 struct RarcFileNode {
     index: u16,
     name_hash: u16,
@@ -205,6 +245,22 @@ impl FromReader for RarcFileNode {
     }
 }
 
+impl ToWriter for RarcFileNode {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        self.index.to_writer(writer, e)?;
+        self.name_hash.to_writer(writer, e)?;
+        self._unk0.to_writer(writer, e)?;
+        self.name_offset.to_writer(writer, e)?;
+        self.data_offset.to_writer(writer, e)?;
+        self.data_length.to_writer(writer, e)?;
+        self._unk1.to_writer(writer, e)?;
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
 struct RarcDirectoryNode {
     _identifier: u32,
     name_offset: u32,
@@ -236,6 +292,20 @@ impl FromReader for RarcDirectoryNode {
     }
 }
 
+impl ToWriter for RarcDirectoryNode {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        self._identifier.to_writer(writer, e)?;
+        self.name_offset.to_writer(writer, e)?;
+        self.name_hash.to_writer(writer, e)?;
+        self.count.to_writer(writer, e)?;
+        self.index.to_writer(writer, e)?;
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
 impl RarcReader {
     /// Creates a new RARC reader.
     pub fn new<R>(reader: &mut R) -> Result<Self>
@@ -427,3 +497,209 @@ impl<'parent> Iterator for Nodes<'parent> {
         }
     }
 }
+
+const RARC_ALIGN: u32 = 0x20;
+const FLAG_FILE: u16 = 0x0100;
+const FLAG_DIRECTORY: u16 = 0x0200;
+const FLAG_LOAD_TO_MRAM: u16 = 0x1000;
+
+struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn add(&mut self, name: &str) -> u32 {
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.offsets.insert(name.to_string(), offset);
+        offset
+    }
+}
+
+/// Directory node identifier: the first four characters of the name in uppercase,
+/// padded with spaces. The root node is always `ROOT`.
+fn node_identifier(name: &str, is_root: bool) -> u32 {
+    if is_root {
+        return u32::from_be_bytes(*b"ROOT");
+    }
+    let mut id = [b' '; 4];
+    for (out, c) in id.iter_mut().zip(name.bytes()) {
+        *out = c.to_ascii_uppercase();
+    }
+    u32::from_be_bytes(id)
+}
+
+/// Writes an RARC archive containing `entries` under a root directory named `root_name`.
+///
+/// Each directory lists its files followed by its subdirectories, then `.` and `..`.
+/// Directory nodes are numbered depth-first. File IDs match entry indices, and file data
+/// is stored in entry order, each file aligned to 32 bytes.
+pub fn write_rarc<W>(w: &mut W, root_name: &str, entries: &[ArchiveEntry]) -> Result<()>
+where W: Write + ?Sized {
+    // Number directory nodes depth-first, root first
+    struct NodeInfo<'a> {
+        name: &'a str,
+        parent: Option<u32>,
+        entries: Vec<&'a ArchiveEntry>,
+    }
+    fn collect_nodes<'a>(
+        nodes: &mut Vec<NodeInfo<'a>>,
+        name: &'a str,
+        parent: Option<u32>,
+        entries: &'a [ArchiveEntry],
+    ) {
+        let mut sorted = entries.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|e| (matches!(e, ArchiveEntry::Directory { .. }), e.name()));
+        let index = nodes.len() as u32;
+        nodes.push(NodeInfo { name, parent, entries: sorted.clone() });
+        for entry in sorted {
+            if let ArchiveEntry::Directory { name, entries } = entry {
+                collect_nodes(nodes, name, Some(index), entries);
+            }
+        }
+    }
+    let mut nodes = Vec::new();
+    collect_nodes(&mut nodes, root_name, None, entries);
+
+    // Node indices of each subdirectory, in node order
+    let mut child_nodes = vec![vec![]; nodes.len()];
+    for (index, node) in nodes.iter().enumerate().skip(1) {
+        child_nodes[node.parent.unwrap() as usize].push(index as u32);
+    }
+
+    let mut strings = StringTable { data: vec![], offsets: HashMap::new() };
+    strings.add(".");
+    strings.add("..");
+
+    let mut dir_nodes = Vec::with_capacity(nodes.len());
+    let mut file_nodes = Vec::new();
+    let mut file_data = Vec::<&[u8]>::new();
+    let mut data_offset = 0u32;
+    for (index, node) in nodes.iter().enumerate() {
+        let name_offset = strings.add(node.name);
+        let count = node.entries.len() + 2;
+        ensure!(count <= u16::MAX as usize, "Too many entries in directory '{}'", node.name);
+        dir_nodes.push(RarcDirectoryNode {
+            _identifier: node_identifier(node.name, index == 0),
+            name_offset,
+            name_hash: NamedHash::hash_name(node.name),
+            count: count as u16,
+            index: file_nodes.len() as u32,
+        });
+        let mut children = child_nodes[index].iter();
+        for entry in &node.entries {
+            let name_offset = strings.add(entry.name());
+            ensure!(name_offset <= u16::MAX as u32, "String table too large");
+            match entry {
+                ArchiveEntry::File { name, data } => {
+                    ensure!(file_nodes.len() < 0xFFFF, "Too many files in archive");
+                    file_nodes.push(RarcFileNode {
+                        index: file_nodes.len() as u16,
+                        name_hash: NamedHash::hash_name(name),
+                        _unk0: FLAG_LOAD_TO_MRAM | FLAG_FILE,
+                        name_offset: name_offset as u16,
+                        data_offset,
+                        data_length: data.len() as u32,
+                        _unk1: 0,
+                    });
+                    file_data.push(data);
+                    data_offset = align_up(data_offset + data.len() as u32, RARC_ALIGN);
+                }
+                ArchiveEntry::Directory { name, .. } => {
+                    file_nodes.push(RarcFileNode {
+                        index: 0xFFFF,
+                        name_hash: NamedHash::hash_name(name),
+                        _unk0: FLAG_DIRECTORY,
+                        name_offset: name_offset as u16,
+                        data_offset: *children.next().unwrap(),
+                        data_length: 0x10,
+                        _unk1: 0,
+                    });
+                }
+            }
+        }
+        for (name, target) in [(".", Some(index as u32)), ("..", node.parent)] {
+            file_nodes.push(RarcFileNode {
+                index: 0xFFFF,
+                name_hash: NamedHash::hash_name(name),
+                _unk0: FLAG_DIRECTORY,
+                name_offset: strings.add(name) as u16,
+                data_offset: target.unwrap_or(u32::MAX),
+                data_length: 0x10,
+                _unk1: 0,
+            });
+        }
+    }
+    ensure!(file_nodes.len() < 0x10000, "Too many entries in archive");
+
+    // Offsets are relative to the end of the 0x20-byte file header
+    let node_offset = (RarcHeader::STATIC_SIZE as u32) - RARC_ALIGN;
+    let directory_offset = align_up(
+        node_offset + (dir_nodes.len() * RarcDirectoryNode::STATIC_SIZE) as u32,
+        RARC_ALIGN,
+    );
+    let string_table_offset = align_up(
+        directory_offset + (file_nodes.len() * RarcFileNode::STATIC_SIZE) as u32,
+        RARC_ALIGN,
+    );
+    let string_table_length = align_up(strings.data.len() as u32, RARC_ALIGN);
+    let file_offset = string_table_offset + string_table_length;
+    let header = RarcHeader {
+        magic: RARC_MAGIC,
+        _file_length: RARC_ALIGN + file_offset + data_offset,
+        header_length: RARC_ALIGN,
+        file_offset,
+        _file_length_2: data_offset,
+        _unk0: data_offset, // MRAM size
+        _unk1: 0,           // ARAM size
+        _unk2: 0,           // DVD size
+        node_count: dir_nodes.len() as u32,
+        node_offset,
+        directory_count: file_nodes.len() as u32,
+        directory_offset,
+        string_table_length,
+        string_table_offset,
+        _file_count: file_nodes.len() as u16, // next free file ID
+        _unk3: 0x0100,                        // file IDs synced with entry indices
+        _unk4: 0,
+    };
+
+    fn pad_to<W>(w: &mut W, position: &mut u64, offset: u32) -> Result<()>
+    where W: Write + ?Sized {
+        let target = (RARC_ALIGN + offset) as u64;
+        ensure!(*position <= target, "RARC layout overflow");
+        w.write_all(&vec![0u8; (target - *position) as usize])?;
+        *position = target;
+        Ok(())
+    }
+    let mut position = RARC_ALIGN as u64;
+    header.to_writer(w, Endian::Big)?;
+    position += (RarcHeader::STATIC_SIZE as u32 - RARC_ALIGN) as u64;
+    for node in &dir_nodes {
+        node.to_writer(w, Endian::Big)?;
+        position += RarcDirectoryNode::STATIC_SIZE as u64;
+    }
+    pad_to(w, &mut position, directory_offset)?;
+    for node in &file_nodes {
+        node.to_writer(w, Endian::Big)?;
+        position += RarcFileNode::STATIC_SIZE as u64;
+    }
+    pad_to(w, &mut position, string_table_offset)?;
+    w.write_all(&strings.data)?;
+    position += strings.data.len() as u64;
+    pad_to(w, &mut position, file_offset)?;
+    let mut offset = file_offset;
+    for data in file_data {
+        pad_to(w, &mut position, offset)?;
+        w.write_all(data)?;
+        position += data.len() as u64;
+        offset = align_up(offset + data.len() as u32, RARC_ALIGN);
+    }
+    pad_to(w, &mut position, file_offset + data_offset)?;
+    Ok(())
+}
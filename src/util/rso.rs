@@ -3,17 +3,27 @@ use std::{
     io::{Read, Seek, SeekFrom, Write},
 };
 
-use anyhow::{anyhow, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use cwdemangle::{demangle, DemangleOptions};
+use itertools::Itertools;
+use object::{elf, Object, ObjectSection, ObjectSymbol};
+use rustc_hash::FxHashSet;
+use tracing::warn;
 
 use crate::{
+    array_ref_mut,
     obj::{
-        ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
-        ObjSymbolFlags, ObjSymbolKind,
+        best_match_for_reloc, ObjArchitecture, ObjInfo, ObjKind, ObjReloc, ObjRelocKind,
+        ObjSection, ObjSectionKind, ObjSections, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
+        ObjSymbolKind,
     },
     util::{
+        align_up,
+        elf::to_obj_reloc_kind as elf_to_obj_reloc_kind,
         file::{read_c_string, read_string},
         reader::{struct_size, Endian, FromReader, ToWriter, DYNAMIC_SIZE},
+        rel::{is_permitted_section, should_write_section},
+        split::default_section_align,
     },
 };
 
@@ -205,6 +215,40 @@ impl FromReader for RsoHeader {
     }
 }
 
+impl ToWriter for RsoHeader {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        0u32.to_writer(writer, e)?; // next
+        0u32.to_writer(writer, e)?; // prev
+        self.num_sections.to_writer(writer, e)?;
+        self.section_info_offset.to_writer(writer, e)?;
+        self.name_offset.to_writer(writer, e)?;
+        self.name_size.to_writer(writer, e)?;
+        self.version.to_writer(writer, e)?;
+        self.bss_size.to_writer(writer, e)?;
+        self.prolog_section.to_writer(writer, e)?;
+        self.epilog_section.to_writer(writer, e)?;
+        self.unresolved_section.to_writer(writer, e)?;
+        0u8.to_writer(writer, e)?; // bss_section
+        self.prolog_offset.to_writer(writer, e)?;
+        self.epilog_offset.to_writer(writer, e)?;
+        self.unresolved_offset.to_writer(writer, e)?;
+        self.internal_rel_offset.to_writer(writer, e)?;
+        self.internal_rel_size.to_writer(writer, e)?;
+        self.external_rel_offset.to_writer(writer, e)?;
+        self.external_rel_size.to_writer(writer, e)?;
+        self.export_table_offset.to_writer(writer, e)?;
+        self.export_table_size.to_writer(writer, e)?;
+        self.export_table_name_offset.to_writer(writer, e)?;
+        self.import_table_offset.to_writer(writer, e)?;
+        self.import_table_size.to_writer(writer, e)?;
+        self.import_table_name_offset.to_writer(writer, e)?;
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct RsoSectionHeader {
     /// Absolute offset of the section.
@@ -243,7 +287,6 @@ impl ToWriter for RsoSectionHeader {
 }
 
 impl RsoSectionHeader {
-    #[allow(dead_code)]
     fn new(offset: u32, size: u32, exec: bool) -> Self {
         Self { offset_and_flags: offset | (exec as u32), size }
     }
@@ -300,7 +343,6 @@ impl ToWriter for RsoRelocation {
 }
 
 impl RsoRelocation {
-    #[allow(dead_code)]
     pub fn new(offset: u32, id: u32, rel_type: u8, sym_offset: u32) -> Self {
         Self { offset, id_and_type: (id << 8) | rel_type as u32, target_offset: sym_offset }
     }
@@ -365,12 +407,156 @@ impl ToWriter for RsoSymbol {
         Ok(())
     }
 
-    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+    fn write_size(&self) -> usize {
+        if self.hash.is_some() {
+            u32::STATIC_SIZE * 4
+        } else {
+            u32::STATIC_SIZE * 3
+        }
+    }
+}
+
+pub fn process_rso_header<R>(reader: &mut R) -> Result<RsoHeader>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(0))?;
+    RsoHeader::from_reader(reader, Endian::Big).context("Failed to read RSO header")
+}
+
+fn read_relocations<R>(reader: &mut R, offset: u32, size: u32) -> Result<Vec<RsoRelocation>>
+where R: Read + Seek + ?Sized {
+    let mut relocations = Vec::with_capacity(size as usize / RsoRelocation::STATIC_SIZE);
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    while reader.stream_position()? < (offset + size) as u64 {
+        relocations.push(RsoRelocation::from_reader(reader, Endian::Big)?);
+    }
+    Ok(relocations)
+}
+
+fn to_obj_reloc_kind(rel_type: u8) -> Result<Option<ObjRelocKind>> {
+    Ok(Some(match rel_type as u32 {
+        elf::R_PPC_NONE => return Ok(None),
+        elf::R_PPC_ADDR32 | elf::R_PPC_UADDR32 => ObjRelocKind::Absolute,
+        elf::R_PPC_ADDR16_LO => ObjRelocKind::PpcAddr16Lo,
+        elf::R_PPC_ADDR16_HI => ObjRelocKind::PpcAddr16Hi,
+        elf::R_PPC_ADDR16_HA => ObjRelocKind::PpcAddr16Ha,
+        elf::R_PPC_REL24 => ObjRelocKind::PpcRel24,
+        elf::R_PPC_REL14 => ObjRelocKind::PpcRel14,
+        elf::R_PPC_EMB_SDA21 => ObjRelocKind::PpcEmbSda21,
+        reloc_type => bail!("Unhandled RSO relocation type {reloc_type}"),
+    }))
+}
+
+/// Locates the section containing the given file offset.
+/// Returns the section index and the section-relative address.
+fn section_for_offset(sections: &ObjSections, offset: u32) -> Result<(usize, u32)> {
+    sections
+        .iter()
+        .find(|(_, s)| {
+            s.kind != ObjSectionKind::Bss
+                && (s.file_offset..s.file_offset + s.size).contains(&(offset as u64))
+        })
+        .map(|(idx, s)| (idx, offset - s.file_offset as u32))
+        .ok_or_else(|| anyhow!("Failed to locate section for file offset {:#X}", offset))
+}
+
+/// RSO files don't contain section names. Determine them from the section kinds and internal
+/// relocations, assuming the standard CodeWarrior section order:
+/// `.init`, `.text`, `.ctors`, `.dtors`, `.rodata`, `.data`, `.bss`.
+fn resolve_section_names(
+    sections: &mut [ObjSection],
+    internal_relocations: &[RsoRelocation],
+) -> Result<()> {
+    let code_sections = sections
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.kind == ObjSectionKind::Code)
+        .map(|(idx, _)| idx)
+        .collect_vec();
+    match *code_sections.as_slice() {
+        [text] => sections[text].rename(".text".to_string())?,
+        [init, text] => {
+            sections[init].rename(".init".to_string())?;
+            sections[text].rename(".text".to_string())?;
+        }
+        _ => {}
+    }
+
+    let bss_sections = sections
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.kind == ObjSectionKind::Bss)
+        .map(|(idx, _)| idx)
+        .collect_vec();
+    if let &[bss] = bss_sections.as_slice() {
+        sections[bss].rename(".bss".to_string())?;
+    }
+
+    // .ctors and .dtors consist of absolute relocations to code, followed by a null pointer.
+    let is_ctors_dtors = |section: &ObjSection| -> bool {
+        if section.size < 4
+            || section.size % 4 != 0
+            || section.data[section.data.len() - 4..] != [0; 4]
+        {
+            return false;
+        }
+        (0..section.size as u32 - 4).step_by(4).all(|offset| {
+            internal_relocations.iter().any(|reloc| {
+                reloc.offset() == section.file_offset as u32 + offset
+                    && reloc.rel_type() as u32 == elf::R_PPC_ADDR32
+                    && sections.iter().any(|s| {
+                        s.elf_index == reloc.id() as usize && s.kind == ObjSectionKind::Code
+                    })
+            })
+        })
+    };
+    let data_sections = sections
+        .iter()
+        .enumerate()
+        .skip_while(|(_, s)| s.kind == ObjSectionKind::Code)
+        .filter(|(_, s)| s.kind == ObjSectionKind::Data)
+        .map(|(idx, _)| idx)
+        .collect_vec();
+    let mut remaining = data_sections.as_slice();
+    if let &[ctors, dtors, ref rest @ ..] = remaining {
+        if is_ctors_dtors(&sections[ctors]) && is_ctors_dtors(&sections[dtors]) {
+            sections[ctors].rename(".ctors".to_string())?;
+            sections[dtors].rename(".dtors".to_string())?;
+            remaining = rest;
+        }
+    }
+    if let &[rodata, data] = remaining {
+        sections[rodata].rename(".rodata".to_string())?;
+        sections[data].rename(".data".to_string())?;
+    }
+    Ok(())
+}
+
+/// Patches a branch instruction with the relative offset to the target.
+fn apply_branch(data: &mut [u8], address: u32, target: u32, kind: ObjRelocKind) -> Result<()> {
+    let diff = target as i32 - address as i32;
+    let ins_ref = array_ref_mut!(data, address as usize, 4);
+    let mut ins = u32::from_be_bytes(*ins_ref);
+    match kind {
+        ObjRelocKind::PpcRel24 => {
+            ensure!((-0x2000000..0x2000000).contains(&diff), "R_PPC_REL24 relocation out of range");
+            ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc);
+        }
+        ObjRelocKind::PpcRel14 => {
+            ensure!((-0x2000..0x2000).contains(&diff), "R_PPC_REL14 relocation out of range");
+            ins = (ins & !0xfffc) | (diff as u32 & 0xfffc);
+        }
+        kind => bail!("Unsupported branch relocation kind {:?}", kind),
+    }
+    *ins_ref = ins.to_be_bytes();
+    Ok(())
 }
 
-pub fn process_rso<R>(reader: &mut R) -> Result<ObjInfo>
+/// Processes an RSO file into an [`ObjInfo`].
+/// RSO files don't have module IDs, so one must be provided to keep module labels unique.
+/// Use 0 when processing a module on its own.
+pub fn process_rso<R>(reader: &mut R, module_id: u32) -> Result<(RsoHeader, ObjInfo)>
 where R: Read + Seek + ?Sized {
-    let header = RsoHeader::from_reader(reader, Endian::Big)?;
+    let header = process_rso_header(reader)?;
     let mut sections = Vec::with_capacity(header.num_sections as usize);
     reader.seek(SeekFrom::Start(header.section_info_offset as u64))?;
     let mut total_bss_size = 0;
@@ -388,13 +574,13 @@ where R: Read + Seek + ?Sized {
             let position = reader.stream_position()?;
             reader.seek(SeekFrom::Start(offset as u64))?;
             let mut data = vec![0u8; size as usize];
-            reader.read_exact(&mut data)?;
+            reader.read_exact(&mut data).with_context(|| {
+                format!("Failed to read RSO section {} data with size {:#X}", idx, size)
+            })?;
             reader.seek(SeekFrom::Start(position))?;
             data
         };
 
-        // println!("Section {} offset {:#X} size {:#X}", idx, offset, size);
-
         sections.push(ObjSection {
             name: format!(".section{}", idx),
             kind: if offset == 0 {
@@ -426,6 +612,14 @@ where R: Read + Seek + ?Sized {
         header.bss_size
     );
 
+    let internal_relocations =
+        read_relocations(reader, header.internal_rel_offset, header.internal_rel_size)
+            .context("Failed to read RSO internal relocations")?;
+    let external_relocations =
+        read_relocations(reader, header.external_rel_offset, header.external_rel_size)
+            .context("Failed to read RSO external relocations")?;
+    resolve_section_names(&mut sections, &internal_relocations)?;
+
     let mut symbols = Vec::new();
     let mut add_symbol = |rel_section_idx: u8, offset: u32, name: &str| -> Result<()> {
         if rel_section_idx > 0 {
@@ -439,7 +633,7 @@ where R: Read + Seek + ?Sized {
                 name: name.to_string(),
                 address: offset as u64,
                 section: Some(section_index),
-                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
                 kind: ObjSymbolKind::Function,
                 ..Default::default()
             });
@@ -450,19 +644,6 @@ where R: Read + Seek + ?Sized {
     add_symbol(header.epilog_section, header.epilog_offset, "_epilog")?;
     add_symbol(header.unresolved_section, header.unresolved_offset, "_unresolved")?;
 
-    reader.seek(SeekFrom::Start(header.external_rel_offset as u64))?;
-    while reader.stream_position()? < (header.external_rel_offset + header.external_rel_size) as u64
-    {
-        let reloc = RsoRelocation::from_reader(reader, Endian::Big)?;
-        log::debug!(
-            "Reloc offset: {:#X}, id: {}, type: {}, sym offset: {:#X}",
-            reloc.offset(),
-            reloc.id(),
-            reloc.rel_type(),
-            reloc.sym_offset()
-        );
-    }
-
     reader.seek(SeekFrom::Start(header.export_table_offset as u64))?;
     while reader.stream_position()? < (header.export_table_offset + header.export_table_size) as u64
     {
@@ -479,13 +660,18 @@ where R: Read + Seek + ?Sized {
             calc
         );
         let demangled_name = demangle(&name, &DemangleOptions::default());
-        let section = sections
+        let found_section = sections
             .iter()
             .enumerate()
-            .find(|&(_, section)| section.elf_index == symbol.section_index as usize)
-            .map(|(idx, _)| idx)
+            .find(|&(_, section)| section.elf_index == symbol.section_index as usize);
+        let (section, kind) = match found_section {
+            Some((idx, section)) => (idx, match section.kind {
+                ObjSectionKind::Code => ObjSymbolKind::Function,
+                _ => ObjSymbolKind::Object,
+            }),
             // HACK: selfiles won't have any sections
-            .unwrap_or(symbol.section_index as usize);
+            None => (symbol.section_index as usize, ObjSymbolKind::Unknown),
+        };
         log::debug!(
             "Export: {}, sym off: {:#X}, section: {}, ELF hash: {:#X}",
             demangled_name.as_deref().unwrap_or(&name),
@@ -493,14 +679,26 @@ where R: Read + Seek + ?Sized {
             symbol.section_index,
             hash_n
         );
+        // _prolog, _epilog and _unresolved are usually exported as well
+        if symbols.iter().any(|s: &ObjSymbol| {
+            s.name == name && s.section == Some(section) && s.address == symbol.offset as u64
+        }) {
+            continue;
+        }
         symbols.push(ObjSymbol {
             name,
             demangled_name,
             address: symbol.offset as u64,
             section: Some(section),
+            // Exported symbols must not be stripped by the linker
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
+            kind,
             ..Default::default()
         });
     }
+
+    // Imports are added as undefined symbols, referenced by external relocations by index.
+    let mut import_symbols = Vec::new();
     reader.seek(SeekFrom::Start(header.import_table_offset as u64))?;
     while reader.stream_position()? < (header.import_table_offset + header.import_table_size) as u64
     {
@@ -513,6 +711,14 @@ where R: Read + Seek + ?Sized {
             symbol.offset,
             symbol.section_index
         );
+        let demangled_name = demangle(&name, &DemangleOptions::default());
+        import_symbols.push(symbols.len());
+        symbols.push(ObjSymbol {
+            name,
+            demangled_name,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..Default::default()
+        });
     }
 
     let name = match header.name_offset {
@@ -520,8 +726,74 @@ where R: Read + Seek + ?Sized {
         _ => read_string(reader, header.name_offset as u64, header.name_size as usize)?,
     };
 
-    let obj = ObjInfo::new(ObjKind::Relocatable, ObjArchitecture::PowerPc, name, symbols, sections);
-    Ok(obj)
+    let mut obj =
+        ObjInfo::new(ObjKind::Relocatable, ObjArchitecture::PowerPc, name, symbols, sections);
+    obj.module_id = module_id;
+
+    for reloc in &internal_relocations {
+        let Some(kind) = to_obj_reloc_kind(reloc.rel_type())? else {
+            continue;
+        };
+        let (section_index, address) = section_for_offset(&obj.sections, reloc.offset())?;
+        let (target_section_index, target_section) =
+            obj.sections.get_elf_index(reloc.id() as usize).ok_or_else(|| {
+                anyhow!("Failed to locate RSO section {} for internal relocation", reloc.id())
+            })?;
+        let target_address = reloc.sym_offset();
+        let existing = obj
+            .symbols
+            .at_section_address(target_section_index, target_address)
+            .filter(|(_, s)| s.referenced_by(kind))
+            .collect_vec();
+        let existing = best_match_for_reloc(existing, kind).map(|(symbol_index, _)| symbol_index);
+        let target_symbol = if let Some(symbol_index) = existing {
+            symbol_index
+        } else {
+            let section_name = target_section.name.trim_start_matches('.');
+            let name = if module_id == 0 {
+                format!("lbl_{}_{:X}", section_name, target_address)
+            } else {
+                format!("lbl_{}_{}_{:X}", module_id, section_name, target_address)
+            };
+            obj.symbols.add_direct(ObjSymbol {
+                name,
+                address: target_address as u64,
+                section: Some(target_section_index),
+                ..Default::default()
+            })?
+        };
+        let section = &mut obj.sections[section_index];
+        if matches!(kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14)
+            && target_section_index == section_index
+        {
+            // Resolve local branches, as with RELs, so that analysis can follow them
+            apply_branch(&mut section.data, address, target_address, kind)?;
+        }
+        section.relocations.insert(address, ObjReloc {
+            kind,
+            target_symbol,
+            addend: 0,
+            module: None,
+        })?;
+    }
+
+    for reloc in &external_relocations {
+        let Some(kind) = to_obj_reloc_kind(reloc.rel_type())? else {
+            continue;
+        };
+        let (section_index, address) = section_for_offset(&obj.sections, reloc.offset())?;
+        let target_symbol = *import_symbols.get(reloc.id() as usize).ok_or_else(|| {
+            anyhow!("Invalid import index {} for external relocation", reloc.id())
+        })?;
+        obj.sections[section_index].relocations.insert(address, ObjReloc {
+            kind,
+            target_symbol,
+            addend: reloc.sym_offset() as i64,
+            module: None,
+        })?;
+    }
+
+    Ok((header, obj))
 }
 
 fn symbol_hash(s: &str) -> u32 {
@@ -534,3 +806,491 @@ fn symbol_hash(s: &str) -> u32 {
         m & !n
     })
 }
+
+/// Determines the alignment of each RSO section from its file offset.
+/// RSO files don't record section alignment, so this works the same way as for RELs.
+pub fn update_rso_section_alignment(obj: &mut ObjInfo, header: &RsoHeader) -> Result<()> {
+    let mut last_offset = header.section_info_offset + header.num_sections * 8;
+    for (_, section) in obj.sections.iter_mut() {
+        if section.align > 0 {
+            // Already set
+            continue;
+        }
+
+        if section.kind == ObjSectionKind::Bss {
+            // Not present in the file
+            section.align = default_section_align(section);
+            continue;
+        }
+
+        let prev_offset = last_offset;
+        last_offset = (section.file_offset + section.size) as u32;
+
+        if section.section_known {
+            // Try the default section alignment for known sections
+            let default_align = default_section_align(section);
+            if align_up(prev_offset, default_align as u32) == section.file_offset as u32 {
+                section.align = default_align;
+                continue;
+            }
+        }
+
+        let mut align = 32;
+        while align >= 4 {
+            if align_up(prev_offset, align) == section.file_offset as u32 {
+                section.align = align as u64;
+                break;
+            }
+            align /= 2;
+        }
+
+        if section.align == 0 {
+            log::warn!(
+                "Failed to determine alignment for RSO section {}: {:#X} -> {:#X}",
+                section.name,
+                prev_offset,
+                section.file_offset
+            );
+            section.align = default_section_align(section);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct RsoWriteInfo {
+    /// Module name, stored in the RSO name table.
+    /// If empty, no name is written.
+    pub name: String,
+    /// Override the number of sections in the file.
+    /// Useful for matching RSOs that included debug sections.
+    pub section_count: Option<usize>,
+    /// If true, don't print warnings about overriding values.
+    pub quiet: bool,
+    /// Override individual section alignment in the file.
+    pub section_align: Option<Vec<u32>>,
+}
+
+fn to_rso_reloc_type(kind: ObjRelocKind) -> Result<u8> {
+    Ok(match kind {
+        ObjRelocKind::Absolute => elf::R_PPC_ADDR32,
+        ObjRelocKind::PpcAddr16Lo => elf::R_PPC_ADDR16_LO,
+        ObjRelocKind::PpcAddr16Hi => elf::R_PPC_ADDR16_HI,
+        ObjRelocKind::PpcAddr16Ha => elf::R_PPC_ADDR16_HA,
+        ObjRelocKind::PpcRel24 => elf::R_PPC_REL24,
+        ObjRelocKind::PpcRel14 => elf::R_PPC_REL14,
+        _ => bail!("Unsupported relocation kind {:?}", kind),
+    } as u8)
+}
+
+/// Appends a null-terminated string to a name table, returning its offset.
+fn add_name(table: &mut Vec<u8>, name: &[u8]) -> u32 {
+    let offset = table.len() as u32;
+    table.extend_from_slice(name);
+    table.push(0);
+    offset
+}
+
+/// Writes an RSO module from a partially linked ELF.
+///
+/// Relocations against symbols defined in the module are written to the internal relocation
+/// table. Relocations against undefined symbols are written to the external relocation table,
+/// and the symbols themselves are added to the import table. Symbols named in `exports`
+/// (along with `_prolog`, `_epilog` and `_unresolved`) are added to the export table.
+pub fn write_rso<W>(
+    w: &mut W,
+    info: &RsoWriteInfo,
+    file: &object::File,
+    exports: &FxHashSet<&[u8]>,
+) -> Result<()>
+where
+    W: Write + Seek + ?Sized,
+{
+    /// Get the alignment of a section, checking for overrides.
+    /// permitted_section_idx increments whenever a permitted section is encountered,
+    /// rather than being the raw ELF section index.
+    fn section_align(
+        permitted_section_idx: usize,
+        section: &object::Section,
+        info: &RsoWriteInfo,
+    ) -> u32 {
+        info.section_align
+            .as_ref()
+            .and_then(|v| v.get(permitted_section_idx))
+            .cloned()
+            .unwrap_or(section.align() as u32)
+    }
+
+    let mut num_sections = file.sections().count() as u32;
+    if let Some(section_count) = info.section_count {
+        if section_count != num_sections as usize && !info.quiet {
+            warn!(from = num_sections, to = section_count, "Overriding section count");
+        }
+        num_sections = section_count as u32;
+    }
+
+    let mut header = RsoHeader {
+        num_sections,
+        section_info_offset: 0, // Calculated below
+        name_offset: 0,
+        name_size: 0,
+        version: 1,
+        bss_size: 0,
+        prolog_section: 0,
+        epilog_section: 0,
+        unresolved_section: 0,
+        prolog_offset: 0,
+        epilog_offset: 0,
+        unresolved_offset: 0,
+        internal_rel_offset: 0,
+        internal_rel_size: 0,
+        external_rel_offset: 0,
+        external_rel_size: 0,
+        export_table_offset: 0,
+        export_table_size: 0,
+        export_table_name_offset: 0,
+        import_table_offset: 0,
+        import_table_size: 0,
+        import_table_name_offset: 0,
+    };
+    let mut offset = header.write_size() as u32;
+    header.section_info_offset = offset;
+    offset += num_sections * RsoSectionHeader::STATIC_SIZE as u32;
+    if !info.name.is_empty() {
+        header.name_offset = offset;
+        header.name_size = info.name.len() as u32;
+        offset += header.name_size;
+    }
+
+    // Lay out section data
+    let mut section_headers = vec![RsoSectionHeader::new(0, 0, false); num_sections as usize];
+    let mut section_offsets = vec![0u32; num_sections as usize];
+    for (idx, section) in file.sections().filter(is_permitted_section).enumerate() {
+        let section_index = section.index().0;
+        ensure!(
+            section_index < num_sections as usize,
+            "Section {} index {} exceeds section count {}",
+            section.name()?,
+            section_index,
+            num_sections
+        );
+        let size = section.size() as u32;
+        if should_write_section(&section) {
+            let align = section_align(idx, &section, info);
+            offset = align_up(offset, align.max(1));
+            section_offsets[section_index] = offset;
+            section_headers[section_index] =
+                RsoSectionHeader::new(offset, size, section.kind() == object::SectionKind::Text);
+            offset += size;
+        } else {
+            header.bss_size += size;
+            section_headers[section_index] = RsoSectionHeader::new(0, size, false);
+        }
+    }
+    let section_data_end = offset;
+    offset = align_up(offset, 4);
+
+    // Collect relocations and imports
+    let mut internal_relocations = Vec::<RsoRelocation>::new();
+    let mut external_relocations = Vec::<(usize, RsoRelocation)>::new();
+    let mut import_symbols = Vec::<object::SymbolIndex>::new();
+    for section in file.sections().filter(is_permitted_section) {
+        let section_offset = section_offsets[section.index().0];
+        for (address, reloc) in section.relocations() {
+            let rel_type = to_rso_reloc_type(elf_to_obj_reloc_kind(reloc.kind())?)?;
+            let symbol_index = match reloc.target() {
+                object::RelocationTarget::Symbol(idx) => idx,
+                reloc_target => bail!("Unsupported relocation target: {reloc_target:?}"),
+            };
+            let symbol = file.symbol_by_index(symbol_index)?;
+            let reloc_offset = section_offset + address as u32;
+            if symbol.is_undefined() {
+                let import_index = match import_symbols.iter().position(|&idx| idx == symbol_index)
+                {
+                    Some(import_index) => import_index,
+                    None => {
+                        import_symbols.push(symbol_index);
+                        import_symbols.len() - 1
+                    }
+                };
+                external_relocations.push((
+                    import_index,
+                    RsoRelocation::new(
+                        reloc_offset,
+                        import_index as u32,
+                        rel_type,
+                        reloc.addend() as u32,
+                    ),
+                ));
+            } else {
+                let Some(target_section) = symbol.section_index() else {
+                    bail!("Relocation against symbol {} without section", symbol.name()?);
+                };
+                internal_relocations.push(RsoRelocation::new(
+                    reloc_offset,
+                    target_section.0 as u32,
+                    rel_type,
+                    (symbol.address() as i64 + reloc.addend()) as u32,
+                ));
+            }
+        }
+    }
+    internal_relocations.sort_by_key(|r| r.offset());
+    // Relocations for each import are grouped together, since
+    // each import table entry points to the first relocation for the symbol.
+    external_relocations.sort_by_key(|(import_index, r)| (*import_index, r.offset()));
+
+    header.internal_rel_offset = offset;
+    header.internal_rel_size = (internal_relocations.len() * RsoRelocation::STATIC_SIZE) as u32;
+    offset += header.internal_rel_size;
+    header.external_rel_offset = offset;
+    header.external_rel_size = (external_relocations.len() * RsoRelocation::STATIC_SIZE) as u32;
+    offset += header.external_rel_size;
+
+    // Build export table
+    let mut export_names = Vec::<u8>::new();
+    let mut export_symbols = Vec::<RsoSymbol>::new();
+    for symbol in file.symbols().filter(|s| s.is_definition()) {
+        let Some(section_index) = symbol.section_index() else {
+            continue;
+        };
+        let name = symbol.name()?;
+        match name {
+            "_prolog" => {
+                header.prolog_section = section_index.0 as u8;
+                header.prolog_offset = symbol.address() as u32;
+            }
+            "_epilog" => {
+                header.epilog_section = section_index.0 as u8;
+                header.epilog_offset = symbol.address() as u32;
+            }
+            "_unresolved" => {
+                header.unresolved_section = section_index.0 as u8;
+                header.unresolved_offset = symbol.address() as u32;
+            }
+            _ => {
+                if !exports.contains(name.as_bytes()) {
+                    continue;
+                }
+            }
+        }
+        export_symbols.push(RsoSymbol {
+            name_offset: add_name(&mut export_names, name.as_bytes()),
+            offset: symbol.address() as u32,
+            section_index: section_index.0 as u32,
+            hash: Some(symbol_hash(name)),
+        });
+    }
+    header.export_table_offset = offset;
+    header.export_table_size = export_symbols.iter().map(|s| s.write_size() as u32).sum();
+    offset += header.export_table_size;
+    header.export_table_name_offset = offset;
+    offset += export_names.len() as u32;
+
+    // Build import table
+    let mut import_names = Vec::<u8>::new();
+    let mut import_table = Vec::<RsoSymbol>::with_capacity(import_symbols.len());
+    for (import_index, &symbol_index) in import_symbols.iter().enumerate() {
+        let symbol = file.symbol_by_index(symbol_index)?;
+        let first_reloc =
+            external_relocations.iter().position(|(idx, _)| *idx == import_index).unwrap();
+        import_table.push(RsoSymbol {
+            name_offset: add_name(&mut import_names, symbol.name_bytes()?),
+            offset: 0,
+            section_index: header.external_rel_offset
+                + (first_reloc * RsoRelocation::STATIC_SIZE) as u32,
+            hash: None,
+        });
+    }
+    header.import_table_offset = offset;
+    header.import_table_size = import_table.iter().map(|s| s.write_size() as u32).sum();
+    offset += header.import_table_size;
+    header.import_table_name_offset = offset;
+    offset += import_names.len() as u32;
+
+    // Write file
+    header.to_writer(w, Endian::Big)?;
+    ensure!(w.stream_position()? as u32 == header.section_info_offset);
+    for section_header in &section_headers {
+        section_header.to_writer(w, Endian::Big)?;
+    }
+    if header.name_size > 0 {
+        ensure!(w.stream_position()? as u32 == header.name_offset);
+        w.write_all(info.name.as_bytes())?;
+    }
+    for section in file.sections().filter(is_permitted_section).filter(should_write_section) {
+        let position = w.stream_position()? as u32;
+        let section_offset = section_offsets[section.index().0];
+        w.write_all(&vec![0u8; (section_offset - position) as usize])?;
+        w.write_all(&section.uncompressed_data()?)?;
+    }
+    ensure!(w.stream_position()? as u32 == section_data_end);
+    w.write_all(&vec![0u8; (header.internal_rel_offset - section_data_end) as usize])?;
+    for reloc in &internal_relocations {
+        reloc.to_writer(w, Endian::Big)?;
+    }
+    ensure!(w.stream_position()? as u32 == header.external_rel_offset);
+    for (_, reloc) in &external_relocations {
+        reloc.to_writer(w, Endian::Big)?;
+    }
+    ensure!(w.stream_position()? as u32 == header.export_table_offset);
+    for symbol in &export_symbols {
+        symbol.to_writer(w, Endian::Big)?;
+    }
+    w.write_all(&export_names)?;
+    ensure!(w.stream_position()? as u32 == header.import_table_offset);
+    for symbol in &import_table {
+        symbol.to_writer(w, Endian::Big)?;
+    }
+    w.write_all(&import_names)?;
+    ensure!(w.stream_position()? as u32 == offset);
+    Ok(())
+}
+
+/// Writes a selfile (SEL) for the static module from a linked ELF.
+///
+/// A selfile is an RSO without any sections or relocations, containing only an export table.
+/// Symbols are referenced by their DOL section index (see [`DOL_SECTION_NAMES`]).
+pub fn write_sel<W>(w: &mut W, file: &object::File, exports: &FxHashSet<&[u8]>) -> Result<()>
+where W: Write + Seek + ?Sized {
+    let mut export_names = Vec::<u8>::new();
+    let mut export_symbols = Vec::<RsoSymbol>::new();
+    for symbol in file.symbols().filter(|s| s.is_definition()) {
+        let name = symbol.name()?;
+        if !exports.contains(name.as_bytes()) {
+            continue;
+        }
+        let (section_index, address) = match symbol.section() {
+            object::SymbolSection::Absolute => (DOL_SECTION_ABS, symbol.address() as u32),
+            object::SymbolSection::Section(section_index) => {
+                let section = file.section_by_index(section_index)?;
+                let section_name = section.name()?;
+                let dol_section_index = if section_name == "extabindex" {
+                    DOL_SECTION_ETI
+                } else {
+                    DOL_SECTION_NAMES.iter().position(|&n| n == Some(section_name)).ok_or_else(
+                        || anyhow!("Can't export symbol {} in section {}", name, section_name),
+                    )? as u32
+                };
+                (dol_section_index, (symbol.address() - section.address()) as u32)
+            }
+            section => bail!("Can't export symbol {} in section {:?}", name, section),
+        };
+        export_symbols.push(RsoSymbol {
+            name_offset: add_name(&mut export_names, name.as_bytes()),
+            offset: address,
+            section_index,
+            hash: Some(symbol_hash(name)),
+        });
+    }
+
+    let mut header = RsoHeader {
+        num_sections: 0,
+        section_info_offset: 0, // Calculated below
+        name_offset: 0,
+        name_size: 0,
+        version: 1,
+        bss_size: 0,
+        prolog_section: 0,
+        epilog_section: 0,
+        unresolved_section: 0,
+        prolog_offset: 0,
+        epilog_offset: 0,
+        unresolved_offset: 0,
+        internal_rel_offset: 0,
+        internal_rel_size: 0,
+        external_rel_offset: 0,
+        external_rel_size: 0,
+        export_table_offset: 0,
+        export_table_size: 0,
+        export_table_name_offset: 0,
+        import_table_offset: 0,
+        import_table_size: 0,
+        import_table_name_offset: 0,
+    };
+    let offset = header.write_size() as u32;
+    header.section_info_offset = offset;
+    header.internal_rel_offset = offset;
+    header.external_rel_offset = offset;
+    header.export_table_offset = offset;
+    header.export_table_size = export_symbols.iter().map(|s| s.write_size() as u32).sum();
+    header.export_table_name_offset = offset + header.export_table_size;
+    header.import_table_offset = header.export_table_name_offset + export_names.len() as u32;
+    header.import_table_name_offset = header.import_table_offset;
+
+    header.to_writer(w, Endian::Big)?;
+    for symbol in &export_symbols {
+        symbol.to_writer(w, Endian::Big)?;
+    }
+    w.write_all(&export_names)?;
+    ensure!(w.stream_position()? as u32 == header.import_table_offset);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use object::write;
+
+    use super::*;
+
+    #[test]
+    fn test_write_rso_imports() {
+        let mut module = write::Object::new(
+            object::BinaryFormat::Elf,
+            object::Architecture::PowerPc,
+            object::Endianness::Big,
+        );
+        let text = module.add_section(vec![], b".text".to_vec(), object::SectionKind::Text);
+        module.append_section_data(text, &[0u8; 12], 4);
+        let mut add_import = |name: &[u8]| {
+            module.add_symbol(write::Symbol {
+                name: name.to_vec(),
+                value: 0,
+                size: 0,
+                kind: object::SymbolKind::Unknown,
+                scope: object::SymbolScope::Dynamic,
+                weak: false,
+                section: write::SymbolSection::Undefined,
+                flags: object::SymbolFlags::None,
+            })
+        };
+        let foo = add_import(b"foo");
+        let bar = add_import(b"bar");
+        for (offset, symbol, addend) in [(0, foo, 0), (4, bar, 0), (8, foo, 8)] {
+            module
+                .add_relocation(text, write::Relocation {
+                    offset,
+                    size: 32,
+                    kind: object::RelocationKind::Elf(elf::R_PPC_ADDR32),
+                    encoding: object::RelocationEncoding::Generic,
+                    symbol,
+                    addend,
+                })
+                .unwrap();
+        }
+        let data = module.write().unwrap();
+        let file = object::File::parse(data.as_slice()).unwrap();
+
+        let info = RsoWriteInfo {
+            name: "test".to_string(),
+            section_count: None,
+            quiet: true,
+            section_align: None,
+        };
+        let mut out = Cursor::new(Vec::new());
+        write_rso(&mut out, &info, &file, &FxHashSet::default()).unwrap();
+
+        let (_, obj) = process_rso(&mut out, 0).unwrap();
+        let (_, section) = obj.sections.by_name(".text").unwrap().unwrap();
+        let targets = section
+            .relocations
+            .iter()
+            .map(|(address, reloc)| {
+                (address, obj.symbols[reloc.target_symbol].name.as_str(), reloc.addend)
+            })
+            .collect_vec();
+        assert_eq!(targets, vec![(0, "foo", 0), (4, "bar", 0), (8, "foo", 8)]);
+    }
+}
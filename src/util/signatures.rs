@@ -1,11 +1,14 @@
 use std::{
+    cmp::{max, min, Ordering},
     collections::{btree_map, BTreeMap},
-    path::Path,
+    fs,
+    path::{Path, PathBuf},
 };
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine};
 use cwdemangle::{demangle, DemangleOptions};
+use ppc750cl::Ins;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 
@@ -17,10 +20,10 @@ use crate::{
     },
     array_ref,
     obj::{
-        ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSymbol, ObjSymbolFlagSet,
-        ObjSymbolKind,
+        ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
+        ObjSymbolFlagSet, ObjSymbolKind,
     },
-    util::elf::process_elf,
+    util::{elf::process_elf, file::buf_reader},
 };
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
@@ -75,6 +78,54 @@ pub fn parse_signatures(sig_str: &str) -> Result<Vec<FunctionSignature>> {
     Ok(serde_yaml::from_str(sig_str)?)
 }
 
+fn collect_signature_files(
+    path: &Path,
+    out: &mut Vec<PathBuf>,
+    dirs: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if path.is_dir() {
+        dirs.push(path.to_path_buf());
+        let mut entries = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory '{}'", path.display()))?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir()
+                || entry.extension().is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("yml") || ext.eq_ignore_ascii_case("yaml")
+                })
+            {
+                collect_signature_files(&entry, out, dirs)?;
+            }
+        }
+    } else {
+        out.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Loads signature databases from the given files and directories.
+/// Directories are searched recursively for `.yml` and `.yaml` files.
+/// Returns the loaded signatures along with the list of files read and directories searched.
+pub fn read_signature_files(
+    paths: &[PathBuf],
+) -> Result<(Vec<FunctionSignature>, Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for path in paths {
+        collect_signature_files(path, &mut files, &mut dirs)?;
+    }
+    let mut signatures = Vec::new();
+    for file in &files {
+        let file_signatures: Vec<FunctionSignature> =
+            serde_yaml::from_reader(&mut buf_reader(file)?)
+                .with_context(|| format!("Failed to parse signatures '{}'", file.display()))?;
+        signatures.extend(file_signatures);
+    }
+    Ok((signatures, files, dirs))
+}
+
 pub fn check_signatures_str(
     section: &ObjSection,
     addr: u32,
@@ -110,6 +161,155 @@ pub fn check_signatures(
     Ok(None)
 }
 
+/// Weight of the opcode sequence similarity in the fuzzy match confidence.
+/// The remainder is given to the relocation shape similarity.
+const FUZZY_OPCODE_WEIGHT: f32 = 0.8;
+
+/// Placeholder opcode for words masked out entirely (e.g. absolute relocations).
+/// Matches any opcode.
+const FUZZY_MASKED_OPCODE: u16 = u16::MAX;
+
+#[inline]
+fn opcode_eq(a: &u16, b: &u16) -> bool {
+    a == b || *a == FUZZY_MASKED_OPCODE || *b == FUZZY_MASKED_OPCODE
+}
+
+/// Scores for a fuzzy signature comparison, each ranging from 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SignatureScore {
+    /// Weighted combination of the opcode and relocation scores.
+    pub confidence: f32,
+    /// Similarity of the instruction opcode sequences, ignoring operands.
+    pub opcodes: f32,
+    /// Similarity of the relocation kind sequences.
+    pub relocations: f32,
+}
+
+/// Instruction-level summary of a function, used for fuzzy signature matching.
+#[derive(Debug, Clone, Default)]
+pub struct FuzzyFunction {
+    /// Opcode of each instruction.
+    pub opcodes: Vec<u16>,
+    /// Sorted copy of `opcodes`, used to bound the similarity cheaply.
+    pub sorted_opcodes: Vec<u16>,
+    /// Relocation kinds, in address order.
+    pub relocations: Vec<ObjRelocKind>,
+}
+
+impl FuzzyFunction {
+    pub fn new(opcodes: Vec<u16>, relocations: Vec<ObjRelocKind>) -> Self {
+        let mut sorted_opcodes = opcodes.clone();
+        sorted_opcodes.sort_unstable();
+        Self { opcodes, sorted_opcodes, relocations }
+    }
+
+    /// Summarizes a function from its data and the relocations within it.
+    pub fn from_data(data: &[u8], relocations: Vec<ObjRelocKind>) -> Self {
+        let opcodes = data
+            .chunks_exact(4)
+            .map(|c| Ins::new(u32::from_be_bytes(c.try_into().unwrap()), 0).op as u16)
+            .collect();
+        Self::new(opcodes, relocations)
+    }
+
+    /// Summarizes a signature. Words masked out entirely are not decoded.
+    pub fn from_signature(sig: &FunctionSignature) -> Result<Self> {
+        let sig_data = STANDARD.decode(&sig.signature)?;
+        let opcodes = sig_data
+            .chunks_exact(8)
+            .map(|chunk| {
+                let ins = u32::from_be_bytes(*array_ref!(chunk, 0, 4));
+                let pat = u32::from_be_bytes(*array_ref!(chunk, 4, 4));
+                if pat == 0 {
+                    FUZZY_MASKED_OPCODE
+                } else {
+                    Ins::new(ins, 0).op as u16
+                }
+            })
+            .collect();
+        let mut relocations =
+            sig.relocations.iter().map(|r| (r.offset, r.kind)).collect::<Vec<_>>();
+        relocations.sort_by_key(|&(offset, _)| offset);
+        Ok(Self::new(opcodes, relocations.into_iter().map(|(_, kind)| kind).collect()))
+    }
+}
+
+/// Length of the longest common subsequence of two sequences.
+fn lcs_len<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> usize {
+    let mut prev = vec![0usize; b.len() + 1];
+    let mut cur = vec![0usize; b.len() + 1];
+    for x in a {
+        for (j, y) in b.iter().enumerate() {
+            cur[j + 1] = if eq(x, y) { prev[j] + 1 } else { max(prev[j + 1], cur[j]) };
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// Number of elements two sorted sequences have in common (multiset intersection).
+/// Masked opcodes sort last, and each matches any remaining opcode on the other side.
+fn common_count(a: &[u16], b: &[u16]) -> usize {
+    let masked = |v: &[u16]| v.iter().rev().take_while(|&&op| op == FUZZY_MASKED_OPCODE).count();
+    let (a_masked, b_masked) = (masked(a), masked(b));
+    let (a, b) = (&a[..a.len() - a_masked], &b[..b.len() - b_masked]);
+    let count = exact_common_count(a, b);
+    min(count + a_masked + b_masked, min(a.len() + a_masked, b.len() + b_masked))
+}
+
+fn exact_common_count(a: &[u16], b: &[u16]) -> usize {
+    let (mut i, mut j, mut count) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                count += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    count
+}
+
+fn similarity(common: usize, a_len: usize, b_len: usize) -> f32 {
+    if a_len + b_len == 0 {
+        1.0
+    } else {
+        (2 * common) as f32 / (a_len + b_len) as f32
+    }
+}
+
+/// Scores a function against a signature by instruction-level similarity, rather than requiring
+/// an exact masked match. Returns `None` if the confidence can't reach `min_confidence`.
+///
+/// The opcode score is derived from the longest common subsequence of opcodes, so inserted or
+/// removed instructions (e.g. from inlining or different compiler flags) only reduce the score.
+pub fn score_signature(
+    function: &FuzzyFunction,
+    signature: &FuzzyFunction,
+    min_confidence: f32,
+) -> Option<SignatureScore> {
+    let relocations = similarity(
+        lcs_len(&function.relocations, &signature.relocations, PartialEq::eq),
+        function.relocations.len(),
+        signature.relocations.len(),
+    );
+    let confidence =
+        |opcodes: f32| opcodes * FUZZY_OPCODE_WEIGHT + relocations * (1.0 - FUZZY_OPCODE_WEIGHT);
+    // The common opcode count bounds the LCS, so skip the expensive comparison where possible
+    let (a, b) = (function.opcodes.len(), signature.opcodes.len());
+    let upper_bound =
+        similarity(common_count(&function.sorted_opcodes, &signature.sorted_opcodes), a, b);
+    if confidence(upper_bound) < min_confidence {
+        return None;
+    }
+    let opcodes = similarity(lcs_len(&function.opcodes, &signature.opcodes, opcode_eq), a, b);
+    let score = SignatureScore { confidence: confidence(opcodes), opcodes, relocations };
+    (score.confidence >= min_confidence).then_some(score)
+}
+
 pub fn apply_symbol(
     obj: &mut ObjInfo,
     target: SectionAddress,
@@ -248,10 +448,6 @@ pub fn compare_signature(existing: &mut FunctionSignature, new: &FunctionSignatu
 
 pub fn generate_signature<P>(path: P, symbol_name: &str) -> Result<Option<FunctionSignature>>
 where P: AsRef<Path> {
-    let mut out_symbols: Vec<OutSymbol> = Vec::new();
-    let mut out_relocs: Vec<OutReloc> = Vec::new();
-    let mut symbol_map: BTreeMap<usize, usize> = BTreeMap::new();
-
     let mut obj = process_elf(path)?;
     if obj.kind == ObjKind::Executable
         && (obj.sda2_base.is_none()
@@ -285,106 +481,166 @@ where P: AsRef<Path> {
         if symbol.name != symbol_name && symbol.name != symbol_name.replace("TRK", "TRK_") {
             continue;
         }
-        let section_idx = symbol.section.unwrap();
-        let section = &obj.sections[section_idx];
-        // let out_symbol_idx = out_symbols.len();
-        out_symbols.push(OutSymbol {
-            kind: symbol.kind,
-            name: symbol.name.clone(),
-            size: symbol.size as u32,
-            flags: symbol.flags,
-            section: Some(section.name.clone()),
-        });
-        // println!(
-        //     "Building signature for {} ({:#010X}-{:#010X})",
-        //     symbol.name,
-        //     symbol.address,
-        //     symbol.address + symbol.size
-        // );
-        let mut instructions = section.data[(symbol.address - section.address) as usize
-            ..(symbol.address - section.address + symbol.size) as usize]
-            .chunks_exact(4)
-            .map(|c| (u32::from_be_bytes(c.try_into().unwrap()), !0u32))
-            .collect::<Vec<(u32, u32)>>();
-        for (idx, (ins, pat)) in instructions.iter_mut().enumerate() {
-            let addr = (symbol.address as usize + idx * 4) as u32;
-            if let Some(reloc) = section.relocations.at(addr) {
-                let symbol_idx = match symbol_map.entry(reloc.target_symbol) {
-                    btree_map::Entry::Vacant(e) => {
-                        let target = &obj.symbols[reloc.target_symbol];
-                        let symbol_idx = out_symbols.len();
-                        e.insert(symbol_idx);
-                        out_symbols.push(OutSymbol {
-                            kind: target.kind,
-                            name: target.name.clone(),
-                            size: if target.kind == ObjSymbolKind::Function {
-                                0
-                            } else {
-                                target.size as u32
-                            },
-                            flags: target.flags,
-                            section: target
-                                .section
-                                .and_then(|idx| obj.sections.get(idx))
-                                .map(|section| section.name.clone()),
-                        });
-                        symbol_idx
-                    }
-                    btree_map::Entry::Occupied(e) => *e.get(),
-                };
-                match reloc.kind {
-                    ObjRelocKind::Absolute => {
-                        *ins = 0;
-                        *pat = 0;
-                    }
-                    ObjRelocKind::PpcAddr16Hi
-                    | ObjRelocKind::PpcAddr16Ha
-                    | ObjRelocKind::PpcAddr16Lo => {
-                        *ins &= !0xFFFF;
-                        *pat = !0xFFFF;
-                    }
-                    ObjRelocKind::PpcRel24 => {
-                        *ins &= !0x3FFFFFC;
-                        *pat = !0x3FFFFFC;
-                    }
-                    ObjRelocKind::PpcRel14 => {
-                        *ins &= !0xFFFC;
-                        *pat = !0xFFFC;
-                    }
-                    ObjRelocKind::PpcEmbSda21 => {
-                        *ins &= !0x1FFFFF;
-                        *pat = !0x1FFFFF;
-                    }
+        return build_signature(&obj, symbol).map(Some);
+    }
+    Ok(None)
+}
+
+/// Generates signatures for every global function in an object, and optionally every global
+/// data object. Relocatable objects already carry their relocations; for executables,
+/// relocations are first discovered by tracking all functions.
+pub fn generate_signatures(
+    obj: &mut ObjInfo,
+    include_objects: bool,
+) -> Result<Vec<FunctionSignature>> {
+    if obj.kind == ObjKind::Executable {
+        let mut tracker = Tracker::new(obj);
+        for (_, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
+            tracker.process_function(obj, symbol)?;
+        }
+        tracker.apply(obj, true)?;
+    }
+    let mut signatures = Vec::new();
+    for (_, symbol) in obj.symbols.iter_ordered() {
+        if symbol.flags.is_local() || symbol.size == 0 || symbol.name.starts_with('@') {
+            continue;
+        }
+        // Skip common and undefined symbols
+        let Some(section_index) = symbol.section.filter(|_| !symbol.flags.is_common()) else {
+            continue;
+        };
+        match symbol.kind {
+            ObjSymbolKind::Function => {}
+            ObjSymbolKind::Object if include_objects => {
+                let section = &obj.sections[section_index];
+                // Signatures are matched by word
+                if section.kind == ObjSectionKind::Bss || symbol.size % 4 != 0 {
+                    continue;
                 }
-                out_relocs.push(OutReloc {
-                    offset: addr - (symbol.address as u32),
-                    kind: reloc.kind,
-                    symbol: symbol_idx,
-                    addend: reloc.addend as i32,
-                });
             }
+            _ => continue,
         }
+        signatures.push(
+            build_signature(obj, symbol)
+                .with_context(|| format!("Failed to generate signature for {}", symbol.name))?,
+        );
+    }
+    Ok(signatures)
+}
 
-        let mut data = vec![0u8; instructions.len() * 8];
-        for (idx, &(ins, pat)) in instructions.iter().enumerate() {
-            data[idx * 8..idx * 8 + 4].copy_from_slice(&ins.to_be_bytes());
-            data[idx * 8 + 4..idx * 8 + 8].copy_from_slice(&pat.to_be_bytes());
+fn build_signature(obj: &ObjInfo, symbol: &ObjSymbol) -> Result<FunctionSignature> {
+    let mut out_symbols: Vec<OutSymbol> = Vec::new();
+    let mut out_relocs: Vec<OutReloc> = Vec::new();
+    let mut symbol_map: BTreeMap<usize, usize> = BTreeMap::new();
+
+    let Some(section_idx) = symbol.section else {
+        bail!("Symbol {} has no section", symbol.name);
+    };
+    let section = &obj.sections[section_idx];
+    out_symbols.push(OutSymbol {
+        kind: symbol.kind,
+        name: symbol.name.clone(),
+        size: symbol.size as u32,
+        flags: symbol.flags,
+        section: Some(section.name.clone()),
+    });
+    let mut instructions = section.data[(symbol.address - section.address) as usize
+        ..(symbol.address - section.address + symbol.size) as usize]
+        .chunks_exact(4)
+        .map(|c| (u32::from_be_bytes(c.try_into().unwrap()), !0u32))
+        .collect::<Vec<(u32, u32)>>();
+    for (idx, (ins, pat)) in instructions.iter_mut().enumerate() {
+        let addr = (symbol.address as usize + idx * 4) as u32;
+        if let Some(reloc) = section.relocations.at(addr) {
+            let symbol_idx = match symbol_map.entry(reloc.target_symbol) {
+                btree_map::Entry::Vacant(e) => {
+                    let target = &obj.symbols[reloc.target_symbol];
+                    let symbol_idx = out_symbols.len();
+                    e.insert(symbol_idx);
+                    out_symbols.push(OutSymbol {
+                        kind: target.kind,
+                        name: target.name.clone(),
+                        size: if target.kind == ObjSymbolKind::Function {
+                            0
+                        } else {
+                            target.size as u32
+                        },
+                        flags: target.flags,
+                        section: target
+                            .section
+                            .and_then(|idx| obj.sections.get(idx))
+                            .map(|section| section.name.clone()),
+                    });
+                    symbol_idx
+                }
+                btree_map::Entry::Occupied(e) => *e.get(),
+            };
+            match reloc.kind {
+                ObjRelocKind::Absolute => {
+                    *ins = 0;
+                    *pat = 0;
+                }
+                ObjRelocKind::PpcAddr16Hi
+                | ObjRelocKind::PpcAddr16Ha
+                | ObjRelocKind::PpcAddr16Lo => {
+                    *ins &= !0xFFFF;
+                    *pat = !0xFFFF;
+                }
+                ObjRelocKind::PpcRel24 => {
+                    *ins &= !0x3FFFFFC;
+                    *pat = !0x3FFFFFC;
+                }
+                ObjRelocKind::PpcRel14 => {
+                    *ins &= !0xFFFC;
+                    *pat = !0xFFFC;
+                }
+                ObjRelocKind::PpcEmbSda21 => {
+                    *ins &= !0x1FFFFF;
+                    *pat = !0x1FFFFF;
+                }
+            }
+            out_relocs.push(OutReloc {
+                offset: addr - (symbol.address as u32),
+                kind: reloc.kind,
+                symbol: symbol_idx,
+                addend: reloc.addend as i32,
+            });
         }
+    }
 
-        let encoded = STANDARD.encode(&data);
-        let mut hasher = Sha1::new();
-        hasher.update(&data);
-        let hash = hasher.finalize();
-        let mut hash_buf = [0u8; 40];
-        let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
-            .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
-        return Ok(Some(FunctionSignature {
-            symbol: 0,
-            hash: hash_str.to_string(),
-            signature: encoded,
-            symbols: out_symbols,
-            relocations: out_relocs,
-        }));
+    let mut data = vec![0u8; instructions.len() * 8];
+    for (idx, &(ins, pat)) in instructions.iter().enumerate() {
+        data[idx * 8..idx * 8 + 4].copy_from_slice(&ins.to_be_bytes());
+        data[idx * 8 + 4..idx * 8 + 8].copy_from_slice(&pat.to_be_bytes());
+    }
+
+    let encoded = STANDARD.encode(&data);
+    let mut hasher = Sha1::new();
+    hasher.update(&data);
+    let hash = hasher.finalize();
+    let mut hash_buf = [0u8; 40];
+    let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
+        .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
+    Ok(FunctionSignature {
+        symbol: 0,
+        hash: hash_str.to_string(),
+        signature: encoded,
+        symbols: out_symbols,
+        relocations: out_relocs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_signature_masked() {
+        let function = FuzzyFunction::new(vec![1, 2, 3, 4, 5], vec![ObjRelocKind::Absolute]);
+        let signature =
+            FuzzyFunction::new(vec![1, 2, FUZZY_MASKED_OPCODE, 4, 5], vec![ObjRelocKind::Absolute]);
+        let score = score_signature(&function, &signature, 0.0).unwrap();
+        assert_eq!(score.opcodes, 1.0);
+        assert_eq!(score.confidence, 1.0);
     }
-    Ok(None)
 }
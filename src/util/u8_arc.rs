@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Result};
+
+use crate::util::{
+    align_up,
+    file::{read_c_string, ArchiveEntry},
+    reader::{struct_size, Endian, FromReader, ToWriter},
+};
+
+pub const U8_MAGIC: [u8; 4] = [0x55, 0xAA, 0x38, 0x2D];
+
+const U8_ALIGN: u32 = 0x20;
+
+struct U8Header {
+    magic: [u8; 4],
+    /// Offset of the root node.
+    node_offset: u32,
+    /// Size of the node table and string table.
+    node_size: u32,
+    /// Offset of the file data.
+    data_offset: u32,
+}
+
+impl FromReader for U8Header {
+    type Args = ();
+
+    const STATIC_SIZE: usize = struct_size([
+        4,                // magic
+        u32::STATIC_SIZE, // node_offset
+        u32::STATIC_SIZE, // node_size
+        u32::STATIC_SIZE, // data_offset
+        16,               // reserved
+    ]);
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let header = Self {
+            magic: <[u8; 4]>::from_reader(reader, e)?,
+            node_offset: u32::from_reader(reader, e)?,
+            node_size: u32::from_reader(reader, e)?,
+            data_offset: u32::from_reader(reader, e)?,
+        };
+        <[u8; 16]>::from_reader(reader, e)?; // reserved
+        if header.magic != U8_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid U8 magic: {:?}", header.magic),
+            ));
+        }
+        Ok(header)
+    }
+}
+
+impl ToWriter for U8Header {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        self.magic.to_writer(writer, e)?;
+        self.node_offset.to_writer(writer, e)?;
+        self.node_size.to_writer(writer, e)?;
+        self.data_offset.to_writer(writer, e)?;
+        [0u8; 16].to_writer(writer, e)?; // reserved
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum U8NodeKind {
+    File,
+    Directory,
+}
+
+struct U8RawNode {
+    kind: U8NodeKind,
+    /// Offset of the name in the string table. (24 bits)
+    name_offset: u32,
+    /// File: offset of the data in the archive.
+    /// Directory: index of the parent node.
+    offset: u32,
+    /// File: size of the data.
+    /// Directory: index of the first node after this directory's contents.
+    length: u32,
+}
+
+impl FromReader for U8RawNode {
+    type Args = ();
+
+    const STATIC_SIZE: usize = struct_size([
+        u32::STATIC_SIZE, // kind, name_offset
+        u32::STATIC_SIZE, // offset
+        u32::STATIC_SIZE, // length
+    ]);
+
+    fn from_reader_args<R>(reader: &mut R, e: Endian, _args: Self::Args) -> io::Result<Self>
+    where R: Read + Seek + ?Sized {
+        let kind_and_name = u32::from_reader(reader, e)?;
+        let kind = match kind_and_name >> 24 {
+            0 => U8NodeKind::File,
+            1 => U8NodeKind::Directory,
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid U8 node kind: {}", kind),
+                ))
+            }
+        };
+        Ok(Self {
+            kind,
+            name_offset: kind_and_name & 0xFFFFFF,
+            offset: u32::from_reader(reader, e)?,
+            length: u32::from_reader(reader, e)?,
+        })
+    }
+}
+
+impl ToWriter for U8RawNode {
+    fn to_writer<W>(&self, writer: &mut W, e: Endian) -> io::Result<()>
+    where W: Write + ?Sized {
+        let kind = match self.kind {
+            U8NodeKind::File => 0u32,
+            U8NodeKind::Directory => 1u32,
+        };
+        (kind << 24 | self.name_offset).to_writer(writer, e)?;
+        self.offset.to_writer(writer, e)?;
+        self.length.to_writer(writer, e)?;
+        Ok(())
+    }
+
+    fn write_size(&self) -> usize { Self::STATIC_SIZE }
+}
+
+/// A node in a U8 archive.
+#[derive(Debug, Clone)]
+pub struct U8Node {
+    pub kind: U8NodeKind,
+    /// Path of the node relative to the archive root.
+    pub path: PathBuf,
+    /// File: offset of the data, relative to the start of the archive.
+    pub offset: u64,
+    /// File: size of the data.
+    pub size: u32,
+}
+
+pub struct U8Reader {
+    nodes: Vec<U8Node>,
+}
+
+impl U8Reader {
+    /// Creates a new U8 reader.
+    pub fn new<R>(reader: &mut R) -> Result<Self>
+    where R: Read + Seek + ?Sized {
+        let base = reader.stream_position()?;
+        let header = U8Header::from_reader(reader, Endian::Big)?;
+
+        reader.seek(SeekFrom::Start(base + header.node_offset as u64))?;
+        let root = U8RawNode::from_reader(reader, Endian::Big)?;
+        ensure!(root.kind == U8NodeKind::Directory, "U8 root node is not a directory");
+        let node_count = root.length;
+        ensure!(
+            node_count as u64 * U8RawNode::STATIC_SIZE as u64 <= header.node_size as u64,
+            "invalid U8 node count: {}",
+            node_count
+        );
+        let mut raw_nodes = Vec::with_capacity(node_count as usize);
+        raw_nodes.push(root);
+        for _ in 1..node_count {
+            raw_nodes.push(U8RawNode::from_reader(reader, Endian::Big)?);
+        }
+        let string_base =
+            base + header.node_offset as u64 + node_count as u64 * U8RawNode::STATIC_SIZE as u64;
+        let string_table_length = header.node_size - node_count * U8RawNode::STATIC_SIZE as u32;
+
+        // Directory ends and parent paths, to determine when to restore the current path
+        let mut dir_stack: Vec<(u32, PathBuf)> = vec![(node_count, PathBuf::new())];
+        let mut current_path = PathBuf::new();
+        let mut nodes = Vec::with_capacity(node_count as usize);
+        for (index, node) in raw_nodes.iter().enumerate().skip(1) {
+            while dir_stack.last().is_some_and(|(end, _)| index as u32 >= *end) {
+                current_path = dir_stack.pop().unwrap().1;
+            }
+            ensure!(node.name_offset < string_table_length, "invalid string table offset");
+            let name = read_c_string(reader, string_base + node.name_offset as u64)?;
+            // Many archives have a "." directory at the root, which is omitted from paths
+            let is_cur_dir = name == ".";
+            let path = if is_cur_dir { current_path.clone() } else { current_path.join(&name) };
+            match node.kind {
+                U8NodeKind::File => nodes.push(U8Node {
+                    kind: U8NodeKind::File,
+                    path,
+                    offset: base + node.offset as u64,
+                    size: node.length,
+                }),
+                U8NodeKind::Directory => {
+                    ensure!(
+                        node.length > index as u32 && node.length <= node_count,
+                        "invalid U8 directory end: {}",
+                        node.length
+                    );
+                    if !is_cur_dir {
+                        nodes.push(U8Node {
+                            kind: U8NodeKind::Directory,
+                            path: path.clone(),
+                            offset: 0,
+                            size: 0,
+                        });
+                    }
+                    dir_stack.push((node.length, current_path));
+                    current_path = path;
+                }
+            }
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Nodes in the archive, in archive order. The root node and "." directories are omitted.
+    pub fn nodes(&self) -> &[U8Node] { &self.nodes }
+
+    /// Find a file in the U8 archive. Comparison is case-insensitive.
+    pub fn find_file<P>(&self, path: P) -> Result<Option<(u64, u32)>>
+    where P: AsRef<Path> {
+        let mut cmp_path = PathBuf::new();
+        for component in path.as_ref().components() {
+            match component {
+                Component::Normal(name) => cmp_path.push(name.to_ascii_lowercase()),
+                Component::RootDir | Component::CurDir => {}
+                component => bail!("Invalid path component: {:?}", component),
+            }
+        }
+        for node in &self.nodes {
+            if node.kind == U8NodeKind::File
+                && node.path.to_string_lossy().to_ascii_lowercase() == cmp_path.to_string_lossy()
+            {
+                return Ok(Some((node.offset, node.size)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Writes a U8 archive containing `entries`.
+///
+/// Entries in each directory are sorted by name (case-insensitive), as Nintendo's tools do.
+/// File data is stored in node order, each file aligned to 32 bytes.
+pub fn write_u8<W>(w: &mut W, entries: &[ArchiveEntry]) -> Result<()>
+where W: Write + ?Sized {
+    struct State<'a> {
+        nodes: Vec<U8RawNode>,
+        strings: Vec<u8>,
+        string_offsets: HashMap<&'a str, u32>,
+        files: Vec<&'a [u8]>,
+        data_size: u32,
+    }
+    impl<'a> State<'a> {
+        fn add_string(&mut self, name: &'a str) -> Result<u32> {
+            if let Some(&offset) = self.string_offsets.get(name) {
+                return Ok(offset);
+            }
+            let offset = self.strings.len() as u32;
+            ensure!(offset <= 0xFFFFFF, "String table too large");
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.string_offsets.insert(name, offset);
+            Ok(offset)
+        }
+
+        fn add_entries(&mut self, parent: u32, entries: &'a [ArchiveEntry]) -> Result<()> {
+            let mut sorted = entries.iter().collect::<Vec<_>>();
+            sorted.sort_by_cached_key(|e| e.name().to_ascii_lowercase());
+            for entry in sorted {
+                let name_offset = self.add_string(entry.name())?;
+                match entry {
+                    ArchiveEntry::File { data, .. } => {
+                        self.nodes.push(U8RawNode {
+                            kind: U8NodeKind::File,
+                            name_offset,
+                            // Relative to the data start, fixed up once the layout is known
+                            offset: self.data_size,
+                            length: data.len() as u32,
+                        });
+                        self.files.push(data);
+                        self.data_size = align_up(self.data_size + data.len() as u32, U8_ALIGN);
+                    }
+                    ArchiveEntry::Directory { entries, .. } => {
+                        let index = self.nodes.len();
+                        self.nodes.push(U8RawNode {
+                            kind: U8NodeKind::Directory,
+                            name_offset,
+                            offset: parent,
+                            length: 0,
+                        });
+                        self.add_entries(index as u32, entries)?;
+                        self.nodes[index].length = self.nodes.len() as u32;
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut state = State {
+        nodes: vec![],
+        strings: vec![],
+        string_offsets: HashMap::new(),
+        files: vec![],
+        data_size: 0,
+    };
+    let root_name = state.add_string("")?;
+    state.nodes.push(U8RawNode {
+        kind: U8NodeKind::Directory,
+        name_offset: root_name,
+        offset: 0,
+        length: 0,
+    });
+    state.add_entries(0, entries)?;
+    state.nodes[0].length = state.nodes.len() as u32;
+
+    let node_offset = U8Header::STATIC_SIZE as u32;
+    let node_size = (state.nodes.len() * U8RawNode::STATIC_SIZE + state.strings.len()) as u32;
+    let data_offset = align_up(node_offset + node_size, U8_ALIGN);
+    for node in &mut state.nodes {
+        if node.kind == U8NodeKind::File {
+            node.offset += data_offset;
+        }
+    }
+
+    let header = U8Header { magic: U8_MAGIC, node_offset, node_size, data_offset };
+    header.to_writer(w, Endian::Big)?;
+    for node in &state.nodes {
+        node.to_writer(w, Endian::Big)?;
+    }
+    w.write_all(&state.strings)?;
+    let mut position = node_offset + node_size;
+    for (node, data) in state.nodes.iter().filter(|n| n.kind == U8NodeKind::File).zip(&state.files)
+    {
+        w.write_all(&vec![0u8; (node.offset - position) as usize])?;
+        w.write_all(data)?;
+        position = node.offset + data.len() as u32;
+    }
+    let end = align_up(position, U8_ALIGN);
+    w.write_all(&vec![0u8; (end - position) as usize])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_find_file_cur_dir() {
+        let entries = vec![ArchiveEntry::Directory {
+            name: ".".to_string(),
+            entries: vec![
+                ArchiveEntry::Directory {
+                    name: "sub".to_string(),
+                    entries: vec![ArchiveEntry::File {
+                        name: "a.bin".to_string(),
+                        data: vec![1, 2, 3, 4],
+                    }],
+                },
+                ArchiveEntry::File { name: "b.bin".to_string(), data: vec![5, 6] },
+            ],
+        }];
+        let mut data = Vec::new();
+        write_u8(&mut data, &entries).unwrap();
+
+        let reader = U8Reader::new(&mut Cursor::new(&data)).unwrap();
+        let paths = reader.nodes().iter().map(|n| n.path.to_string_lossy()).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["b.bin", "sub", "sub/a.bin"]);
+        for path in ["sub/a.bin", "./sub/a.bin", "/sub/A.BIN"] {
+            let (offset, size) = reader.find_file(path).unwrap().unwrap();
+            assert_eq!(&data[offset as usize..offset as usize + size as usize], &[1, 2, 3, 4]);
+        }
+        assert!(reader.find_file("b.bin").unwrap().is_some());
+        assert!(reader.find_file("a.bin").unwrap().is_none());
+    }
+}
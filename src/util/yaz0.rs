@@ -1,11 +1,16 @@
 // Source: https://github.com/Julgodis/picori/blob/650da9f4fe6050b39b80d5360416591c748058d5/src/yaz0.rs
 // License: MIT
 // Modified to use `std::io::Read`/`Seek` and project's FromReader trait.
+// Compression added using the shared LZ encoder.
 use std::io::{Read, Seek};
 
 use anyhow::{ensure, Result};
 
-use crate::util::reader::{skip_bytes, struct_size, Endian, FromReader};
+use crate::util::{
+    lz,
+    lz::CompressionMode,
+    reader::{skip_bytes, struct_size, Endian, FromReader},
+};
 
 pub const YAZ0_MAGIC: [u8; 4] = *b"Yaz0";
 
@@ -99,3 +104,156 @@ where R: Read + Seek + ?Sized {
 
     Ok(())
 }
+
+/// Compresses the data into a new allocated [`Vec`], including the Yaz0 header.
+pub fn compress(input: &[u8], mode: CompressionMode) -> Vec<u8> {
+    let mut output = Vec::with_capacity(Header::STATIC_SIZE + input.len() + input.len() / 8 + 1);
+    output.extend_from_slice(&YAZ0_MAGIC);
+    output.extend_from_slice(&(input.len() as u32).to_be_bytes());
+    output.extend_from_slice(&[0u8; 8]);
+
+    let mut code_pos = output.len();
+    let mut code_bits = 0;
+    lz::encode(input, 0x1000, 0x111, mode, |pos, m| {
+        if code_bits == 0 {
+            code_pos = output.len();
+            output.push(0);
+            code_bits = 8;
+        }
+        code_bits -= 1;
+        if m.len < lz::MIN_MATCH {
+            output[code_pos] |= 1 << code_bits;
+            output.push(input[pos]);
+        } else {
+            let dist = pos - m.pos - 1;
+            if m.len >= 0x12 {
+                output.push((dist >> 8) as u8);
+                output.push(dist as u8);
+                output.push((m.len - 0x12) as u8);
+            } else {
+                output.push((((m.len - 2) << 4) | (dist >> 8)) as u8);
+                output.push(dist as u8);
+            }
+        }
+    });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Port of the brute-force search in Nintendo's Yaz0 encoder.
+    fn simple_enc(src: &[u8], pos: usize) -> (usize, usize) {
+        let start = pos.saturating_sub(0x1000);
+        let (mut num_bytes, mut match_pos) = (1, 0);
+        for i in start..pos {
+            let j = (0..src.len() - pos)
+                .find(|&j| src[i + j] != src[pos + j])
+                .unwrap_or(src.len() - pos);
+            if j > num_bytes {
+                num_bytes = j;
+                match_pos = i;
+            }
+        }
+        if num_bytes == 2 {
+            num_bytes = 1;
+        }
+        (num_bytes, match_pos)
+    }
+
+    /// Port of Nintendo's Yaz0 encoder, used as a reference for [`CompressionMode::Matching`].
+    fn reference_compress(src: &[u8]) -> Vec<u8> {
+        let mut output = YAZ0_MAGIC.to_vec();
+        output.extend_from_slice(&(src.len() as u32).to_be_bytes());
+        output.extend_from_slice(&[0u8; 8]);
+        let (mut code_pos, mut code_bits) = (0, 0);
+        let mut pos = 0;
+        let mut pending: Option<(usize, usize)> = None;
+        while pos < src.len() {
+            let (mut num_bytes, match_pos) = if let Some(m) = pending.take() {
+                m
+            } else {
+                let m = simple_enc(src, pos);
+                if m.0 >= 3 {
+                    let next = simple_enc(src, pos + 1);
+                    if next.0 >= m.0 + 2 {
+                        pending = Some(next);
+                        (1, 0)
+                    } else {
+                        m
+                    }
+                } else {
+                    m
+                }
+            };
+            if code_bits == 0 {
+                code_pos = output.len();
+                output.push(0);
+                code_bits = 8;
+            }
+            code_bits -= 1;
+            if num_bytes < 3 {
+                output[code_pos] |= 1 << code_bits;
+                output.push(src[pos]);
+                pos += 1;
+            } else {
+                num_bytes = num_bytes.min(0xFF + 0x12);
+                let dist = pos - match_pos - 1;
+                if num_bytes >= 0x12 {
+                    output.extend_from_slice(&[(dist >> 8) as u8, dist as u8]);
+                    output.push((num_bytes - 0x12) as u8);
+                } else {
+                    output.push((((num_bytes - 2) << 4) | (dist >> 8)) as u8);
+                    output.push(dist as u8);
+                }
+                pos += num_bytes;
+            }
+        }
+        output
+    }
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_run() {
+        let input = [b'a'; 0x20];
+        let expected = b"Yaz0\x00\x00\x00\x20\x00\x00\x00\x00\x00\x00\x00\x00\x80a\x00\x00\x0D";
+        assert_eq!(compress(&input, CompressionMode::Matching), expected);
+    }
+
+    #[test]
+    fn test_compress_matching() {
+        // Long runs exceed the maximum match length
+        let mut runs = vec![0u8; 0x400];
+        runs.extend(pseudo_random(0x80, 1));
+        runs.extend([0xFF; 0x180]);
+        runs.extend(pseudo_random(0x40, 2));
+
+        // A match longer than the maximum at the next position defers the current one,
+        // even though both exceed the maximum length
+        let block = pseudo_random(0x200, 3);
+        let mut deferred = block.clone();
+        deferred.push(b'Z');
+        deferred.extend_from_slice(&block[..0x150]);
+        deferred.push(b'Q');
+        deferred.push(b'Z');
+        deferred.extend_from_slice(&block);
+
+        for input in [runs, deferred] {
+            let compressed = compress(&input, CompressionMode::Matching);
+            assert_eq!(compressed, reference_compress(&input));
+            assert_eq!(decompress_file(&mut Cursor::new(&compressed)).unwrap(), input);
+        }
+    }
+}